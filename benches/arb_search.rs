@@ -0,0 +1,137 @@
+//! Benchmarks the arb search's hot path: one `sim_arb` interval, and full `step_arb` searches
+//! with each of `search_configs()`. They run on a recorded fixture, so no node is needed.
+//!
+//! Record the fixture (with an archive node, over any transport), then run the benchmarks:
+//! ```sh
+//! cargo run --features test-utils --example record_bench_fixture -- ws://localhost:8545
+//! cargo bench --features test-utils --bench arb_search
+//! ```
+//!
+//! Criterion reports throughput as ops/sec (sims/sec for searches); allocations per op are
+//! counted separately, on one run of each benchmark before it's timed.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hindsight::{
+    sim::core::bench::{search_configs, ArbFixture},
+    util::ETH,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fs::File,
+    io::BufReader,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::runtime::Runtime;
+
+const FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/fixtures/bench/arb_search.json"
+);
+
+/// Counts allocations made by the whole process.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Returns `f`'s output & the number of allocations made while it ran.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let res = f();
+    (res, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+fn load_fixture() -> ArbFixture {
+    let file = File::open(FIXTURE).unwrap_or_else(|err| {
+        panic!(
+            "failed to open {} ({}); record it with `cargo run --features test-utils --example record_bench_fixture -- <RPC_URL>`",
+            FIXTURE, err
+        )
+    });
+    serde_json::from_reader(BufReader::new(file)).expect("failed to parse bench fixture")
+}
+
+fn runtime() -> Runtime {
+    // forks block in place when they read state, which needs a multi-threaded runtime
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("failed to build tokio runtime")
+}
+
+fn bench_sim_arb(c: &mut Criterion) {
+    let rt = runtime();
+    let fixture = load_fixture();
+    let forks = fixture.forks();
+    let amount_in = ETH;
+
+    // sims that read state the fixture doesn't have fail; make sure this one doesn't before timing it
+    let (res, allocations) = count_allocations(|| rt.block_on(fixture.sim_arb(&forks, amount_in)));
+    res.expect("sim_arb failed on the bench fixture; re-record it");
+    println!("sim_arb/single_interval: {} allocations/op", allocations);
+
+    let mut group = c.benchmark_group("sim_arb");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("single_interval", |b| {
+        b.to_async(&rt).iter(|| async {
+            fixture
+                .sim_arb(&forks, amount_in)
+                .await
+                .expect("sim_arb failed")
+        })
+    });
+    group.finish();
+}
+
+fn bench_step_arb(c: &mut Criterion) {
+    let rt = runtime();
+    let fixture = load_fixture();
+    let forks = fixture.forks();
+
+    let mut group = c.benchmark_group("step_arb");
+    // full searches are slow; criterion's minimum sample count keeps this bearable
+    group.sample_size(10);
+    for (name, search) in search_configs() {
+        let sims_before = forks.num_sims();
+        let (res, allocations) =
+            count_allocations(|| rt.block_on(fixture.step_arb(forks.clone(), search)));
+        res.expect("step_arb failed on the bench fixture; re-record it");
+        let sims = forks.num_sims() - sims_before;
+        println!(
+            "step_arb/{}: {} sims, {} allocations/op",
+            name, sims, allocations
+        );
+
+        // throughput is in sims, so configs that sim more amounts are comparable
+        group.throughput(Throughput::Elements(sims as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &search, |b, search| {
+            b.to_async(&rt).iter(|| async {
+                fixture
+                    .step_arb(forks.clone(), *search)
+                    .await
+                    .expect("step_arb failed")
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_sim_arb, bench_step_arb);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+//! Records the fixture that `benches/arb_search.rs` runs on: an arb search for one historical
+//! MEV-Share event, and the fork state it touches.
+//!
+//! Needs an archive node, over WebSocket, HTTP, or IPC:
+//! ```sh
+//! cargo run --features test-utils --example record_bench_fixture -- ws://localhost:8545 [fixtures/bench/arb_search.json]
+//! ```
+use ethers::{providers::Middleware, types::H256};
+use hindsight::{config::Secret, sim::core::bench::ArbFixture, util::get_client, Result};
+use mev_share_sse::{EventHistory, EventTransactionLog, Hint};
+use std::{fs::File, io::BufWriter, path::Path};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let rpc_url = args.next().ok_or(anyhow::anyhow!(
+        "usage: record_bench_fixture <RPC_URL> [OUT_PATH]"
+    ))?;
+    let out_path = args
+        .next()
+        .unwrap_or("fixtures/bench/arb_search.json".to_owned());
+    let client = get_client(Some(vec![Secret::new(rpc_url)])).await?;
+
+    // a swap on UniswapV3's USDC/WETH (0.05%) pool
+    let tx_hash: H256 =
+        "0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a".parse()?;
+    let event = EventHistory {
+        block: 17637019,
+        timestamp: 1688673408,
+        hint: Hint {
+            txs: vec![],
+            hash: tx_hash,
+            logs: vec![EventTransactionLog {
+                address: "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse()?,
+                topics: vec![
+                    // Swap(address,address,int256,int256,uint160,uint128,int24)
+                    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67".parse()?,
+                ],
+                data: Default::default(),
+            }],
+            gas_used: None,
+            mev_gas_price: None,
+        },
+    };
+    let tx = client
+        .get_transaction(tx_hash)
+        .await?
+        .ok_or(anyhow::anyhow!("tx {:?} not found", tx_hash))?;
+
+    let fixture = ArbFixture::record(&client, tx, &event).await?;
+    if let Some(dir) = Path::new(&out_path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    serde_json::to_writer(BufWriter::new(File::create(&out_path)?), &fixture)?;
+    println!(
+        "recorded {} accounts to {}",
+        fixture.snapshot.accounts.len(),
+        out_path
+    );
+    Ok(())
+}
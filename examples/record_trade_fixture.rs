@@ -0,0 +1,36 @@
+//! Records a fixture of `derive_trade_params` (see `fixtures/derive_trade_params`): a tx's receipt,
+//! & the calls that deriving its trade params makes.
+//!
+//! Needs a node that has the tx, over WebSocket, HTTP, or IPC:
+//! ```sh
+//! cargo run --features test-utils --example record_trade_fixture -- ws://localhost:8545 <TX_HASH> fixtures/derive_trade_params/v3_swap.json
+//! ```
+use ethers::types::H256;
+use hindsight::{config::Secret, sim::core::trade_fixture::TradeFixture, util::get_client, Result};
+use std::{fs::File, io::BufWriter, path::Path};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: record_trade_fixture <RPC_URL> <TX_HASH> <OUT_PATH>";
+    let (Some(rpc_url), Some(tx_hash), Some(out_path)) = (args.next(), args.next(), args.next())
+    else {
+        return Err(anyhow::anyhow!(usage));
+    };
+    let tx_hash: H256 = tx_hash.parse()?;
+    let client = get_client(Some(vec![Secret::new(rpc_url)])).await?;
+
+    let fixture = TradeFixture::record(&client, tx_hash).await?;
+    if let Some(dir) = Path::new(&out_path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    serde_json::to_writer_pretty(BufWriter::new(File::create(&out_path)?), &fixture)?;
+    println!(
+        "recorded {} swap logs & {} calls of tx {:?} to {}",
+        fixture.event.hint.logs.len(),
+        fixture.calls.len(),
+        tx_hash,
+        out_path
+    );
+    Ok(())
+}
@@ -0,0 +1,64 @@
+//! Simulates the backrun-arbs of one historical MEV-Share event, without saving anything.
+//!
+//! Needs an archive node:
+//! ```sh
+//! cargo run --example simulate_event -- ws://localhost:8545
+//! ```
+use ethers::{providers::Middleware, types::H256};
+use hindsight::{config::Secret, util::get_client, Hindsight, Result, SimOptions};
+use mev_share_sse::{EventHistory, EventTransactionLog, Hint};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let rpc_url = std::env::args()
+        .nth(1)
+        .ok_or(anyhow::anyhow!("usage: simulate_event <RPC_URL>"))?;
+    let client = get_client(Some(vec![Secret::new(rpc_url)])).await?;
+    let hindsight = Hindsight::builder()
+        .client(client.clone())
+        .sim_options(SimOptions::default())
+        .concurrency(1)
+        .retry_timeout(None)
+        .build()
+        .await?;
+
+    // a swap on UniswapV3's USDC/WETH (0.05%) pool
+    let tx_hash: H256 =
+        "0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a".parse()?;
+    let event = EventHistory {
+        block: 17637019,
+        timestamp: 1688673408,
+        hint: Hint {
+            txs: vec![],
+            hash: tx_hash,
+            logs: vec![EventTransactionLog {
+                address: "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse()?,
+                topics: vec![
+                    // Swap(address,address,int256,int256,uint160,uint128,int24)
+                    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67".parse()?,
+                ],
+                data: Default::default(),
+            }],
+            gas_used: None,
+            mev_gas_price: None,
+        },
+    };
+    let tx = client
+        .get_transaction(tx_hash)
+        .await?
+        .ok_or(anyhow::anyhow!("tx {:?} not found", tx_hash))?;
+
+    let batch = hindsight.simulate_event(&event, &tx).await?;
+    println!("max profit: {} wei", batch.max_profit);
+    for res in &batch.results {
+        let backrun = &res.backrun_trade;
+        println!(
+            "{:?} -> {:?}: {} ETH in, {} ETH profit",
+            backrun.start_pool,
+            backrun.end_pool,
+            ethers::utils::format_ether(backrun.amount_in),
+            backrun.profit_eth
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! The chain hindsight simulates on, and the addresses that differ between chains: the wrapped
+//! native token that backruns start & end with, the DEX factories (& Curve pools) arb pools are
+//! found with, and Multicall3.
+
+use crate::{interfaces::Dex, util::WETH, Result};
+use ethers::{providers::Middleware, types::Address};
+use std::str::FromStr;
+
+/// Multicall3, which is deployed at the same address on every chain it's on.
+const MULTICALL3: &str = "0xcA11bde05977b3631167028862bE2a173976CA11";
+
+/// UniswapV3's SwapRouter, deployed at the same address on mainnet & goerli.
+const V3_SWAP_ROUTER: &str = "0xE592427A0AEce9B31B97fE4dFE3C2bFc8E7d0A3b";
+
+/// Chains with built-in addresses, and `Custom` for every other chain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Chain {
+    #[default]
+    Mainnet,
+    Goerli,
+    Sepolia,
+    /// Any other chain (e.g. a devnet); every address must be configured.
+    Custom,
+}
+
+impl Chain {
+    const NAMES: [&'static str; 4] = ["mainnet", "goerli", "sepolia", "custom"];
+}
+
+impl FromStr for Chain {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "mainnet" => Ok(Self::Mainnet),
+            "goerli" => Ok(Self::Goerli),
+            "sepolia" => Ok(Self::Sepolia),
+            "custom" => Ok(Self::Custom),
+            _ => Err(anyhow::anyhow!(
+                "unknown chain '{}' (expected one of: {})",
+                s,
+                Self::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Chain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Mainnet => Self::NAMES[0],
+            Self::Goerli => Self::NAMES[1],
+            Self::Sepolia => Self::NAMES[2],
+            Self::Custom => Self::NAMES[3],
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Chain id & contract addresses of the chain being simulated. Defaults to mainnet's.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChainConfig {
+    pub chain: Chain,
+    pub chain_id: u64,
+    /// The wrapped native token (WETH); only pairs with it can be arbed.
+    pub weth: Address,
+    pub uniswap_v2_factory: Address,
+    pub uniswap_v3_factory: Address,
+    /// SushiSwap's (UniswapV2-clone) factory; None on chains without SushiSwap.
+    pub sushiswap_factory: Option<Address>,
+    /// Multicall3; if None, it's looked up by chain id, and calls are made individually on chains
+    /// without it.
+    pub multicall: Option<Address>,
+    /// Tokens (besides WETH) that two-hop arbs may route through, for tokens that no other pool
+    /// pairs with WETH.
+    pub intermediate_tokens: Vec<Address>,
+    /// Curve pools that may be arbed against: 2-coin stableswap pools (indexing coins w/ int128)
+    /// pairing ETH (or WETH) w/ another token.
+    pub curve_pools: Vec<Address>,
+    /// UniswapV3's SwapRouter, which routes through Curve pools swap on V3 pools with; such
+    /// routes w/ V3 pools fail without it.
+    pub uniswap_v3_router: Option<Address>,
+    /// Tokens worth about 1 ETH (liquid staking tokens & the like). Trades of them w/ WETH are
+    /// valued 1:1 & searched in both directions, since their price says little about which way
+    /// the arb goes.
+    pub eth_equivalent_tokens: Vec<Address>,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::preset(Chain::Mainnet)
+    }
+}
+
+impl ChainConfig {
+    /// Returns the built-in addresses of `chain`. `Chain::Custom` has none; they must all be set.
+    pub fn preset(chain: Chain) -> Self {
+        let address = |s: &str| Address::from_str(s).expect("valid preset address");
+        match chain {
+            Chain::Mainnet => Self {
+                chain,
+                chain_id: 1,
+                weth: WETH,
+                uniswap_v2_factory: address("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+                uniswap_v3_factory: address("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+                sushiswap_factory: Some(address("0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac")),
+                multicall: Some(address(MULTICALL3)),
+                intermediate_tokens: vec![
+                    address("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"), // USDC
+                    address("0xdAC17F958D2ee523a2206206994597C13D831ec7"), // USDT
+                    address("0x6B175474E89094C44Da98b5e2316fB04b7f7dC5F"), // DAI
+                ],
+                curve_pools: vec![
+                    address("0xDC24316b9AE028F1497c275EB9192a3Ea0f67022"), // stETH/ETH
+                    address("0xa1F8A6807c402E4A15ef4EBa36528A3FED24E577"), // frxETH/ETH
+                ],
+                uniswap_v3_router: Some(address(V3_SWAP_ROUTER)),
+                eth_equivalent_tokens: vec![
+                    address("0xae7ab96520DE3A18E5e111B5EaAb095312D7fE84"), // stETH
+                    address("0x7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0"), // wstETH
+                    address("0xae78736Cd615f374D3085123A210448E74Fc6393"), // rETH
+                    address("0xBe9895146f7AF43049ca1c1AE358B0541Ea49704"), // cbETH
+                    address("0x5E8422345238F34275888049021821E8E08CAa1f"), // frxETH
+                    address("0xac3E018457B222d93114458476f3E3416Abbe38F"), // sfrxETH
+                ],
+            },
+            Chain::Goerli => Self {
+                chain,
+                chain_id: 5,
+                weth: address("0xB4FBF271143F4FBf7B91A5ded31805e42b2208d6"),
+                uniswap_v2_factory: address("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+                uniswap_v3_factory: address("0x1F98431c8aD98523631AE4a59f267346ea31F984"),
+                sushiswap_factory: Some(address("0xc35DADB65012eC5796536bD9864eD8773aBc74C4")),
+                multicall: Some(address(MULTICALL3)),
+                intermediate_tokens: vec![],
+                curve_pools: vec![],
+                uniswap_v3_router: Some(address(V3_SWAP_ROUTER)),
+                eth_equivalent_tokens: vec![],
+            },
+            Chain::Sepolia => Self {
+                chain,
+                chain_id: 11_155_111,
+                weth: address("0xfFf9976782d46CC05630D1f6eBAb18b2324d6B14"),
+                uniswap_v2_factory: address("0xF62c03E08ada871A0bEb309762E260a7a6a880E6"),
+                uniswap_v3_factory: address("0x0227628f3F023bb0B980b67D528571c95c6DaC1c"),
+                sushiswap_factory: None,
+                multicall: Some(address(MULTICALL3)),
+                intermediate_tokens: vec![],
+                curve_pools: vec![],
+                uniswap_v3_router: None,
+                eth_equivalent_tokens: vec![],
+            },
+            Chain::Custom => Self {
+                chain,
+                chain_id: 0,
+                weth: Address::zero(),
+                uniswap_v2_factory: Address::zero(),
+                uniswap_v3_factory: Address::zero(),
+                sushiswap_factory: None,
+                multicall: None,
+                intermediate_tokens: vec![],
+                curve_pools: vec![],
+                uniswap_v3_router: None,
+                eth_equivalent_tokens: vec![],
+            },
+        }
+    }
+
+    /// Returns (factory address, DEX) for each V2-style DEX on the chain.
+    pub fn v2_factories(&self) -> Vec<(Address, Dex)> {
+        let mut factories = vec![(self.uniswap_v2_factory, Dex::UniswapV2)];
+        if let Some(sushiswap_factory) = self.sushiswap_factory {
+            factories.push((sushiswap_factory, Dex::SushiSwap));
+        }
+        factories
+    }
+
+    /// Returns the settings a custom chain is missing.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.chain_id == 0 {
+            problems.push(format!("CHAIN_ID must be set for CHAIN={}", self.chain));
+        }
+        for (name, address) in [
+            ("WETH_ADDRESS", self.weth),
+            ("UNISWAP_V2_FACTORY", self.uniswap_v2_factory),
+            ("UNISWAP_V3_FACTORY", self.uniswap_v3_factory),
+        ] {
+            if address.is_zero() {
+                problems.push(format!("{} must be set for CHAIN={}", name, self.chain));
+            }
+        }
+        problems
+    }
+
+    /// Fails if `client` is connected to a node on another chain, where every address is wrong.
+    pub async fn verify<M: Middleware>(&self, client: &M) -> Result<()> {
+        let chain_id = client
+            .get_chainid()
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to get the node's chain id: {}", err))?;
+        if chain_id != self.chain_id.into() {
+            return Err(anyhow::anyhow!(
+                "RPC node is on chain {}, but hindsight is configured for {} (chain {}); set CHAIN (or CHAIN_ID) to match the node",
+                chain_id,
+                self.chain,
+                self.chain_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_chain_presets() -> Result<()> {
+        for name in Chain::NAMES {
+            let chain = Chain::from_str(name)?;
+            assert_eq!(chain.to_string(), name);
+            let config = ChainConfig::preset(chain);
+            if chain == Chain::Custom {
+                assert_eq!(config.problems().len(), 4);
+            } else {
+                assert!(config.problems().is_empty(), "{:?}", config.problems());
+            }
+        }
+        assert_eq!(Chain::from_str("Sepolia")?, Chain::Sepolia);
+        assert!(Chain::from_str("arbitrum").is_err());
+        assert_eq!(ChainConfig::default().weth, WETH);
+        assert_eq!(ChainConfig::default().v2_factories().len(), 2);
+        assert_eq!(ChainConfig::default().intermediate_tokens.len(), 3);
+        assert_eq!(ChainConfig::default().curve_pools.len(), 2);
+        assert_eq!(ChainConfig::default().eth_equivalent_tokens.len(), 6);
+        assert_eq!(
+            ChainConfig::preset(Chain::Sepolia).v2_factories(),
+            vec![(
+                ChainConfig::preset(Chain::Sepolia).uniswap_v2_factory,
+                Dex::UniswapV2
+            )]
+        );
+        Ok(())
+    }
+}
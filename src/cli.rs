@@ -1,11 +1,69 @@
-use clap::{Parser, Subcommand};
-use hindsight::data::db::DbEngine;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ethers::types::{Address, H256};
+use hindsight::data::{arbs::SortOrder, db::DbEngine, units::AmountUnits};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+    /// Increase log verbosity. `-d` enables debug logs, `-dd` enables trace logs.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub debug: u8,
+    /// Print logs as JSON.
+    #[arg(long, global = true)]
+    pub log_json: bool,
+    /// Save arbs for events that already have arbs saved, rather than replacing (mongo) or
+    /// skipping (file) them. Postgres & SQLite always replace them.
+    #[arg(long, global = true)]
+    pub allow_duplicates: bool,
+    /// Derive users' trades only from what their hints shared & simulating their txs, never from
+    /// their receipts, like a searcher would have to in real time. Same as HINT_ONLY=true.
+    #[arg(long, global = true)]
+    pub hint_only: bool,
+    /// Print command results as text, or as a single JSON document on stdout (logs go to stderr).
+    #[arg(long, value_enum, global = true, default_value_t = ReportFormat::Text)]
+    pub output: ReportFormat,
+    #[command(flatten)]
+    pub search: SearchArgs,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
+
+/// Overrides for the search for the best backrun amount; default to the values in `Config`.
+#[derive(Args)]
+pub struct SearchArgs {
+    /// Number of amounts simulated per grid pass (min 2).
+    #[arg(long, global = true)]
+    pub intervals: Option<usize>,
+    /// Max number of grid passes (min 1).
+    #[arg(long, global = true)]
+    pub depth: Option<usize>,
+    /// Stop searching once the range of amounts is narrower than this, in gwei.
+    #[arg(long, global = true)]
+    pub tolerance_gwei: Option<u64>,
+    /// Save every amount simulated by each search with its result, to plot profit curves.
+    #[arg(long, global = true)]
+    pub trace: bool,
+}
+
+/// Pools & tokens to simulate (or skip). Each flag may be repeated, and replaces the
+/// corresponding env var (FILTER_POOLS, FILTER_TOKENS, DENY_TOKENS) when given.
+///
+/// `--pool` and `--token` also filter the saved arbs read by `export`, `export-bundles`, `analyze` & `top-arbs`.
+#[derive(Args)]
+pub struct FilterArgs {
+    /// Only simulate events that swap on this pool (or export/analyze arbs that swap on it).
+    #[arg(long = "pool", global = true)]
+    pub pools: Vec<Address>,
+    /// Only simulate events that swap a pair including this token (or export/analyze arbs that swap it).
+    #[arg(long = "token", global = true)]
+    pub tokens: Vec<Address>,
+    /// Never simulate events that swap a pair including this token.
+    #[arg(long = "deny-token", global = true)]
+    pub deny_tokens: Vec<Address>,
 }
 
 impl Cli {
@@ -31,21 +89,85 @@ pub enum Commands {
         /// Scan until this timestamp.
         #[arg(long)]
         timestamp_end: Option<u32>,
-        /// Number of transactions to simulate concurrently. Defaults to 1/2 the CPU cores on host.
+        /// Number of transactions to simulate concurrently. Defaults to EVENT_CONCURRENCY, or 1/2 the CPU cores on host.
         #[arg(short = 'n', long)]
         batch_size: Option<usize>,
-        /// DB Engine to use to store arb data. Defaults to "mongo".
+        /// DB Engine to use to store arb data.
         #[arg(
             long = "db",
-            help = &format!("<{}>: DB engine to store arb data, defaults to mongo", DbEngine::enum_flags())
+            help = &format!("<{}>: DB engine to store arb data. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
         )]
         db_engine: Option<DbEngine>,
+        /// Resume from the last checkpoint saved by an interrupted scan.
+        ///
+        /// With --block-start, scan only the blocks between --block-start and --block-end (or the latest
+        /// saved arb) that have no arbs saved, then carry on from the latest saved arb if --block-end isn't set.
+        #[arg(long, conflicts_with = "timestamp_start")]
+        resume: bool,
+        /// Longest run of blocks without saved arbs that --resume doesn't rescan; not every block has events to simulate.
+        #[arg(long, default_value_t = 25)]
+        max_gap: u64,
+        /// Re-simulate events that already have results in the DB.
+        #[arg(long)]
+        force: bool,
+        /// Save the state each event's sims touch (to `./cache/state`), so `simulate-tx --offline`
+        /// can re-simulate them without a node.
+        #[arg(long)]
+        record_state: bool,
+    },
+    /// Re-simulate events that failed for reasons that may have passed (transport errors & timeouts),
+    /// as saved by `scan` (in `./cache/failed_events.ndjson`). Events that succeed are removed from it.
+    RetryFailed {
+        /// Number of transactions to simulate concurrently. Defaults to EVENT_CONCURRENCY, or 1/2 the CPU cores on host.
+        #[arg(short = 'n', long)]
+        batch_size: Option<usize>,
+        #[arg(
+            long = "db",
+            help = &format!("<{}>: DB engine to store arb data. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        db_engine: Option<DbEngine>,
+    },
+    /// Fetch MEV-Share events into the local event cache (`./cache/events.ndjson`).
+    ///
+    /// Without a start block or timestamp, only events newer than the last fetch are downloaded.
+    FetchEvents {
+        /// Fetch events starting from this block.
+        #[arg(short, long)]
+        block_start: Option<u32>,
+        /// Fetch events starting from this timestamp.
+        #[arg(short, long)]
+        timestamp_start: Option<u32>,
+        /// Stop fetching events at this block.
+        #[arg(long)]
+        block_end: Option<u32>,
+        /// Stop fetching events at this timestamp.
+        #[arg(long)]
+        timestamp_end: Option<u32>,
+        /// Number of events to request per page. Defaults to the API's max.
+        #[arg(short = 'n', long)]
+        page_size: Option<u64>,
     },
-    /// Export arbs from DB to a JSON file.
+    /// Simulate arbitrage opportunities for live MEV-Share events as they stream in. Automatically saves results to DB.
+    Listen {
+        /// Number of transactions to simulate concurrently. Defaults to EVENT_CONCURRENCY, or 1/2 the CPU cores on host.
+        #[arg(short = 'n', long)]
+        batch_size: Option<usize>,
+        /// Max number of events waiting to be simulated; new events are dropped while the queue is full.
+        #[arg(short, long, default_value_t = 256)]
+        queue_size: usize,
+        /// DB Engine to use to store arb data.
+        #[arg(
+            long = "db",
+            help = &format!("<{}>: DB engine to store arb data. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        db_engine: Option<DbEngine>,
+    },
+    /// Export arbs from DB to a (newline-delimited) JSON or CSV file.
     Export {
         /// File to save arbs to.
         ///
-        /// All files are saved in `./arbData/`. (Default="arbs_{unix-timestamp}.json")
+        /// Arbs are saved as newline-delimited JSON; one arb per line. Files ending in `.gz` are gzip-compressed.
+        /// All files are saved in `./arbData/`. (Default="arbs_{unix-timestamp}.ndjson")
         #[arg(short, long)]
         filename: Option<String>,
         /// Export arbs starting from this timestamp.
@@ -61,13 +183,13 @@ pub enum Commands {
         #[arg(long)]
         block_end: Option<u32>,
         /// Minimum profit of arb to export, in ETH decimal format (e.g. 0.01 => 1e16 wei)
-        #[arg(short = 'p', long)]
+        #[arg(short = 'p', long, visible_alias = "profit-min")]
         min_profit: Option<f64>,
-        /// DB Engine to use to store arb data. Defaults to "mongo".
+        /// DB Engine to read arb data from.
         /// TODO: DRY this up
         #[arg(
             long = "db",
-            help = &format!("<{}>: DB engine to read arb data from, defaults to mongo", DbEngine::enum_flags())
+            help = &format!("<{}>: DB engine to read arb data from. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
         )]
         read_db: Option<DbEngine>,
         #[arg(
@@ -76,5 +198,222 @@ pub enum Commands {
             help = &format!("<{}>: DB engine to write arb data to, default None (save to file). Ignored if --filename is specified.", DbEngine::enum_flags())
         )]
         write_db: Option<DbEngine>,
+        /// File format to export arbs in. Ignored if --db-out is specified without --filename.
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// Units of amounts & prices in file exports: wei (raw), eth (whole ETH or tokens, as exact
+        /// decimals), or both side by side. Defaults to EXPORT_UNITS, or else wei for JSON & CSV and
+        /// eth for Parquet.
+        #[arg(long)]
+        units: Option<AmountUnits>,
+        /// Same as `--units eth`.
+        #[arg(long, conflicts_with = "units")]
+        eth_units: bool,
+    },
+    /// Export the signed backruns of arbs saved in the DB as Flashbots-style bundles (newline-delimited JSON).
+    ///
+    /// Backruns are only signed when AUTH_SIGNER_KEY is set during `scan`.
+    ExportBundles {
+        /// File to save bundles to, in `./arbData/`. (Default="bundles_{unix-timestamp}.ndjson")
+        #[arg(short, long)]
+        filename: Option<String>,
+        /// Export bundles starting from this timestamp.
+        #[arg(short, long)]
+        timestamp_start: Option<u32>,
+        /// Stop exporting bundles at this timestamp.
+        #[arg(long)]
+        timestamp_end: Option<u32>,
+        /// Export bundles starting from this block.
+        #[arg(short, long)]
+        block_start: Option<u32>,
+        /// Stop exporting bundles at this block.
+        #[arg(long)]
+        block_end: Option<u32>,
+        #[arg(
+            long = "from-db",
+            help = &format!("<{}>: DB engine to read arbs from. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        from_db: Option<DbEngine>,
+    },
+    /// Summarize the arbs in the DB: profit stats, top token pairs & pools, profit per day, and pool variants.
+    Analyze {
+        /// Analyze arbs starting from this timestamp.
+        #[arg(short, long)]
+        timestamp_start: Option<u32>,
+        /// Stop analyzing arbs at this timestamp.
+        #[arg(long)]
+        timestamp_end: Option<u32>,
+        /// Analyze arbs starting from this block.
+        #[arg(short, long)]
+        block_start: Option<u32>,
+        /// Stop analyzing arbs at this block.
+        #[arg(long)]
+        block_end: Option<u32>,
+        /// Minimum profit of arbs to analyze, in ETH decimal format (e.g. 0.01 => 1e16 wei)
+        #[arg(short = 'p', long, visible_alias = "profit-min")]
+        min_profit: Option<f64>,
+        #[arg(
+            long = "db",
+            help = &format!("<{}>: DB engine to read arb data from. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        read_db: Option<DbEngine>,
     },
+    /// Print the biggest profitable arbs in the DB as a table: the most profitable, largest or latest.
+    TopArbs {
+        /// Number of arbs to print.
+        #[arg(short = 'n', long, default_value_t = 25)]
+        limit: i64,
+        /// <profit|amount-in|block>: Sort arbs by profit, backrun amount in, or block; biggest first.
+        #[arg(short, long, default_value_t = SortOrder::Profit)]
+        sort: SortOrder,
+        /// List arbs starting from this timestamp.
+        #[arg(short, long)]
+        timestamp_start: Option<u32>,
+        /// Stop listing arbs at this timestamp.
+        #[arg(long)]
+        timestamp_end: Option<u32>,
+        /// List arbs starting from this block.
+        #[arg(short, long)]
+        block_start: Option<u32>,
+        /// Stop listing arbs at this block.
+        #[arg(long)]
+        block_end: Option<u32>,
+        /// Minimum profit of arbs to list, in ETH decimal format (e.g. 0.01 => 1e16 wei)
+        #[arg(short = 'p', long, visible_alias = "profit-min")]
+        min_profit: Option<f64>,
+        #[arg(
+            long = "db",
+            help = &format!("<{}>: DB engine to read arb data from. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        read_db: Option<DbEngine>,
+    },
+    /// Re-simulate saved arbs at their stored amounts, and check that they end with the balances
+    /// they saved. Uses the state saved by `scan --record-state` when there is one.
+    Replay {
+        /// Replay arbs starting from this timestamp.
+        #[arg(short, long)]
+        timestamp_start: Option<u32>,
+        /// Stop replaying arbs at this timestamp.
+        #[arg(long)]
+        timestamp_end: Option<u32>,
+        /// Replay arbs starting from this block.
+        #[arg(short, long)]
+        block_start: Option<u32>,
+        /// Stop replaying arbs at this block.
+        #[arg(long)]
+        block_end: Option<u32>,
+        /// Minimum profit of arbs to replay, in ETH decimal format (e.g. 0.01 => 1e16 wei)
+        #[arg(short = 'p', long, visible_alias = "profit-min")]
+        min_profit: Option<f64>,
+        /// Max number of arbs to replay.
+        #[arg(short = 'n', long)]
+        limit: Option<i64>,
+        /// How far (in basis points) a replayed balance may drift from the saved one and still pass.
+        #[arg(long, default_value_t = 1)]
+        tolerance_bps: u64,
+        #[arg(
+            long = "db",
+            help = &format!("<{}>: DB engine to read arb data from. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        read_db: Option<DbEngine>,
+    },
+    /// Serve the arbs in the DB over a read-only JSON API (`/arbs`, `/arbs/<event hash>`,
+    /// `/stats/summary` & `/ranges`). Requires the `serve` feature.
+    Serve {
+        /// Address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        #[arg(
+            long = "db",
+            help = &format!("<{}>: DB engine to read arb data from. Defaults to mongo if MONGO_URL is set, then postgres if POSTGRES_URL is set, then sqlite", DbEngine::enum_flags())
+        )]
+        read_db: Option<DbEngine>,
+    },
+    /// Simulate the optimal backrun-arbitrage for a single landed transaction.
+    SimulateTx {
+        /// Hash of the transaction to backrun.
+        tx_hash: H256,
+        /// Fork from this block. Defaults to the block before the tx landed.
+        #[arg(short, long)]
+        block: Option<u64>,
+        /// Re-simulate from the state saved by `scan --record-state`, without a node.
+        #[arg(long, conflicts_with = "block")]
+        offline: bool,
+    },
+    /// Inspect hindsight's configuration.
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Inspect and maintain the local event cache (`./cache/events.ndjson`).
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Inspect the audit log of processed events (`AUDIT_LOG_PATH`).
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective configuration (env vars, `.env` and defaults), with secrets masked.
+    Show,
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Print the number of cached events, the blocks they cover, and the fetch cursor.
+    Stats,
+    /// Re-fetch the events in a block range, replacing the cached ones.
+    Refresh {
+        /// Refresh events starting from this block.
+        #[arg(short, long)]
+        block_start: u64,
+        /// Refresh events until this block (inclusive).
+        #[arg(long)]
+        block_end: u64,
+    },
+    /// Remove cached events landed before a block.
+    Evict {
+        /// Remove events landed before this block.
+        #[arg(long)]
+        before: u64,
+    },
+    /// Remove corrupted lines (e.g. left by an interrupted write) from the cache.
+    Repair,
+}
+
+#[derive(Subcommand)]
+pub enum AuditCommands {
+    /// Print the audit records of an event, oldest first, as JSON lines.
+    Grep {
+        /// Hash of the event's tx.
+        event_hash: H256,
+        /// Audit log to read. Defaults to AUDIT_LOG_PATH.
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+}
+
+/// Output formats of `--output`: supported by `scan`, `simulate-tx`, `analyze`, `top-arbs`, `replay` & `cache stats`.
+#[derive(Clone, Debug, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+}
+
+/// File formats supported by `export`.
+#[derive(Clone, Debug, ValueEnum)]
+pub enum ExportFormat {
+    /// Newline-delimited JSON; one event's arbs per line.
+    Json,
+    /// Gzip-compressed newline-delimited JSON.
+    JsonGz,
+    /// One row per simulated backrun.
+    Csv,
+    /// One row per simulated backrun, in columnar format. Requires building with `--features parquet`.
+    Parquet,
 }
@@ -0,0 +1,1069 @@
+use crate::{debug, info, warn, Result};
+use async_trait::async_trait;
+use ethers::providers::{
+    Http, HttpClientError, Ipc, IpcError, JsonRpcClient, JsonRpcError, ProviderError, RpcError, Ws,
+    WsClientError,
+};
+use futures::{SinkExt, StreamExt};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+/// JSON-RPC methods that only read chain state, so retrying them can't have side effects.
+const IDEMPOTENT_METHODS: [&'static str; 16] = [
+    "eth_blockNumber",
+    "eth_call",
+    "eth_chainId",
+    "eth_estimateGas",
+    "eth_feeHistory",
+    "eth_gasPrice",
+    "eth_getBalance",
+    "eth_getBlockByHash",
+    "eth_getBlockByNumber",
+    "eth_getCode",
+    "eth_getLogs",
+    "eth_getStorageAt",
+    "eth_getTransactionByHash",
+    "eth_getTransactionCount",
+    "eth_getTransactionReceipt",
+    "net_version",
+];
+/// Weight of the latest request in an endpoint's latency average.
+const LATENCY_EWMA_WEIGHT: f64 = 0.2;
+
+/// How failed requests are retried by `RetryTransport`.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Backoff before the first retry; doubles on every retry, with up to ±50% jitter.
+    pub initial_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff * 2u32.pow(retry);
+        backoff.mul_f64(rand::thread_rng().gen_range(0.5..1.5))
+    }
+}
+
+/// Wraps a JSON-RPC transport, retrying idempotent requests that fail because of the transport
+/// (e.g. a dropped connection). Errors returned by the node (reverts, invalid params, ...) and
+/// requests with side effects (e.g. `eth_sendRawTransaction`) are never retried.
+#[derive(Clone, Debug)]
+pub struct RetryTransport<T> {
+    inner: T,
+    policy: RetryPolicy,
+}
+
+impl<T> RetryTransport<T> {
+    pub fn new(inner: T, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+fn is_idempotent(method: &str) -> bool {
+    IDEMPOTENT_METHODS.contains(&method)
+}
+
+/// Returns true if `err` came from the transport rather than from the node.
+fn is_transport_error<E: RpcError>(err: &E) -> bool {
+    err.as_error_response().is_none() && err.as_serde_error().is_none()
+}
+
+#[async_trait]
+impl<T: JsonRpcClient> JsonRpcClient for RetryTransport<T> {
+    type Error = T::Error;
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+    where
+        P: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        if !is_idempotent(method) {
+            return self.inner.request(method, params).await;
+        }
+        // params are serialized once so they can be re-sent
+        let params = match serde_json::to_value(&params) {
+            Ok(params) => params,
+            Err(_) => return self.inner.request(method, params).await,
+        };
+        let mut retries = 0;
+        loop {
+            match self.inner.request(method, &params).await {
+                Err(err) if retries < self.policy.max_retries && is_transport_error(&err) => {
+                    let backoff = self.policy.backoff(retries);
+                    retries += 1;
+                    debug!(
+                        "{} failed: {}. retrying in {:?} ({}/{})",
+                        method, err, backoff, retries, self.policy.max_retries
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// Connection to an RPC node, picked by the scheme of its URL: `ws://`/`wss://` for WebSocket,
+/// `http://`/`https://` for HTTP, and anything else is taken as the path of an IPC socket.
+#[derive(Clone, Debug)]
+pub enum RpcTransport {
+    Ws(Ws),
+    Http(Http),
+    Ipc(Ipc),
+}
+
+impl RpcTransport {
+    /// Connects to `rpc_url`. WebSocket connections are re-established up to `ws_reconnects` times.
+    pub async fn connect(rpc_url: &str, ws_reconnects: usize) -> Result<Self> {
+        Ok(
+            if rpc_url.starts_with("ws://") || rpc_url.starts_with("wss://") {
+                Self::Ws(Ws::connect_with_reconnects(rpc_url, ws_reconnects).await?)
+            } else if rpc_url.starts_with("http://") || rpc_url.starts_with("https://") {
+                Self::Http(Http::from_str(rpc_url)?)
+            } else {
+                Self::Ipc(Ipc::connect(rpc_url.trim_start_matches("ipc://")).await?)
+            },
+        )
+    }
+
+    /// Name of the transport, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Ws(_) => "WebSocket",
+            Self::Http(_) => "HTTP",
+            Self::Ipc(_) => "IPC",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RpcTransportError {
+    Ws(WsClientError),
+    Http(HttpClientError),
+    Ipc(IpcError),
+}
+
+impl std::fmt::Display for RpcTransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ws(err) => write!(f, "{}", err),
+            Self::Http(err) => write!(f, "{}", err),
+            Self::Ipc(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for RpcTransportError {}
+
+impl RpcError for RpcTransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Ws(err) => err.as_error_response(),
+            Self::Http(err) => err.as_error_response(),
+            Self::Ipc(err) => err.as_error_response(),
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Ws(err) => err.as_serde_error(),
+            Self::Http(err) => err.as_serde_error(),
+            Self::Ipc(err) => err.as_serde_error(),
+        }
+    }
+}
+
+impl From<RpcTransportError> for ProviderError {
+    fn from(err: RpcTransportError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for RpcTransport {
+    type Error = RpcTransportError;
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+    where
+        P: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Self::Ws(ws) => ws
+                .request(method, params)
+                .await
+                .map_err(RpcTransportError::Ws),
+            Self::Http(http) => http
+                .request(method, params)
+                .await
+                .map_err(RpcTransportError::Http),
+            Self::Ipc(ipc) => ipc
+                .request(method, params)
+                .await
+                .map_err(RpcTransportError::Ipc),
+        }
+    }
+}
+
+/// Serves JSON-RPC on a local WebSocket, forwarding each request to `client`, and returns a
+/// connection to it. It's for code that only takes a plain WebSocket provider (rusty-sando's fork
+/// backend), so it can fetch state through any client, e.g. one on HTTP or IPC endpoints, w/ its
+/// retries & failover.
+///
+/// The bridge serves a single connection, and stops when it's closed.
+pub async fn ws_bridge<T>(client: Arc<ethers::providers::Provider<T>>) -> Result<Ws>
+where
+    T: JsonRpcClient + 'static,
+{
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("ws://{}", listener.local_addr()?);
+    tokio::spawn(async move {
+        let conn = match listener.accept().await {
+            Ok((stream, _)) => tokio_tungstenite::accept_async(stream).await,
+            Err(err) => {
+                warn!("WebSocket bridge failed to accept a connection: {}", err);
+                return;
+            }
+        };
+        let (mut sink, mut stream) = match conn {
+            Ok(conn) => conn.split(),
+            Err(err) => {
+                warn!("WebSocket bridge handshake failed: {}", err);
+                return;
+            }
+        };
+        let (responses, mut outgoing) = tokio::sync::mpsc::unbounded_channel();
+        let writer = tokio::spawn(async move {
+            while let Some(msg) = outgoing.recv().await {
+                if sink.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+        while let Some(Ok(msg)) = stream.next().await {
+            match msg {
+                Message::Text(request) => {
+                    // requests are answered concurrently; responses are matched to them by id
+                    let (client, responses) = (client.clone(), responses.clone());
+                    tokio::spawn(async move {
+                        let response = bridge_response(&client, &request).await;
+                        let _ = responses.send(Message::Text(response.to_string()));
+                    });
+                }
+                Message::Ping(data) => {
+                    let _ = responses.send(Message::Pong(data));
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        drop(responses);
+        let _ = writer.await;
+    });
+    Ok(Ws::connect(url).await?)
+}
+
+/// Answers the JSON-RPC `request` w/ `client`, passing on the node's error if it fails.
+async fn bridge_response<T: JsonRpcClient>(
+    client: &ethers::providers::Provider<T>,
+    request: &str,
+) -> serde_json::Value {
+    let request: serde_json::Value = match serde_json::from_str(request) {
+        Ok(request) => request,
+        Err(err) => return rpc_error(json!(null), -32700, err.to_string()),
+    };
+    let id = request.get("id").cloned().unwrap_or_default();
+    let Some(method) = request.get("method").and_then(|method| method.as_str()) else {
+        return rpc_error(id, -32600, "missing method".to_owned());
+    };
+    let params = request.get("params").cloned().unwrap_or(json!([]));
+    match client.request::<_, serde_json::Value>(method, params).await {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(err) => match err.as_error_response() {
+            Some(node_err) => json!({"jsonrpc": "2.0", "id": id, "error": node_err}),
+            None => rpc_error(id, -32603, err.to_string()),
+        },
+    }
+}
+
+/// A JSON-RPC error response to the request `id`.
+fn rpc_error(id: serde_json::Value, code: i64, message: String) -> serde_json::Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message},
+    })
+}
+
+/// Max rate of requests to an RPC endpoint, enforced by `RateLimiter`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// Sustained requests per second.
+    pub requests_per_sec: f64,
+    /// Requests that can be sent at once after the endpoint's been idle.
+    pub burst: u32,
+}
+
+/// Formats as `<requests/sec>:<burst>`.
+impl std::fmt::Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.requests_per_sec, self.burst)
+    }
+}
+
+/// Parses `<requests/sec>` or `<requests/sec>:<burst>`. The burst defaults to one second's worth
+/// of requests.
+impl FromStr for RateLimit {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (rate, burst) = match s.split_once(':') {
+            Some((rate, burst)) => (rate, Some(burst)),
+            None => (s, None),
+        };
+        let requests_per_sec = rate
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| format!("invalid requests/sec: {}", rate))?;
+        let burst = match burst {
+            Some(burst) => burst
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid burst: {}", burst))?,
+            None => requests_per_sec.ceil().max(1.0) as u32,
+        };
+        if !(requests_per_sec > 0.0 && requests_per_sec.is_finite()) {
+            return Err(format!("requests/sec must be positive: {}", rate));
+        }
+        if burst < 1 {
+            return Err("burst must be at least 1".to_owned());
+        }
+        Ok(Self {
+            requests_per_sec,
+            burst,
+        })
+    }
+}
+
+/// Time spent waiting on a `RateLimiter`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThrottleStats {
+    /// Number of requests that had to wait.
+    pub throttled: u64,
+    /// Total time requests waited.
+    pub total_wait: Duration,
+    /// How long a request sent now would wait.
+    pub current_wait: Duration,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Requests that can be sent right away. Negative when requests are queued for tokens that
+    /// haven't refilled yet.
+    tokens: f64,
+    refilled_at: Instant,
+    throttled: u64,
+    total_wait: Duration,
+}
+
+/// Token bucket limiting the rate of requests to an endpoint. Requests over the limit wait for
+/// their turn rather than failing.
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// Starts with a full bucket (`limit.burst` requests).
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bucket: Mutex::new(Bucket {
+                tokens: limit.burst as f64,
+                refilled_at: Instant::now(),
+                throttled: 0,
+                total_wait: Duration::ZERO,
+            }),
+        }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        // the bucket is only updated by simple arithmetic, so it's consistent even if a holder panicked
+        self.bucket.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Adds the tokens refilled since the bucket was last refilled, up to `limit.burst`.
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.limit.requests_per_sec)
+            .min(self.limit.burst as f64);
+        // concurrent callers may get here out of order
+        bucket.refilled_at = bucket.refilled_at.max(now);
+    }
+
+    /// Takes a token at `now`. Returns how long to wait before sending the request.
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket();
+        self.refill(&mut bucket, now);
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        let wait = Duration::from_secs_f64(-bucket.tokens / self.limit.requests_per_sec);
+        bucket.throttled += 1;
+        bucket.total_wait += wait;
+        wait
+    }
+
+    /// Waits until a request may be sent.
+    pub async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    pub fn stats(&self) -> ThrottleStats {
+        let mut bucket = self.bucket();
+        self.refill(&mut bucket, Instant::now());
+        ThrottleStats {
+            throttled: bucket.throttled,
+            total_wait: bucket.total_wait,
+            current_wait: if bucket.tokens >= 0.0 {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64(-bucket.tokens / self.limit.requests_per_sec)
+            },
+        }
+    }
+}
+
+/// How `FailoverTransport` decides that an endpoint is down, and when to try it again.
+#[derive(Clone, Debug)]
+pub struct FailoverPolicy {
+    /// Requests that take longer than this fail over to the next endpoint.
+    pub request_timeout: Duration,
+    /// Number of consecutive failures after which an endpoint is considered dead.
+    pub max_failures: u32,
+    /// How long a dead endpoint is skipped before a request is sent to it again.
+    pub dead_retry_interval: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(30),
+            max_failures: 3,
+            dead_retry_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Health of an RPC endpoint, tracked from the requests sent to it.
+#[derive(Clone, Debug, Default)]
+pub struct EndpointHealth {
+    pub consecutive_failures: u32,
+    /// Exponentially-weighted moving average of the endpoint's response time.
+    pub latency_ewma: Option<Duration>,
+    /// Last time a request to the endpoint failed (or was sent to it while it was dead).
+    last_failure: Option<Instant>,
+}
+
+impl EndpointHealth {
+    pub fn is_dead(&self, policy: &FailoverPolicy) -> bool {
+        self.consecutive_failures >= policy.max_failures
+    }
+
+    fn record_latency(&mut self, latency: Duration) {
+        self.latency_ewma = Some(match self.latency_ewma {
+            Some(avg) => {
+                avg.mul_f64(1.0 - LATENCY_EWMA_WEIGHT) + latency.mul_f64(LATENCY_EWMA_WEIGHT)
+            }
+            None => latency,
+        });
+    }
+}
+
+/// Returns `url` without its path, query or credentials, which often hold API keys.
+pub(crate) fn redact_url(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let host = rest.split(['/', '?']).next().unwrap_or_default();
+            format!(
+                "{}://{}",
+                scheme,
+                host.rsplit('@').next().unwrap_or_default()
+            )
+        }
+        // IPC path
+        None => url.to_owned(),
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint<T> {
+    /// Redacted URL, for logs.
+    name: String,
+    transport: T,
+    health: Mutex<EndpointHealth>,
+    /// Limits the rate of requests to the endpoint, if it has a rate limit.
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl<T> Endpoint<T> {
+    fn health(&self) -> std::sync::MutexGuard<'_, EndpointHealth> {
+        // health is only updated by simple assignments, so it's consistent even if a holder panicked
+        self.health.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn record_success(&self, latency: Duration, policy: &FailoverPolicy) {
+        let mut health = self.health();
+        if health.is_dead(policy) {
+            info!("RPC endpoint {} is back up", self.name);
+        }
+        health.consecutive_failures = 0;
+        health.record_latency(latency);
+    }
+
+    fn record_failure(&self, err: &dyn std::fmt::Display, policy: &FailoverPolicy) {
+        let mut health = self.health();
+        health.consecutive_failures += 1;
+        health.last_failure = Some(Instant::now());
+        if health.consecutive_failures == policy.max_failures {
+            warn!(
+                "RPC endpoint {} is down after {} consecutive failures ({}). retrying it every {:?}",
+                self.name, health.consecutive_failures, err, policy.dead_retry_interval
+            );
+        } else {
+            debug!("request to RPC endpoint {} failed: {}", self.name, err);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FailoverError<E> {
+    Endpoint(E),
+    Timeout(Duration),
+    Serialize(serde_json::Error),
+    NoEndpoints,
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for FailoverError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Endpoint(err) => write!(f, "{}", err),
+            Self::Timeout(timeout) => write!(f, "request timed out after {:?}", timeout),
+            Self::Serialize(err) => write!(f, "failed to serialize request: {}", err),
+            Self::NoEndpoints => write!(f, "no RPC endpoints configured"),
+        }
+    }
+}
+
+impl<E: std::error::Error> std::error::Error for FailoverError<E> {}
+
+impl<E: RpcError> RpcError for FailoverError<E> {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Endpoint(err) => err.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Endpoint(err) => err.as_serde_error(),
+            Self::Serialize(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<E: RpcError + Send + Sync + 'static> From<FailoverError<E>> for ProviderError {
+    fn from(err: FailoverError<E>) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(err))
+    }
+}
+
+/// Spreads requests across several RPC endpoints round-robin. Requests that fail because of the
+/// transport, or time out, fail over to the next endpoint. Endpoints that fail repeatedly are
+/// considered dead and skipped, except for one request every `dead_retry_interval` to see if
+/// they've come back up.
+#[derive(Clone, Debug)]
+pub struct FailoverTransport<T> {
+    endpoints: Arc<Vec<Endpoint<T>>>,
+    next: Arc<AtomicUsize>,
+    policy: FailoverPolicy,
+}
+
+impl<T> FailoverTransport<T> {
+    /// `endpoints` are (url, transport) pairs.
+    pub fn new(endpoints: Vec<(String, T)>, policy: FailoverPolicy) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(url, transport)| (url, transport, None))
+            .collect();
+        Self::rate_limited(endpoints, policy)
+    }
+
+    /// `endpoints` are (url, transport, limiter) triples. Requests to an endpoint wait for its
+    /// limiter, if it has one; endpoints may share a limiter.
+    pub fn rate_limited(
+        endpoints: Vec<(String, T, Option<Arc<RateLimiter>>)>,
+        policy: FailoverPolicy,
+    ) -> Self {
+        let endpoints = endpoints
+            .into_iter()
+            .map(|(url, transport, limiter)| Endpoint {
+                name: redact_url(&url),
+                transport,
+                health: Mutex::new(EndpointHealth::default()),
+                limiter,
+            })
+            .collect();
+        Self {
+            endpoints: Arc::new(endpoints),
+            next: Arc::new(AtomicUsize::new(0)),
+            policy,
+        }
+    }
+
+    pub fn transports(&self) -> impl Iterator<Item = &T> {
+        self.endpoints.iter().map(|endpoint| &endpoint.transport)
+    }
+
+    /// Redacted URL and health of each endpoint.
+    pub fn health(&self) -> Vec<(String, EndpointHealth)> {
+        self.endpoints
+            .iter()
+            .map(|endpoint| (endpoint.name.to_owned(), endpoint.health().clone()))
+            .collect()
+    }
+
+    /// Redacted URL and throttling of each rate-limited endpoint.
+    pub fn throttling(&self) -> Vec<(String, ThrottleStats)> {
+        self.endpoints
+            .iter()
+            .filter_map(|endpoint| {
+                let limiter = endpoint.limiter.as_ref()?;
+                Some((endpoint.name.to_owned(), limiter.stats()))
+            })
+            .collect()
+    }
+
+    pub fn log_health(&self) {
+        for (name, health) in self.health() {
+            info!(
+                "RPC endpoint {}: {} (avg latency {:?}, {} consecutive failures)",
+                name,
+                if health.is_dead(&self.policy) {
+                    "down"
+                } else {
+                    "up"
+                },
+                health.latency_ewma.unwrap_or_default(),
+                health.consecutive_failures
+            );
+        }
+        for (name, throttle) in self.throttling() {
+            info!(
+                "RPC endpoint {} rate limit: {} requests throttled for {:?} in total, current wait {:?}",
+                name, throttle.throttled, throttle.total_wait, throttle.current_wait
+            );
+        }
+    }
+
+    /// Indices of the endpoints, starting from the next one in round-robin order.
+    fn round_robin(&self) -> impl Iterator<Item = usize> {
+        let num_endpoints = self.endpoints.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..num_endpoints).map(move |i| (start + i) % num_endpoints)
+    }
+
+    /// Returns the next live endpoint (round-robin) whose transport passes `filter`. If all of
+    /// them are dead, returns the first one that passes anyway.
+    pub fn next_endpoint(&self, filter: impl Fn(&T) -> bool) -> Option<&T> {
+        let mut fallback = None;
+        for i in self.round_robin() {
+            let endpoint = &self.endpoints[i];
+            if !filter(&endpoint.transport) {
+                continue;
+            }
+            if !endpoint.health().is_dead(&self.policy) {
+                return Some(&endpoint.transport);
+            }
+            fallback = fallback.or(Some(&endpoint.transport));
+        }
+        fallback
+    }
+
+    /// Order in which endpoints are tried for a request: live endpoints round-robin, then dead ones
+    /// as a last resort. A dead endpoint that's due to be retried goes first, so it's tried once.
+    fn request_order(&self) -> Vec<usize> {
+        let mut live = vec![];
+        let mut dead = vec![];
+        for i in self.round_robin() {
+            let mut health = self.endpoints[i].health();
+            if !health.is_dead(&self.policy) {
+                live.push(i);
+            } else if health.last_failure.map_or(true, |failed_at| {
+                failed_at.elapsed() >= self.policy.dead_retry_interval
+            }) {
+                // other requests keep skipping it until this one fails (or succeeds)
+                health.last_failure = Some(Instant::now());
+                live.insert(0, i);
+            } else {
+                dead.push(i);
+            }
+        }
+        live.extend(dead);
+        live
+    }
+}
+
+#[async_trait]
+impl<T: JsonRpcClient> JsonRpcClient for FailoverTransport<T>
+where
+    T::Error: 'static,
+{
+    type Error = FailoverError<T::Error>;
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+    where
+        P: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // params are serialized once so they can be re-sent
+        let params = serde_json::to_value(&params).map_err(FailoverError::Serialize)?;
+        let mut last_err = FailoverError::NoEndpoints;
+        for i in self.request_order() {
+            let endpoint = &self.endpoints[i];
+            // waiting for the limiter doesn't count towards the request's timeout or latency
+            if let Some(limiter) = &endpoint.limiter {
+                limiter.acquire().await;
+            }
+            let started = Instant::now();
+            let res = tokio::time::timeout(
+                self.policy.request_timeout,
+                endpoint.transport.request(method, &params),
+            )
+            .await;
+            let err = match res {
+                Ok(Ok(res)) => {
+                    endpoint.record_success(started.elapsed(), &self.policy);
+                    return Ok(res);
+                }
+                Ok(Err(err)) if !is_transport_error(&err) => {
+                    // the node answered; errors like reverts would be the same on every endpoint
+                    endpoint.record_success(started.elapsed(), &self.policy);
+                    return Err(FailoverError::Endpoint(err));
+                }
+                Ok(Err(err)) => FailoverError::Endpoint(err),
+                Err(_) => FailoverError::Timeout(self.policy.request_timeout),
+            };
+            endpoint.record_failure(&err, &self.policy);
+            last_err = err;
+        }
+        Err(last_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::{
+        providers::{Middleware, MockError, MockProvider, MockResponse, Provider},
+        types::U64,
+    };
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails the first `disconnects` requests as if the connection dropped, then forwards to `inner`.
+    #[derive(Debug)]
+    struct FlakyTransport {
+        inner: MockProvider,
+        disconnects: AtomicU32,
+        requests: AtomicU32,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for FlakyTransport {
+        type Error = MockError;
+
+        async fn request<P, R>(&self, method: &str, params: P) -> Result<R, MockError>
+        where
+            P: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let disconnected = self
+                .disconnects
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+            if disconnected {
+                // not an error response or a serde error, so it's treated like a dropped connection
+                return Err(MockError::EmptyResponses);
+            }
+            self.inner.request(method, params).await
+        }
+    }
+
+    fn flaky_transport(disconnects: u32) -> (FlakyTransport, MockProvider) {
+        let mock = MockProvider::new();
+        let transport = FlakyTransport {
+            inner: mock.clone(),
+            disconnects: AtomicU32::new(disconnects),
+            requests: AtomicU32::new(0),
+        };
+        (transport, mock)
+    }
+
+    fn flaky_client(disconnects: u32) -> (Provider<RetryTransport<FlakyTransport>>, MockProvider) {
+        let (transport, mock) = flaky_transport(disconnects);
+        let policy = RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(1),
+        };
+        (Provider::new(RetryTransport::new(transport, policy)), mock)
+    }
+
+    fn num_requests(client: &Provider<RetryTransport<FlakyTransport>>) -> u32 {
+        client.as_ref().inner().requests.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn it_retries_after_a_disconnect() -> crate::Result<()> {
+        let (client, mock) = flaky_client(1);
+        mock.push(U64::from(17637019))?;
+        assert_eq!(client.get_block_number().await?, U64::from(17637019));
+        assert_eq!(num_requests(&client), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_does_not_retry_node_errors() -> crate::Result<()> {
+        let (client, mock) = flaky_client(0);
+        mock.push_response(MockResponse::Error(ethers::providers::JsonRpcError {
+            code: -32602,
+            message: "invalid params".to_owned(),
+            data: None,
+        }));
+        assert!(client.get_block_number().await.is_err());
+        assert_eq!(num_requests(&client), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_does_not_retry_side_effects() -> crate::Result<()> {
+        let (client, _mock) = flaky_client(1);
+        let res: Result<ethers::types::H256, _> =
+            client.request("eth_sendRawTransaction", ["0x00"]).await;
+        assert!(res.is_err());
+        assert_eq!(num_requests(&client), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_bridges_requests_over_websocket() -> crate::Result<()> {
+        let mock = MockProvider::new();
+        // responses are popped last-in-first-out
+        mock.push_response(MockResponse::Error(ethers::providers::JsonRpcError {
+            code: -32000,
+            message: "header not found".to_owned(),
+            data: None,
+        }));
+        mock.push(U64::from(17637019))?;
+        let client = Provider::new(ws_bridge(Arc::new(Provider::new(mock))).await?);
+        assert_eq!(client.get_block_number().await?, U64::from(17637019));
+        let err = client.get_block_number().await.unwrap_err();
+        let err = err
+            .as_error_response()
+            .expect("the node's error is passed on");
+        assert_eq!(
+            (err.code, err.message.as_str()),
+            (-32000, "header not found")
+        );
+        Ok(())
+    }
+
+    fn failover_client(
+        disconnects: [u32; 2],
+        policy: FailoverPolicy,
+    ) -> (
+        Provider<FailoverTransport<FlakyTransport>>,
+        [MockProvider; 2],
+    ) {
+        let (transport_a, mock_a) = flaky_transport(disconnects[0]);
+        let (transport_b, mock_b) = flaky_transport(disconnects[1]);
+        let transport = FailoverTransport::new(
+            vec![
+                ("ws://a.example/v2/secret".to_owned(), transport_a),
+                ("ws://b.example/v2/secret".to_owned(), transport_b),
+            ],
+            policy,
+        );
+        (Provider::new(transport), [mock_a, mock_b])
+    }
+
+    fn endpoint_requests(client: &Provider<FailoverTransport<FlakyTransport>>) -> Vec<u32> {
+        client
+            .as_ref()
+            .transports()
+            .map(|transport| transport.requests.load(Ordering::SeqCst))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_fails_over_to_live_endpoints() -> crate::Result<()> {
+        let policy = FailoverPolicy {
+            request_timeout: Duration::from_secs(1),
+            max_failures: 2,
+            dead_retry_interval: Duration::from_secs(3600),
+        };
+        let (client, [_, mock_b]) = failover_client([u32::MAX, 0], policy);
+        for _ in 0..5 {
+            mock_b.push(U64::from(17637019))?;
+        }
+        // requests alternate between endpoints; the ones sent to A fail over to B
+        for _ in 0..3 {
+            assert_eq!(client.get_block_number().await?, U64::from(17637019));
+        }
+        assert_eq!(endpoint_requests(&client), vec![2, 3]);
+        let health = client.as_ref().health();
+        assert_eq!(health[0].0, "ws://a.example");
+        assert_eq!(health[0].1.consecutive_failures, 2);
+        assert!(health[1].1.latency_ewma.is_some());
+
+        // A is dead, so it's skipped until it's due to be retried
+        for _ in 0..2 {
+            assert_eq!(client.get_block_number().await?, U64::from(17637019));
+        }
+        assert_eq!(endpoint_requests(&client), vec![2, 5]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_rate_limits() {
+        assert_eq!(
+            "25".parse(),
+            Ok(RateLimit {
+                requests_per_sec: 25.0,
+                burst: 25
+            })
+        );
+        assert_eq!(
+            "0.5:10".parse(),
+            Ok(RateLimit {
+                requests_per_sec: 0.5,
+                burst: 10
+            })
+        );
+        assert_eq!("0.5".parse::<RateLimit>().map(|limit| limit.burst), Ok(1));
+        assert!("fast".parse::<RateLimit>().is_err());
+        assert!("10:lots".parse::<RateLimit>().is_err());
+        assert!("0".parse::<RateLimit>().is_err());
+        assert!("10:0".parse::<RateLimit>().is_err());
+    }
+
+    #[test]
+    fn it_throttles_requests_over_the_rate_limit() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_sec: 10.0,
+            burst: 2,
+        });
+        let start = limiter.bucket().refilled_at;
+        let ms = |ms| Duration::from_millis(ms);
+        let close = |a: Duration, b: Duration| a.max(b) - a.min(b) < ms(1);
+        // the burst goes through right away, then requests queue 100ms apart
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert_eq!(limiter.reserve(start), Duration::ZERO);
+        assert!(close(limiter.reserve(start), ms(100)));
+        assert!(close(limiter.reserve(start), ms(200)));
+        // after 250ms, 2.5 tokens have refilled, which pays for the queue & half a request
+        assert!(close(limiter.reserve(start + ms(250)), ms(50)));
+        // idle time refills at most a burst's worth
+        assert_eq!(limiter.reserve(start + ms(10_000)), Duration::ZERO);
+        assert_eq!(limiter.reserve(start + ms(10_000)), Duration::ZERO);
+        assert!(close(limiter.reserve(start + ms(10_000)), ms(100)));
+
+        let stats = limiter.stats();
+        assert_eq!(stats.throttled, 4);
+        assert!(close(stats.total_wait, ms(450)));
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_endpoint_rate_limits() -> crate::Result<()> {
+        let (transport, mock) = flaky_transport(0);
+        let limiter = Arc::new(RateLimiter::new(RateLimit {
+            requests_per_sec: 50.0,
+            burst: 1,
+        }));
+        let client = Provider::new(FailoverTransport::rate_limited(
+            vec![(
+                "ws://a.example/v2/secret".to_owned(),
+                transport,
+                Some(limiter),
+            )],
+            FailoverPolicy::default(),
+        ));
+        for _ in 0..3 {
+            mock.push(U64::from(17637019))?;
+        }
+        let started = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(client.get_block_number().await?, U64::from(17637019));
+        }
+        // the first request uses the burst; the others wait 20ms each
+        assert!(started.elapsed() >= Duration::from_millis(35));
+        let throttling = client.as_ref().throttling();
+        assert_eq!(throttling[0].0, "ws://a.example");
+        assert_eq!(throttling[0].1.throttled, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_retries_dead_endpoints() -> crate::Result<()> {
+        let policy = FailoverPolicy {
+            request_timeout: Duration::from_secs(1),
+            max_failures: 1,
+            dead_retry_interval: Duration::ZERO,
+        };
+        let (client, [mock_a, mock_b]) = failover_client([1, 0], policy.to_owned());
+        mock_a.push(U64::from(17637019))?;
+        mock_b.push(U64::from(17637019))?;
+        client.get_block_number().await?;
+        assert!(client.as_ref().health()[0].1.is_dead(&policy));
+        // A is due to be retried, so it's tried first even though it's B's turn
+        client.get_block_number().await?;
+        assert_eq!(endpoint_requests(&client), vec![2, 1]);
+        assert!(!client.as_ref().health()[0].1.is_dead(&policy));
+        Ok(())
+    }
+}
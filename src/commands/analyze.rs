@@ -0,0 +1,9 @@
+use crate::commands::print_result;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams, ArbReader};
+use crate::Result;
+
+/// Prints a report of the arbs in `read_db` matching `params`, as text or (if `json`) JSON.
+pub async fn run(params: ArbFilterParams, read_db: &ArbDatabase, json: bool) -> Result<()> {
+    let report = read_db.analyze_arbs(&params).await?;
+    print_result(&report, json)
+}
@@ -0,0 +1,22 @@
+use crate::data::audit::AuditLog;
+use crate::Result;
+use ethers::types::H256;
+use std::path::Path;
+
+/// Prints the audit records of the event for `event_hash` in the audit log at `path`, one JSON
+/// object per line.
+pub fn grep(path: &Path, event_hash: H256) -> Result<()> {
+    let records = AuditLog::grep(path, event_hash)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {}", path.display(), err))?;
+    if records.is_empty() {
+        eprintln!(
+            "no audit records for {:?} in {}",
+            event_hash,
+            path.display()
+        );
+    }
+    for record in records {
+        println!("{}", serde_json::to_string(&record)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use crate::commands::print_result;
+use crate::data::event_cache::{EventCache, EventCacheStats, EventCursor};
+use crate::event_history::fetch_latest_events;
+use crate::info;
+use crate::Result;
+use mev_share_sse::{EventClient, EventHistoryParams};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Summary of the event cache, as printed by `cache stats`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStatsSummary {
+    pub events_file: PathBuf,
+    /// Size of the events file, in bytes.
+    pub size: u64,
+    pub events: usize,
+    /// Lines that aren't valid events, e.g. from an interrupted write.
+    pub corrupted: usize,
+    /// First & last block of the cached events; None if there aren't any.
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    /// Where the next fetch starts from.
+    pub cursor: Option<EventCursor>,
+}
+
+impl CacheStatsSummary {
+    pub fn new(cache: &EventCache, stats: EventCacheStats) -> Self {
+        Self {
+            events_file: cache.events_path(),
+            size: stats.size,
+            events: stats.events,
+            corrupted: stats.corrupted,
+            first_block: stats.block_range.map(|(first, _)| first),
+            last_block: stats.block_range.map(|(_, last)| last),
+            cursor: stats.cursor,
+        }
+    }
+}
+
+impl std::fmt::Display for CacheStatsSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "events file: {}", self.events_file.display())?;
+        writeln!(f, "size: {} bytes", self.size)?;
+        writeln!(f, "events: {}", self.events)?;
+        writeln!(f, "corrupted lines: {}", self.corrupted)?;
+        match (self.first_block, self.last_block) {
+            (Some(first), Some(last)) => writeln!(f, "blocks: {}..={}", first, last)?,
+            _ => writeln!(f, "blocks: none")?,
+        }
+        match &self.cursor {
+            Some(cursor) => write!(
+                f,
+                "cursor: block={} timestamp={}",
+                cursor.block, cursor.timestamp
+            ),
+            None => write!(f, "cursor: none"),
+        }
+    }
+}
+
+/// Prints a summary of the event cache, as text or (if `json`) JSON.
+pub fn stats(cache: &EventCache, json: bool) -> Result<()> {
+    let stats = cache.stats()?;
+    print_result(&CacheStatsSummary::new(cache, stats), json)
+}
+
+/// Re-fetches the events landed in blocks `block_start..=block_end`, replacing the cached ones.
+///
+/// The whole range is fetched before anything is removed, so a failed fetch leaves the cache as it was.
+pub async fn refresh(
+    block_start: u64,
+    block_end: u64,
+    mevshare: &EventClient,
+    cache: &EventCache,
+) -> Result<()> {
+    info!(
+        "refreshing cached events in blocks {}..={}",
+        block_start, block_end
+    );
+    let events = fetch_latest_events(
+        mevshare,
+        EventHistoryParams {
+            block_start: Some(block_start),
+            block_end: Some(block_end),
+            timestamp_start: None,
+            timestamp_end: None,
+            limit: None,
+            offset: None,
+        },
+    )
+    .await?;
+    let saved = cache.replace_range(block_start, block_end, &events)?;
+    info!(
+        "refreshed {} events, saved {} to {}",
+        events.len(),
+        saved,
+        cache.events_path().display()
+    );
+    Ok(())
+}
+
+/// Removes events landed before `block` from the cache.
+pub fn evict(before: u64, cache: &EventCache) -> Result<()> {
+    let removed = cache.evict_before(before)?;
+    info!("removed {} events landed before block {}", removed, before);
+    Ok(())
+}
+
+/// Removes corrupted lines (e.g. from an interrupted write) from the cache.
+pub fn repair(cache: &EventCache) -> Result<()> {
+    let removed = cache.drop_corrupted()?;
+    info!(
+        "removed {} corrupted lines from {}",
+        removed,
+        cache.events_path().display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::render;
+    use ethers::types::H256;
+
+    #[test]
+    fn it_reads_back_json_stats() -> Result<()> {
+        let summary = CacheStatsSummary {
+            events_file: "cache/events.ndjson".into(),
+            size: 1024,
+            events: 3,
+            corrupted: 1,
+            first_block: Some(17_000_000),
+            last_block: Some(17_000_002),
+            cursor: Some(EventCursor {
+                block: 17_000_002,
+                timestamp: 1_680_000_000,
+                hashes: vec![H256::from_low_u64_be(1)],
+            }),
+        };
+        let json = render(&summary, true)?;
+        assert_eq!(serde_json::from_str::<CacheStatsSummary>(&json)?, summary);
+        assert!(json.contains("\"firstBlock\": 17000000"), "{}", json);
+        Ok(())
+    }
+}
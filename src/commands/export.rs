@@ -1,12 +1,76 @@
-use crate::data::arbs::{ArbDatabase, ArbFilterParams, WriteEngine};
+use crate::data::arbs::{ArbDatabase, ArbFilterParams, ArbReader, WriteEngine};
+use crate::data::{db::Db, units::AmountUnits, CsvWriter, FileWriter};
 use crate::Result;
 
+/// Exports arbs matching `params` to `write_dest`. Arbs for events already saved in a file or
+/// DB destination are skipped or replaced, unless `allow_duplicates` is set.
+///
+/// File exports render amounts in `units`, or by default raw (JSON & CSV) or in ETH (Parquet).
+/// DBs always save them raw.
 pub async fn run(
     params: ArbFilterParams,
     read_db: &ArbDatabase,
     write_dest: WriteEngine,
+    allow_duplicates: bool,
+    units: Option<AmountUnits>,
 ) -> Result<()> {
     println!("exporting arbs... {:?}", params);
-    read_db.export_arbs(write_dest, &params).await?;
+    let (num_exported, location) = match write_dest {
+        WriteEngine::File(filename) => {
+            let writer = FileWriter::new(filename)
+                .allowing_duplicates(allow_duplicates)
+                .with_units(units.unwrap_or_default());
+            (read_db.export_arbs(&writer, &params).await?, writer.path())
+        }
+        WriteEngine::FileGz(filename) => {
+            let writer = FileWriter::gzipped(filename)
+                .allowing_duplicates(allow_duplicates)
+                .with_units(units.unwrap_or_default());
+            (read_db.export_arbs(&writer, &params).await?, writer.path())
+        }
+        WriteEngine::Csv(filename) => {
+            let writer = CsvWriter::new(filename, units.unwrap_or_default());
+            (read_db.export_arbs(&writer, &params).await?, writer.path())
+        }
+        WriteEngine::Parquet(filename) => {
+            let units = units.unwrap_or(AmountUnits::Eth);
+            export_parquet(filename, read_db, &params, units).await?
+        }
+        WriteEngine::Db(db_engine) => {
+            let write_db =
+                Db::new(db_engine.to_owned().allowing_duplicates(allow_duplicates)).await;
+            (
+                read_db.export_arbs(&write_db.connect, &params).await?,
+                db_engine.to_string(),
+            )
+        }
+    };
+    println!("exported {} arbs to {}", num_exported, location);
     Ok(())
 }
+
+/// Exports arbs to a parquet file. Returns the number of arbs exported and the file's path.
+#[cfg(feature = "parquet")]
+async fn export_parquet(
+    filename: Option<String>,
+    read_db: &ArbDatabase,
+    params: &ArbFilterParams,
+    units: AmountUnits,
+) -> Result<(usize, String)> {
+    let writer = crate::data::ParquetWriter::new(filename, units);
+    let num_exported = read_db.export_arbs(&writer, params).await?;
+    writer.close()?;
+    Ok((num_exported, writer.path()))
+}
+
+#[cfg(not(feature = "parquet"))]
+async fn export_parquet(
+    _filename: Option<String>,
+    _read_db: &ArbDatabase,
+    _params: &ArbFilterParams,
+    _units: AmountUnits,
+) -> Result<(usize, String)> {
+    Err(anyhow::format_err!(
+        "parquet exports aren't supported by this build; rebuild with `--features parquet`"
+    ))
+}
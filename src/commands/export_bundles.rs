@@ -0,0 +1,22 @@
+use crate::data::arbs::{ArbDatabase, ArbFilterParams, ArbReader};
+use crate::data::BundleWriter;
+use crate::util::RpcClient;
+use crate::Result;
+
+/// Exports a bundle for every signed backrun of the arbs in `read_db` matching `params`.
+pub async fn run(
+    client: &RpcClient,
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    filename: Option<String>,
+) -> Result<()> {
+    println!("exporting bundles... {:?}", params);
+    let writer = BundleWriter::new(client.clone(), filename)?;
+    let num_exported = read_db.export_arbs(&writer, &params).await?;
+    println!(
+        "exported bundles for {} arbs to {}",
+        num_exported,
+        writer.path()
+    );
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use crate::data::event_cache::EventCache;
+use crate::event_history::fetch_event_pages;
+use crate::info;
+use crate::Result;
+use mev_share_sse::{EventClient, EventHistoryParams};
+
+#[derive(Clone, Debug)]
+pub struct FetchEventsOptions {
+    pub block_start: Option<u32>,
+    pub block_end: Option<u32>,
+    pub timestamp_start: Option<u32>,
+    pub timestamp_end: Option<u32>,
+    /// Number of events to request per page. Defaults to the API's max.
+    pub page_size: Option<u64>,
+}
+
+/// Fetches MEV-Share events into the on-disk event cache, saving each page as it's fetched.
+///
+/// If neither `block_start` nor `timestamp_start` is set, fetching resumes from the cache's cursor,
+/// so only events newer than the last fetch are downloaded.
+pub async fn run(
+    params: FetchEventsOptions,
+    mevshare: &EventClient,
+    cache: &EventCache,
+) -> Result<()> {
+    let mut timestamp_start = params.timestamp_start.map(u64::from);
+    if params.block_start.is_none() && timestamp_start.is_none() {
+        if let Some(cursor) = cache.load_cursor()? {
+            info!(
+                "resuming from cursor: block={} timestamp={}",
+                cursor.block, cursor.timestamp
+            );
+            // the cursor's timestamp may have been partially fetched; `append` skips saved events
+            timestamp_start = Some(cursor.timestamp);
+        }
+    }
+    let event_params = EventHistoryParams {
+        block_start: params.block_start.map(u64::from),
+        block_end: params.block_end.map(u64::from),
+        timestamp_start,
+        timestamp_end: params.timestamp_end.map(u64::from),
+        limit: params.page_size,
+        offset: None,
+    };
+    info!(
+        "fetching events (block_start={:?} block_end={:?} timestamp_start={:?} timestamp_end={:?})",
+        event_params.block_start,
+        event_params.block_end,
+        event_params.timestamp_start,
+        event_params.timestamp_end
+    );
+    let mut saved = 0;
+    let fetched = fetch_event_pages(mevshare, event_params, |page| {
+        saved += cache.append(&page)?;
+        Ok(())
+    })
+    .await?;
+    info!(
+        "fetched {} events, saved {} new events to {}",
+        fetched,
+        saved,
+        cache.events_path().display()
+    );
+    Ok(())
+}
@@ -0,0 +1,388 @@
+use crate::{
+    commands::scan::uniswap_topics,
+    data::arbs::ArbWriter,
+    debug,
+    error::HindsightError,
+    info,
+    interfaces::SimArbResultBatch,
+    notify::Notifier,
+    sim::{
+        core::SimOptions,
+        processor::{simulate_backrun_arbs, H256Map},
+    },
+    util::{filter_events_by_topic, RpcClient},
+    warn, Error, Result,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Transaction, H256},
+};
+use futures::StreamExt;
+use mev_share_sse::{Event, EventClient, EventHistory, Hint};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{mpsc, Semaphore};
+
+/// Flashbots' MEV-Share SSE stream of live hints.
+pub const MEV_SHARE_STREAM_URL: &'static str = "https://mev-share.flashbots.net";
+/// Time to wait before the first reconnection attempt; doubles on every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How long to wait for a hinted tx to land before giving up on it (~5 blocks).
+const TX_LANDING_TIMEOUT: Duration = Duration::from_secs(60);
+const TX_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const LATENCY_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct ListenOptions {
+    /// Max number of txs to simulate concurrently.
+    pub batch_size: usize,
+    /// Max number of hints waiting to be simulated. New hints are dropped while it's full.
+    pub queue_size: usize,
+}
+
+/// A hint from the stream, with the time it was received.
+#[derive(Clone, Debug)]
+struct LiveEvent {
+    event: EventHistory,
+    received: Instant,
+}
+
+/// Converts a streamed hint into the `EventHistory` representation used by historical events,
+/// stamped with the head block & time at which it was received.
+pub fn event_to_history(event: Event, block: u64, timestamp: u64) -> EventHistory {
+    EventHistory {
+        block,
+        timestamp,
+        hint: Hint {
+            txs: event.transactions,
+            hash: event.hash,
+            logs: event.logs,
+            gas_used: None,
+            mev_gas_price: None,
+        },
+    }
+}
+
+/// Summary of the latencies recorded over one reporting interval.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub mean: Duration,
+    pub median: Duration,
+    pub max: Duration,
+}
+
+/// Latency from hint receipt to sim completion, reported (and reset) every minute.
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    samples: Mutex<Vec<Duration>>,
+    /// Hints dropped because the queue was full.
+    dropped: AtomicU64,
+}
+
+impl LatencyStats {
+    pub fn record(&self, latency: Duration) {
+        self.samples
+            .lock()
+            .expect("latency stats poisoned")
+            .push(latency);
+    }
+
+    pub fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Summarizes the latencies recorded since the last call and resets them.
+    /// Returns `None` if no sims completed in that time.
+    pub fn take_summary(&self) -> Option<LatencySummary> {
+        let mut samples =
+            std::mem::take(&mut *self.samples.lock().expect("latency stats poisoned"));
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        let total: Duration = samples.iter().sum();
+        Some(LatencySummary {
+            count: samples.len(),
+            mean: total / samples.len() as u32,
+            median: samples[samples.len() / 2],
+            max: samples[samples.len() - 1],
+        })
+    }
+
+    /// Returns the number of hints dropped since the last call and resets it.
+    pub fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+
+    fn report(&self) {
+        let dropped = self.take_dropped();
+        match self.take_summary() {
+            Some(summary) => info!(
+                "live sims in the last minute: {}\tlatency mean={:?} median={:?} max={:?}\tdropped hints: {}",
+                summary.count, summary.mean, summary.median, summary.max, dropped
+            ),
+            None => info!(
+                "live sims in the last minute: 0\tdropped hints: {}",
+                dropped
+            ),
+        }
+    }
+}
+
+/// Subscribes to the MEV-Share event stream and pushes relevant hints into `queue`,
+/// reconnecting with exponential backoff whenever the stream drops.
+async fn stream_events(
+    mevshare: EventClient,
+    client: RpcClient,
+    queue: mpsc::Sender<LiveEvent>,
+    stats: Arc<LatencyStats>,
+) {
+    let filter_topics = uniswap_topics();
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        match mevshare.events(MEV_SHARE_STREAM_URL).await {
+            Ok(mut stream) => {
+                info!("connected to mev-share event stream");
+                while let Some(event) = stream.next().await {
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(err) => {
+                            warn!("mev-share event stream error: {}", err);
+                            break;
+                        }
+                    };
+                    // only reset the backoff once the stream is actually delivering events
+                    backoff = INITIAL_BACKOFF;
+                    let received = Instant::now();
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|t| t.as_secs())
+                        .unwrap_or_default();
+                    let events = filter_events_by_topic(
+                        &vec![event_to_history(event, 0, timestamp)],
+                        &filter_topics,
+                    );
+                    if events.is_empty() {
+                        continue;
+                    }
+                    let head = match client.get_block_number().await {
+                        Ok(head) => head.as_u64(),
+                        Err(err) => {
+                            warn!("failed to get head block: {}", err);
+                            continue;
+                        }
+                    };
+                    for mut event in events {
+                        event.block = head;
+                        let hash = event.hint.hash;
+                        if let Err(mpsc::error::TrySendError::Full(_)) =
+                            queue.try_send(LiveEvent { event, received })
+                        {
+                            debug!("sim queue full, dropping hint {:?}", hash);
+                            stats.record_dropped();
+                        }
+                    }
+                }
+                warn!("mev-share event stream dropped");
+            }
+            Err(err) => warn!("failed to connect to mev-share event stream: {}", err),
+        }
+        if queue.is_closed() {
+            return;
+        }
+        info!("reconnecting to mev-share event stream in {:?}", backoff);
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Polls for a hinted tx until it lands onchain. Hints don't carry signed txs,
+/// so we can't simulate a backrun until the tx is public.
+async fn wait_for_tx(client: &RpcClient, tx_hash: H256) -> Result<Transaction> {
+    let deadline = Instant::now() + TX_LANDING_TIMEOUT;
+    loop {
+        if let Some(tx) = client.get_transaction(tx_hash).await? {
+            if tx.block_number.is_some() {
+                return Ok(tx);
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err::<_, Error>(HindsightError::TxNotLanded(tx_hash).into());
+        }
+        tokio::time::sleep(TX_POLL_INTERVAL).await;
+    }
+}
+
+/// Simulates the backrun of a live hint against the head block that its tx landed on top of.
+async fn simulate_live_event(
+    client: RpcClient,
+    event: EventHistory,
+    sim_options: SimOptions,
+    sims: Arc<Semaphore>,
+) -> Result<SimArbResultBatch> {
+    // don't wait for txs we'd skip anyway
+    if !sim_options.event_filter.allows_event(&client, &event).await {
+        return Err(HindsightError::EventFiltered(event.hint.hash).into());
+    }
+    let tx = wait_for_tx(&client, event.hint.hash).await?;
+    let _sim_permit = sims.acquire_owned().await?;
+    let event_map = H256Map::from([(tx.hash, event)]);
+    let mut batch = simulate_backrun_arbs(&client, tx, &event_map, sim_options).await?;
+    batch.live = true;
+    Ok(batch)
+}
+
+/// Simulates backrun-arbitrages for hints from the live MEV-Share event stream as they arrive,
+/// saving results to `write_db` and passing profitable ones to `notifier`. Runs until the process
+/// is stopped.
+pub async fn run(
+    params: ListenOptions,
+    rpc_client: &RpcClient,
+    mevshare: &EventClient,
+    sim_options: SimOptions,
+    write_db: &dyn ArbWriter,
+    notifier: Option<&Notifier>,
+) -> Result<()> {
+    info!(
+        "listening for live events (sim concurrency: {}, queue size: {})",
+        params.batch_size, params.queue_size
+    );
+    let stats = Arc::new(LatencyStats::default());
+    let (queue_tx, mut queue_rx) = mpsc::channel(params.queue_size.max(1));
+    tokio::task::spawn(stream_events(
+        mevshare.to_owned(),
+        rpc_client.clone(),
+        queue_tx,
+        stats.clone(),
+    ));
+
+    // hints taken off the queue are held here until their sims finish, so the total number of
+    // hints in memory is bounded by twice the queue size
+    let in_flight = Arc::new(Semaphore::new(params.queue_size.max(1)));
+    let sims = Arc::new(Semaphore::new(params.batch_size.max(1)));
+    let (results_tx, mut results_rx) = mpsc::unbounded_channel();
+    let client = rpc_client.clone();
+    tokio::task::spawn(async move {
+        while let Ok(permit) = in_flight.clone().acquire_owned().await {
+            let live_event: LiveEvent = match queue_rx.recv().await {
+                Some(live_event) => live_event,
+                None => return,
+            };
+            let client = client.clone();
+            let sim_options = sim_options.clone();
+            let sims = sims.clone();
+            let results_tx = results_tx.clone();
+            tokio::task::spawn(async move {
+                let _permit = permit;
+                let tx_hash = live_event.event.hint.hash;
+                let res = simulate_live_event(client, live_event.event, sim_options, sims).await;
+                let _ = results_tx.send((tx_hash, live_event.received.elapsed(), res));
+            });
+        }
+    });
+
+    let mut report_interval = tokio::time::interval(LATENCY_REPORT_INTERVAL);
+    // the first tick completes immediately
+    report_interval.tick().await;
+    let mut arbs_found = 0;
+    loop {
+        tokio::select! {
+            res = results_rx.recv() => {
+                let (tx_hash, latency, res) = match res {
+                    Some(res) => res,
+                    None => return Err(anyhow::format_err!("live sim dispatcher stopped")),
+                };
+                let batch = match res {
+                    Ok(batch) => batch,
+                    Err(err) => {
+                        debug!("live sim failed for tx {:?}: {}", tx_hash, err);
+                        continue;
+                    }
+                };
+                stats.record(latency);
+                if batch.max_profit > 0.into() {
+                    arbs_found += 1;
+                    info!(
+                        "live arb found for tx {:?}: profit={:?} (profitable arbs found: {})",
+                        tx_hash, batch.max_profit, arbs_found
+                    );
+                }
+                let batch = vec![batch];
+                write_db.write_arbs(&batch).await?;
+                if let Some(notifier) = notifier {
+                    notifier.notify(&batch[0]);
+                }
+            }
+            _ = report_interval.tick() => {
+                stats.report();
+                (**rpc_client).as_ref().inner().log_health();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_streamed_hints() -> Result<()> {
+        let event: Event = serde_json::from_value(serde_json::json!({
+            "hash": "0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a",
+            "logs": [
+                {
+                    "address": "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640",
+                    "topics": [
+                        "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
+                        "0x0000000000000000000000000000000000000000000000000000000000000000",
+                        "0x0000000000000000000000000000000000000000000000000000000000000000"
+                    ],
+                    "data": "0x"
+                }
+            ],
+            "txs": null
+        }))?;
+        let hash = event.hash;
+        let history = event_to_history(event, 17637018, 1688673408);
+        assert_eq!(history.block, 17637018);
+        assert_eq!(history.timestamp, 1688673408);
+        assert_eq!(history.hint.hash, hash);
+        assert_eq!(history.hint.logs.len(), 1);
+        // swap hints are kept by the same filter `scan` uses
+        assert_eq!(
+            filter_events_by_topic(&vec![history], &uniswap_topics()).len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_summarizes_latency() {
+        let stats = LatencyStats::default();
+        assert_eq!(stats.take_summary(), None);
+        for ms in [300, 100, 200] {
+            stats.record(Duration::from_millis(ms));
+        }
+        stats.record_dropped();
+        assert_eq!(
+            stats.take_summary(),
+            Some(LatencySummary {
+                count: 3,
+                mean: Duration::from_millis(200),
+                median: Duration::from_millis(200),
+                max: Duration::from_millis(300),
+            })
+        );
+        assert_eq!(stats.take_dropped(), 1);
+        // both reset after being taken
+        assert_eq!(stats.take_summary(), None);
+        assert_eq!(stats.take_dropped(), 0);
+    }
+}
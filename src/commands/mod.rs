@@ -1,2 +1,34 @@
+pub mod analyze;
+pub mod audit;
+pub mod cache;
 pub mod export;
+pub mod export_bundles;
+pub mod fetch_events;
+pub mod listen;
+pub mod replay;
+pub mod retry_failed;
 pub mod scan;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod simulate_tx;
+pub mod top_arbs;
+
+use crate::Result;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Renders a command's `result` for stdout: as a single JSON document if `json` (for scripts),
+/// or else as text.
+pub fn render<T: Serialize + Display>(result: &T, json: bool) -> Result<String> {
+    Ok(if json {
+        serde_json::to_string_pretty(result)?
+    } else {
+        result.to_string()
+    })
+}
+
+/// Prints a command's `result` to stdout; see `render`.
+pub fn print_result<T: Serialize + Display>(result: &T, json: bool) -> Result<()> {
+    println!("{}", render(result, json)?);
+    Ok(())
+}
@@ -0,0 +1,424 @@
+use super::top_arbs::align_columns;
+use crate::chain::ChainConfig;
+use crate::data::arbs::{ArbDatabase, ArbFilterParams, ArbReader};
+use crate::error::HindsightError;
+use crate::interfaces::{SimArbResult, SimArbResultBatch};
+use crate::sim::core::{resim_backrun, Braindance, SimForks};
+use crate::sim::snapshot::{self, EventSnapshot};
+use crate::util::{get_block_info, wei_to_eth, RpcClient};
+use crate::{Error, Result};
+use ethers::providers::Middleware;
+use ethers::types::{Address, Transaction, H256, I256, U256};
+use futures::{future, StreamExt};
+use rusty_sando::types::BlockInfo;
+use serde::Serialize;
+
+const NUM_COLUMNS: usize = 10;
+/// Columns from this one on are amounts.
+const FIRST_AMOUNT_COLUMN: usize = 5;
+
+/// Where a replay got the state it re-simulated a backrun on.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReplaySource {
+    /// A fresh fork from the node, at the arb's placement.
+    Node,
+    /// The state saved by `scan --record-state`.
+    Snapshot,
+}
+
+/// A saved backrun re-simulated at its `amount_in`, as printed by `replay`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayCheck {
+    pub event_hash: H256,
+    pub block: u64,
+    pub start_pool: Address,
+    pub end_pool: Address,
+    pub amount_in: U256,
+    /// WETH (wei) the backrun ended with when it was saved.
+    pub stored_balance_end: U256,
+    /// WETH (wei) the backrun ends with now; None if the replay failed.
+    pub replayed_balance_end: Option<U256>,
+    /// Why the replay failed.
+    pub error: Option<String>,
+    pub source: ReplaySource,
+    /// Whether the replayed balance is within the tolerance of the stored one.
+    pub passed: bool,
+}
+
+impl ReplayCheck {
+    /// Checks the `replayed` balance of `result`, one of `arb`'s backruns, against the one it saved.
+    /// Passes if they're within `tolerance_bps` basis points of the saved balance.
+    pub fn new(
+        arb: &SimArbResultBatch,
+        result: &SimArbResult,
+        source: ReplaySource,
+        replayed: Result<U256>,
+        tolerance_bps: u64,
+    ) -> Self {
+        let backrun = &result.backrun_trade;
+        let (replayed_balance_end, error) = match replayed {
+            Ok(balance) => (Some(balance), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        let passed = replayed_balance_end.map_or(false, |replayed| {
+            abs_diff(replayed, backrun.balance_end) * 10_000 <= backrun.balance_end * tolerance_bps
+        });
+        Self {
+            event_hash: arb.event.hint.hash,
+            block: arb.event.block,
+            start_pool: backrun.start_pool,
+            end_pool: backrun.end_pool,
+            amount_in: backrun.amount_in,
+            stored_balance_end: backrun.balance_end,
+            replayed_balance_end,
+            error,
+            source,
+            passed,
+        }
+    }
+
+    /// Replayed minus stored balance (wei), if the replay succeeded.
+    pub fn drift(&self) -> Option<I256> {
+        self.replayed_balance_end.map(|replayed| {
+            I256::from_raw(replayed).saturating_sub(I256::from_raw(self.stored_balance_end))
+        })
+    }
+
+    /// Absolute drift, in basis points of the stored balance.
+    pub fn drift_bps(&self) -> Option<f64> {
+        let replayed = self.replayed_balance_end?;
+        let diff = abs_diff(replayed, self.stored_balance_end);
+        Some(if diff.is_zero() {
+            0.0
+        } else {
+            wei_to_eth(diff) / wei_to_eth(self.stored_balance_end) * 10_000.0
+        })
+    }
+
+    fn status(&self) -> &'static str {
+        match (self.passed, &self.error) {
+            (true, _) => "PASS",
+            (false, None) => "FAIL",
+            (false, Some(_)) => "ERROR",
+        }
+    }
+
+    /// Cells of the check's row in the table. Hashes & addresses are shortened (`0x1234…abcd`).
+    fn row(&self) -> [String; NUM_COLUMNS] {
+        let eth = |wei: U256| format!("{:.9}", wei_to_eth(wei));
+        [
+            self.status().to_owned(),
+            self.block.to_string(),
+            self.event_hash.to_string(),
+            self.start_pool.to_string(),
+            self.end_pool.to_string(),
+            eth(self.amount_in),
+            eth(self.stored_balance_end),
+            self.replayed_balance_end.map_or("-".to_owned(), eth),
+            self.drift()
+                .map_or("-".to_owned(), |drift| drift.to_string()),
+            self.drift_bps()
+                .map_or("-".to_owned(), |bps| format!("{:.2}", bps)),
+        ]
+    }
+}
+
+fn abs_diff(a: U256, b: U256) -> U256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
+/// Totals of a replay, and how far the replayed balances drifted from the stored ones.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaySummary {
+    pub replayed: usize,
+    /// Number of backruns replayed on state snapshots, rather than the node.
+    pub from_snapshots: usize,
+    pub passed: usize,
+    pub failed: usize,
+    /// Number of backruns whose replay failed.
+    pub errored: usize,
+    /// Mean absolute drift of the replays that ran, in basis points.
+    pub mean_drift_bps: f64,
+    pub max_drift_bps: f64,
+    /// Event & block of the backrun that drifted the most.
+    pub max_drift_at: Option<(H256, u64)>,
+}
+
+impl ReplaySummary {
+    pub fn new(checks: &[ReplayCheck]) -> Self {
+        let mut summary = Self {
+            replayed: checks.len(),
+            ..Default::default()
+        };
+        let mut drifts = 0;
+        for check in checks {
+            if check.source == ReplaySource::Snapshot {
+                summary.from_snapshots += 1;
+            }
+            match check.status() {
+                "PASS" => summary.passed += 1,
+                "FAIL" => summary.failed += 1,
+                _ => summary.errored += 1,
+            }
+            if let Some(bps) = check.drift_bps() {
+                drifts += 1;
+                summary.mean_drift_bps += bps;
+                if summary.max_drift_at.is_none() || bps > summary.max_drift_bps {
+                    summary.max_drift_bps = bps;
+                    summary.max_drift_at = Some((check.event_hash, check.block));
+                }
+            }
+        }
+        if drifts > 0 {
+            summary.mean_drift_bps /= drifts as f64;
+        }
+        summary
+    }
+}
+
+impl std::fmt::Display for ReplaySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "replayed {} backruns ({} on state snapshots): {} passed, {} failed, {} errored",
+            self.replayed, self.from_snapshots, self.passed, self.failed, self.errored
+        )?;
+        match self.max_drift_at {
+            Some((event_hash, block)) => write!(
+                f,
+                "drift: mean {:.2} bps, max {:.2} bps (tx {:?} in block {})",
+                self.mean_drift_bps, self.max_drift_bps, event_hash, block
+            ),
+            None => write!(f, "drift: none replayed"),
+        }
+    }
+}
+
+/// Formats checks as a table, with columns padded to line up. Amounts are right-aligned.
+fn table(checks: &[ReplayCheck]) -> String {
+    let header = [
+        "status",
+        "block",
+        "tx",
+        "start pool",
+        "end pool",
+        "amount in (Ξ)",
+        "stored end (Ξ)",
+        "replayed end (Ξ)",
+        "drift (wei)",
+        "drift (bps)",
+    ]
+    .map(|cell| cell.to_owned());
+    let rows = std::iter::once(header)
+        .chain(checks.iter().map(ReplayCheck::row))
+        .collect::<Vec<_>>();
+    align_columns(&rows, FIRST_AMOUNT_COLUMN)
+}
+
+/// Forks the state `arb` was simulated on: its state snapshot if `scan --record-state` saved one,
+/// otherwise a fresh fork from the node at the arb's placement. Its braindance contract is set up
+/// as `braindance` configures, which should match how the arb was simulated.
+/// Returns the forks, the user's tx, and the block they fork.
+async fn fork_arb(
+    client: &RpcClient,
+    chain: &ChainConfig,
+    braindance: Braindance,
+    arb: &SimArbResultBatch,
+) -> Result<(SimForks, Transaction, BlockInfo, ReplaySource)> {
+    let tx_hash = arb.event.hint.hash;
+    if EventSnapshot::path(snapshot::default_dir(), tx_hash).exists() {
+        let snapshot = EventSnapshot::load(snapshot::default_dir(), tx_hash)?;
+        let forks = SimForks::from_snapshot(&snapshot.state, None, snapshot.following_txs)
+            .on_chain(chain)
+            .with_braindance(braindance, chain.weth)?;
+        return Ok((
+            forks,
+            snapshot.tx,
+            snapshot.state.block_info(),
+            ReplaySource::Snapshot,
+        ));
+    }
+    let tx = client
+        .get_transaction(tx_hash)
+        .await?
+        .ok_or::<Error>(HindsightError::TxNotLanded(tx_hash).into())?;
+    let block_info = get_block_info(client, arb.event.block - 1).await?;
+    let forks = SimForks::for_placement(client, &tx, &block_info, None, arb.placement)
+        .await?
+        .on_chain(chain)
+        .with_braindance(braindance, chain.weth)?;
+    Ok((forks, tx, block_info, ReplaySource::Node))
+}
+
+/// Re-simulates each of `arb`'s backruns at its saved `amount_in`, and checks the balances they
+/// end with against the saved ones.
+async fn replay_arb(
+    client: &RpcClient,
+    chain: &ChainConfig,
+    braindance: Braindance,
+    arb: &SimArbResultBatch,
+    tolerance_bps: u64,
+) -> Vec<ReplayCheck> {
+    let (forks, tx, block_info, source) = match fork_arb(client, chain, braindance, arb).await {
+        Ok(forked) => forked,
+        Err(err) => {
+            return arb
+                .results
+                .iter()
+                .map(|result| {
+                    let err = anyhow::anyhow!("failed to fork state: {}", err);
+                    ReplayCheck::new(arb, result, ReplaySource::Node, Err(err), tolerance_bps)
+                })
+                .collect()
+        }
+    };
+    let (forks, tx, block_info) = (&forks, &tx, &block_info);
+    future::join_all(arb.results.iter().map(|result| async move {
+        let replayed = resim_backrun(forks, tx.to_owned(), block_info, result).await;
+        ReplayCheck::new(arb, result, source, replayed, tolerance_bps)
+    }))
+    .await
+}
+
+/// Re-simulates the backruns of the arbs in `read_db` matching `params` at their saved amounts,
+/// `concurrency` events at a time on `chain` (w/ `braindance`), and prints whether each ends with
+/// the balance it saved (within `tolerance_bps` basis points), as a table or (if `json`) JSON.
+///
+/// Fails if any backrun didn't reproduce, after printing them all.
+pub async fn run(
+    client: &RpcClient,
+    chain: &ChainConfig,
+    braindance: Braindance,
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    limit: Option<i64>,
+    tolerance_bps: u64,
+    concurrency: usize,
+    json: bool,
+) -> Result<()> {
+    let arbs = read_db.read_arbs(&params, None, limit, None).await?;
+    let checks = futures::stream::iter(
+        arbs.iter()
+            .map(|arb| replay_arb(client, chain, braindance, arb, tolerance_bps)),
+    )
+    .buffered(concurrency)
+    .collect::<Vec<_>>()
+    .await
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    let summary = ReplaySummary::new(&checks);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "checks": checks,
+                "summary": summary,
+            }))?
+        );
+    } else if checks.is_empty() {
+        println!("no arbs found.");
+    } else {
+        println!("{}\n", table(&checks));
+        for check in &checks {
+            if let Some(err) = &check.error {
+                println!(
+                    "failed to replay tx {:?} in block {}: {}",
+                    check.event_hash, check.block, err
+                );
+            }
+        }
+        println!("{}", summary);
+    }
+
+    let mismatches = summary.failed + summary.errored;
+    if mismatches > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} replayed backruns didn't reproduce within {} bps",
+            mismatches,
+            summary.replayed,
+            tolerance_bps
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(stored: u64, replayed: Result<u64>, tolerance_bps: u64) -> ReplayCheck {
+        let arb = SimArbResultBatch::test_example();
+        let mut result = SimArbResult::test_example();
+        result.backrun_trade.balance_end = stored.into();
+        ReplayCheck::new(
+            &arb,
+            &result,
+            ReplaySource::Node,
+            replayed.map(U256::from),
+            tolerance_bps,
+        )
+    }
+
+    #[test]
+    fn it_checks_drift_against_tolerance() {
+        let exact = check(1_000_000, Ok(1_000_000), 0);
+        assert!(exact.passed);
+        assert_eq!(exact.drift(), Some(I256::zero()));
+        assert_eq!(exact.drift_bps(), Some(0.0));
+
+        // 1 bps under the stored balance
+        let drifted = check(1_000_000, Ok(999_900), 1);
+        assert!(drifted.passed);
+        assert_eq!(drifted.drift(), Some(I256::from(-100)));
+        assert!((drifted.drift_bps().unwrap() - 1.0).abs() < 1e-9);
+        assert!(!check(1_000_000, Ok(999_900), 0).passed);
+        assert!(!check(1_000_000, Ok(1_000_101), 1).passed);
+
+        let errored = check(1_000_000, Err(anyhow::anyhow!("swap reverted")), 1);
+        assert!(!errored.passed);
+        assert_eq!(errored.status(), "ERROR");
+        assert_eq!(errored.drift(), None);
+    }
+
+    #[test]
+    fn it_summarizes_replays() {
+        let checks = vec![
+            check(1_000_000, Ok(1_000_000), 1),
+            check(1_000_000, Ok(1_000_500), 1),
+            check(1_000_000, Err(anyhow::anyhow!("swap reverted")), 1),
+        ];
+        let summary = ReplaySummary::new(&checks);
+        assert_eq!(
+            (
+                summary.replayed,
+                summary.passed,
+                summary.failed,
+                summary.errored
+            ),
+            (3, 1, 1, 1)
+        );
+        assert!((summary.max_drift_bps - 5.0).abs() < 1e-9);
+        assert!((summary.mean_drift_bps - 2.5).abs() < 1e-9);
+        assert_eq!(
+            summary.max_drift_at,
+            Some((checks[1].event_hash, checks[1].block))
+        );
+
+        let table = table(&checks);
+        assert_eq!(table.lines().count(), 4);
+        assert!(
+            table.lines().nth(2).unwrap().starts_with("FAIL"),
+            "{}",
+            table
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::data::failed_events::FailedEvents;
+use crate::event_history::fetch_latest_events;
+use crate::hindsight::{Hindsight, OrderflowProgress};
+use crate::sim::processor::H256Map;
+use crate::util::{fetch_txs, RpcClient};
+use crate::{info, warn, Result};
+use ethers::types::{H256, U256};
+use ethers::utils::format_ether;
+use mev_share_sse::{EventClient, EventHistory, EventHistoryParams};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+
+/// What `retry-failed` did, as printed.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryFailedSummary {
+    /// Saved events whose errors may have passed, which were retried.
+    pub retryable: usize,
+    /// Saved events that weren't retried, since they'd fail the same way again.
+    pub not_retryable: usize,
+    /// Retryable events whose event or tx couldn't be found; they stay saved.
+    pub not_found: usize,
+    /// Retried events that were simulated, and removed from the failed events.
+    pub simulated: usize,
+    /// Retried events that failed again.
+    pub still_failing: usize,
+    /// Simulated events with a profitable arb.
+    pub arbs_found: usize,
+    /// Sum of the most profitable arb (wei) of each simulated event.
+    pub total_profit: U256,
+}
+
+impl std::fmt::Display for RetryFailedSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "retried: {}\tsimulated: {}\tstill failing: {}\tnot found: {}\tnot retryable: {}\tprofitable arbs found: {}\ttotal profit: {} ETH",
+            self.retryable,
+            self.simulated,
+            self.still_failing,
+            self.not_found,
+            self.not_retryable,
+            self.arbs_found,
+            format_ether(self.total_profit)
+        )
+    }
+}
+
+/// Finds the events of `hashes`, landed in `blocks`: from the event cache if they're there,
+/// or else from the Event History API, a block at a time.
+async fn find_events(
+    mevshare: &EventClient,
+    hindsight: &Hindsight,
+    hashes: &HashSet<H256>,
+    blocks: &BTreeSet<u64>,
+) -> Result<Vec<EventHistory>> {
+    let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+        return Ok(vec![]);
+    };
+    let mut events = hindsight
+        .event_cache
+        .load_events(Some(*first), Some(*last))
+        .unwrap_or_else(|err| {
+            warn!("failed to read event cache: {}", err);
+            vec![]
+        })
+        .into_iter()
+        .filter(|event| hashes.contains(&event.hint.hash))
+        .collect::<Vec<_>>();
+    let cached = events
+        .iter()
+        .map(|event| event.block)
+        .collect::<HashSet<_>>();
+    for block in blocks.iter().filter(|block| !cached.contains(block)) {
+        let fetched = fetch_latest_events(
+            mevshare,
+            EventHistoryParams {
+                block_start: Some(*block),
+                block_end: Some(*block),
+                timestamp_start: None,
+                timestamp_end: None,
+                limit: None,
+                offset: None,
+            },
+        )
+        .await?;
+        events.extend(
+            fetched
+                .into_iter()
+                .filter(|event| hashes.contains(&event.hint.hash)),
+        );
+    }
+    Ok(events)
+}
+
+/// Re-simulates the events saved in `failed_events` whose errors may have passed (transport errors
+/// & timeouts) w/ `hindsight`, which should save its failures to `failed_events` too, so that the
+/// events that succeed are removed and those that fail again count another attempt.
+/// Prints what happened, as text or (if `json`) JSON.
+pub async fn run(
+    rpc_client: &RpcClient,
+    mevshare: &EventClient,
+    hindsight: Hindsight,
+    failed_events: &FailedEvents,
+    batch_size: usize,
+    json: bool,
+) -> Result<()> {
+    let (retryable, not_retryable): (Vec<_>, Vec<_>) = failed_events
+        .list()
+        .into_iter()
+        .partition(|event| event.retryable());
+    let mut summary = RetryFailedSummary {
+        retryable: retryable.len(),
+        not_retryable: not_retryable.len(),
+        ..Default::default()
+    };
+    info!(
+        "retrying {} failed events ({} aren't retryable)",
+        retryable.len(),
+        not_retryable.len()
+    );
+    let hashes = retryable
+        .iter()
+        .map(|event| event.event_hash)
+        .collect::<HashSet<_>>();
+    let blocks = retryable
+        .iter()
+        .filter_map(|event| event.block)
+        .collect::<BTreeSet<_>>();
+    let events = find_events(mevshare, &hindsight, &hashes, &blocks).await?;
+    let txs = fetch_txs(rpc_client, &events).await?;
+    summary.not_found = retryable.len() - txs.len();
+    if summary.not_found > 0 {
+        warn!(
+            "couldn't find the events or txs of {} failed events",
+            summary.not_found
+        );
+    }
+    hindsight.report(OrderflowProgress::Queued(txs.len()));
+    let event_map = events
+        .into_iter()
+        .map(|event| (event.hint.hash, event))
+        .collect::<H256Map<EventHistory>>();
+    // forced, since failed events have no results saved (unless they've been simulated since)
+    let results = hindsight
+        .process_orderflow(&txs, batch_size, event_map, true)
+        .await?;
+    summary.simulated = results.len();
+    summary.still_failing = txs.len() - results.len();
+    for res in results.iter().filter(|res| res.max_profit > 0.into()) {
+        summary.arbs_found += 1;
+        summary.total_profit += res.max_profit;
+    }
+    super::print_result(&summary, json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_back_json_summaries() {
+        let summary = RetryFailedSummary {
+            retryable: 3,
+            not_retryable: 1,
+            not_found: 1,
+            simulated: 1,
+            still_failing: 1,
+            arbs_found: 1,
+            total_profit: U256::exp10(16),
+        };
+        let json = crate::commands::render(&summary, true).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["stillFailing"], 1);
+        assert_eq!(
+            serde_json::from_str::<RetryFailedSummary>(&json).unwrap(),
+            summary
+        );
+    }
+}
@@ -1,14 +1,23 @@
-use crate::data::arbs::ArbDatabase;
+use crate::data::checkpoint::Checkpoint;
 use crate::data::db::DbEngine;
 use crate::event_history::event_history_url;
-use crate::hindsight::Hindsight;
+use crate::hindsight::{Hindsight, OrderflowProgress};
 use crate::info;
+use crate::interfaces::BlockRange;
 use crate::sim::processor::H256Map;
-use crate::util::{fetch_txs, filter_events_by_topic, WsClient};
+use crate::util::{fetch_txs, filter_events_by_topic, RpcClient};
 use crate::Result;
-use ethers::types::H256;
+use ethers::types::{Transaction, H256, U256};
+use ethers::utils::format_ether;
+use futures::{channel::mpsc, SinkExt};
 use mev_share_sse::{EventClient, EventHistory, EventHistoryParams};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 
 #[derive(Clone, Debug)]
 pub struct ScanOptions {
@@ -18,6 +27,43 @@ pub struct ScanOptions {
     pub timestamp_start: u32,
     pub timestamp_end: Option<u32>,
     pub db_engine: DbEngine,
+    /// Re-simulate events that already have results in the DB.
+    pub force: bool,
+}
+
+/// Totals of a scan, as printed by `scan --output json`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanSummary {
+    /// Events fetched (& filtered for swaps) by the scan, whether they were simulated or not.
+    pub events_processed: usize,
+    /// Simulated events with a profitable arb.
+    pub arbs_found: usize,
+    /// Sum of the most profitable arb (wei) of each event.
+    pub total_profit: U256,
+    /// Whether the scan stopped early, because it was asked to shut down.
+    pub interrupted: bool,
+}
+
+impl std::ops::AddAssign for ScanSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.events_processed += other.events_processed;
+        self.arbs_found += other.arbs_found;
+        self.total_profit += other.total_profit;
+        self.interrupted |= other.interrupted;
+    }
+}
+
+impl std::fmt::Display for ScanSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "events processed: {}\tprofitable arbs found: {}\ttotal profit: {} ETH",
+            self.events_processed,
+            self.arbs_found,
+            format_ether(self.total_profit)
+        )
+    }
 }
 
 impl Into<EventHistoryParams> for ScanOptions {
@@ -33,7 +79,8 @@ impl Into<EventHistoryParams> for ScanOptions {
     }
 }
 
-fn uniswap_topics() -> Vec<H256> {
+/// Topics of the swap events that hindsight can simulate arbs for.
+pub fn uniswap_topics() -> Vec<H256> {
     vec![
         // univ3
         // Swap(address,address,int256,int256,uint160,uint128,int24)
@@ -46,23 +93,107 @@ fn uniswap_topics() -> Vec<H256> {
     ]
 }
 
+/// Scans MEV-Share events and simulates backrun-arbitrages for them, saving results to `hindsight.db`.
+/// Events that already have results in the DB are skipped unless `params.force` is set.
+///
+/// Events are fetched page by page while the txs already fetched are simulated, all in one
+/// pipeline. A checkpoint is saved as each batch of results is written, up to the latest
+/// timestamp whose events are all done.
+///
+/// If `shutdown` is set, the scan stops fetching events, and stops once the events already
+/// queued are simulated & saved. Returns the scan's totals.
 pub async fn run(
     params: ScanOptions,
-    ws_client: &WsClient,
+    rpc_client: &RpcClient,
     mevshare: &EventClient,
     hindsight: &Hindsight,
-    write_db: &ArbDatabase,
-) -> Result<()> {
+    shutdown: Arc<AtomicBool>,
+) -> Result<ScanSummary> {
     info!(
         "scanning events starting at block={:?} timestamp={:?}",
         params.block_start, params.timestamp_start
     );
+    let scan = ScanState::default();
+    let (queue, txs) = mpsc::channel(params.batch_size.max(1));
+    // running totals of the arbs found
+    let mut summary = ScanSummary::default();
 
-    let mut event_params: EventHistoryParams = params.clone().into();
+    let events = queue_events(
+        &params, rpc_client, mevshare, hindsight, &shutdown, &scan, queue,
+    );
+    let sims = hindsight.to_owned().process_orderflow_stream(
+        txs,
+        scan.queued.clone(),
+        params.batch_size,
+        |batch| {
+            let arbs = batch
+                .iter()
+                .filter_map(|processed| processed.result.as_ref())
+                .filter(|res| res.max_profit > 0.into());
+            for res in arbs {
+                summary.arbs_found += 1;
+                summary.total_profit += res.max_profit;
+            }
+            let checkpoint = scan
+                .checkpoints
+                .lock()
+                .expect("checkpoints poisoned")
+                .done(batch.iter().map(|processed| processed.tx_hash));
+            async move {
+                match checkpoint {
+                    Some(checkpoint) => checkpoint.save().await,
+                    None => Ok(()),
+                }
+            }
+        },
+    );
+    let (fetched, simulated) = tokio::join!(events, sims);
+    simulated?;
+    let fetched = fetched?;
+    if !fetched.interrupted {
+        // the whole range is done, including its last timestamp
+        let checkpoint = scan
+            .checkpoints
+            .lock()
+            .expect("checkpoints poisoned")
+            .finish();
+        if let Some(checkpoint) = checkpoint {
+            checkpoint.save().await?;
+        }
+    }
+    summary += fetched;
+    if summary.interrupted {
+        info!("shutting down. {}", summary);
+    } else {
+        info!("scan complete. {}", summary);
+    }
+    Ok(summary)
+}
 
+/// Fetches the events of the scan `params` page by page, and queues the txs of the swaps that
+/// need simulating on `queue`, in order, counting & registering them in `scan`. Events that
+/// already have results in the DB are skipped unless `params.force` is set.
+///
+/// Runs until the scan's range is done (or forever, if it has no end), or `shutdown` is set.
+/// Returns the number of events fetched, and whether the scan was interrupted.
+async fn queue_events(
+    params: &ScanOptions,
+    rpc_client: &RpcClient,
+    mevshare: &EventClient,
+    hindsight: &Hindsight,
+    shutdown: &AtomicBool,
+    scan: &ScanState,
+    mut queue: mpsc::Sender<(Transaction, Option<EventHistory>)>,
+) -> Result<ScanSummary> {
+    let mut event_params: EventHistoryParams = params.clone().into();
     let filter_topics = uniswap_topics();
+    let mut summary = ScanSummary::default();
     /* ========================== event processing ====================================== */
     loop {
+        if shutdown.load(Ordering::SeqCst) {
+            summary.interrupted = true;
+            break;
+        }
         // fetch events
         let events = mevshare
             .event_history(&event_history_url(), event_params.to_owned())
@@ -70,13 +201,18 @@ pub async fn run(
         // if the api returns 0 results, we've completely run out of events to process
         // so wait, then restart loop
         if events.len() == 0 {
+            if params.block_end.is_some() || params.timestamp_end.is_some() {
+                // if we're processing a specific block range, we're done
+                break;
+            }
             // sleep 12s to allow for new events to be indexed
-            std::thread::sleep(std::time::Duration::from_secs(12));
+            tokio::time::sleep(std::time::Duration::from_secs(12)).await;
             continue;
         }
 
         // update params for next batch of events
-        event_params.offset = Some(event_params.offset.unwrap() + events.len() as u64);
+        let num_fetched = events.len();
+        event_params.offset = Some(event_params.offset.unwrap() + num_fetched as u64);
 
         info!(
             "fetched {} events. first event timestamp={}",
@@ -84,57 +220,286 @@ pub async fn run(
             events[0].timestamp
         );
         // filter out irrelevant events
-        let events = filter_events_by_topic(&events, &filter_topics);
+        let swaps = filter_events_by_topic(&events, &filter_topics);
         info!(
             "filtered for uniswap events. {} events ready to process.",
-            events.len()
+            swaps.len()
         );
-        // map events by hash for fast lookups
-        let event_map = events
-            .iter()
-            .map(|event| (event.hint.hash, event.to_owned()))
-            .collect::<H256Map<EventHistory>>();
-
-        let mut events_offset = 0;
-        let mut txs = vec![];
 
         // Concurrently fetch all landed txs for each event.
         // Only request `batch_size` at a time to avoid overloading the RPC endpoint.
-        while events_offset < events.len() {
-            let this_batch = events
-                .iter()
-                .skip(events_offset)
-                .take(params.batch_size)
-                .map(|event| event.to_owned())
-                .collect::<Vec<EventHistory>>();
-            events_offset += this_batch.len();
-            // get txs for relevant events
-            txs.append(&mut fetch_txs(&ws_client, &this_batch).await?);
+        let mut txs = vec![];
+        for batch in swaps.chunks(params.batch_size.max(1)) {
+            txs.append(&mut fetch_txs(&rpc_client, &batch.to_vec()).await?);
+        }
+        if !params.force {
+            let unsimulated = hindsight.unsimulated(&txs).await?;
+            if unsimulated.len() < txs.len() {
+                info!(
+                    "skipping {} events already simulated",
+                    txs.len() - unsimulated.len()
+                );
+                hindsight.report(OrderflowProgress::Skipped(txs.len() - unsimulated.len()));
+            }
+            txs = unsimulated;
+        }
+        let mut txs = txs
+            .into_iter()
+            .map(|tx| (tx.hash, tx))
+            .collect::<H256Map<Transaction>>();
+        // queued in the order of their events, which are registered before any are simulated
+        let page = swaps
+            .iter()
+            .filter_map(|event| Some((txs.remove(&event.hint.hash)?, event.to_owned())))
+            .collect::<Vec<_>>();
+        {
+            let mut checkpoints = scan.checkpoints.lock().expect("checkpoints poisoned");
+            let mut page = page.iter().peekable();
+            for event in &events {
+                checkpoints.fetched(event);
+                if let Some((tx, _)) = page.next_if(|(tx, _)| tx.hash == event.hint.hash) {
+                    checkpoints.queue(tx.hash, event);
+                }
+            }
+        }
+        scan.queued.fetch_add(page.len(), Ordering::SeqCst);
+        hindsight.report(OrderflowProgress::Queued(page.len()));
+        for (tx, event) in page {
+            if shutdown.load(Ordering::SeqCst) {
+                summary.interrupted = true;
+                return Ok(summary);
+            }
+            // the sims only hang up if writing their results failed, which `run` returns
+            if queue.send((tx, Some(event))).await.is_err() {
+                return Ok(summary);
+            }
         }
-
-        /* ========================== batch-sized arb processing ========================
-           Here, *at least* `batch_size` txs should be passed to `process_orderflow`.
-           In `process_orderflow`, *at most* `batch_size` txs are simulated at a time.
-           The last iteration will process only (remaining_txs % batch_size) txs, so it's
-           most efficient when (txs.len() % batch_size == 0) and/or (txs.len() much greater than batch_size).
-        */
-        hindsight
-            .to_owned()
-            .process_orderflow(&txs, params.batch_size, Some(write_db.clone()), event_map)
-            .await?;
-        info!("simulated arbs for {} transactions", txs.len());
         info!("offset: {:?}", event_params.offset);
+        summary.events_processed += swaps.len();
 
         // if the api returns < limit, we're processing the most recent events
         // so we pause to avoid the loop spamming the api
-        if events.len() < event_params.limit.unwrap_or(500) as usize {
+        if num_fetched < event_params.limit.unwrap_or(500) as usize {
             if params.block_end.is_some() || params.timestamp_end.is_some() {
                 // if we're processing a specific block range, we're done
                 break;
             }
             // sleep 12s to allow for new events to be indexed
-            std::thread::sleep(std::time::Duration::from_secs(12));
+            tokio::time::sleep(std::time::Duration::from_secs(12)).await;
+        }
+    }
+    Ok(summary)
+}
+
+/// A scan's progress, shared by the task fetching its events & the writer of its results.
+#[derive(Debug, Default)]
+struct ScanState {
+    /// Number of txs queued to be simulated so far.
+    queued: Arc<AtomicUsize>,
+    checkpoints: Mutex<CheckpointTracker>,
+}
+
+/// Tracks which of a scan's queued events are done (simulated, skipped, or failed), to checkpoint
+/// the latest event whose timestamp's events are all done. A resumed scan starts at the timestamp
+/// after its checkpoint's, so a checkpoint can't be in the middle of a timestamp.
+///
+/// Events must be fetched (& queued) in order of their timestamps.
+#[derive(Debug, Default)]
+struct CheckpointTracker {
+    /// Queued events that aren't checkpointed yet, in order, as (tx hash, event, done).
+    queued: VecDeque<(H256, Checkpoint, bool)>,
+    /// The latest event fetched, whether it was queued or not.
+    latest: Option<Checkpoint>,
+    /// The latest event fetched before `latest`'s timestamp.
+    before_latest: Option<Checkpoint>,
+    /// Timestamp of the last checkpoint returned.
+    checkpointed: Option<u64>,
+}
+
+impl CheckpointTracker {
+    /// Registers an event that was fetched, whether it's queued or not.
+    fn fetched(&mut self, event: &EventHistory) {
+        let checkpoint = Checkpoint {
+            block: event.block,
+            timestamp: event.timestamp,
+        };
+        if let Some(latest) = &self.latest {
+            if latest.timestamp < event.timestamp {
+                self.before_latest = Some(latest.to_owned());
+            }
+        }
+        self.latest = Some(checkpoint);
+    }
+
+    /// Registers the fetched `event`, whose tx `tx_hash` is queued to be simulated.
+    fn queue(&mut self, tx_hash: H256, event: &EventHistory) {
+        let checkpoint = Checkpoint {
+            block: event.block,
+            timestamp: event.timestamp,
+        };
+        self.queued.push_back((tx_hash, checkpoint, false));
+    }
+
+    /// Marks the events of `tx_hashes` done. Returns the new checkpoint, if it moved.
+    fn done(&mut self, tx_hashes: impl IntoIterator<Item = H256>) -> Option<Checkpoint> {
+        for tx_hash in tx_hashes {
+            let event = self
+                .queued
+                .iter_mut()
+                .find(|(hash, _, done)| *hash == tx_hash && !done);
+            if let Some((_, _, done)) = event {
+                *done = true;
+            }
+        }
+        let checkpoint = match self.queued.iter().find(|(_, _, done)| !done) {
+            Some((_, pending, _)) => {
+                // events before the first pending one's timestamp are all done
+                let cutoff = pending.timestamp;
+                let mut checkpoint = None;
+                while let Some((_, event, _)) = self.queued.front() {
+                    if event.timestamp >= cutoff {
+                        break;
+                    }
+                    checkpoint = self.queued.pop_front().map(|(_, event, _)| event);
+                }
+                checkpoint
+            }
+            None => {
+                // all done, but the latest timestamp may have more events on the next page
+                self.queued.clear();
+                self.before_latest.to_owned()
+            }
+        };
+        self.advance(checkpoint)
+    }
+
+    /// Returns the checkpoint of the whole scan once its range is done.
+    fn finish(&mut self) -> Option<Checkpoint> {
+        self.queued.clear();
+        self.advance(self.latest.to_owned())
+    }
+
+    fn advance(&mut self, checkpoint: Option<Checkpoint>) -> Option<Checkpoint> {
+        let checkpoint =
+            checkpoint.filter(|checkpoint| Some(checkpoint.timestamp) > self.checkpointed)?;
+        self.checkpointed = Some(checkpoint.timestamp);
+        Some(checkpoint)
+    }
+}
+
+/// Scans the blocks in each of `gaps`, e.g. the blocks within a resumed scan's window that have no arbs saved.
+/// Other than their blocks, gaps are scanned with `params`. Returns the totals of all the gaps' scans.
+pub async fn run_gaps(
+    params: ScanOptions,
+    gaps: &[BlockRange],
+    rpc_client: &RpcClient,
+    mevshare: &EventClient,
+    hindsight: &Hindsight,
+    shutdown: Arc<AtomicBool>,
+) -> Result<ScanSummary> {
+    let mut summary = ScanSummary::default();
+    for (i, gap) in gaps.iter().enumerate() {
+        if shutdown.load(Ordering::SeqCst) {
+            summary.interrupted = true;
+            break;
         }
+        info!(
+            "scanning gap {}/{}: blocks {}..={}",
+            i + 1,
+            gaps.len(),
+            gap.start,
+            gap.end
+        );
+        summary += run(
+            ScanOptions {
+                block_start: gap.start as u32,
+                block_end: Some(gap.end as u32),
+                timestamp_start: 1,
+                timestamp_end: None,
+                ..params.to_owned()
+            },
+            rpc_client,
+            mevshare,
+            hindsight,
+            shutdown.clone(),
+        )
+        .await?;
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_sums_and_reads_back_json_summaries() {
+        let mut summary = ScanSummary {
+            events_processed: 100,
+            arbs_found: 2,
+            total_profit: U256::exp10(16),
+            interrupted: false,
+        };
+        summary += ScanSummary {
+            events_processed: 50,
+            arbs_found: 1,
+            total_profit: U256::exp10(17),
+            interrupted: true,
+        };
+        assert_eq!(summary.events_processed, 150);
+        assert_eq!(summary.total_profit, U256::from(11) * U256::exp10(16));
+        assert!(summary.interrupted);
+
+        let json = crate::commands::render(&summary, true).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["eventsProcessed"], 150);
+        assert_eq!(parsed["arbsFound"], 3);
+        assert_eq!(serde_json::from_str::<ScanSummary>(&json).unwrap(), summary);
+    }
+
+    fn event(n: u64, timestamp: u64) -> EventHistory {
+        serde_json::from_value(serde_json::json!({
+            "block": 17637000 + timestamp / 12,
+            "timestamp": timestamp,
+            "hint": {"txs": null, "hash": H256::from_low_u64_be(n), "logs": []},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_checkpoints_timestamps_whose_events_are_all_done() {
+        let mut tracker = CheckpointTracker::default();
+        // 4 & 5 share a timestamp; 3 isn't queued (e.g. it's already simulated)
+        let events = [
+            event(1, 100),
+            event(2, 112),
+            event(3, 124),
+            event(4, 136),
+            event(5, 136),
+        ];
+        for event in &events {
+            tracker.fetched(event);
+            if event.hint.hash != H256::from_low_u64_be(3) {
+                tracker.queue(event.hint.hash, event);
+            }
+        }
+        let hash = H256::from_low_u64_be;
+
+        // 1 is still simulating
+        assert!(tracker.done([hash(2)]).is_none());
+        let checkpoint = tracker.done([hash(1)]).unwrap();
+        assert_eq!(checkpoint.timestamp, 112);
+        // 5 is done, but 4 (on the same timestamp) isn't
+        assert!(tracker.done([hash(5)]).is_none());
+        // all done, but the next page might have more events on the last timestamp
+        let checkpoint = tracker.done([hash(4)]).unwrap();
+        assert_eq!(checkpoint.timestamp, 124);
+        assert!(tracker.done(Vec::new()).is_none());
+        let checkpoint = tracker.finish().unwrap();
+        assert_eq!(
+            (checkpoint.block, checkpoint.timestamp),
+            (17637000 + 136 / 12, 136)
+        );
+        assert!(tracker.finish().is_none());
     }
-    Ok(())
 }
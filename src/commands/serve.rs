@@ -0,0 +1,281 @@
+use crate::data::arbs::{ArbDatabase, ArbFilterParams};
+use crate::{info, warn, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use ethers::types::{Address, H256, U256};
+use serde::Deserialize;
+use std::net::SocketAddr;
+
+/// Arbs returned by `GET /arbs` unless `limit` is set.
+const DEFAULT_PAGE_SIZE: i64 = 100;
+/// Most arbs returned by one `GET /arbs`, so a request can't read a whole DB into memory.
+const MAX_PAGE_SIZE: i64 = 1000;
+/// Header of `GET /arbs` responses holding the number of arbs matching the filters.
+const TOTAL_COUNT_HEADER: &'static str = "x-total-count";
+
+/// Filters & page of `GET /arbs`; `GET /stats/summary` takes the same filters.
+#[derive(Debug, Default, Deserialize)]
+struct ArbsQuery {
+    /// Minimum profit (wei), as a decimal number.
+    min_profit: Option<String>,
+    token: Option<Address>,
+    pool: Option<Address>,
+    block_start: Option<u32>,
+    block_end: Option<u32>,
+    timestamp_start: Option<u32>,
+    timestamp_end: Option<u32>,
+    limit: Option<i64>,
+    offset: Option<u64>,
+}
+
+impl ArbsQuery {
+    fn filter_params(&self) -> std::result::Result<ArbFilterParams, ApiError> {
+        let min_profit = self
+            .min_profit
+            .as_deref()
+            .map(U256::from_dec_str)
+            .transpose()
+            .map_err(|err| ApiError::bad_request(format!("invalid min_profit: {}", err)))?;
+        Ok(ArbFilterParams {
+            block_start: self.block_start,
+            block_end: self.block_end,
+            timestamp_start: self.timestamp_start,
+            timestamp_end: self.timestamp_end,
+            min_profit,
+            event_tx: None,
+            tokens: self.token.map(|token| vec![token]),
+            pools: self.pool.map(|pool| vec![pool]),
+        })
+    }
+
+    fn limit(&self) -> std::result::Result<i64, ApiError> {
+        match self.limit.unwrap_or(DEFAULT_PAGE_SIZE) {
+            limit @ 1..=MAX_PAGE_SIZE => Ok(limit),
+            limit => Err(ApiError::bad_request(format!(
+                "limit must be between 1 and {} (got {})",
+                MAX_PAGE_SIZE, limit
+            ))),
+        }
+    }
+}
+
+/// An error response: its status, w/ `{"error": message}` as its body.
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+impl ApiError {
+    fn bad_request(message: String) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            message,
+        }
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        warn!("failed to read arbs: {}", err);
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.message }));
+        (self.status, body).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+/// `GET /arbs`: a page of the arbs matching the query's filters, in the order they landed, w/
+/// the number of matching arbs in the `x-total-count` header.
+async fn list_arbs(
+    State(db): State<ArbDatabase>,
+    Query(query): Query<ArbsQuery>,
+) -> ApiResult<Response> {
+    let params = query.filter_params()?;
+    let limit = query.limit()?;
+    let total = db.get_num_arbs(&params).await?;
+    let arbs = db
+        .read_arbs(&params, query.offset, Some(limit), None)
+        .await?;
+    let mut headers = HeaderMap::new();
+    headers.insert(TOTAL_COUNT_HEADER, HeaderValue::from(total));
+    Ok((headers, Json(arbs)).into_response())
+}
+
+/// `GET /arbs/:event_hash`: the arbs of the event for a tx hash.
+async fn get_arb(
+    State(db): State<ArbDatabase>,
+    Path(event_hash): Path<H256>,
+) -> ApiResult<Response> {
+    let arb = db
+        .read_arbs(&ArbFilterParams::event_tx(event_hash), None, Some(1), None)
+        .await?
+        .pop()
+        .ok_or(ApiError {
+            status: StatusCode::NOT_FOUND,
+            message: format!("no arbs saved for event {:?}", event_hash),
+        })?;
+    Ok(Json(arb).into_response())
+}
+
+/// `GET /stats/summary`: the report `analyze` prints, of the arbs matching the query's filters.
+async fn summary(
+    State(db): State<ArbDatabase>,
+    Query(query): Query<ArbsQuery>,
+) -> ApiResult<Response> {
+    let report = db.analyze_arbs(&query.filter_params()?).await?;
+    Ok(Json(report).into_response())
+}
+
+/// `GET /ranges`: the earliest & latest blocks & timestamps of the saved arbs.
+async fn ranges(State(db): State<ArbDatabase>) -> ApiResult<Response> {
+    Ok(Json(db.get_previously_saved_ranges().await?).into_response())
+}
+
+/// Read-only JSON API over the arbs in `db`.
+fn router(db: ArbDatabase) -> Router {
+    Router::new()
+        .route("/arbs", get(list_arbs))
+        .route("/arbs/:event_hash", get(get_arb))
+        .route("/stats/summary", get(summary))
+        .route("/ranges", get(ranges))
+        .with_state(db)
+}
+
+/// Serves the arbs in `read_db` over HTTP at `addr` until the process is stopped.
+pub async fn run(read_db: ArbDatabase, addr: SocketAddr) -> Result<()> {
+    let server = axum::Server::try_bind(&addr)?.serve(router(read_db).into_make_service());
+    info!("serving arbs at http://{}", server.local_addr());
+    server.await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{
+        arbs::ArbWriter,
+        file::FileWriter,
+        report::ArbReport,
+        sqlite::{SqliteConfig, SqliteConnect},
+    };
+    use crate::interfaces::{SimArbResultBatch, StoredArbsRanges};
+    use rand::Rng;
+    use std::sync::Arc;
+
+    /// Starts a server over `db` on a free port, returning its base URL.
+    fn serve(db: ArbDatabase) -> Result<String> {
+        let server =
+            axum::Server::try_bind(&"127.0.0.1:0".parse()?)?.serve(router(db).into_make_service());
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        Ok(url)
+    }
+
+    fn test_arbs() -> Vec<SimArbResultBatch> {
+        (0..10)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::from_low_u64_be(i + 1);
+                arb.event.block = 100 + i;
+                arb.event.timestamp = 0x6464beef + (i * 12);
+                arb.max_profit = U256::from(i) * U256::exp10(18);
+                arb
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn it_serves_arbs_from_sqlite_and_file_dbs() -> Result<()> {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        let sqlite_path =
+            std::env::temp_dir().join(format!("test_hindsight_serve_{}.sqlite", rnum));
+        let file_path = std::env::temp_dir().join(format!("test_hindsight_serve_{}.ndjson", rnum));
+        let sqlite: ArbDatabase = Arc::new(
+            SqliteConnect::new(SqliteConfig {
+                path: sqlite_path.to_owned(),
+            })
+            .await?,
+        );
+        let file: ArbDatabase = Arc::new(FileWriter::new(Some(
+            file_path.to_string_lossy().into_owned(),
+        )));
+        let arbs = test_arbs();
+        let token = arbs[0].results[0].user_trade.tokens.token;
+        let http = reqwest::Client::new();
+        for db in [sqlite, file] {
+            db.write_arbs(&arbs).await?;
+            let url = serve(db)?;
+
+            // filtered & paged
+            let res = http
+                .get(format!(
+                    "{}/arbs?min_profit={}&token={:?}&block_start=102&limit=3&offset=1",
+                    url,
+                    U256::from(4) * U256::exp10(18),
+                    token
+                ))
+                .send()
+                .await?;
+            assert_eq!(res.status(), 200);
+            assert_eq!(res.headers()[TOTAL_COUNT_HEADER], "6");
+            let page: Vec<SimArbResultBatch> = res.json().await?;
+            assert_eq!(
+                page.iter().map(|arb| arb.event.block).collect::<Vec<_>>(),
+                vec![105, 106, 107]
+            );
+
+            let arb: SimArbResultBatch = http
+                .get(format!("{}/arbs/{:?}", url, arbs[3].event.hint.hash))
+                .send()
+                .await?
+                .json()
+                .await?;
+            assert_eq!(arb.event.block, 103);
+            let res = http
+                .get(format!("{}/arbs/{:?}", url, H256::from_low_u64_be(0xdead)))
+                .send()
+                .await?;
+            assert_eq!(res.status(), 404);
+
+            let report: ArbReport = http
+                .get(format!("{}/stats/summary?block_end=104", url))
+                .send()
+                .await?
+                .json()
+                .await?;
+            assert_eq!(report.events, 5);
+            assert_eq!(report.arbs, 4);
+
+            let ranges: StoredArbsRanges = http
+                .get(format!("{}/ranges", url))
+                .send()
+                .await?
+                .json()
+                .await?;
+            assert_eq!((ranges.earliest_block, ranges.latest_block), (100, 109));
+
+            for query in ["min_profit=0.1", "limit=0", "limit=1001", "block_start=-1"] {
+                let res = http.get(format!("{}/arbs?{}", url, query)).send().await?;
+                assert_eq!(res.status(), 400, "{}", query);
+            }
+        }
+        std::fs::remove_file(sqlite_path)?;
+        std::fs::remove_file(file_path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+use crate::commands::print_result;
+use crate::error::HindsightError;
+use crate::event_history::fetch_latest_events;
+use crate::info;
+use crate::interfaces::{PoolVariant, SimArbResult};
+use crate::sim::core::{
+    find_optimal_backrun_amount_in_out, replay_backruns, ArbSearchOutcome, SimOptions,
+};
+use crate::sim::snapshot::{self, EventSnapshot};
+use crate::util::{get_block_info, RpcClient};
+use crate::{Error, Result};
+use ethers::providers::Middleware;
+use ethers::types::{Address, Transaction, H256};
+use ethers::utils::format_ether;
+use mev_share_sse::{EventClient, EventHistory, EventHistoryParams, EventTransactionLog, Hint};
+use serde::{Deserialize, Serialize};
+
+/// Finds the MEV-Share event for `tx`, or synthesizes one from the tx receipt's logs
+/// if the event isn't available from the Event History API.
+async fn get_event(
+    client: &RpcClient,
+    mevshare: &EventClient,
+    tx: &Transaction,
+    tx_block: u64,
+) -> Result<EventHistory> {
+    let events = fetch_latest_events(
+        mevshare,
+        EventHistoryParams {
+            block_start: Some(tx_block),
+            block_end: Some(tx_block),
+            timestamp_start: None,
+            timestamp_end: None,
+            limit: None,
+            offset: None,
+        },
+    )
+    .await?;
+    if let Some(event) = events.into_iter().find(|event| event.hint.hash == tx.hash) {
+        return Ok(event);
+    }
+
+    info!(
+        "event not found for tx {:?}, synthesizing hints from receipt",
+        tx.hash
+    );
+    let receipt = client
+        .get_transaction_receipt(tx.hash)
+        .await?
+        .ok_or::<Error>(HindsightError::TxNotLanded(tx.hash).into())?;
+    let block = client
+        .get_block(tx_block)
+        .await?
+        .ok_or::<Error>(HindsightError::BlockNotFound(tx_block).into())?;
+    Ok(EventHistory {
+        block: tx_block,
+        timestamp: block.timestamp.as_u64(),
+        hint: Hint {
+            txs: vec![],
+            hash: tx.hash,
+            logs: receipt
+                .logs
+                .into_iter()
+                .filter(|log| !log.topics.is_empty())
+                .map(|log| EventTransactionLog {
+                    address: log.address,
+                    // MEV-Share only shares the first topic (the event signature), w/o the data
+                    topics: vec![log.topics[0]],
+                    data: Default::default(),
+                })
+                .collect(),
+            gas_used: None,
+            mev_gas_price: None,
+        },
+    })
+}
+
+/// Simulates the optimal backrun-arbitrage for a single landed transaction and prints the results,
+/// as text or (if `json`) JSON.
+///
+/// Forks from `block` if specified, otherwise from the block before the tx landed.
+pub async fn run(
+    client: &RpcClient,
+    mevshare: &EventClient,
+    tx_hash: H256,
+    block: Option<u64>,
+    sim_options: SimOptions,
+    json: bool,
+) -> Result<()> {
+    let tx = client
+        .get_transaction(tx_hash)
+        .await?
+        .ok_or::<Error>(HindsightError::TxNotLanded(tx_hash).into())?;
+    let tx_block = tx
+        .block_number
+        .ok_or::<Error>(HindsightError::TxNotLanded(tx_hash).into())?
+        .as_u64();
+    let event = get_event(client, mevshare, &tx, tx_block).await?;
+    let sim_block = block.unwrap_or(tx_block - 1);
+    let block_info = get_block_info(client, sim_block).await?;
+
+    let outcome =
+        find_optimal_backrun_amount_in_out(client, tx, &event, &block_info, sim_options).await?;
+    print_result(&SimulateTxSummary::new(tx_hash, sim_block, &outcome), json)
+}
+
+/// Re-simulates the optimal backrun-arbitrage for a tx from the state that `scan --record-state`
+/// saved for it, without a node, and prints the results as text or (if `json`) JSON.
+pub async fn run_offline(tx_hash: H256, sim_options: SimOptions, json: bool) -> Result<()> {
+    let snapshot = EventSnapshot::load(snapshot::default_dir(), tx_hash)?;
+    let outcome = replay_backruns(&snapshot, sim_options).await?;
+    print_result(
+        &SimulateTxSummary::new(tx_hash, snapshot.state.block_number, &outcome),
+        json,
+    )
+}
+
+/// Describes a pool by its variant & fee tier, e.g. `UniswapV3, 0.05%`.
+fn pool_label(variant: PoolVariant, fee: Option<u32>) -> String {
+    match fee {
+        // fee tiers are in hundredths of a bip
+        Some(fee) => format!("{:?}, {}%", variant, fee as f64 / 10_000.0),
+        None => format!("{:?}", variant),
+    }
+}
+
+/// An arb pool whose sims failed for `simulate-tx`'s tx.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimFailure {
+    pub pool: Address,
+    pub error: String,
+}
+
+/// What `simulate-tx` found for a tx, as printed.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulateTxSummary {
+    pub tx_hash: H256,
+    /// Block the sims forked from.
+    pub sim_block: u64,
+    /// The most profitable backrun found against each arb pool.
+    pub results: Vec<SimArbResult>,
+    /// Arb pools whose sims failed, and why.
+    pub failures: Vec<SimFailure>,
+}
+
+impl SimulateTxSummary {
+    pub fn new(tx_hash: H256, sim_block: u64, outcome: &ArbSearchOutcome) -> Self {
+        Self {
+            tx_hash,
+            sim_block,
+            results: outcome.results.to_owned(),
+            failures: outcome
+                .failures
+                .iter()
+                .map(|(pool, err)| SimFailure {
+                    pool: *pool,
+                    error: err.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for SimulateTxSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "tx {:?} (forked from block {})",
+            self.tx_hash, self.sim_block
+        )?;
+        if self.results.is_empty() {
+            write!(f, "\nno backrun-arbitrage found.")?;
+        }
+        for (i, res) in self.results.iter().enumerate() {
+            let backrun = &res.backrun_trade;
+            write!(f, "\nresult #{}", i)?;
+            write!(f, "\n  amount_in:\t{} Ξ", format_ether(backrun.amount_in))?;
+            write!(f, "\n  profit:\t{} Ξ", format_ether(backrun.profit))?;
+            write!(
+                f,
+                "\n  start_pool:\t{:?} ({})",
+                backrun.start_pool,
+                pool_label(backrun.start_variant, backrun.start_fee)
+            )?;
+            write!(
+                f,
+                "\n  end_pool:\t{:?} ({})",
+                backrun.end_pool,
+                pool_label(backrun.end_variant, backrun.end_fee)
+            )?;
+            write!(
+                f,
+                "\n  prices:\t{} (user pool), {} (alt pool) TKN/WETH",
+                format_ether(backrun.user_pool_price),
+                format_ether(backrun.alt_pool_price)
+            )?;
+            if !backrun.route.is_empty() {
+                for leg in backrun.legs(&res.user_trade.tokens) {
+                    write!(
+                        f,
+                        "\n  route:\t{:?} -> {:?} on {:?} ({:?})",
+                        leg.token_in, leg.token_out, leg.pool, leg.variant
+                    )?;
+                }
+            }
+        }
+        for failure in &self.failures {
+            write!(
+                f,
+                "\nfailed to sim arb against pool {:?}: {}",
+                failure.pool, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::render;
+
+    #[test]
+    fn it_reads_back_json_summaries() -> Result<()> {
+        let summary = SimulateTxSummary {
+            tx_hash: H256::from_low_u64_be(1),
+            sim_block: 17_637_018,
+            results: vec![SimArbResult::test_example()],
+            failures: vec![SimFailure {
+                pool: Address::from_low_u64_be(2),
+                error: "pool is too shallow".to_owned(),
+            }],
+        };
+        let json = render(&summary, true)?;
+        let parsed: SimulateTxSummary = serde_json::from_str(&json)?;
+        assert_eq!(parsed.tx_hash, summary.tx_hash);
+        assert_eq!(parsed.sim_block, summary.sim_block);
+        assert_eq!(parsed.failures, summary.failures);
+        assert_eq!(
+            serde_json::to_value(&parsed.results)?,
+            serde_json::to_value(&summary.results)?
+        );
+        Ok(())
+    }
+}
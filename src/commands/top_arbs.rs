@@ -0,0 +1,192 @@
+use crate::data::arbs::{ArbDatabase, ArbFilterParams, ArbReader, SortOrder};
+use crate::interfaces::SimArbResultBatch;
+use crate::util::wei_to_eth;
+use crate::Result;
+use chrono::NaiveDateTime;
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+
+const NUM_COLUMNS: usize = 8;
+/// Columns from this one on are amounts.
+const FIRST_AMOUNT_COLUMN: usize = 5;
+
+/// An arb's most profitable result, as printed by `top-arbs`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopArb {
+    pub event_hash: H256,
+    pub block: u64,
+    pub timestamp: u64,
+    pub token: Address,
+    pub token_symbol: Option<String>,
+    pub start_pool: Address,
+    pub end_pool: Address,
+    /// WETH (wei) swapped by the backrun.
+    pub amount_in: U256,
+    /// Profit (wei) after paying for gas.
+    pub profit: U256,
+    /// Profit in USD, if the arb was priced in USD.
+    pub profit_usd: Option<f64>,
+}
+
+impl TopArb {
+    /// Describes the most profitable result of `arb`. None if the arb has no results.
+    pub fn from_batch(arb: &SimArbResultBatch) -> Option<Self> {
+        let best = arb.best_result()?;
+        Some(Self {
+            event_hash: arb.event.hint.hash,
+            block: arb.event.block,
+            timestamp: arb.event.timestamp,
+            token: best.user_trade.tokens.token,
+            token_symbol: best.user_trade.token_symbol.to_owned(),
+            start_pool: best.backrun_trade.start_pool,
+            end_pool: best.backrun_trade.end_pool,
+            amount_in: best.backrun_trade.amount_in,
+            profit: best.backrun_trade.profit,
+            profit_usd: arb.to_usd(best.backrun_trade.profit),
+        })
+    }
+
+    /// Cells of the arb's row in the table. Addresses are shortened (`0x1234…abcd`).
+    fn row(&self) -> [String; NUM_COLUMNS] {
+        let timestamp = NaiveDateTime::from_timestamp_opt(self.timestamp as i64, 0)
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        [
+            self.block.to_string(),
+            timestamp,
+            self.token_symbol
+                .to_owned()
+                .unwrap_or_else(|| self.token.to_string()),
+            self.start_pool.to_string(),
+            self.end_pool.to_string(),
+            format!("{:.6}", wei_to_eth(self.amount_in)),
+            format!("{:.6}", wei_to_eth(self.profit)),
+            self.profit_usd
+                .map_or("-".to_owned(), |usd| format!("{:.2}", usd)),
+        ]
+    }
+}
+
+/// Formats arbs as a table, with columns padded to line up. Amounts are right-aligned.
+fn table(arbs: &[TopArb]) -> String {
+    let header = [
+        "block",
+        "timestamp (UTC)",
+        "token",
+        "start pool",
+        "end pool",
+        "amount in (Ξ)",
+        "profit (Ξ)",
+        "profit ($)",
+    ]
+    .map(|cell| cell.to_owned());
+    let rows = std::iter::once(header)
+        .chain(arbs.iter().map(TopArb::row))
+        .collect::<Vec<_>>();
+    align_columns(&rows, FIRST_AMOUNT_COLUMN)
+}
+
+/// Joins `rows` into lines, with columns padded to line up. Cells from `first_amount_column` on
+/// are right-aligned.
+pub(crate) fn align_columns<const N: usize>(
+    rows: &[[String; N]],
+    first_amount_column: usize,
+) -> String {
+    let widths = (0..N)
+        .map(|col| {
+            rows.iter()
+                .map(|row| row[col].chars().count())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    rows.iter()
+        .map(|row| {
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(col, (cell, &width))| {
+                    if col >= first_amount_column {
+                        format!("{:>width$}", cell, width = width)
+                    } else {
+                        format!("{:<width$}", cell, width = width)
+                    }
+                });
+            cells.collect::<Vec<_>>().join("  ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints the `limit` biggest profitable arbs in `read_db` matching `params`, as a table or (if `json`) JSON.
+pub async fn run(
+    params: ArbFilterParams,
+    read_db: &ArbDatabase,
+    sort: SortOrder,
+    limit: i64,
+    json: bool,
+) -> Result<()> {
+    let arbs = read_db
+        .read_arbs(&params.profitable(), None, Some(limit), Some(sort))
+        .await?
+        .iter()
+        .filter_map(TopArb::from_batch)
+        .collect::<Vec<_>>();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&arbs)?);
+    } else if arbs.is_empty() {
+        println!("no arbs found.");
+    } else {
+        println!("{}", table(&arbs));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    #[test]
+    fn it_aligns_table_columns() {
+        let mut batch = SimArbResultBatch::test_example();
+        batch.results = vec![SimArbResult::test_example()];
+        let arb = TopArb::from_batch(&batch).unwrap();
+        let mut big_arb = arb.clone();
+        big_arb.block = 17_000_000;
+        big_arb.token_symbol = None;
+        big_arb.profit = U256::exp10(19);
+        big_arb.profit_usd = Some(20_000.0);
+        let table = table(&[big_arb, arb]);
+        let lines = table.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].starts_with("block     timestamp (UTC)"),
+            "{}",
+            table
+        );
+        assert!(lines[1].contains("10.000000  20000.00"), "{}", table);
+        assert!(lines[2].contains("TKN"), "{}", table);
+        // rows are as wide as the header, since amounts are right-aligned to it
+        let width = |line: &str| line.chars().count();
+        assert!(
+            lines.iter().all(|line| width(line) == width(lines[0])),
+            "{}",
+            table
+        );
+    }
+
+    #[test]
+    fn it_reads_back_json_arbs() {
+        let mut batch = SimArbResultBatch::test_example();
+        batch.results = vec![SimArbResult::test_example()];
+        let arbs = vec![TopArb::from_batch(&batch).unwrap()];
+
+        let json = serde_json::to_string_pretty(&arbs).unwrap();
+        assert!(json.contains("\"eventHash\""), "{}", json);
+        assert_eq!(serde_json::from_str::<Vec<TopArb>>(&json).unwrap(), arbs);
+    }
+}
@@ -1,19 +1,23 @@
-use futures::future::join_all;
-use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::{cmp::Reverse, collections::HashSet, str::FromStr, sync::Arc};
 
-use super::db::DbEngine;
+use super::{
+    db::DbEngine,
+    report::{ArbReport, ArbReportBuilder, ArbSummary},
+};
 use crate::{
-    data::{db::Db, file::FileWriter},
-    info,
-    interfaces::{SimArbResultBatch, StoredArbsRanges},
-    Result,
+    debug, info,
+    interfaces::{BlockRange, SimArbResultBatch, StoredArbsRanges},
+    trace, Result,
 };
 use async_trait::async_trait;
-use deadqueue::unlimited::Queue;
-use ethers::{types::U256, utils::format_ether};
+use ethers::{
+    types::{Address, H256, U256},
+    utils::format_ether,
+};
 
-const NUM_ARBS_PER_READ: i64 = 3000;
+const NUM_ARBS_PER_READ: i64 = 1000;
+/// Log export progress every this many pages.
+const PROGRESS_LOG_INTERVAL: usize = 10;
 
 #[derive(Clone, Debug)]
 pub struct ArbFilterParams {
@@ -22,6 +26,12 @@ pub struct ArbFilterParams {
     pub timestamp_start: Option<u32>,
     pub timestamp_end: Option<u32>,
     pub min_profit: Option<U256>,
+    /// Only match the arbs of the event for this tx hash.
+    pub event_tx: Option<H256>,
+    /// Only match arbs with a result whose user trade swaps one of these tokens.
+    pub tokens: Option<Vec<Address>>,
+    /// Only match arbs with a result whose backrun swaps on one of these pools.
+    pub pools: Option<Vec<Address>>,
 }
 
 impl Default for ArbFilterParams {
@@ -38,6 +48,101 @@ impl ArbFilterParams {
             timestamp_start: None,
             timestamp_end: None,
             min_profit: None,
+            event_tx: None,
+            tokens: None,
+            pools: None,
+        }
+    }
+
+    /// Same filter, but only matching arbs with a profit.
+    pub fn profitable(&self) -> Self {
+        Self {
+            min_profit: Some(self.min_profit.unwrap_or_default().max(U256::one())),
+            ..self.to_owned()
+        }
+    }
+
+    /// Matches the arbs of the event for `tx_hash`.
+    pub fn event_tx(tx_hash: H256) -> Self {
+        Self {
+            event_tx: Some(tx_hash),
+            ..Self::none()
+        }
+    }
+
+    /// Returns true if `arb` satisfies every filter that's set. Used by backends that filter in memory.
+    pub fn matches(&self, arb: &SimArbResultBatch) -> bool {
+        let block = arb.event.block;
+        let timestamp = arb.event.timestamp;
+        self.block_start.map_or(true, |start| block >= start as u64)
+            && self.block_end.map_or(true, |end| block <= end as u64)
+            && self
+                .timestamp_start
+                .map_or(true, |start| timestamp >= start as u64)
+            && self
+                .timestamp_end
+                .map_or(true, |end| timestamp <= end as u64)
+            && self.min_profit.map_or(true, |min| arb.max_profit >= min)
+            && self
+                .event_tx
+                .map_or(true, |tx_hash| arb.event.hint.hash == tx_hash)
+            && self.tokens.as_ref().map_or(true, |tokens| {
+                arb.results.iter().any(|res| {
+                    tokens.contains(&res.user_trade.tokens.token)
+                        || tokens.contains(&res.user_trade.tokens.weth)
+                })
+            })
+            && self.pools.as_ref().map_or(true, |pools| {
+                arb.results.iter().any(|res| {
+                    pools.contains(&res.backrun_trade.start_pool)
+                        || pools.contains(&res.backrun_trade.end_pool)
+                })
+            })
+    }
+}
+
+/// Order to read arbs in, biggest first. Arbs are read in the order they landed when unsorted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    /// Most profitable first.
+    Profit,
+    /// Largest backrun (of the arb's most profitable result) first.
+    AmountIn,
+    /// Latest block first.
+    Block,
+}
+
+impl SortOrder {
+    /// Sorts arbs in memory; for backends that can't sort them server-side.
+    pub fn sort(&self, arbs: &mut [SimArbResultBatch]) {
+        match self {
+            SortOrder::Profit => arbs.sort_by_key(|arb| Reverse(arb.max_profit)),
+            SortOrder::AmountIn => arbs.sort_by_key(|arb| {
+                Reverse(arb.best_result().map(|res| res.backrun_trade.amount_in))
+            }),
+            SortOrder::Block => arbs.sort_by_key(|arb| Reverse(arb.event.block)),
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortOrder::Profit => write!(f, "profit"),
+            SortOrder::AmountIn => write!(f, "amount-in"),
+            SortOrder::Block => write!(f, "block"),
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "profit" => Ok(SortOrder::Profit),
+            "amount-in" => Ok(SortOrder::AmountIn),
+            "block" => Ok(SortOrder::Block),
+            _ => Err(format!("invalid sort order: {}", s)),
         }
     }
 }
@@ -45,160 +150,223 @@ impl ArbFilterParams {
 #[derive(Clone, Debug)]
 pub enum WriteEngine {
     File(Option<String>),
+    /// Gzip-compressed newline-delimited JSON.
+    FileGz(Option<String>),
+    Csv(Option<String>),
+    /// Requires the `parquet` feature.
+    Parquet(Option<String>),
     Db(DbEngine),
 }
 
+/// Destination for simulated arbs.
 #[async_trait]
-pub trait ArbDb: Sync + Send {
+pub trait ArbWriter: Sync + Send {
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()>;
+}
+
+/// Source of previously simulated arbs.
+#[async_trait]
+pub trait ArbReader: Sync + Send {
     async fn read_arbs(
         &self,
         filter_params: &ArbFilterParams,
         offset: Option<u64>,
         limit: Option<i64>,
+        sort: Option<SortOrder>,
     ) -> Result<Vec<SimArbResultBatch>>;
     async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64>;
+    /// Returns which of `event_hashes` have arbs saved, w/ one lookup for all of them.
+    async fn get_saved_events(&self, event_hashes: &[H256]) -> Result<HashSet<H256>>;
     async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges>;
+    /// Gets the contiguous ranges of blocks that have arbs saved, in order. Blocks with arbs that are
+    /// at most `max_gap` blocks apart are in the same range, since not every block has events to simulate.
+    async fn get_covered_ranges(&self, max_gap: u64) -> Result<Vec<BlockRange>>;
+    /// Exports arbs matching `filter_params` to `write_dest`. Returns the number of arbs exported.
+    async fn export_arbs(
+        &self,
+        write_dest: &dyn ArbWriter,
+        filter_params: &ArbFilterParams,
+    ) -> Result<usize>;
+    /// Aggregates the arbs matching `filter_params` into a report.
+    async fn analyze_arbs(&self, filter_params: &ArbFilterParams) -> Result<ArbReport>;
+}
+
+/// A full backend that arbs can be both read from and written to.
+pub trait ArbDb: ArbReader + ArbWriter {}
+impl<T: ArbReader + ArbWriter> ArbDb for T {}
+
+/* Forward both halves through `Arc` so that shared handles (e.g. `ArbDatabase`)
+   can be passed wherever a `&dyn ArbWriter` or `&dyn ArbReader` is expected.
+*/
+#[async_trait]
+impl<T: ArbWriter + ?Sized> ArbWriter for Arc<T> {
+    async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        self.as_ref().write_arbs(arbs).await
+    }
+}
+
+#[async_trait]
+impl<T: ArbReader + ?Sized> ArbReader for Arc<T> {
+    async fn read_arbs(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<SimArbResultBatch>> {
+        self.as_ref()
+            .read_arbs(filter_params, offset, limit, sort)
+            .await
+    }
+    async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
+        self.as_ref().get_num_arbs(filter_params).await
+    }
+    async fn get_saved_events(&self, event_hashes: &[H256]) -> Result<HashSet<H256>> {
+        self.as_ref().get_saved_events(event_hashes).await
+    }
+    async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
+        self.as_ref().get_previously_saved_ranges().await
+    }
+    async fn get_covered_ranges(&self, max_gap: u64) -> Result<Vec<BlockRange>> {
+        self.as_ref().get_covered_ranges(max_gap).await
+    }
     async fn export_arbs(
         &self,
-        write_dest: WriteEngine,
+        write_dest: &dyn ArbWriter,
         filter_params: &ArbFilterParams,
-    ) -> Result<()>;
+    ) -> Result<usize> {
+        self.as_ref().export_arbs(write_dest, filter_params).await
+    }
+    async fn analyze_arbs(&self, filter_params: &ArbFilterParams) -> Result<ArbReport> {
+        self.as_ref().analyze_arbs(filter_params).await
+    }
 }
 
-/// Saves arbs to given write engine (file or db).
+/// Saves arbs from `src` to `write_dest`. Returns the number of arbs written.
+///
+/// Arbs are read and written one page (`NUM_ARBS_PER_READ` arbs) at a time,
+/// so memory use doesn't grow with the number of arbs exported.
 pub async fn export_arbs_core(
-    src: Arc<dyn ArbDb>,
-    write_dest: WriteEngine,
+    src: Arc<dyn ArbReader>,
+    write_dest: &dyn ArbWriter,
     filter_params: &ArbFilterParams,
-) -> Result<()> {
-    /* Spawns a reader thread and a writer thread.
-       Reader thread reads arbs from `src` and pushes them to a thread-safe queue.
-       Writer thread pops arbs from the queue and writes them to `write_dest`.
-       When the reader thread is done, it unlocks a mutex that the writer thread is waiting on.
-       When the writer thread is done, it quits and the function returns.
-    */
-
+) -> Result<usize> {
     // determine total number of arbs now to prevent running forever in case `scan` is running concurrently
     let total_arbs = src.get_num_arbs(filter_params).await?;
     info!("total arbs: {}", total_arbs);
-    let offset_lock = Arc::new(Mutex::new(0));
-
-    // thread-safe queue
-    let arb_queue_handle: Arc<Queue<SimArbResultBatch>> = Arc::new(Queue::new());
-    // thread-safe mutex to keep writer thread from quitting before we're done reading
-    let process_done = Arc::new(Mutex::new(()));
-
-    // arc clones to give to the reader thread
-    let arb_queue = arb_queue_handle.clone();
-    let filter_params = filter_params.clone();
-    let lock = process_done.clone();
-
-    // spawn reader thread
-    let read_handle = tokio::spawn(async move {
-        info!("starting reader thread...");
-        // lock process_done to keep writer thread from quitting before we're done reading
-        let _process_lock = lock.lock().await;
-        // read NUM_ARBS_PER_READ arbs at a time
-        let mut offset = offset_lock.lock().await;
-        while *offset < total_arbs {
-            let arbs = src
-                .read_arbs(&filter_params, Some(*offset), Some(NUM_ARBS_PER_READ))
-                .await
-                .expect("failed to read arbs");
-            if arbs.len() == 0 {
-                break;
-            }
-            *offset = *offset + NUM_ARBS_PER_READ as u64;
-            println!("offset {}", offset);
-            let start_block = arbs.iter().map(|arb| arb.event.block).min().unwrap_or(0);
-            let end_block = arbs
-                .iter()
-                .map(|arb| arb.event.block)
-                .max()
-                .unwrap_or(u64::MAX);
-            let start_timestamp = arbs
-                .iter()
-                .map(|arb| arb.event.timestamp)
-                .min()
-                .unwrap_or(0);
-            let end_timestamp = arbs
-                .iter()
-                .map(|arb| arb.event.timestamp)
-                .max()
-                .unwrap_or(u64::MAX);
-            let sum_profit = arbs
-                .iter()
-                .fold(0.into(), |acc: U256, arb| acc + arb.max_profit);
-            info!("SUM PROFIT: {} Ξ", format_ether(sum_profit));
-            info!("(start,end) block: ({}, {})", start_block, end_block);
-            info!(
-                "time range: {} days",
-                (end_timestamp - start_timestamp) as f64 / 86400_f64
-            );
 
-            for arb in arbs {
-                println!("im arb: {:?}", arb.event.hint.hash);
-                arb_queue.push(arb);
-                println!("arb q: len {}", arb_queue.len());
-            }
-            // arb_lock is dropped here, unlocking the arb_queue mutex
+    let mut offset = 0;
+    let mut num_pages = 0;
+    let mut total_written = 0;
+    let mut sum_profit = U256::zero();
+    while offset < total_arbs {
+        let arbs = src
+            .read_arbs(filter_params, Some(offset), Some(NUM_ARBS_PER_READ), None)
+            .await?;
+        // some backends filter pages in memory, so an empty page doesn't mean we're done
+        offset += NUM_ARBS_PER_READ as u64;
+        num_pages += 1;
+        debug!("offset {}", offset);
+        if arbs.is_empty() {
+            continue;
         }
-        // _process_lock is dropped here, unlocking the process_done mutex
-    });
-
-    // arc clone to give to the writer thread
-    let arb_queue = arb_queue_handle.clone();
-
-    // init chosen write engine
-    let write_engine = match write_dest.clone() {
-        WriteEngine::File(filename) => Arc::new(FileWriter::new(filename)),
-        WriteEngine::Db(db_engine) => Db::new(db_engine).await.connect,
-    };
-
-    let total_arbs = Arc::new(Mutex::new(0));
-    // start writer thread
-    let all_arbs = total_arbs.clone();
-    let write_handle = tokio::spawn(async move {
-        info!("starting writer thread...");
-        loop {
-            println!("[w] arb q {}", arb_queue.len());
-            let mut batch_arbs = vec![];
-            for _ in 0..arb_queue.len() {
-                let arb = arb_queue.pop().await;
-                batch_arbs.push(arb);
-            }
-
-            info!("finna write {} arbs", batch_arbs.len());
-            let batch_len = batch_arbs.len();
-            if batch_len > 0 {
-                write_engine
-                    .write_arbs(&batch_arbs)
-                    .await
-                    .expect("failed to write arbs");
-                info!("exported {} arbs", batch_len);
-                let total_arbs = all_arbs.clone();
-                let mut total_arbs = total_arbs.lock().await;
-                *total_arbs += batch_len;
-            } else {
-                info!("no arbs to write, sleeping...");
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-            }
-
-            // if process_done is unlocked, reader thread is done
-            // then if our queue is empty, we can quit
-            if process_done.try_lock().is_ok() && arb_queue.len() == 0 {
-                info!("reader thread done, writer thread quitting...");
-                break;
-            }
+        trace!(
+            "writing arbs: {:?}",
+            arbs.iter()
+                .map(|arb| arb.event.hint.hash)
+                .collect::<Vec<_>>()
+        );
+        sum_profit = arbs
+            .iter()
+            .fold(sum_profit, |acc: U256, arb| acc + arb.max_profit);
+        write_dest.write_arbs(&arbs).await?;
+        total_written += arbs.len();
+
+        if num_pages % PROGRESS_LOG_INTERVAL == 0 {
+            info!(
+                "exported {} arbs so far (profit: {} Ξ)",
+                total_written,
+                format_ether(sum_profit)
+            );
         }
-    });
+    }
 
-    join_all(vec![read_handle, write_handle]).await;
+    info!(
+        "wrote total of {} arbs (profit: {} Ξ)",
+        total_written,
+        format_ether(sum_profit)
+    );
+
+    Ok(total_written)
+}
+
+/// Builds a report of the arbs in `src` in memory, reading one page at a time.
+/// Used by backends that can't aggregate arbs themselves.
+pub async fn analyze_arbs_core(
+    src: Arc<dyn ArbReader>,
+    filter_params: &ArbFilterParams,
+) -> Result<ArbReport> {
+    let events = src.get_num_arbs(filter_params).await?;
+    let profitable = filter_params.profitable();
+    let total_arbs = src.get_num_arbs(&profitable).await?;
+    let mut report = ArbReportBuilder::default();
+    let mut offset = 0;
+    while offset < total_arbs {
+        let arbs = src
+            .read_arbs(&profitable, Some(offset), Some(NUM_ARBS_PER_READ), None)
+            .await?;
+        offset += NUM_ARBS_PER_READ as u64;
+        for arb in &arbs {
+            report.add(&ArbSummary::from(arb));
+        }
+    }
+    Ok(report.build(events))
+}
 
-    info!("wrote total of {} arbs", total_arbs.lock().await);
+/// Groups ascending, deduplicated `blocks` into the ranges returned by `ArbReader::get_covered_ranges`.
+/// Used by backends that can't group blocks themselves.
+pub fn covered_ranges(blocks: impl IntoIterator<Item = u64>, max_gap: u64) -> Vec<BlockRange> {
+    let mut ranges: Vec<BlockRange> = vec![];
+    for block in blocks {
+        match ranges.last_mut() {
+            Some(range) if block - range.end <= max_gap + 1 => range.end = block,
+            _ => ranges.push(BlockRange {
+                start: block,
+                end: block,
+            }),
+        }
+    }
+    ranges
+}
 
-    Ok(())
+/// Returns the ranges of blocks in `window` that aren't `covered` (see `ArbReader::get_covered_ranges`).
+/// Runs of at most `max_gap` uncovered blocks at the edges of `window` are ignored, like those between
+/// covered ranges.
+pub fn coverage_gaps(covered: &[BlockRange], window: BlockRange, max_gap: u64) -> Vec<BlockRange> {
+    let mut gaps = vec![];
+    let mut next = window.start;
+    for range in covered {
+        if range.end < next {
+            continue;
+        }
+        if range.start > window.end {
+            break;
+        }
+        if range.start > next && range.start - next > max_gap {
+            gaps.push(BlockRange {
+                start: next,
+                end: range.start - 1,
+            });
+        }
+        next = range.end + 1;
+    }
+    if next <= window.end && window.end - next + 1 > max_gap {
+        gaps.push(BlockRange {
+            start: next,
+            end: window.end,
+        });
+    }
+    gaps
 }
 
 pub type ArbDatabase = Arc<dyn ArbDb>;
@@ -0,0 +1,301 @@
+use crate::{
+    error::HindsightError,
+    interfaces::{Dex, PoolVariant, SimArbResultBatch, UserTradeParams},
+    sim::core::ArbSearchOutcome,
+    Error, Result,
+};
+use ethers::{
+    providers::ProviderError,
+    types::{Address, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// Why an event produced no backrun.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum FailureReason {
+    EventNotCached,
+    TxNotLanded,
+    /// The tx has no receipt, long after its block; it was dropped.
+    Dropped,
+    BlockNotFound,
+    /// The event doesn't match the event filter, so it was skipped.
+    Filtered,
+    NonWethPair,
+    /// The pool's Swap logs were too short to decode.
+    MalformedSwapLog,
+    NoArbPool,
+    /// Trade params were derived, but no pool was left to arb against.
+    NoCandidatePools,
+    UnsupportedToken,
+    ShallowPool,
+    UserTxFailed,
+    GasPriceBelowBaseFee,
+    SwapReverted,
+    SwapHalted,
+    Timeout,
+    /// A request to the node failed.
+    Transport,
+    Other,
+}
+
+impl From<&Error> for FailureReason {
+    fn from(err: &Error) -> Self {
+        match err.downcast_ref::<HindsightError>() {
+            Some(HindsightError::EventNotCached(_)) => Self::EventNotCached,
+            Some(HindsightError::TxNotLanded(_)) => Self::TxNotLanded,
+            Some(HindsightError::TxDropped(_)) => Self::Dropped,
+            Some(HindsightError::BlockNotFound(_)) => Self::BlockNotFound,
+            Some(HindsightError::EventFiltered(_)) => Self::Filtered,
+            Some(HindsightError::NonWethPair(_)) => Self::NonWethPair,
+            Some(HindsightError::MalformedSwapLog { .. }) => Self::MalformedSwapLog,
+            Some(HindsightError::NoArbPool(_)) => Self::NoArbPool,
+            Some(HindsightError::UnsupportedToken(_)) => Self::UnsupportedToken,
+            Some(HindsightError::ShallowPool { .. }) => Self::ShallowPool,
+            Some(HindsightError::UserTxFailed { .. }) => Self::UserTxFailed,
+            Some(HindsightError::GasPriceBelowBaseFee { .. }) => Self::GasPriceBelowBaseFee,
+            Some(HindsightError::SwapReverted { .. }) => Self::SwapReverted,
+            Some(HindsightError::SwapHalted(_)) => Self::SwapHalted,
+            Some(HindsightError::Timeout(_)) => Self::Timeout,
+            Some(_) => Self::Other,
+            None if err.chain().any(|cause| cause.is::<ProviderError>()) => Self::Transport,
+            None => Self::Other,
+        }
+    }
+}
+
+/// A trade derived from the event's tx.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditTrade {
+    pub pool: Address,
+    pub pool_variant: PoolVariant,
+    pub dex: Option<Dex>,
+    pub token: Address,
+    pub token_symbol: Option<String>,
+    /// Number of other pools found to arb against.
+    pub arb_pools: usize,
+}
+
+impl From<&UserTradeParams> for AuditTrade {
+    fn from(params: &UserTradeParams) -> Self {
+        Self {
+            pool: params.pool,
+            pool_variant: params.pool_variant,
+            dex: params.dex,
+            token: params.tokens.token,
+            token_symbol: params.token_symbol.to_owned(),
+            arb_pools: params.arb_pools.len(),
+        }
+    }
+}
+
+/// How an event's sims ended.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditOutcome {
+    /// The most profitable backrun found; its profit may be zero.
+    Simulated {
+        start_pool: Address,
+        end_pool: Address,
+        /// Pool prices (TKN per WETH) that picked the backrun's direction; see `BackrunResult`.
+        #[serde(default)]
+        user_pool_price: U256,
+        #[serde(default)]
+        alt_pool_price: U256,
+        amount_in: U256,
+        profit: U256,
+        /// Number of candidate pools whose sims failed.
+        failed_pools: usize,
+    },
+    /// No backrun was simulated, because the event was skipped or every sim failed.
+    Failed {
+        reason: FailureReason,
+        error: String,
+    },
+}
+
+/// What happened to one event, from the moment it was picked up to its result (or failure).
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditRecord {
+    pub event_hash: H256,
+    /// Block the event's tx landed in, if it's known.
+    pub block: Option<u64>,
+    /// Trades derived from the tx; empty if it failed before they were derived.
+    pub trades: Vec<AuditTrade>,
+    /// Number of pools the backrun searches ran against.
+    pub candidate_pools: usize,
+    /// Number of sims run, including the search's iterations.
+    pub sims: usize,
+    pub outcome: Option<AuditOutcome>,
+    pub duration_ms: u64,
+}
+
+impl AuditRecord {
+    pub fn new(event_hash: H256, block: Option<u64>) -> Self {
+        Self {
+            event_hash,
+            block,
+            trades: vec![],
+            candidate_pools: 0,
+            sims: 0,
+            outcome: None,
+            duration_ms: 0,
+        }
+    }
+
+    /// Records what the backrun searches found.
+    pub fn searched(&mut self, outcome: &ArbSearchOutcome) {
+        self.trades = outcome.params.iter().map(AuditTrade::from).collect();
+        self.candidate_pools = outcome.results.len() + outcome.failures.len();
+        self.sims = outcome.num_sims;
+        self.outcome = Some(
+            match outcome
+                .results
+                .iter()
+                .max_by_key(|res| res.backrun_trade.profit)
+            {
+                Some(best) => AuditOutcome::Simulated {
+                    start_pool: best.backrun_trade.start_pool,
+                    end_pool: best.backrun_trade.end_pool,
+                    user_pool_price: best.backrun_trade.user_pool_price,
+                    alt_pool_price: best.backrun_trade.alt_pool_price,
+                    amount_in: best.backrun_trade.amount_in,
+                    profit: best.backrun_trade.profit,
+                    failed_pools: outcome.failures.len(),
+                },
+                // every search failed; they usually fail for the same reason
+                None => match outcome.failures.first() {
+                    Some((_, err)) => AuditOutcome::Failed {
+                        reason: err.into(),
+                        error: outcome.failure_summary(),
+                    },
+                    None => AuditOutcome::Failed {
+                        reason: FailureReason::NoCandidatePools,
+                        error: "no pools to arb against".to_owned(),
+                    },
+                },
+            },
+        );
+    }
+
+    /// Records how the event's sims ended, after `duration`.
+    pub fn finish(&mut self, res: &Result<SimArbResultBatch>, duration: Duration) {
+        self.duration_ms = duration.as_millis() as u64;
+        if let Err(err) = res {
+            self.outcome = Some(AuditOutcome::Failed {
+                reason: err.into(),
+                error: err.to_string(),
+            });
+        }
+    }
+}
+
+/// Newline-delimited JSON file that an `AuditRecord` is appended to for every event processed,
+/// including events that were skipped or failed, which never reach the DB.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Opens the audit log at `path`, creating it if it doesn't exist. Records are appended.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn file(&self) -> MutexGuard<File> {
+        self.file.lock().expect("audit log poisoned")
+    }
+
+    /// Appends `record` as one line.
+    pub fn write(&self, record: &AuditRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        // one write per line, so concurrent writers can't interleave records
+        self.file().write_all(&line)?;
+        Ok(())
+    }
+
+    /// Reads the records for `event_hash` from the audit log at `path`, oldest first.
+    /// Lines that aren't valid records (e.g. from an interrupted write) are skipped.
+    pub fn grep(path: impl AsRef<Path>, event_hash: H256) -> Result<Vec<AuditRecord>> {
+        let file = File::open(path)?;
+        let mut records = vec![];
+        for line in BufReader::new(file).lines() {
+            if let Ok(record) = serde_json::from_str::<AuditRecord>(&line?) {
+                if record.event_hash == event_hash {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_appends_and_greps_records() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "hindsight_audit_test_{}.ndjson",
+            rand::random::<u64>()
+        ));
+        let log = AuditLog::open(path.to_owned())?;
+        let hash = H256::from_low_u64_be(0xf00d);
+        let mut failed = AuditRecord::new(hash, Some(17_637_019));
+        let err: Error = HindsightError::EventFiltered(hash).into();
+        failed.finish(&Err(err), Duration::from_millis(1_500));
+        log.write(&failed)?;
+        log.write(&AuditRecord::new(H256::from_low_u64_be(0xbeef), None))?;
+        let mut retried = AuditRecord::new(hash, Some(17_637_019));
+        retried.searched(&ArbSearchOutcome::default());
+        log.write(&retried)?;
+        drop(log);
+        // later runs append to the same log
+        let log = AuditLog::open(path.to_owned())?;
+        log.write(&retried)?;
+
+        let records = AuditLog::grep(&path, hash)?;
+        assert_eq!(records, vec![failed, retried.clone(), retried]);
+        assert_eq!(records[0].duration_ms, 1_500);
+        assert!(matches!(
+            records[0].outcome,
+            Some(AuditOutcome::Failed {
+                reason: FailureReason::Filtered,
+                ..
+            })
+        ));
+        assert!(matches!(
+            records[1].outcome,
+            Some(AuditOutcome::Failed {
+                reason: FailureReason::NoCandidatePools,
+                ..
+            })
+        ));
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
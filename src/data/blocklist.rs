@@ -0,0 +1,74 @@
+use crate::Result;
+use ethers::types::Address;
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+/// Tokens that can't be simulated (e.g. fee-on-transfer tokens). Saved to a JSON file
+/// whenever a token is added, so they're skipped in future runs.
+#[derive(Debug)]
+pub struct TokenBlocklist {
+    path: PathBuf,
+    tokens: Mutex<BTreeSet<Address>>,
+}
+
+impl TokenBlocklist {
+    /// Loads the blocklist saved at `path`. Returns an empty blocklist if the file doesn't exist.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let tokens = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path,
+            tokens: Mutex::new(tokens),
+        })
+    }
+
+    fn tokens(&self) -> MutexGuard<BTreeSet<Address>> {
+        self.tokens.lock().expect("token blocklist poisoned")
+    }
+
+    pub fn contains(&self, token: &Address) -> bool {
+        self.tokens().contains(token)
+    }
+
+    /// Adds `token` to the blocklist and saves it.
+    pub fn insert(&self, token: Address) -> Result<()> {
+        let mut tokens = self.tokens();
+        if !tokens.insert(token) {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&*tokens)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_saves_blocked_tokens() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "hindsight_blocklist_test_{}.json",
+            rand::random::<u64>()
+        ));
+        let blocklist = TokenBlocklist::load(path.to_owned())?;
+        let token = Address::from_low_u64_be(0xf00d);
+        assert!(!blocklist.contains(&token));
+        blocklist.insert(token)?;
+        assert!(blocklist.contains(&token));
+
+        let blocklist = TokenBlocklist::load(path.to_owned())?;
+        assert!(blocklist.contains(&token));
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{
+    data::{
+        arbs::ArbWriter,
+        file::{parse_filename, EXPORT_DIR},
+    },
+    error::HindsightError,
+    info,
+    interfaces::SimArbResultBatch,
+    sim::bundle::Bundle,
+    util::RpcClient,
+    Error, Result,
+};
+use async_trait::async_trait;
+use ethers::providers::Middleware;
+use std::io::Write;
+
+/// Saves a Flashbots-style bundle for every signed backrun to a newline-delimited JSON file;
+/// one bundle per line.
+#[derive(Clone, Debug)]
+pub struct BundleWriter {
+    pub filename: String,
+    /// Used to fetch the user txs, which aren't saved with arbs.
+    client: RpcClient,
+}
+
+impl BundleWriter {
+    pub fn new(client: RpcClient, filename: Option<String>) -> Result<Self> {
+        let filename = filename.unwrap_or(format!(
+            "bundles_{}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs()
+        ));
+        Ok(Self {
+            filename: parse_filename(Some(filename), &[".ndjson", ".json"])?,
+            client,
+        })
+    }
+
+    /// Path of the export file, relative to the working directory.
+    pub fn path(&self) -> String {
+        format!("{}/{}", EXPORT_DIR, self.filename)
+    }
+}
+
+#[async_trait]
+impl ArbWriter for BundleWriter {
+    /// Append bundles to the file. Arbs without signed backruns are skipped.
+    async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        let mut lines = vec![];
+        for arb in arbs {
+            if arb.results.iter().all(|res| res.backrun_txs.is_none()) {
+                continue;
+            }
+            let tx_hash = arb.event.hint.hash;
+            let user_tx = self
+                .client
+                .get_transaction(tx_hash)
+                .await?
+                .ok_or::<Error>(HindsightError::TxNotLanded(tx_hash).into())?;
+            for res in &arb.results {
+                if let Some(bundle) = Bundle::new(&user_tx, res, arb.event.block.into()) {
+                    lines.push(serde_json::to_string(&bundle)?);
+                }
+            }
+        }
+        if lines.is_empty() {
+            info!("no signed backruns found to export.");
+            return Ok(());
+        }
+        info!(
+            "exporting {} bundles to file {}...",
+            lines.len(),
+            self.path()
+        );
+        std::fs::create_dir_all(EXPORT_DIR)?;
+        let mut file = std::fs::File::options()
+            .append(true)
+            .create(true)
+            .open(self.path())?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+}
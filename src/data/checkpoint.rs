@@ -0,0 +1,36 @@
+use crate::Result;
+use serde::{Deserialize, Serialize};
+
+/// Directory for local state that should survive restarts.
+pub const CACHE_DIR: &'static str = "./cache";
+const CHECKPOINT_FILE: &'static str = "checkpoint.json";
+
+/// The latest event processed by `scan`, such that every event up to its timestamp is done. Saved
+/// as results are written so that an interrupted scan can be resumed with `--resume`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Checkpoint {
+    pub block: u64,
+    pub timestamp: u64,
+}
+
+impl Checkpoint {
+    fn path() -> String {
+        format!("{}/{}", CACHE_DIR, CHECKPOINT_FILE)
+    }
+
+    /// Loads the last saved checkpoint. Returns None if no checkpoint has been saved.
+    pub async fn load() -> Result<Option<Self>> {
+        match tokio::fs::read(Self::path()).await {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn save(&self) -> Result<()> {
+        tokio::fs::create_dir_all(CACHE_DIR).await?;
+        tokio::fs::write(Self::path(), serde_json::to_vec_pretty(self)?).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    data::{
+        arbs::ArbWriter,
+        file::{parse_filename, EXPORT_DIR},
+        units::{format_amount, AmountUnits, ETH_DECIMALS},
+    },
+    info,
+    interfaces::{PoolVariant, SearchBound, SimArbResultBatch},
+    Result,
+};
+use async_trait::async_trait;
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+
+/// One simulated backrun, flattened for spreadsheets.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CsvRow {
+    tx_hash: H256,
+    block: u64,
+    timestamp: u64,
+    token: Address,
+    token_symbol: Option<String>,
+    token_decimals: Option<u8>,
+    pool_variant: PoolVariant,
+    start_pool: Address,
+    end_pool: Address,
+    /// Pool prices (TKN per WETH) that picked the arb's direction; see `BackrunResult`.
+    user_pool_price: String,
+    /// `<column>_units` columns hold the preceding amount in whole units; they're only written
+    /// w/ `AmountUnits::Both`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_pool_price_units: Option<String>,
+    alt_pool_price: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alt_pool_price_units: Option<String>,
+    /// Price of the user's pool before the user's tx; zero if unknown.
+    user_pool_price_before: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_pool_price_before_units: Option<String>,
+    /// Empty if the price before the user's tx is unknown.
+    price_impact_bps: Option<u64>,
+    amount_in: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    amount_in_units: Option<String>,
+    balance_end: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    balance_end_units: Option<String>,
+    profit: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    profit_units: Option<String>,
+    /// Empty if the arb wasn't priced in USD.
+    eth_usd_price: Option<f64>,
+    profit_usd: Option<f64>,
+    gas_used_buy: u64,
+    gas_used_sell: u64,
+    /// Always in wei.
+    effective_gas_price: String,
+    search_bound: Option<SearchBound>,
+    search_truncated: bool,
+    /// Where in its block the backrun was simulated, e.g. `top-of-block`.
+    placement: String,
+}
+
+/// Saves arbs to a CSV file; each row is one `SimArbResult`.
+#[derive(Clone, Debug)]
+pub struct CsvWriter {
+    pub filename: String,
+    /// How amounts & prices are rendered. Gas prices are always in wei.
+    pub units: AmountUnits,
+}
+
+impl CsvWriter {
+    pub fn new(filename: Option<String>, units: AmountUnits) -> Self {
+        CsvWriter {
+            filename: parse_filename(filename, &[".csv"]).expect("failed to parse filename"),
+            units,
+        }
+    }
+
+    /// Path of the export file, relative to the working directory.
+    pub fn path(&self) -> String {
+        format!("{}/{}", EXPORT_DIR, self.filename)
+    }
+
+    /// Renders an amount (or price) w/ 18 decimals for its column.
+    fn render_amount(&self, amount: U256) -> String {
+        if self.units.raw() {
+            amount.to_string()
+        } else {
+            format_amount(amount, ETH_DECIMALS)
+        }
+    }
+
+    /// Renders an amount for its `_units` column, if it has one.
+    fn render_units(&self, amount: U256) -> Option<String> {
+        (self.units == AmountUnits::Both).then(|| format_amount(amount, ETH_DECIMALS))
+    }
+
+    fn to_rows(&self, arb: &SimArbResultBatch) -> Vec<CsvRow> {
+        arb.results
+            .iter()
+            .map(|res| CsvRow {
+                tx_hash: arb.event.hint.hash,
+                block: arb.event.block,
+                timestamp: arb.event.timestamp,
+                token: res.user_trade.tokens.token,
+                token_symbol: res.user_trade.token_symbol.to_owned(),
+                token_decimals: res.user_trade.token_decimals,
+                pool_variant: res.user_trade.pool_variant,
+                start_pool: res.backrun_trade.start_pool,
+                end_pool: res.backrun_trade.end_pool,
+                user_pool_price: self.render_amount(res.backrun_trade.user_pool_price),
+                user_pool_price_units: self.render_units(res.backrun_trade.user_pool_price),
+                alt_pool_price: self.render_amount(res.backrun_trade.alt_pool_price),
+                alt_pool_price_units: self.render_units(res.backrun_trade.alt_pool_price),
+                user_pool_price_before: self.render_amount(res.user_trade.price_before),
+                user_pool_price_before_units: self.render_units(res.user_trade.price_before),
+                price_impact_bps: res.user_trade.price_impact_bps,
+                amount_in: self.render_amount(res.backrun_trade.amount_in),
+                amount_in_units: self.render_units(res.backrun_trade.amount_in),
+                balance_end: self.render_amount(res.backrun_trade.balance_end),
+                balance_end_units: self.render_units(res.backrun_trade.balance_end),
+                profit: self.render_amount(res.backrun_trade.profit),
+                profit_units: self.render_units(res.backrun_trade.profit),
+                eth_usd_price: arb.eth_usd_price,
+                profit_usd: arb.to_usd(res.backrun_trade.profit),
+                gas_used_buy: res.backrun_trade.gas_used_buy,
+                gas_used_sell: res.backrun_trade.gas_used_sell,
+                effective_gas_price: res.backrun_trade.effective_gas_price.to_string(),
+                search_bound: res.backrun_trade.search_bound,
+                search_truncated: res.backrun_trade.search_truncated,
+                placement: arb.placement.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl ArbWriter for CsvWriter {
+    /// Append arbs to the CSV file, writing the header first if the file is new.
+    async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        tokio::fs::create_dir_all(EXPORT_DIR).await?;
+        let filename = self.path();
+        let rows = arbs
+            .iter()
+            .flat_map(|arb| self.to_rows(arb))
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            info!("no arbs found to export.");
+            return Ok(());
+        }
+        info!("exporting {} rows to file {}...", rows.len(), filename);
+        let file = File::options().append(true).create(true).open(&filename)?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file);
+        for row in rows {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+    use rand::Rng;
+
+    #[tokio::test]
+    async fn it_writes_csv_rows() -> Result<()> {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        let writer = CsvWriter::new(Some(format!("test_arbs_{}", rnum)), AmountUnits::Eth);
+        let mut arb = SimArbResultBatch::test_example();
+        arb.results = vec![SimArbResult::test_example()];
+        writer.write_arbs(&vec![arb.clone()]).await?;
+        writer.write_arbs(&vec![arb.clone()]).await?;
+
+        let mut reader = csv::Reader::from_path(writer.path())?;
+        let headers = reader.headers()?.clone();
+        let rows = reader
+            .deserialize::<CsvRow>()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        tokio::fs::remove_file(writer.path()).await?;
+
+        assert_eq!(
+            headers.iter().collect::<Vec<_>>(),
+            vec![
+                "tx_hash",
+                "block",
+                "timestamp",
+                "token",
+                "token_symbol",
+                "token_decimals",
+                "pool_variant",
+                "start_pool",
+                "end_pool",
+                "user_pool_price",
+                "alt_pool_price",
+                "user_pool_price_before",
+                "price_impact_bps",
+                "amount_in",
+                "balance_end",
+                "profit",
+                "eth_usd_price",
+                "profit_usd",
+                "gas_used_buy",
+                "gas_used_sell",
+                "effective_gas_price",
+                "search_bound",
+                "search_truncated",
+                "placement"
+            ]
+        );
+        assert_eq!(rows.len(), 2);
+        let backrun = &arb.results[0].backrun_trade;
+        assert_eq!(rows[0].tx_hash, arb.event.hint.hash);
+        assert_eq!(rows[0].block, arb.event.block);
+        assert_eq!(rows[0].token_symbol, Some("TKN".to_owned()));
+        assert_eq!(rows[0].start_pool, backrun.start_pool);
+        assert_eq!(
+            rows[0].alt_pool_price,
+            format_amount(backrun.alt_pool_price, ETH_DECIMALS)
+        );
+        assert_eq!(rows[0].price_impact_bps, Some(454));
+        assert_eq!(
+            rows[0].amount_in,
+            format_amount(backrun.amount_in, ETH_DECIMALS)
+        );
+        assert_eq!(rows[0].profit, format_amount(backrun.profit, ETH_DECIMALS));
+        assert_eq!(rows[0].eth_usd_price, arb.eth_usd_price);
+        assert_eq!(rows[0].profit_usd, arb.to_usd(backrun.profit));
+        assert_eq!(rows[0].gas_used_buy, backrun.gas_used_buy);
+        assert_eq!(
+            rows[0].effective_gas_price,
+            backrun.effective_gas_price.to_string()
+        );
+        Ok(())
+    }
+    #[tokio::test]
+    async fn it_writes_raw_and_whole_units_side_by_side() -> Result<()> {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        let writer = CsvWriter::new(Some(format!("test_arbs_{}", rnum)), AmountUnits::Both);
+        let mut arb = SimArbResultBatch::test_example();
+        let mut res = SimArbResult::test_example();
+        // more digits than an f64 holds
+        res.backrun_trade.amount_in = U256::from_dec_str("123456789012345678901234567").unwrap();
+        arb.results = vec![res];
+        writer.write_arbs(&vec![arb]).await?;
+
+        let mut reader = csv::Reader::from_path(writer.path())?;
+        let headers = reader.headers()?.clone();
+        let rows = reader
+            .deserialize::<CsvRow>()
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        tokio::fs::remove_file(writer.path()).await?;
+
+        let headers = headers.iter().collect::<Vec<_>>();
+        let amount_in = headers.iter().position(|h| *h == "amount_in").unwrap();
+        assert_eq!(headers[amount_in + 1], "amount_in_units");
+        assert_eq!(headers.len(), 30);
+        assert_eq!(rows[0].amount_in, "123456789012345678901234567");
+        assert_eq!(
+            rows[0].amount_in_units.as_deref(),
+            Some("123456789.012345678901234567")
+        );
+        Ok(())
+    }
+}
@@ -1,39 +1,61 @@
 use crate::{
+    client::redact_url,
+    config::Config,
     data::{
         arbs::ArbDatabase,
+        file::{FileConfig, FileWriter},
         mongo::{MongoConfig, MongoConnect},
         postgres::{PostgresConfig, PostgresConnect},
+        sqlite::{SqliteConfig, SqliteConnect},
     },
     Result,
 };
 use std::sync::Arc;
-use strum::{EnumIter, IntoEnumIterator};
 
 pub struct Db {
     pub connect: ArbDatabase,
 }
 
-#[derive(Clone, Debug, EnumIter)]
+#[derive(Clone, Debug)]
 pub enum DbEngine {
     Mongo(MongoConfig),
     Postgres(PostgresConfig),
+    File(FileConfig),
+    Sqlite(SqliteConfig),
 }
 
+/// Names of all DB engines, as accepted by `DbEngine::from_str`.
+const DB_ENGINE_NAMES: [&'static str; 4] = ["mongo", "postgres", "file", "sqlite"];
+
 impl DbEngine {
     pub fn enum_flags() -> String {
-        format!(
-            "{}",
-            DbEngine::iter()
-                .map(|engine| engine.to_string())
-                .reduce(|a, b| format!("{} | {}", a, b))
-                .expect("failed to reduce db engines to string")
-        )
+        DB_ENGINE_NAMES.join(" | ")
+    }
+
+    /// Whether mongo & file DBs save arbs for events that are already saved (rather than
+    /// replacing or skipping them). Postgres & SQLite tables are keyed on the event's tx hash,
+    /// so they always replace them.
+    pub fn allowing_duplicates(mut self, allow_duplicates: bool) -> Self {
+        match &mut self {
+            DbEngine::Mongo(config) => config.allow_duplicates = allow_duplicates,
+            DbEngine::File(config) => config.allow_duplicates = allow_duplicates,
+            DbEngine::Postgres(_) | DbEngine::Sqlite(_) => {}
+        }
+        self
     }
 }
 
 impl Default for DbEngine {
+    /// Mongo if `MONGO_URL` is set, otherwise Postgres if `POSTGRES_URL` is set, otherwise SQLite.
     fn default() -> Self {
-        DbEngine::Mongo(MongoConfig::default())
+        let config = Config::default();
+        if config.mongo_url.is_some() {
+            DbEngine::Mongo(MongoConfig::default())
+        } else if config.postgres_url.is_some() {
+            DbEngine::Postgres(PostgresConfig::default())
+        } else {
+            DbEngine::Sqlite(SqliteConfig::default())
+        }
     }
 }
 
@@ -42,6 +64,8 @@ impl std::fmt::Display for DbEngine {
         match self {
             DbEngine::Mongo(_) => write!(f, "mongo"),
             DbEngine::Postgres(_) => write!(f, "postgres"),
+            DbEngine::File(_) => write!(f, "file"),
+            DbEngine::Sqlite(_) => write!(f, "sqlite"),
         }
     }
 }
@@ -53,6 +77,8 @@ impl std::str::FromStr for DbEngine {
         match s {
             "mongo" => Ok(DbEngine::Mongo(MongoConfig::default())),
             "postgres" => Ok(DbEngine::Postgres(PostgresConfig::default())),
+            "file" => Ok(DbEngine::File(FileConfig::default())),
+            "sqlite" => Ok(DbEngine::Sqlite(SqliteConfig::default())),
             _ => Err(format!("invalid db engine: {}", s)),
         }
     }
@@ -62,19 +88,34 @@ impl Db {
     pub async fn new(engine: DbEngine) -> Self {
         match engine {
             DbEngine::Mongo(config) => Db {
-                connect: Arc::new(
-                    MongoConnect::new(config.to_owned())
-                        .await
-                        .expect(&format!("failed to connect to mongo db at {}", config.url)),
-                ),
+                connect: Arc::new(MongoConnect::new(config.to_owned()).await.expect(&format!(
+                    "failed to connect to mongo db at {}",
+                    redact_url(config.url.expose())
+                ))),
             },
             DbEngine::Postgres(config) => {
                 Db {
                     connect: Arc::new(PostgresConnect::new(config.to_owned()).await.expect(
-                        &format!("failed to connect to postgres db at {:?}", config.url),
+                        &format!(
+                            "failed to connect to postgres db at {}",
+                            redact_url(config.url.expose())
+                        ),
                     )),
                 }
             }
+            DbEngine::File(config) => Db {
+                connect: Arc::new(
+                    FileWriter::new(Some(config.filename))
+                        .allowing_duplicates(config.allow_duplicates),
+                ),
+            },
+            DbEngine::Sqlite(config) => Db {
+                connect: Arc::new(
+                    SqliteConnect::new(config.to_owned())
+                        .await
+                        .expect(&format!("failed to open sqlite db at {:?}", config.path)),
+                ),
+            },
         }
     }
 }
@@ -0,0 +1,383 @@
+use crate::{data::checkpoint::CACHE_DIR, warn, Result};
+use ethers::types::H256;
+use mev_share_sse::EventHistory;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+const EVENTS_FILE: &'static str = "events.ndjson";
+const CURSOR_FILE: &'static str = "events_cursor.json";
+
+/// The latest event saved to the event cache, so the next fetch can start from there.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EventCursor {
+    pub block: u64,
+    pub timestamp: u64,
+    /// Hashes of the saved events at `timestamp`. Fetches resume from `timestamp` (in case it
+    /// was only partially fetched), so these are skipped rather than saved twice.
+    pub hashes: Vec<H256>,
+}
+
+impl EventCursor {
+    /// Moves the cursor forward to `event`, if it's not older than the cursor.
+    fn advance(&mut self, event: &EventHistory) {
+        if event.timestamp > self.timestamp {
+            *self = Self::from(event);
+        } else if event.timestamp == self.timestamp && !self.hashes.contains(&event.hint.hash) {
+            self.hashes.push(event.hint.hash);
+        }
+    }
+}
+
+impl From<&EventHistory> for EventCursor {
+    fn from(event: &EventHistory) -> Self {
+        Self {
+            block: event.block,
+            timestamp: event.timestamp,
+            hashes: vec![event.hint.hash],
+        }
+    }
+}
+
+/// Summary of the events in an `EventCache`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventCacheStats {
+    /// Number of (unique) events saved.
+    pub events: usize,
+    /// Number of lines that aren't valid events, e.g. from an interrupted write.
+    pub corrupted: usize,
+    /// (first, last) block of the saved events.
+    pub block_range: Option<(u64, u64)>,
+    /// Size of the events file, in bytes.
+    pub size: u64,
+    pub cursor: Option<EventCursor>,
+}
+
+/// Events read from the events file, along with the number of lines that couldn't be parsed.
+struct EventLines {
+    events: Vec<EventHistory>,
+    corrupted: usize,
+}
+
+/// MEV-Share events saved to disk as newline-delimited JSON, with a cursor marking the latest one.
+#[derive(Clone, Debug)]
+pub struct EventCache {
+    dir: PathBuf,
+}
+
+impl Default for EventCache {
+    fn default() -> Self {
+        Self::new(CACHE_DIR)
+    }
+}
+
+impl EventCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn events_path(&self) -> PathBuf {
+        self.dir.join(EVENTS_FILE)
+    }
+
+    fn cursor_path(&self) -> PathBuf {
+        self.dir.join(CURSOR_FILE)
+    }
+
+    /// Loads the cursor. Returns None if no events have been saved, or if the cursor file is
+    /// corrupted, in which case the next fetch starts over (`load_events` skips duplicates).
+    pub fn load_cursor(&self) -> Result<Option<EventCursor>> {
+        match std::fs::read(self.cursor_path()) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(cursor) => Ok(Some(cursor)),
+                Err(err) => {
+                    warn!(
+                        "ignoring corrupted event cursor {}: {}",
+                        self.cursor_path().display(),
+                        err
+                    );
+                    Ok(None)
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Reads every event in the events file, skipping (and counting) lines that aren't events.
+    fn read_lines(&self) -> Result<EventLines> {
+        let file = match std::fs::File::open(self.events_path()) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(EventLines {
+                    events: vec![],
+                    corrupted: 0,
+                })
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut events = vec![];
+        let mut corrupted = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => events.push(event),
+                Err(_) => corrupted += 1,
+            }
+        }
+        Ok(EventLines { events, corrupted })
+    }
+
+    /// Rewrites the events file with only the events that `keep` returns true for, dropping
+    /// corrupted lines. Returns the number of events removed.
+    fn retain(&self, mut keep: impl FnMut(&EventHistory) -> bool) -> Result<usize> {
+        let lines = self.read_lines()?;
+        let num_events = lines.events.len();
+        let kept = lines
+            .events
+            .into_iter()
+            .filter(|event| keep(event))
+            .collect::<Vec<_>>();
+        if kept.len() == num_events && lines.corrupted == 0 {
+            return Ok(0);
+        }
+        // written to a temp file first, so an interrupted rewrite can't lose events
+        let tmp_path = self.events_path().with_extension("ndjson.tmp");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&tmp_path)?);
+        for event in &kept {
+            writeln!(file, "{}", serde_json::to_string(event)?)?;
+        }
+        file.flush()?;
+        std::fs::rename(tmp_path, self.events_path())?;
+        Ok(num_events - kept.len())
+    }
+
+    /// Appends `events` to the cache and advances the cursor, skipping events already saved at
+    /// the cursor's timestamp. Returns the number of events saved.
+    pub fn append(&self, events: &[EventHistory]) -> Result<usize> {
+        let mut cursor = self.load_cursor()?;
+        let mut lines = vec![];
+        for event in events {
+            if let Some(cursor) = &cursor {
+                if event.timestamp == cursor.timestamp && cursor.hashes.contains(&event.hint.hash) {
+                    continue;
+                }
+            }
+            lines.push(serde_json::to_string(event)?);
+            match &mut cursor {
+                Some(cursor) => cursor.advance(event),
+                None => cursor = Some(EventCursor::from(event)),
+            }
+        }
+        if lines.is_empty() {
+            return Ok(0);
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let mut file = std::fs::File::options()
+            .append(true)
+            .create(true)
+            .open(self.events_path())?;
+        for line in &lines {
+            writeln!(file, "{}", line)?;
+        }
+        // events are saved before the cursor, so an interrupted write can only cause re-fetches
+        if let Some(cursor) = cursor {
+            std::fs::write(self.cursor_path(), serde_json::to_vec_pretty(&cursor)?)?;
+        }
+        Ok(lines.len())
+    }
+
+    /// Loads the cached events landed in blocks `block_start..=block_end`, without duplicates.
+    ///
+    /// Corrupted lines are skipped with a warning; `drop_corrupted` removes them for good.
+    pub fn load_events(
+        &self,
+        block_start: Option<u64>,
+        block_end: Option<u64>,
+    ) -> Result<Vec<EventHistory>> {
+        let lines = self.read_lines()?;
+        if lines.corrupted > 0 {
+            warn!(
+                "skipped {} corrupted lines in {}",
+                lines.corrupted,
+                self.events_path().display()
+            );
+        }
+        let mut seen = HashSet::new();
+        Ok(lines
+            .events
+            .into_iter()
+            .filter(|event| {
+                block_start.map_or(true, |block| event.block >= block)
+                    && block_end.map_or(true, |block| event.block <= block)
+                    && seen.insert(event.hint.hash)
+            })
+            .collect())
+    }
+
+    pub fn stats(&self) -> Result<EventCacheStats> {
+        let lines = self.read_lines()?;
+        let hashes = lines
+            .events
+            .iter()
+            .map(|event| event.hint.hash)
+            .collect::<HashSet<_>>();
+        let blocks = lines.events.iter().map(|event| event.block);
+        Ok(EventCacheStats {
+            events: hashes.len(),
+            corrupted: lines.corrupted,
+            block_range: blocks.clone().min().zip(blocks.max()),
+            size: std::fs::metadata(self.events_path()).map_or(0, |meta| meta.len()),
+            cursor: self.load_cursor()?,
+        })
+    }
+
+    /// Removes events landed before `block`. Returns the number of events removed.
+    pub fn evict_before(&self, block: u64) -> Result<usize> {
+        self.retain(|event| event.block >= block)
+    }
+
+    /// Removes lines that aren't valid events. Returns the number of lines removed.
+    pub fn drop_corrupted(&self) -> Result<usize> {
+        let corrupted = self.read_lines()?.corrupted;
+        if corrupted > 0 {
+            self.retain(|_| true)?;
+        }
+        Ok(corrupted)
+    }
+
+    /// Replaces the events landed in blocks `block_start..=block_end` with `events`.
+    /// Returns the number of events saved.
+    pub fn replace_range(
+        &self,
+        block_start: u64,
+        block_end: u64,
+        events: &[EventHistory],
+    ) -> Result<usize> {
+        self.retain(|event| event.block < block_start || event.block > block_end)?;
+        // removed events at the cursor's timestamp would otherwise be skipped as already saved
+        if let Some(mut cursor) = self.load_cursor()? {
+            let num_hashes = cursor.hashes.len();
+            cursor
+                .hashes
+                .retain(|hash| !events.iter().any(|event| event.hint.hash == *hash));
+            if cursor.hashes.len() != num_hashes {
+                std::fs::write(self.cursor_path(), serde_json::to_vec_pretty(&cursor)?)?;
+            }
+        }
+        self.append(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mev_share_sse::Hint;
+
+    fn event(block: u64, timestamp: u64, hash: u64) -> EventHistory {
+        EventHistory {
+            block,
+            timestamp,
+            hint: Hint {
+                txs: vec![],
+                hash: H256::from_low_u64_be(hash),
+                logs: vec![],
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        }
+    }
+
+    #[test]
+    fn it_caches_events_with_cursor() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "hindsight_event_cache_test_{}",
+            rand::random::<u64>()
+        ));
+        let cache = EventCache::new(&dir);
+        assert_eq!(cache.load_cursor()?, None);
+
+        assert_eq!(cache.append(&[event(1, 10, 1), event(2, 20, 2)])?, 2);
+        assert_eq!(
+            cache.load_cursor()?,
+            Some(EventCursor {
+                block: 2,
+                timestamp: 20,
+                hashes: vec![H256::from_low_u64_be(2)],
+            })
+        );
+
+        // the next fetch resumes from the cursor's timestamp; its events are only saved once
+        assert_eq!(cache.append(&[event(2, 20, 2), event(2, 20, 3)])?, 1);
+        assert_eq!(cache.load_cursor()?.unwrap().hashes.len(), 2);
+        assert_eq!(cache.append(&[event(3, 30, 4)])?, 1);
+
+        // re-fetching an older window doesn't move the cursor back or duplicate loaded events
+        assert_eq!(cache.append(&[event(1, 10, 1)])?, 1);
+        assert_eq!(cache.load_cursor()?.unwrap().timestamp, 30);
+        assert_eq!(cache.load_events(None, None)?.len(), 4);
+        assert_eq!(
+            cache
+                .load_events(Some(2), Some(2))?
+                .iter()
+                .map(|event| event.hint.hash.to_low_u64_be())
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_maintains_event_cache() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!(
+            "hindsight_event_cache_test_{}",
+            rand::random::<u64>()
+        ));
+        let cache = EventCache::new(&dir);
+        assert_eq!(cache.stats()?, EventCacheStats::default());
+        cache.append(&[event(1, 10, 1), event(2, 20, 2), event(3, 30, 3)])?;
+
+        // an interrupted write leaves a partial line; it's skipped rather than failing the load
+        let mut file = std::fs::File::options()
+            .append(true)
+            .open(cache.events_path())?;
+        writeln!(file, "{{\"block\":4,\"timest")?;
+        assert_eq!(cache.load_events(None, None)?.len(), 3);
+        let stats = cache.stats()?;
+        assert_eq!(stats.events, 3);
+        assert_eq!(stats.corrupted, 1);
+        assert_eq!(stats.block_range, Some((1, 3)));
+        assert_eq!(cache.drop_corrupted()?, 1);
+        assert_eq!(cache.stats()?.corrupted, 0);
+
+        // refreshing the latest block replaces its events, even those listed in the cursor
+        assert_eq!(
+            cache.replace_range(3, 3, &[event(3, 30, 3), event(3, 30, 4)])?,
+            2
+        );
+        assert_eq!(cache.stats()?.events, 4);
+        assert_eq!(cache.evict_before(3)?, 2);
+        assert_eq!(
+            cache
+                .load_events(None, None)?
+                .iter()
+                .map(|event| event.hint.hash.to_low_u64_be())
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+
+        // a corrupted cursor is ignored, so the next fetch starts over
+        std::fs::write(cache.cursor_path(), "{")?;
+        assert_eq!(cache.load_cursor()?, None);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
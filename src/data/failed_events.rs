@@ -0,0 +1,186 @@
+use crate::{data::audit::FailureReason, data::checkpoint::CACHE_DIR, Error, Result};
+use ethers::types::H256;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+const FAILED_EVENTS_FILE: &'static str = "failed_events.ndjson";
+
+/// An event that didn't produce a batch of results, as saved for `retry-failed`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FailedEvent {
+    pub event_hash: H256,
+    pub block: Option<u64>,
+    pub reason: FailureReason,
+    /// The last attempt's error message.
+    pub error: String,
+    /// Number of times the event has failed.
+    pub attempts: u32,
+}
+
+impl FailedEvent {
+    /// Whether the event failed for reasons that may not happen again (transport errors &
+    /// timeouts), so it's worth re-simulating.
+    pub fn retryable(&self) -> bool {
+        matches!(
+            self.reason,
+            FailureReason::Transport | FailureReason::Timeout
+        )
+    }
+}
+
+/// Events that failed to simulate, saved on disk (one JSON line per failure) so they can be
+/// re-processed later.
+///
+/// Each failure is appended; the latest line for an event wins when the store is loaded.
+/// Removing events rewrites the file w/o them.
+pub struct FailedEvents {
+    path: PathBuf,
+    events: Mutex<HashMap<H256, FailedEvent>>,
+}
+
+impl FailedEvents {
+    pub fn default_path() -> PathBuf {
+        Path::new(CACHE_DIR).join(FAILED_EVENTS_FILE)
+    }
+
+    /// Opens the store at `path`, loading the events saved there, if any.
+    /// Lines that aren't valid entries (e.g. from an interrupted write) are skipped.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let mut events = HashMap::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    if let Ok(event) = serde_json::from_str::<FailedEvent>(&line?) {
+                        events.insert(event.event_hash, event);
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        Ok(Self {
+            path,
+            events: Mutex::new(events),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn events(&self) -> MutexGuard<HashMap<H256, FailedEvent>> {
+        self.events.lock().expect("failed events poisoned")
+    }
+
+    /// The saved events, oldest block first.
+    pub fn list(&self) -> Vec<FailedEvent> {
+        let mut events = self.events().values().cloned().collect::<Vec<_>>();
+        events.sort_by_key(|event| (event.block, event.event_hash));
+        events
+    }
+
+    /// Saves that the event `event_hash` (landed in `block`) failed w/ `err`, counting the attempt.
+    pub fn record(&self, event_hash: H256, block: Option<u64>, err: &Error) -> Result<FailedEvent> {
+        let mut events = self.events();
+        let attempts = events.get(&event_hash).map_or(0, |event| event.attempts) + 1;
+        let event = FailedEvent {
+            event_hash,
+            block,
+            reason: err.into(),
+            error: err.to_string(),
+            attempts,
+        };
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_vec(&event)?;
+        line.push(b'\n');
+        // appended while the lock is held, so lines for the same event stay in order
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)?;
+        events.insert(event_hash, event.to_owned());
+        Ok(event)
+    }
+
+    /// Removes `event_hashes` from the store, e.g. once they've been simulated.
+    /// Returns how many were removed; the file is only rewritten if any were.
+    pub fn remove(&self, event_hashes: &[H256]) -> Result<usize> {
+        let mut events = self.events();
+        let removed = event_hashes
+            .iter()
+            .filter(|hash| events.remove(*hash).is_some())
+            .count();
+        if removed == 0 {
+            return Ok(0);
+        }
+        let mut data = vec![];
+        for event in events.values() {
+            data.append(&mut serde_json::to_vec(event)?);
+            data.push(b'\n');
+        }
+        // written aside then renamed over the old file, so an interruption can't lose entries
+        let tmp_path = self.path.with_extension("ndjson.tmp");
+        std::fs::write(&tmp_path, data)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::HindsightError;
+
+    #[test]
+    fn it_records_and_removes_failed_events() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "hindsight_failed_events_test_{}.ndjson",
+            rand::random::<u64>()
+        ));
+        let store = FailedEvents::open(path.to_owned())?;
+        let timed_out = H256::from_low_u64_be(0xf00d);
+        let no_pool = H256::from_low_u64_be(0xbeef);
+        let timeout: Error = HindsightError::Timeout(timed_out).into();
+        store.record(timed_out, Some(17_637_019), &timeout)?;
+        store.record(
+            no_pool,
+            Some(17_637_018),
+            &HindsightError::NoArbPool(no_pool).into(),
+        )?;
+        store.record(timed_out, Some(17_637_019), &timeout)?;
+
+        // later runs load what earlier ones saved
+        let store = FailedEvents::open(path.to_owned())?;
+        let events = store.list();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_hash, no_pool);
+        assert!(!events[0].retryable());
+        assert_eq!(events[1].attempts, 2);
+        assert_eq!(events[1].reason, FailureReason::Timeout);
+        assert!(events[1].retryable());
+
+        assert_eq!(store.remove(&[timed_out, H256::zero()])?, 1);
+        assert_eq!(store.remove(&[timed_out])?, 0);
+        let store = FailedEvents::open(path.to_owned())?;
+        assert_eq!(
+            store
+                .list()
+                .iter()
+                .map(|event| event.event_hash)
+                .collect::<Vec<_>>(),
+            vec![no_pool]
+        );
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
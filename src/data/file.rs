@@ -1,47 +1,194 @@
 use crate::{
-    data::arbs::{ArbDb, ArbFilterParams, WriteEngine},
+    data::{
+        arbs::{
+            analyze_arbs_core, covered_ranges, export_arbs_core, ArbFilterParams, ArbReader,
+            ArbWriter, SortOrder,
+        },
+        report::ArbReport,
+        units::{render_arb, AmountUnits},
+    },
     info,
-    interfaces::{SimArbResultBatch, StoredArbsRanges},
+    interfaces::{BlockRange, SimArbResultBatch, StoredArbsRanges},
     Result,
 };
 use async_trait::async_trait;
+use ethers::types::H256;
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde_json::Value;
 use std::{
+    collections::{BTreeSet, HashSet},
     fs::File,
-    io::{BufWriter, Write},
+    io::{BufWriter, Read, Write},
+    sync::Arc,
 };
+use tokio::sync::Mutex;
 
 pub const EXPORT_DIR: &'static str = "./arbData";
+/// First two bytes of every gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-fn parse_filename(filename: Option<String>) -> Result<String> {
+/// Returns `filename`, or a timestamped default, ensuring it ends with one of `extensions`.
+/// The first extension is the default.
+pub(crate) fn parse_filename(filename: Option<String>, extensions: &[&str]) -> Result<String> {
     let filename = filename.unwrap_or(format!(
-        "arbs_{}.json",
+        "arbs_{}{}",
         std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs()
+            .as_secs(),
+        extensions[0]
     ));
-    Ok(if filename.ends_with(".json") {
+    Ok(if extensions.iter().any(|ext| filename.ends_with(ext)) {
         filename.to_owned()
     } else {
-        format!("{}.json", filename)
+        format!("{}{}", filename, extensions[0])
     })
 }
 
+/// Parses arbs from a file's contents. Accepts newline-delimited JSON (one arb per line),
+/// as well as JSON arrays of arbs (including several arrays written back-to-back by older versions).
+fn parse_arbs(data: &str) -> Result<Vec<SimArbResultBatch>> {
+    let mut arbs = vec![];
+    for value in serde_json::Deserializer::from_str(data).into_iter::<Value>() {
+        match value? {
+            Value::Array(values) => {
+                for value in values {
+                    arbs.push(serde_json::from_value(value)?);
+                }
+            }
+            value => arbs.push(serde_json::from_value(value)?),
+        }
+    }
+    Ok(arbs)
+}
+
+/// Decompresses a file's contents if they're gzipped (judging by their magic bytes).
+fn decode_file(data: Vec<u8>) -> Result<String> {
+    if data.starts_with(&GZIP_MAGIC) {
+        // every write appends a gzip member, so read them all
+        let mut decoded = String::new();
+        MultiGzDecoder::new(data.as_slice()).read_to_string(&mut decoded)?;
+        Ok(decoded)
+    } else {
+        Ok(String::from_utf8(data)?)
+    }
+}
+
+/// Writes `arbs` as newline-delimited JSON, w/ their amounts in `units`.
+fn write_ndjson<W: Write>(
+    writer: &mut W,
+    arbs: &[&SimArbResultBatch],
+    units: AmountUnits,
+) -> Result<()> {
+    for arb in arbs {
+        if units.whole() {
+            serde_json::to_writer(&mut *writer, &render_arb(arb, units)?)?;
+        } else {
+            serde_json::to_writer(&mut *writer, arb)?;
+        }
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Config for using a local file as the arb DB.
+#[derive(Clone, Debug)]
+pub struct FileConfig {
+    pub filename: String,
+    /// Append arbs for events that are already in the file, rather than skipping them.
+    pub allow_duplicates: bool,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            filename: "arbs.ndjson".to_owned(),
+            allow_duplicates: false,
+        }
+    }
+}
+
+/// Saves arbs to a newline-delimited JSON file; each line is one `SimArbResultBatch`.
+/// Files named `*.gz` are gzip-compressed.
+///
+/// Arbs for events that are already in the file are skipped, unless duplicates are allowed.
 #[derive(Clone, Debug)]
 pub struct FileWriter {
     pub filename: String,
+    pub allow_duplicates: bool,
+    /// How amounts are rendered; see `with_units`.
+    pub units: AmountUnits,
+    /// Tx hashes of the events saved in the file; loaded on the first write.
+    /// Also serializes writes, so concurrent writers can't both save an event.
+    saved_events: Arc<Mutex<Option<HashSet<H256>>>>,
 }
 
 impl FileWriter {
     pub fn new(filename: Option<String>) -> Self {
-        return FileWriter {
-            filename: parse_filename(filename).expect("failed to parse filename"),
-        };
+        Self::with_extensions(filename, &[".ndjson", ".json", ".gz"])
+    }
+
+    /// Saves arbs to a gzip-compressed file. `.ndjson.gz` is appended to `filename` unless it ends with `.gz`.
+    pub fn gzipped(filename: Option<String>) -> Self {
+        Self::with_extensions(filename, &[".ndjson.gz", ".gz"])
+    }
+
+    fn with_extensions(filename: Option<String>, extensions: &[&str]) -> Self {
+        FileWriter {
+            filename: parse_filename(filename, extensions).expect("failed to parse filename"),
+            allow_duplicates: false,
+            units: AmountUnits::default(),
+            saved_events: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Whether arbs are gzipped when they're written.
+    pub fn is_gzipped(&self) -> bool {
+        self.filename.ends_with(".gz")
+    }
+
+    /// Append arbs for events that are already in the file, rather than skipping them.
+    pub fn allowing_duplicates(mut self, allow_duplicates: bool) -> Self {
+        self.allow_duplicates = allow_duplicates;
+        self
+    }
+
+    /// Render amounts in `units` (raw by default). Amounts in whole units (`AmountUnits::Eth`)
+    /// replace the raw ones, so the file can't be read back as arbs (e.g. w/ `--db file`).
+    pub fn with_units(mut self, units: AmountUnits) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Path of the export file, relative to the working directory.
+    pub fn path(&self) -> String {
+        format!("{}/{}", EXPORT_DIR, self.filename)
     }
 
     pub async fn save_arbs_to_file(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
         // create EXPORT_DIR if it doesn't exist
         tokio::fs::create_dir_all(EXPORT_DIR).await?;
-        let filename = format!("{}/{}", EXPORT_DIR, self.filename);
+        let filename = self.path();
+        let mut saved_events = self.saved_events.lock().await;
+        if saved_events.is_none() && !self.allow_duplicates {
+            *saved_events = Some(
+                self.load_arbs_from_file()
+                    .await?
+                    .iter()
+                    .map(|arb| arb.event.hint.hash)
+                    .collect(),
+            );
+        }
+        let mut new_events = HashSet::new();
+        let arbs = arbs
+            .iter()
+            .filter(|arb| match saved_events.as_ref() {
+                Some(saved_events) => {
+                    !saved_events.contains(&arb.event.hint.hash)
+                        && new_events.insert(arb.event.hint.hash)
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
         if arbs.len() > 0 {
             info!("exporting {} arbs to file {}...", arbs.len(), filename);
             let file = File::options()
@@ -49,44 +196,364 @@ impl FileWriter {
                 .create(true)
                 .open(filename.to_owned())?;
             let mut writer = BufWriter::new(file);
-            serde_json::to_writer_pretty(&mut writer, &arbs)?;
+            if self.is_gzipped() {
+                // each write appends a gzip member; readers decode them as one stream
+                let mut encoder = GzEncoder::new(&mut writer, Compression::default());
+                write_ndjson(&mut encoder, &arbs, self.units)?;
+                encoder.finish()?;
+            } else {
+                write_ndjson(&mut writer, &arbs, self.units)?;
+            }
             writer.flush()?;
+            if let Some(saved_events) = saved_events.as_mut() {
+                saved_events.extend(new_events);
+            }
         } else {
-            info!("no arbs found to export.");
+            info!("no new arbs to export.");
         }
         Ok(())
     }
+
+    /// Loads all arbs from the file, decompressing it if it's gzipped.
+    /// Returns an empty list if the file doesn't exist.
+    pub async fn load_arbs_from_file(&self) -> Result<Vec<SimArbResultBatch>> {
+        let data = match tokio::fs::read(self.path()).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        parse_arbs(&decode_file(data)?)
+    }
 }
 
 #[async_trait]
-impl ArbDb for FileWriter {
+impl ArbWriter for FileWriter {
     /// Write arbs to a file.
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
         self.save_arbs_to_file(arbs).await
     }
+}
 
-    /* The following aren't really needed, but the trait requires them. Maybe I should break up the trait a bit.
-    (TODO: try breaking ArbDb trait into ArbReader and ArbWriter)
-    */
+#[async_trait]
+impl ArbReader for FileWriter {
+    /// Read arbs from the file. Filters & sorting are applied in memory.
     async fn read_arbs(
         &self,
-        _filter_params: &ArbFilterParams,
-        _offset: Option<u64>,
-        _limit: Option<i64>,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+        sort: Option<SortOrder>,
     ) -> Result<Vec<SimArbResultBatch>> {
-        unimplemented!()
+        let mut arbs = self
+            .load_arbs_from_file()
+            .await?
+            .into_iter()
+            .filter(|arb| filter_params.matches(arb))
+            .collect::<Vec<_>>();
+        if let Some(sort) = sort {
+            sort.sort(&mut arbs);
+        }
+        let arbs = arbs.into_iter().skip(offset.unwrap_or(0) as usize);
+        Ok(match limit {
+            Some(limit) if limit > 0 => arbs.take(limit as usize).collect(),
+            _ => arbs.collect(),
+        })
     }
-    async fn get_num_arbs(&self, _filter_params: &ArbFilterParams) -> Result<u64> {
-        unimplemented!()
+
+    async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
+        Ok(self
+            .load_arbs_from_file()
+            .await?
+            .iter()
+            .filter(|arb| filter_params.matches(arb))
+            .count() as u64)
     }
+
+    async fn get_saved_events(&self, event_hashes: &[H256]) -> Result<HashSet<H256>> {
+        let event_hashes = event_hashes.iter().collect::<HashSet<_>>();
+        Ok(self
+            .load_arbs_from_file()
+            .await?
+            .iter()
+            .map(|arb| arb.event.hint.hash)
+            .filter(|hash| event_hashes.contains(hash))
+            .collect())
+    }
+
+    /// Gets the extrema of the blocks and timestamps of the arbs in the file.
     async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
-        unimplemented!()
+        let arbs = self.load_arbs_from_file().await?;
+        if arbs.is_empty() {
+            // same placeholders as an empty mongo collection
+            return Ok(StoredArbsRanges {
+                earliest_block: 1,
+                latest_block: 2,
+                earliest_timestamp: 1,
+                latest_timestamp: 2,
+            });
+        }
+        let blocks = arbs.iter().map(|arb| arb.event.block);
+        let timestamps = arbs.iter().map(|arb| arb.event.timestamp);
+        Ok(StoredArbsRanges {
+            earliest_block: blocks.clone().min().unwrap_or(1),
+            latest_block: blocks.max().unwrap_or(2),
+            earliest_timestamp: timestamps.clone().min().unwrap_or(1),
+            latest_timestamp: timestamps.max().unwrap_or(2),
+        })
+    }
+
+    async fn get_covered_ranges(&self, max_gap: u64) -> Result<Vec<BlockRange>> {
+        let blocks = self
+            .load_arbs_from_file()
+            .await?
+            .iter()
+            .map(|arb| arb.event.block)
+            .collect::<BTreeSet<_>>();
+        Ok(covered_ranges(blocks, max_gap))
     }
+
     async fn export_arbs(
         &self,
-        _write_dest: WriteEngine,
-        _filter_params: &ArbFilterParams,
-    ) -> Result<()> {
-        unimplemented!()
+        write_dest: &dyn ArbWriter,
+        filter_params: &ArbFilterParams,
+    ) -> Result<usize> {
+        let src = Arc::new(self.clone());
+        export_arbs_core(src, write_dest, filter_params).await
+    }
+
+    async fn analyze_arbs(&self, filter_params: &ArbFilterParams) -> Result<ArbReport> {
+        analyze_arbs_core(Arc::new(self.clone()), filter_params).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ethers::types::Address;
+    use rand::Rng;
+
+    fn test_writer() -> FileWriter {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        FileWriter::new(Some(format!("test_arbs_{}", rnum)))
+    }
+
+    #[tokio::test]
+    async fn it_writes_and_reads_batches() -> Result<()> {
+        let writer = test_writer();
+        let batch1 = vec![
+            SimArbResultBatch::test_example(),
+            SimArbResultBatch::test_example(),
+        ];
+        let batch2 = vec![SimArbResultBatch::test_example()];
+        writer.write_arbs(&batch1).await?;
+        writer.write_arbs(&batch2).await?;
+        let arbs = writer
+            .read_arbs(&ArbFilterParams::none(), None, None, None)
+            .await?;
+        tokio::fs::remove_file(writer.path()).await?;
+        assert_eq!(arbs.len(), 3);
+        assert_eq!(arbs[0].event.hint.hash, batch1[0].event.hint.hash);
+        assert_eq!(arbs[2].event.hint.hash, batch2[0].event.hint.hash);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_skips_arbs_for_saved_events() -> Result<()> {
+        let writer = test_writer();
+        let arbs = vec![SimArbResultBatch::test_example()];
+        writer.write_arbs(&arbs).await?;
+        // a new writer has to find the saved event in the file
+        let writer = FileWriter::new(Some(writer.filename));
+        writer.write_arbs(&arbs).await?;
+        writer.write_arbs(&arbs).await?;
+        let num_arbs = writer.get_num_arbs(&ArbFilterParams::none()).await?;
+
+        let duplicates =
+            FileWriter::new(Some(writer.filename.to_owned())).allowing_duplicates(true);
+        duplicates.write_arbs(&arbs).await?;
+        let num_duplicates = writer.get_num_arbs(&ArbFilterParams::none()).await?;
+        tokio::fs::remove_file(writer.path()).await?;
+
+        assert_eq!(num_arbs, 1);
+        assert_eq!(num_duplicates, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_filters_arbs_from_file() -> Result<()> {
+        let writer = test_writer();
+        let arbs = (0..10)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.block = 100 + i;
+                arb.event.timestamp = 0x6464beef + (i * 12);
+                arb.max_profit = i.into();
+                arb
+            })
+            .collect::<Vec<_>>();
+        writer.write_arbs(&arbs).await?;
+
+        let filter_params = ArbFilterParams {
+            block_start: Some(102),
+            block_end: Some(108),
+            timestamp_start: None,
+            timestamp_end: None,
+            min_profit: Some(4.into()),
+            event_tx: None,
+            tokens: None,
+            pools: None,
+        };
+        let num_arbs = writer.get_num_arbs(&filter_params).await?;
+        let page = writer
+            .read_arbs(&filter_params, Some(1), Some(2), None)
+            .await?;
+        let ranges = writer.get_previously_saved_ranges().await?;
+        tokio::fs::remove_file(writer.path()).await?;
+
+        assert_eq!(num_arbs, 5);
+        assert_eq!(
+            page.iter().map(|arb| arb.event.block).collect::<Vec<_>>(),
+            vec![105, 106]
+        );
+        assert_eq!(ranges.earliest_block, 100);
+        assert_eq!(ranges.latest_block, 109);
+        assert_eq!(ranges.latest_timestamp, 0x6464beef + 108);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_filters_arbs_by_token_and_pool_from_file() -> Result<()> {
+        let writer = test_writer();
+        let mut arb = SimArbResultBatch::test_example();
+        arb.results = vec![crate::interfaces::SimArbResult::test_example()];
+        let mut no_results = SimArbResultBatch::test_example();
+        no_results.event.hint.hash = H256::random();
+        writer.write_arbs(&vec![arb, no_results]).await?;
+
+        let by_token = writer
+            .get_num_arbs(&ArbFilterParams {
+                tokens: Some(vec![Address::from_low_u64_be(2)]),
+                ..ArbFilterParams::none()
+            })
+            .await?;
+        let by_pool = writer
+            .get_num_arbs(&ArbFilterParams {
+                pools: Some(vec![Address::from_low_u64_be(4)]),
+                ..ArbFilterParams::none()
+            })
+            .await?;
+        let by_other_pool = writer
+            .get_num_arbs(&ArbFilterParams {
+                pools: Some(vec![Address::from_low_u64_be(9)]),
+                ..ArbFilterParams::none()
+            })
+            .await?;
+        tokio::fs::remove_file(writer.path()).await?;
+
+        assert_eq!(by_token, 1);
+        assert_eq!(by_pool, 1);
+        assert_eq!(by_other_pool, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_sorts_arbs_from_file() -> Result<()> {
+        let writer = test_writer();
+        writer
+            .write_arbs(&SimArbResultBatch::sort_examples())
+            .await?;
+
+        let mut orders = vec![];
+        for sort in [SortOrder::Profit, SortOrder::AmountIn, SortOrder::Block] {
+            let sorted = writer
+                .read_arbs(&ArbFilterParams::none(), None, Some(3), Some(sort))
+                .await?;
+            orders.push(
+                sorted
+                    .iter()
+                    .map(|arb| arb.event.hint.hash.to_low_u64_be())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        tokio::fs::remove_file(writer.path()).await?;
+
+        // sorted before the limit is applied
+        assert_eq!(orders, vec![vec![1, 2, 0], vec![2, 0, 1], vec![0, 2, 1]]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_round_trips_gzipped_arbs() -> Result<()> {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        let writer = FileWriter::gzipped(Some(format!("test_arbs_{}", rnum)));
+        assert!(writer.filename.ends_with(".ndjson.gz"));
+        let arbs = (0..10_000)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::from_low_u64_be(i);
+                arb.event.block = 100 + i;
+                arb.results = vec![crate::interfaces::SimArbResult::test_example()];
+                arb
+            })
+            .collect::<Vec<_>>();
+        // written a page at a time, like an export
+        for page in arbs.chunks(1000) {
+            writer.write_arbs(&page.to_vec()).await?;
+        }
+        let uncompressed_size = arbs
+            .iter()
+            .map(|arb| serde_json::to_vec(arb).unwrap().len() + 1)
+            .sum::<usize>();
+        let compressed_size = std::fs::metadata(writer.path())?.len() as usize;
+        // the reader detects compression by its magic bytes, not the filename
+        let renamed = FileWriter::new(Some(format!("test_arbs_{}.ndjson", rnum)));
+        tokio::fs::rename(writer.path(), renamed.path()).await?;
+        let saved = renamed
+            .read_arbs(&ArbFilterParams::none(), None, None, None)
+            .await?;
+        tokio::fs::remove_file(renamed.path()).await?;
+
+        assert!(
+            compressed_size * 10 < uncompressed_size,
+            "{} bytes compressed, {} uncompressed",
+            compressed_size,
+            uncompressed_size
+        );
+        assert_eq!(saved.len(), arbs.len());
+        assert_eq!(saved[9_999].event.hint.hash, arbs[9_999].event.hint.hash);
+        assert_eq!(saved[9_999].results.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_concatenated_json_arrays() -> Result<()> {
+        let batch1 = vec![SimArbResultBatch::test_example()];
+        let batch2 = vec![
+            SimArbResultBatch::test_example(),
+            SimArbResultBatch::test_example(),
+        ];
+        let data = format!(
+            "{}{}",
+            serde_json::to_string_pretty(&batch1)?,
+            serde_json::to_string_pretty(&batch2)?
+        );
+        let arbs = parse_arbs(&data)?;
+        assert_eq!(arbs.len(), 3);
+        assert_eq!(arbs[1].event.hint.hash, batch2[0].event.hint.hash);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_finds_saved_events_in_one_pass() -> Result<()> {
+        let writer = test_writer();
+        let arb = SimArbResultBatch::test_example();
+        writer.write_arbs(&vec![arb.clone()]).await?;
+        let unsaved = H256::from_low_u64_be(1);
+        let saved = writer
+            .get_saved_events(&[unsaved, arb.event.hint.hash])
+            .await?;
+        tokio::fs::remove_file(writer.path()).await?;
+        assert_eq!(saved, HashSet::from([arb.event.hint.hash]));
+        Ok(())
     }
 }
@@ -0,0 +1,165 @@
+use crate::Result;
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+/// Token metadata used by the price code.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TokenMetadata {
+    /// Not every token implements `symbol()` as a string, so this may be missing.
+    pub symbol: Option<String>,
+    pub decimals: U256,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+    pair_tokens: HashMap<Address, (Address, Address)>,
+    tokens: HashMap<Address, TokenMetadata>,
+}
+
+/// Pool tokens & token metadata, which never change, cached across events.
+/// If it has a path, it can be saved to disk so that restarts are warm.
+#[derive(Debug, Default)]
+pub struct MetadataCache {
+    path: Option<PathBuf>,
+    metadata: Mutex<Metadata>,
+    /// Whether anything was added since the cache was last saved.
+    dirty: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl MetadataCache {
+    /// Loads the cache saved at `path`. Returns an empty cache if the file doesn't exist.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let metadata = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Metadata::default(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self {
+            path: Some(path),
+            metadata: Mutex::new(metadata),
+            ..Default::default()
+        })
+    }
+
+    fn metadata(&self) -> MutexGuard<Metadata> {
+        self.metadata.lock().expect("metadata cache poisoned")
+    }
+
+    fn count<T>(&self, cached: Option<T>) -> Option<T> {
+        let counter = if cached.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        cached
+    }
+
+    pub fn pair_tokens(&self, pool: &Address) -> Option<(Address, Address)> {
+        let cached = self.metadata().pair_tokens.get(pool).copied();
+        self.count(cached)
+    }
+
+    /// Returns the pools in `pools` whose tokens aren't cached.
+    pub fn missing_pair_tokens(&self, pools: &[Address]) -> Vec<Address> {
+        let metadata = self.metadata();
+        pools
+            .iter()
+            .filter(|pool| !metadata.pair_tokens.contains_key(pool))
+            .copied()
+            .collect()
+    }
+
+    pub fn insert_pair_tokens(&self, pool: Address, tokens: (Address, Address)) {
+        self.metadata().pair_tokens.insert(pool, tokens);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn token(&self, token: &Address) -> Option<TokenMetadata> {
+        let cached = self.metadata().tokens.get(token).cloned();
+        self.count(cached)
+    }
+
+    /// Returns the tokens in `tokens` whose metadata isn't cached.
+    pub fn missing_tokens(&self, tokens: &[Address]) -> Vec<Address> {
+        let metadata = self.metadata();
+        tokens
+            .iter()
+            .filter(|token| !metadata.tokens.contains_key(token))
+            .copied()
+            .collect()
+    }
+
+    pub fn insert_token(&self, token: Address, metadata: TokenMetadata) {
+        self.metadata().tokens.insert(token, metadata);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the number of (hits, misses) since the cache was loaded.
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Saves the cache to its path, if it has one and anything was added since it was last saved.
+    pub fn save(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let metadata = self.metadata();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(&*metadata)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_saves_metadata() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "hindsight_metadata_test_{}.json",
+            rand::random::<u64>()
+        ));
+        let cache = MetadataCache::load(path.to_owned())?;
+        let pool = Address::from_low_u64_be(1);
+        let tokens = (Address::from_low_u64_be(2), Address::from_low_u64_be(3));
+        let metadata = TokenMetadata {
+            symbol: Some("TKN".to_owned()),
+            decimals: 18.into(),
+        };
+        assert_eq!(cache.pair_tokens(&pool), None);
+        cache.insert_pair_tokens(pool, tokens);
+        cache.insert_token(tokens.0, metadata.to_owned());
+        assert_eq!(cache.pair_tokens(&pool), Some(tokens));
+        assert_eq!(cache.stats(), (1, 1));
+        cache.save()?;
+
+        let cache = MetadataCache::load(path.to_owned())?;
+        assert_eq!(cache.pair_tokens(&pool), Some(tokens));
+        assert_eq!(cache.token(&tokens.0), Some(metadata));
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -1,8 +1,27 @@
 pub mod arbs;
+pub mod audit;
+pub mod blocklist;
+mod bundle_writer;
+pub mod checkpoint;
+mod csv_writer;
 pub mod db;
+pub mod event_cache;
+pub mod failed_events;
 mod file;
+pub mod metadata;
 mod mongo;
+#[cfg(feature = "parquet")]
+mod parquet_writer;
 mod postgres;
+pub mod report;
+mod sqlite;
+pub mod units;
 
+pub use bundle_writer::BundleWriter;
+pub use csv_writer::CsvWriter;
+pub use file::{FileConfig, FileWriter};
 pub use mongo::MongoConfig;
+#[cfg(feature = "parquet")]
+pub use parquet_writer::ParquetWriter;
 pub use postgres::PostgresConfig;
+pub use sqlite::SqliteConfig;
@@ -1,18 +1,25 @@
-use super::arbs::{export_arbs_core, ArbDb, ArbFilterParams, WriteEngine};
+use super::arbs::{
+    covered_ranges, export_arbs_core, ArbFilterParams, ArbReader, ArbWriter, SortOrder,
+};
+use super::report::{ArbReport, ArbReportBuilder, ArbSummary, BestArb};
+use crate::config::Secret;
 use crate::interfaces::SimArbResultBatch;
-use crate::interfaces::StoredArbsRanges;
+use crate::interfaces::{BlockRange, PoolVariant, StoredArbsRanges};
 use crate::Result;
 use async_trait::async_trait;
-use futures::stream::TryStreamExt;
+use ethers::types::{Address, H256, U256};
+use futures::{future::try_join_all, stream::TryStreamExt};
 use mongodb::bson::Document;
 use mongodb::options::Tls;
 use mongodb::options::TlsOptions;
 use mongodb::{
     bson::doc,
-    options::{FindOneOptions, FindOptions},
+    options::{AggregateOptions, FindOneOptions, FindOptions, ReplaceOptions},
     Collection,
 };
-use mongodb::{options::ClientOptions, Client as DbClient, Database};
+use mongodb::{options::ClientOptions, Client as DbClient, Database, IndexModel};
+use serde::Deserialize;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -23,24 +30,86 @@ const ARB_COLLECTION: &'static str = "arbs";
 #[derive(Debug, Clone)]
 pub struct MongoConnect {
     arb_collection: Arc<Collection<SimArbResultBatch>>,
+    allow_duplicates: bool,
 }
 
 #[derive(Clone, Debug)]
 pub struct MongoConfig {
-    pub url: String,
+    pub url: Secret<String>,
     pub tls_ca_file_path: Option<PathBuf>,
+    /// Insert arbs for events that are already saved, rather than replacing them.
+    pub allow_duplicates: bool,
 }
 
 impl Default for MongoConfig {
     fn default() -> Self {
         let config = crate::config::Config::default();
         Self {
-            url: config.mongo_url,
+            url: config
+                .mongo_url
+                .expect("MONGO_URL must be set to use mongo"),
             tls_ca_file_path: config.tls_ca_file_mongo,
+            allow_duplicates: false,
+        }
+    }
+}
+
+/// An arb projected down to the fields that `ArbReport` needs (see `analyze_arbs`).
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectedArb {
+    timestamp: u64,
+    max_profit: U256,
+    #[serde(default)]
+    profit_usd: Option<f64>,
+    #[serde(default)]
+    results: Option<Vec<ProjectedResult>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProjectedResult {
+    profit: U256,
+    token: Address,
+    start_pool: Address,
+    end_pool: Address,
+    start_variant: PoolVariant,
+    end_variant: PoolVariant,
+    #[serde(default)]
+    price_impact_bps: Option<u64>,
+}
+
+impl From<ProjectedArb> for ArbSummary {
+    fn from(arb: ProjectedArb) -> Self {
+        Self {
+            timestamp: arb.timestamp,
+            max_profit: arb.max_profit,
+            profit_usd: arb.profit_usd,
+            best: arb
+                .results
+                .unwrap_or_default()
+                .into_iter()
+                .max_by_key(|res| res.profit)
+                .map(|res| BestArb {
+                    token: res.token,
+                    start_pool: res.start_pool,
+                    end_pool: res.end_pool,
+                    start_variant: res.start_variant,
+                    end_variant: res.end_variant,
+                    price_impact_bps: res.price_impact_bps,
+                }),
         }
     }
 }
 
+/// Addresses as they're stored in arbs (lowercase hex strings).
+fn addresses_to_bson(addresses: &[Address]) -> Vec<String> {
+    addresses
+        .iter()
+        .map(|address| format!("{:?}", address))
+        .collect()
+}
+
 impl Into<Document> for ArbFilterParams {
     fn into(self) -> Document {
         let block_start = self.block_start.unwrap_or(1);
@@ -59,7 +128,7 @@ impl Into<Document> for ArbFilterParams {
             }
         };
 
-        doc! {
+        let mut filter = doc! {
                 "event.block": {
                     "$gte": block_start as u32,
                     "$lte": block_end as u32,
@@ -69,7 +138,84 @@ impl Into<Document> for ArbFilterParams {
                     "$lte": timestamp_end as u32,
                 },
                 "maxProfit": max_profit,
+        };
+        if let Some(event_tx) = self.event_tx {
+            filter.insert("event.hint.hash", format!("{:?}", event_tx));
+        }
+        let mut any_of = vec![];
+        if let Some(tokens) = self.tokens {
+            let tokens = addresses_to_bson(&tokens);
+            any_of.push(doc! {
+                "$or": [
+                    { "results.userTrade.tokens.token": { "$in": tokens.to_owned() } },
+                    { "results.userTrade.tokens.weth": { "$in": tokens } },
+                ]
+            });
+        }
+        if let Some(pools) = self.pools {
+            let pools = addresses_to_bson(&pools);
+            any_of.push(doc! {
+                "$or": [
+                    { "results.backrunTrade.startPool": { "$in": pools.to_owned() } },
+                    { "results.backrunTrade.endPool": { "$in": pools } },
+                ]
+            });
+        }
+        if !any_of.is_empty() {
+            filter.insert("$and", any_of);
+        }
+        filter
+    }
+}
+
+/// Pipeline stages that sort arbs by `sort`, biggest first.
+///
+/// Amounts are stored as hex strings without leading zeros, so they're sorted by their lengths,
+/// then the strings.
+fn sort_stages(sort: SortOrder) -> Vec<Document> {
+    let profit_len = |res: &str| doc! { "$strLenCP": format!("{}.backrunTrade.profit", res) };
+    let by_amount = |amount: &str| {
+        vec![
+            doc! {
+                "$addFields": {
+                    "_sortLen": { "$strLenCP": { "$ifNull": [amount, ""] } },
+                    "_sortAmount": amount,
+                }
+            },
+            doc! { "$sort": { "_sortLen": -1, "_sortAmount": -1, "event.hint.hash": 1 } },
+        ]
+    };
+    match sort {
+        SortOrder::Profit => by_amount("$maxProfit"),
+        SortOrder::AmountIn => {
+            // find the most profitable result, then sort by its amount in
+            let (this_len, value_len) = (profit_len("$$this"), profit_len("$$value"));
+            let more_profitable = doc! {
+                "$or": [
+                    { "$gt": [this_len.to_owned(), value_len.to_owned()] },
+                    {
+                        "$and": [
+                            { "$eq": [this_len, value_len] },
+                            { "$gt": ["$$this.backrunTrade.profit", "$$value.backrunTrade.profit"] },
+                        ]
+                    },
+                ]
+            };
+            let mut stages = vec![doc! {
+                "$addFields": {
+                    "_best": {
+                        "$reduce": {
+                            "input": "$results",
+                            "initialValue": { "$arrayElemAt": ["$results", 0] },
+                            "in": { "$cond": [more_profitable, "$$this", "$$value"] },
+                        }
+                    }
+                }
+            }];
+            stages.extend(by_amount("$_best.backrunTrade.amountIn"));
+            stages
         }
+        SortOrder::Block => vec![doc! { "$sort": { "event.block": -1, "event.hint.hash": 1 } }],
     }
 }
 
@@ -77,15 +223,41 @@ impl Into<Document> for ArbFilterParams {
 impl MongoConnect {
     /// Creates a new ArbDb instance, which connects to the arb collection.
     pub async fn new(config: MongoConfig) -> Result<Self> {
+        let allow_duplicates = config.allow_duplicates;
         let db = MongoConnect::init_db(config).await?;
         let arb_collection = Arc::new(db.collection::<SimArbResultBatch>(ARB_COLLECTION));
-        // TODO: use indexes
-        Ok(Self { arb_collection })
+        // looked up before simulating each event, to skip events that were already simulated,
+        // and on every write, to replace the event's previous arbs
+        arb_collection
+            .create_index(
+                IndexModel::builder()
+                    .keys(doc! { "event.hint.hash": 1 })
+                    .build(),
+                None,
+            )
+            .await?;
+        // used to filter arbs by token & pool (`--token`/`--pool`)
+        arb_collection
+            .create_indexes(
+                [
+                    "results.userTrade.tokens.token",
+                    "results.userTrade.tokens.weth",
+                    "results.backrunTrade.startPool",
+                    "results.backrunTrade.endPool",
+                ]
+                .map(|key| IndexModel::builder().keys(doc! { key: 1 }).build()),
+                None,
+            )
+            .await?;
+        Ok(Self {
+            arb_collection,
+            allow_duplicates,
+        })
     }
 
     /// if tls_ca_file_path is None, then TLS is disabled
     async fn init_db(config: MongoConfig) -> Result<Arc<Database>> {
-        let mut options = ClientOptions::parse(config.url).await?;
+        let mut options = ClientOptions::parse(config.url.expose()).await?;
         options.app_name = Some(PROJECT_NAME.to_owned());
         options.tls = Some(config.tls_ca_file_path.map_or(Tls::Disabled, |ca_path| {
             Tls::Enabled(TlsOptions::builder().ca_file_path(ca_path).build())
@@ -127,13 +299,29 @@ impl MongoConnect {
 }
 
 #[async_trait]
-impl ArbDb for MongoConnect {
-    /// Write given arbs to the DB.
+impl ArbWriter for MongoConnect {
+    /// Write given arbs to the DB, replacing any arbs saved for the same events
+    /// (unless duplicates are allowed).
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
-        self.arb_collection.insert_many(arbs, None).await?;
+        if self.allow_duplicates {
+            self.arb_collection.insert_many(arbs, None).await?;
+            return Ok(());
+        }
+        let upsert = ReplaceOptions::builder().upsert(true).build();
+        try_join_all(arbs.iter().map(|arb| {
+            self.arb_collection.replace_one(
+                doc! { "event.hint.hash": format!("{:?}", arb.event.hint.hash) },
+                arb,
+                upsert.to_owned(),
+            )
+        }))
+        .await?;
         Ok(())
     }
+}
 
+#[async_trait]
+impl ArbReader for MongoConnect {
     async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
         Ok(self
             .arb_collection
@@ -141,25 +329,70 @@ impl ArbDb for MongoConnect {
             .await?)
     }
 
+    async fn get_saved_events(&self, event_hashes: &[H256]) -> Result<HashSet<H256>> {
+        let event_hashes = event_hashes
+            .iter()
+            .map(|hash| format!("{:?}", hash))
+            .collect::<Vec<_>>();
+        self.arb_collection
+            .distinct(
+                "event.hint.hash",
+                doc! { "event.hint.hash": { "$in": event_hashes } },
+                None,
+            )
+            .await?
+            .iter()
+            .map(|hash| {
+                hash.as_str()
+                    .ok_or(anyhow::anyhow!("invalid event hash: {}", hash))?
+                    .parse()
+                    .map_err(Into::into)
+            })
+            .collect()
+    }
+
     /// Load all arbs from the DB.
     async fn read_arbs(
         &self,
         filter_params: &ArbFilterParams,
         offset: Option<u64>,
         limit: Option<i64>,
+        sort: Option<SortOrder>,
     ) -> Result<Vec<SimArbResultBatch>> {
-        // small optimization: match non-zero profit if min_profit is set and > 0
-        let mut cursor = self
-            .arb_collection
-            .find(
-                Some(filter_params.to_owned().into()),
-                Some(FindOptions::builder().skip(offset).limit(limit).build()),
-            )
-            .await?;
-
         let mut results = vec![];
-        while let Some(res) = cursor.try_next().await? {
-            results.push(res);
+        if let Some(sort) = sort {
+            // sorting by amounts takes an aggregation, since they're stored as strings
+            let filter: Document = filter_params.to_owned().into();
+            let mut pipeline = vec![doc! { "$match": filter }];
+            pipeline.extend(sort_stages(sort));
+            if let Some(offset) = offset {
+                pipeline.push(doc! { "$skip": offset as i64 });
+            }
+            if let Some(limit) = limit {
+                pipeline.push(doc! { "$limit": limit });
+            }
+            let mut cursor = self
+                .arb_collection
+                .aggregate(
+                    pipeline,
+                    Some(AggregateOptions::builder().allow_disk_use(true).build()),
+                )
+                .await?;
+            while let Some(doc) = cursor.try_next().await? {
+                results.push(mongodb::bson::from_document::<SimArbResultBatch>(doc)?);
+            }
+        } else {
+            // small optimization: match non-zero profit if min_profit is set and > 0
+            let mut cursor = self
+                .arb_collection
+                .find(
+                    Some(filter_params.to_owned().into()),
+                    Some(FindOptions::builder().skip(offset).limit(limit).build()),
+                )
+                .await?;
+            while let Some(res) = cursor.try_next().await? {
+                results.push(res);
+            }
         }
         // gotta filter profits in memory bc mongo doesn't support bigint comparisons
         let results = results
@@ -193,14 +426,74 @@ impl ArbDb for MongoConnect {
         })
     }
 
+    /// Only the distinct blocks of the arbs are fetched (in order); they're grouped into ranges in memory.
+    async fn get_covered_ranges(&self, max_gap: u64) -> Result<Vec<BlockRange>> {
+        let pipeline = vec![
+            doc! { "$group": { "_id": "$event.block" } },
+            doc! { "$sort": { "_id": 1 } },
+        ];
+        let mut cursor = self.arb_collection.aggregate(pipeline, None).await?;
+        let mut blocks = vec![];
+        while let Some(doc) = cursor.try_next().await? {
+            blocks.push(
+                doc.get_i64("_id")
+                    .or_else(|_| doc.get_i32("_id").map(i64::from))? as u64,
+            );
+        }
+        Ok(covered_ranges(blocks, max_gap))
+    }
+
     async fn export_arbs(
         &self,
-        write_dest: WriteEngine,
+        write_dest: &dyn ArbWriter,
         filter_params: &ArbFilterParams,
-    ) -> Result<()> {
+    ) -> Result<usize> {
         let src = Arc::new(self.clone());
-        export_arbs_core(src, write_dest, filter_params).await?;
-        Ok(())
+        export_arbs_core(src, write_dest, filter_params).await
+    }
+
+    /// Filters and projects arbs in mongo, so only the fields the report needs are fetched, then
+    /// aggregates them in memory, since mongo can't sum or compare bigints.
+    async fn analyze_arbs(&self, filter_params: &ArbFilterParams) -> Result<ArbReport> {
+        let events = self.get_num_arbs(filter_params).await?;
+        let profitable = filter_params.profitable();
+        let min_profit = profitable.min_profit.unwrap_or_default();
+        let filter: Document = profitable.into();
+        let pipeline = vec![
+            doc! { "$match": filter },
+            doc! {
+                "$project": {
+                    "_id": 0,
+                    "timestamp": "$event.timestamp",
+                    "maxProfit": 1,
+                    "profitUsd": 1,
+                    "results": {
+                        "$map": {
+                            "input": "$results",
+                            "as": "res",
+                            "in": {
+                                "profit": "$$res.backrunTrade.profit",
+                                "token": "$$res.userTrade.tokens.token",
+                                "startPool": "$$res.backrunTrade.startPool",
+                                "endPool": "$$res.backrunTrade.endPool",
+                                "startVariant": "$$res.backrunTrade.startVariant",
+                                "endVariant": "$$res.backrunTrade.endVariant",
+                                "priceImpactBps": "$$res.userTrade.priceImpactBps",
+                            },
+                        },
+                    },
+                },
+            },
+        ];
+        let mut cursor = self.arb_collection.aggregate(pipeline, None).await?;
+        let mut report = ArbReportBuilder::default();
+        while let Some(doc) = cursor.try_next().await? {
+            let arb: ProjectedArb = mongodb::bson::from_document(doc)?;
+            if arb.max_profit >= min_profit {
+                report.add(&arb.into());
+            }
+        }
+        Ok(report.build(events))
     }
 }
 
@@ -208,7 +501,7 @@ impl ArbDb for MongoConnect {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{config::Config, interfaces::SimArbResultBatch, Result};
+    use crate::{data::file::FileWriter, interfaces::SimArbResultBatch, Result};
 
     async fn inject_test_arbs(
         connect: &MongoConnect,
@@ -226,12 +519,7 @@ mod test {
     }
 
     async fn connect() -> Result<MongoConnect> {
-        let config = Config::default();
-        let connect = MongoConnect::new(MongoConfig {
-            url: config.mongo_url,
-            tls_ca_file_path: config.tls_ca_file_mongo,
-        })
-        .await?;
+        let connect = MongoConnect::new(MongoConfig::default()).await?;
         Ok(connect)
     }
 
@@ -243,11 +531,24 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn it_replaces_arbs_for_saved_events() -> Result<()> {
+        let connect = connect().await?;
+        let arbs = vec![SimArbResultBatch::test_example()];
+        connect.write_arbs(&arbs).await?;
+        connect.write_arbs(&arbs).await?;
+        let num_arbs = connect
+            .get_num_arbs(&ArbFilterParams::event_tx(arbs[0].event.hint.hash))
+            .await?;
+        assert_eq!(num_arbs, 1);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn it_reads_from_db() -> Result<()> {
         let connect = connect().await?;
         let arbs = connect
-            .read_arbs(&ArbFilterParams::default(), None, None)
+            .read_arbs(&ArbFilterParams::default(), None, None, None)
             .await?;
         println!("arbs: {:?}", arbs);
         Ok(())
@@ -267,9 +568,13 @@ mod test {
                     timestamp_start: Some(0x6464beef),
                     timestamp_end: Some(0x6464deaf),
                     min_profit: Some(1.into()),
+                    event_tx: None,
+                    tokens: None,
+                    pools: None,
                 },
                 Some(1),
                 Some(3),
+                None,
             )
             .await?;
         println!(
@@ -300,7 +605,7 @@ mod test {
         inject_test_arbs(&connect, 13).await?;
         connect
             .export_arbs(
-                WriteEngine::File(Some("test_arbs.json".to_owned())),
+                &FileWriter::new(Some("test_arbs.json".to_owned())),
                 &ArbFilterParams::default(),
             )
             .await?;
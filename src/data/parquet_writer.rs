@@ -0,0 +1,384 @@
+use crate::{
+    data::{
+        arbs::ArbWriter,
+        file::{parse_filename, EXPORT_DIR},
+        units::AmountUnits,
+    },
+    info,
+    interfaces::SimArbResultBatch,
+    Result,
+};
+use arrow::{
+    array::{
+        ArrayRef, Decimal128Builder, FixedSizeBinaryBuilder, Float64Builder, StringBuilder,
+        StringDictionaryBuilder, TimestampSecondBuilder, UInt64Builder, UInt8Builder,
+    },
+    datatypes::{DataType, Field, Int8Type, Schema, SchemaRef, TimeUnit},
+    record_batch::RecordBatch,
+};
+use async_trait::async_trait;
+use ethers::types::U256;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use std::{
+    fs::File,
+    sync::{Arc, Mutex},
+};
+
+/// Max rows buffered in memory before they're written to the file as a row group.
+const MAX_ROW_GROUP_SIZE: usize = 100_000;
+/// Decimal precision of amount columns; the most an i128 can hold.
+const DECIMAL_PRECISION: u8 = 38;
+/// ETH amounts are stored as decimals with 18 places, so their unscaled values are wei.
+const ETH_SCALE: i8 = 18;
+/// Raw amounts are stored as decimals with no places, i.e. as wei.
+const WEI_SCALE: i8 = 0;
+/// Amounts of each backrun, whose columns depend on the export's units; see `amount_columns`.
+const AMOUNTS: [&'static str; 3] = ["amount_in", "balance_end", "profit"];
+/// Gas prices are stored in gwei, so their unscaled values are wei.
+const GWEI_SCALE: i8 = 9;
+
+/// Names & scales of the columns of the amount `name` in `units`: `<name>` in wei, and/or
+/// `<name>_eth` in ETH.
+fn amount_columns(name: &str, units: AmountUnits) -> Vec<(String, i8)> {
+    let mut columns = vec![];
+    if units.raw() {
+        columns.push((name.to_owned(), WEI_SCALE));
+    }
+    if units.whole() {
+        columns.push((format!("{}_eth", name), ETH_SCALE));
+    }
+    columns
+}
+
+/// Schema of the parquet file; one row per simulated backrun (`SimArbResult`).
+fn schema(units: AmountUnits) -> SchemaRef {
+    let address = DataType::FixedSizeBinary(20);
+    let eth = DataType::Decimal128(DECIMAL_PRECISION, ETH_SCALE);
+    let amounts = AMOUNTS.iter().flat_map(|name| {
+        amount_columns(name, units)
+            .into_iter()
+            .map(|(column, scale)| {
+                Field::new(
+                    column,
+                    DataType::Decimal128(DECIMAL_PRECISION, scale),
+                    false,
+                )
+            })
+    });
+    let fields = vec![
+        Field::new("tx_hash", DataType::FixedSizeBinary(32), false),
+        Field::new("block", DataType::UInt64, false),
+        Field::new(
+            "timestamp",
+            DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+            false,
+        ),
+        Field::new("token", address.to_owned(), false),
+        Field::new("token_symbol", DataType::Utf8, true),
+        Field::new("token_decimals", DataType::UInt8, true),
+        Field::new(
+            "pool_variant",
+            DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Utf8)),
+            false,
+        ),
+        Field::new("start_pool", address.to_owned(), false),
+        Field::new("end_pool", address, false),
+        // TKN per WETH; null if it's too large for a decimal
+        Field::new("user_pool_price", eth.to_owned(), true),
+        Field::new("alt_pool_price", eth.to_owned(), true),
+        Field::new("user_pool_price_before", eth.to_owned(), true),
+        // null if the price before the user's tx is unknown
+        Field::new("price_impact_bps", DataType::UInt64, true),
+    ]
+    .into_iter()
+    .chain(amounts)
+    .chain([
+        Field::new("eth_usd_price", DataType::Float64, true),
+        Field::new("profit_usd", DataType::Float64, true),
+        Field::new("gas_used_buy", DataType::UInt64, false),
+        Field::new("gas_used_sell", DataType::UInt64, false),
+        Field::new(
+            "effective_gas_price_gwei",
+            DataType::Decimal128(DECIMAL_PRECISION, GWEI_SCALE),
+            false,
+        ),
+        Field::new(
+            "placement",
+            DataType::Dictionary(Box::new(DataType::Int8), Box::new(DataType::Utf8)),
+            false,
+        ),
+    ]);
+    Arc::new(Schema::new(fields.collect::<Vec<_>>()))
+}
+
+/// Converts a wei amount to the unscaled value of a decimal column.
+fn to_decimal(amount: U256) -> Result<i128> {
+    if amount > U256::from(i128::MAX as u128) {
+        return Err(anyhow::format_err!(
+            "{} wei is too large for a parquet decimal",
+            amount
+        ));
+    }
+    Ok(amount.as_u128() as i128)
+}
+
+/// Flattens arbs into a record batch; one row per `SimArbResult`.
+fn to_record_batch(arbs: &[SimArbResultBatch], units: AmountUnits) -> Result<RecordBatch> {
+    let rows = arbs.iter().map(|arb| arb.results.len()).sum();
+    let eth_builder = || {
+        Decimal128Builder::with_capacity(rows)
+            .with_precision_and_scale(DECIMAL_PRECISION, ETH_SCALE)
+    };
+    let mut tx_hash = FixedSizeBinaryBuilder::with_capacity(rows, 32);
+    let mut block = UInt64Builder::with_capacity(rows);
+    let mut timestamp = TimestampSecondBuilder::with_capacity(rows).with_timezone("UTC");
+    let mut token = FixedSizeBinaryBuilder::with_capacity(rows, 20);
+    let mut token_symbol = StringBuilder::new();
+    let mut token_decimals = UInt8Builder::with_capacity(rows);
+    let mut pool_variant = StringDictionaryBuilder::<Int8Type>::new();
+    let mut start_pool = FixedSizeBinaryBuilder::with_capacity(rows, 20);
+    let mut end_pool = FixedSizeBinaryBuilder::with_capacity(rows, 20);
+    let mut user_pool_price = eth_builder()?;
+    let mut alt_pool_price = eth_builder()?;
+    let mut user_pool_price_before = eth_builder()?;
+    let mut price_impact_bps = UInt64Builder::with_capacity(rows);
+    // one builder per column of each of `AMOUNTS`
+    let mut amounts = AMOUNTS
+        .iter()
+        .map(|name| {
+            amount_columns(name, units)
+                .into_iter()
+                .map(|(_, scale)| {
+                    Decimal128Builder::with_capacity(rows)
+                        .with_precision_and_scale(DECIMAL_PRECISION, scale)
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut eth_usd_price = Float64Builder::with_capacity(rows);
+    let mut profit_usd = Float64Builder::with_capacity(rows);
+    let mut gas_used_buy = UInt64Builder::with_capacity(rows);
+    let mut gas_used_sell = UInt64Builder::with_capacity(rows);
+    let mut effective_gas_price = Decimal128Builder::with_capacity(rows)
+        .with_precision_and_scale(DECIMAL_PRECISION, GWEI_SCALE)?;
+    let mut placement = StringDictionaryBuilder::<Int8Type>::new();
+
+    for arb in arbs {
+        for res in &arb.results {
+            let backrun = &res.backrun_trade;
+            tx_hash.append_value(arb.event.hint.hash)?;
+            block.append_value(arb.event.block);
+            timestamp.append_value(arb.event.timestamp as i64);
+            token.append_value(res.user_trade.tokens.token)?;
+            token_symbol.append_option(res.user_trade.token_symbol.as_ref());
+            token_decimals.append_option(res.user_trade.token_decimals);
+            pool_variant.append_value(format!("{:?}", res.user_trade.pool_variant));
+            start_pool.append_value(backrun.start_pool)?;
+            end_pool.append_value(backrun.end_pool)?;
+            // prices of tokens w/ many decimals can overflow a decimal column
+            user_pool_price.append_option(to_decimal(backrun.user_pool_price).ok());
+            alt_pool_price.append_option(to_decimal(backrun.alt_pool_price).ok());
+            user_pool_price_before.append_option(to_decimal(res.user_trade.price_before).ok());
+            price_impact_bps.append_option(res.user_trade.price_impact_bps);
+            let values = [backrun.amount_in, backrun.balance_end, backrun.profit];
+            for (builders, value) in amounts.iter_mut().zip(values) {
+                // the unscaled value is wei at either scale
+                let value = to_decimal(value)?;
+                for builder in builders {
+                    builder.append_value(value);
+                }
+            }
+            eth_usd_price.append_option(arb.eth_usd_price);
+            profit_usd.append_option(arb.to_usd(backrun.profit));
+            gas_used_buy.append_value(backrun.gas_used_buy);
+            gas_used_sell.append_value(backrun.gas_used_sell);
+            effective_gas_price.append_value(to_decimal(backrun.effective_gas_price)?);
+            placement.append_value(arb.placement.to_string());
+        }
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(tx_hash.finish()),
+        Arc::new(block.finish()),
+        Arc::new(timestamp.finish()),
+        Arc::new(token.finish()),
+        Arc::new(token_symbol.finish()),
+        Arc::new(token_decimals.finish()),
+        Arc::new(pool_variant.finish()),
+        Arc::new(start_pool.finish()),
+        Arc::new(end_pool.finish()),
+        Arc::new(user_pool_price.finish()),
+        Arc::new(alt_pool_price.finish()),
+        Arc::new(user_pool_price_before.finish()),
+        Arc::new(price_impact_bps.finish()),
+    ];
+    for builders in amounts.iter_mut() {
+        for builder in builders {
+            columns.push(Arc::new(builder.finish()));
+        }
+    }
+    let rest: [ArrayRef; 6] = [
+        Arc::new(eth_usd_price.finish()),
+        Arc::new(profit_usd.finish()),
+        Arc::new(gas_used_buy.finish()),
+        Arc::new(gas_used_sell.finish()),
+        Arc::new(effective_gas_price.finish()),
+        Arc::new(placement.finish()),
+    ];
+    columns.extend(rest);
+    Ok(RecordBatch::try_new(schema(units), columns)?)
+}
+
+/// Saves arbs to a parquet file; each row is one `SimArbResult`.
+///
+/// The file is created on the first write, and rows are written out a row group at a time.
+/// Parquet files can't be appended to, so the file is only readable once the writer is closed.
+#[derive(Clone)]
+pub struct ParquetWriter {
+    pub filename: String,
+    /// Columns of backrun amounts: `<amount>` in wei, and/or `<amount>_eth` in ETH.
+    pub units: AmountUnits,
+    writer: Arc<Mutex<Option<ArrowWriter<File>>>>,
+}
+
+impl ParquetWriter {
+    pub fn new(filename: Option<String>, units: AmountUnits) -> Self {
+        ParquetWriter {
+            filename: parse_filename(filename, &[".parquet"]).expect("failed to parse filename"),
+            units,
+            writer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Path of the export file, relative to the working directory.
+    pub fn path(&self) -> String {
+        format!("{}/{}", EXPORT_DIR, self.filename)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Option<ArrowWriter<File>>>> {
+        self.writer
+            .lock()
+            .map_err(|err| anyhow::format_err!("parquet writer poisoned: {}", err))
+    }
+
+    /// Writes the buffered rows and the file's footer. Does nothing if no arbs were written.
+    pub fn close(&self) -> Result<()> {
+        if let Some(writer) = self.lock()?.take() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ArbWriter for ParquetWriter {
+    /// Buffers arbs' rows, writing a row group to the file whenever enough rows are buffered.
+    async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        let batch = to_record_batch(arbs, self.units)?;
+        if batch.num_rows() == 0 {
+            info!("no arbs found to export.");
+            return Ok(());
+        }
+        tokio::fs::create_dir_all(EXPORT_DIR).await?;
+        let mut writer = self.lock()?;
+        if writer.is_none() {
+            let props = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .set_max_row_group_size(MAX_ROW_GROUP_SIZE)
+                .build();
+            *writer = Some(ArrowWriter::try_new(
+                File::create(self.path())?,
+                schema(self.units),
+                Some(props),
+            )?);
+        }
+        info!(
+            "exporting {} rows to file {}...",
+            batch.num_rows(),
+            self.path()
+        );
+        writer
+            .as_mut()
+            .expect("writer was just created")
+            .write(&batch)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+    use arrow::{
+        array::{Array, Decimal128Array},
+        compute::sum,
+    };
+    use ethers::types::H256;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use rand::Rng;
+
+    #[tokio::test]
+    async fn it_writes_parquet_rows() -> Result<()> {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        let writer = ParquetWriter::new(Some(format!("test_arbs_{}", rnum)), AmountUnits::Eth);
+        let arbs = (0..3)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::from_low_u64_be(i);
+                arb.results = vec![SimArbResult::test_example(); 2];
+                arb
+            })
+            .collect::<Vec<_>>();
+        writer.write_arbs(&arbs[..2].to_vec()).await?;
+        writer.write_arbs(&arbs[2..].to_vec()).await?;
+        writer.close()?;
+
+        let reader =
+            ParquetRecordBatchReaderBuilder::try_new(File::open(writer.path())?)?.build()?;
+        let batches = reader.collect::<std::result::Result<Vec<_>, _>>()?;
+        tokio::fs::remove_file(writer.path()).await?;
+
+        assert_eq!(
+            batches[0].schema().fields(),
+            schema(AmountUnits::Eth).fields()
+        );
+        let rows = batches.iter().map(|batch| batch.num_rows()).sum::<usize>();
+        assert_eq!(rows, 6);
+        let profit = batches
+            .iter()
+            .map(|batch| {
+                let profits = batch
+                    .column_by_name("profit_eth")
+                    .unwrap()
+                    .as_any()
+                    .downcast_ref::<Decimal128Array>()
+                    .unwrap();
+                sum(profits).unwrap_or_default()
+            })
+            .sum::<i128>();
+        let expected = SimArbResult::test_example().backrun_trade.profit.as_u128() as i128;
+        assert_eq!(profit, expected * 6);
+        Ok(())
+    }
+    #[test]
+    fn it_writes_wei_and_eth_amount_columns() -> Result<()> {
+        let mut arb = SimArbResultBatch::test_example();
+        let mut res = SimArbResult::test_example();
+        // more digits than an f64 holds
+        res.backrun_trade.amount_in = U256::from_dec_str("123456789012345678901234567").unwrap();
+        arb.results = vec![res];
+        let batch = to_record_batch(&[arb], AmountUnits::Both)?;
+        let amount_in = |column: &str| {
+            let column = batch.column_by_name(column).unwrap();
+            let column = column.as_any().downcast_ref::<Decimal128Array>().unwrap();
+            column.value_as_string(0)
+        };
+        assert_eq!(amount_in("amount_in"), "123456789012345678901234567");
+        assert_eq!(amount_in("amount_in_eth"), "123456789.012345678901234567");
+        assert!(batch.column_by_name("profit").is_some());
+        assert!(schema(AmountUnits::Wei)
+            .column_with_name("profit_eth")
+            .is_none());
+        Ok(())
+    }
+}
@@ -1,36 +1,48 @@
-use super::arbs::{ArbDb, ArbFilterParams, WriteEngine};
+use super::{
+    arbs::{export_arbs_core, ArbFilterParams, ArbReader, ArbWriter, SortOrder},
+    report::{
+        impact_buckets, profit_buckets, ArbReport, DailyProfit, PairStats, PoolStats,
+        IMPACT_BUCKET_BOUNDS_BPS, PROFIT_BUCKET_BOUNDS, REPORT_TOP_N,
+    },
+};
 use crate::{
-    interfaces::{SimArbResultBatch, StoredArbsRanges},
+    config::Secret,
+    debug,
+    interfaces::{BlockRange, SimArbResultBatch, StoredArbsRanges},
     Result,
 };
 use async_trait::async_trait;
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use ethers::{
-    types::{H256, U256},
+    types::{Address, H256, U256},
     utils::{format_ether, parse_ether},
 };
-use futures::future::join_all;
+use futures::future::try_join_all;
 use mev_share_sse::{EventHistory, Hint};
 use rust_decimal::prelude::*;
-use std::sync::Arc;
-use tokio_postgres::{connect, Client, NoTls};
+use serde_json::{json, Value};
+use std::{collections::HashSet, sync::Arc};
+use tokio_postgres::{connect, Client, NoTls, Row};
 
 const ARBS_TABLE: &'static str = "hindsight";
 
+#[derive(Clone)]
 pub struct PostgresConnect {
     client: Arc<Client>,
 }
 
 #[derive(Clone, Debug)]
 pub struct PostgresConfig {
-    pub url: String,
+    pub url: Secret<String>,
 }
 
 impl Default for PostgresConfig {
     fn default() -> Self {
         let config = crate::config::Config::default();
         Self {
-            url: config.postgres_url.unwrap(),
+            url: config
+                .postgres_url
+                .expect("POSTGRES_URL must be set to use postgres"),
         }
     }
 }
@@ -38,39 +50,209 @@ impl Default for PostgresConfig {
 fn where_filter(filter: &ArbFilterParams) -> String {
     let mut params = vec![];
     if let Some(block_start) = filter.block_start {
-        params.push(format!("block_number >= {}", block_start));
+        params.push(format!("event_block >= {}", block_start));
     }
     if let Some(block_end) = filter.block_end {
-        params.push(format!("block_number <= {}", block_end));
+        params.push(format!("event_block <= {}", block_end));
     }
+    // event_timestamp is stored in UTC
     if let Some(timestamp_start) = filter.timestamp_start {
-        params.push(format!("timestamp >= {}", timestamp_start));
+        params.push(format!(
+            "event_timestamp >= (to_timestamp({}) AT TIME ZONE 'UTC')",
+            timestamp_start
+        ));
     }
     if let Some(timestamp_end) = filter.timestamp_end {
-        params.push(format!("timestamp <= {}", timestamp_end));
+        params.push(format!(
+            "event_timestamp <= (to_timestamp({}) AT TIME ZONE 'UTC')",
+            timestamp_end
+        ));
     }
     if let Some(min_profit) = filter.min_profit {
         params.push(format!("profit__eth__ >= {}", format_ether(min_profit)));
     }
-    params.join(" AND ")
+    if let Some(event_tx) = filter.event_tx {
+        params.push(format!("tx_hash = '{:?}'", event_tx));
+    }
+    if let Some(tokens) = &filter.tokens {
+        params.push(results_contain_any(
+            tokens
+                .iter()
+                .flat_map(|token| {
+                    [
+                        json!({ "userTrade": { "tokens": { "token": token } } }),
+                        json!({ "userTrade": { "tokens": { "weth": token } } }),
+                    ]
+                })
+                .collect(),
+        ));
+    }
+    if let Some(pools) = &filter.pools {
+        params.push(results_contain_any(
+            pools
+                .iter()
+                .flat_map(|pool| {
+                    [
+                        json!({ "backrunTrade": { "startPool": pool } }),
+                        json!({ "backrunTrade": { "endPool": pool } }),
+                    ]
+                })
+                .collect(),
+        ));
+    }
+    if params.is_empty() {
+        return "".to_owned();
+    }
+    format!(" WHERE {}", params.join(" AND "))
 }
 
-fn select_arbs_query(filter: &ArbFilterParams) -> String {
-    let mut query = "SELECT * FROM ".to_string();
-    query.push_str(ARBS_TABLE);
-    query.push_str(" WHERE ");
+/// Matches rows with a result that contains any of `results`. `@>` is served by the GIN index on `results`.
+fn results_contain_any(results: Vec<Value>) -> String {
+    if results.is_empty() {
+        return "FALSE".to_owned();
+    }
+    let clauses = results
+        .iter()
+        .map(|res| format!("results @> '[{}]'", res))
+        .collect::<Vec<_>>();
+    format!("({})", clauses.join(" OR "))
+}
+
+/// Joins each arb with the amount in (`best.amount_in`) of its most profitable result. Results'
+/// amounts are hex strings without leading zeros, so comparing their lengths, then the strings,
+/// compares their values.
+const BEST_AMOUNT_IN_JOIN: &'static str = " LEFT JOIN LATERAL (
+    SELECT res->'backrunTrade'->>'amountIn' AS amount_in FROM jsonb_array_elements(results) AS res
+    ORDER BY length(res->'backrunTrade'->>'profit') DESC, res->'backrunTrade'->>'profit' DESC
+    LIMIT 1
+) AS best ON TRUE";
+
+fn select_arbs_query(
+    filter: &ArbFilterParams,
+    offset: Option<u64>,
+    limit: Option<i64>,
+    sort: Option<SortOrder>,
+) -> String {
+    let mut query = format!(
+        "SELECT tx_hash, profit__eth__, event_block, event_timestamp, event, results, failures, live, eth_usd_price, profit__usd__, placement, hint, non_hinted, hint_only FROM {}",
+        ARBS_TABLE
+    );
+    if sort == Some(SortOrder::AmountIn) {
+        query.push_str(BEST_AMOUNT_IN_JOIN);
+    }
     query.push_str(&where_filter(filter));
+    query.push_str(match sort {
+        None => " ORDER BY event_timestamp, tx_hash",
+        Some(SortOrder::Profit) => " ORDER BY profit__eth__ DESC NULLS LAST, tx_hash",
+        Some(SortOrder::AmountIn) => {
+            " ORDER BY length(best.amount_in) DESC NULLS LAST, best.amount_in DESC, tx_hash"
+        }
+        Some(SortOrder::Block) => " ORDER BY event_block DESC, tx_hash",
+    });
+    if let Some(limit) = limit {
+        query.push_str(&format!(" LIMIT {}", limit));
+    }
+    if let Some(offset) = offset {
+        query.push_str(&format!(" OFFSET {}", offset));
+    }
     query
 }
 
 fn count_arbs_query(filter: &ArbFilterParams) -> String {
     let mut query = "SELECT COUNT(*) FROM ".to_string();
     query.push_str(ARBS_TABLE);
-    query.push_str(" WHERE ");
     query.push_str(&where_filter(filter));
     query
 }
 
+/// Selects the most profitable result (`arb`) of each profitable arb matching `filter`, along with
+/// the arb's `profit` (ETH). Results' profits are hex strings without leading zeros, so comparing
+/// their lengths, then the strings, compares their values.
+fn best_results_query(filter: &ArbFilterParams) -> String {
+    format!(
+        "WITH best AS (
+            SELECT DISTINCT ON (tx_hash) tx_hash, profit__eth__ AS profit, res AS arb
+            FROM {}, jsonb_array_elements(results) AS res{}
+            ORDER BY tx_hash, length(res->'backrunTrade'->>'profit') DESC, res->'backrunTrade'->>'profit' DESC
+        )",
+        ARBS_TABLE,
+        where_filter(&filter.profitable())
+    )
+}
+
+/// Groups the blocks of the saved arbs into ranges of blocks at most `max_gap` blocks apart; a new
+/// range starts at every block further than that from the previous one.
+fn covered_ranges_query(max_gap: u64) -> String {
+    format!(
+        "SELECT MIN(event_block), MAX(event_block) FROM (
+            SELECT event_block, SUM(new_range) OVER (ORDER BY event_block) AS range_id FROM (
+                SELECT event_block,
+                    CASE WHEN event_block - LAG(event_block) OVER (ORDER BY event_block) <= {}
+                    THEN 0 ELSE 1 END AS new_range
+                FROM (SELECT DISTINCT event_block FROM {}) blocks
+            ) starts
+        ) ranges GROUP BY range_id ORDER BY MIN(event_block)",
+        max_gap + 1,
+        ARBS_TABLE
+    )
+}
+
+/// Converts an amount of ETH, as stored in `profit__eth__`, to wei.
+fn eth_to_wei(eth: Option<Decimal>) -> Result<U256> {
+    Ok(parse_ether(eth.unwrap_or_default().to_string())?)
+}
+
+/// Parses a row selected by `select_arbs_query`.
+fn parse_arb_row(row: &Row) -> Result<SimArbResultBatch> {
+    let profit: Option<Decimal> = row.get(1);
+    let max_profit = parse_ether(profit.unwrap_or_default().to_string())?;
+    let event: Option<Value> = row.get(4);
+    let results: Option<Value> = row.get(5);
+    let event = if let Some(event) = event {
+        serde_json::from_value(event)?
+    } else {
+        // rows written before the event column was added only have the basics
+        EventHistory {
+            block: row.get::<_, i32>(2) as u64,
+            timestamp: row.get::<_, NaiveDateTime>(3).timestamp() as u64,
+            hint: Hint {
+                txs: vec![],
+                hash: H256::from_str(&row.get::<_, String>(0))?,
+                logs: vec![],
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        }
+    };
+    let results = if let Some(results) = results {
+        serde_json::from_value(results)?
+    } else {
+        vec![]
+    };
+    Ok(SimArbResultBatch {
+        event,
+        results,
+        max_profit,
+        failures: row.get::<_, i32>(6) as usize,
+        live: row.get(7),
+        eth_usd_price: row.get(8),
+        profit_usd: row.get(9),
+        placement: row
+            .get::<_, String>(10)
+            .parse()
+            .map_err(|err: String| anyhow::format_err!(err))?,
+        hint: row
+            .get::<_, Option<Value>>(11)
+            .map(serde_json::from_value)
+            .transpose()?,
+        non_hinted: match row.get::<_, Option<Value>>(12) {
+            Some(non_hinted) => serde_json::from_value(non_hinted)?,
+            None => vec![],
+        },
+        hint_only: row.get(13),
+    })
+}
+
 impl PostgresConnect {
     pub async fn new(config: PostgresConfig) -> Result<Self> {
         // TODO: add env var for postgres tls if/when implemented
@@ -80,7 +262,7 @@ impl PostgresConnect {
         // } else {
         //     NoTls
         // };
-        let (client, connection) = connect(&config.url, NoTls).await?;
+        let (client, connection) = connect(config.url.expose(), NoTls).await?;
         // The connection object performs the actual communication with the database,
         // so spawn it off to run on its own.
         tokio::spawn(async move {
@@ -89,20 +271,32 @@ impl PostgresConnect {
             }
         });
 
-        // create arbs table pessimistically (simplified version for now: {hash, profit})
+        // create arbs table pessimistically; event & results columns are added to tables
+        // created by older versions, which only stored {hash, profit, block, timestamp}
         client
-            .execute(
-                &format!(
-                    "CREATE TABLE IF NOT EXISTS {} (
-                        tx_hash VARCHAR(66) NOT NULL PRIMARY KEY,
-                        profit__eth__ NUMERIC,
-                        event_block INTEGER NOT NULL,
-                        event_timestamp TIMESTAMP NOT NULL
-                    )",
-                    ARBS_TABLE
-                ),
-                &[],
-            )
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {table} (
+                    tx_hash VARCHAR(66) NOT NULL PRIMARY KEY,
+                    profit__eth__ NUMERIC,
+                    event_block INTEGER NOT NULL,
+                    event_timestamp TIMESTAMP NOT NULL
+                );
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS event JSONB;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS results JSONB;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS failures INTEGER NOT NULL DEFAULT 0;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS live BOOLEAN NOT NULL DEFAULT FALSE;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS eth_usd_price DOUBLE PRECISION;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS profit__usd__ DOUBLE PRECISION;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS placement TEXT NOT NULL DEFAULT 'top-of-block';
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS hint JSONB;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS non_hinted JSONB;
+                ALTER TABLE {table} ADD COLUMN IF NOT EXISTS hint_only BOOLEAN NOT NULL DEFAULT FALSE;
+                CREATE INDEX IF NOT EXISTS {table}_event_block_idx ON {table} (event_block);
+                CREATE INDEX IF NOT EXISTS {table}_event_timestamp_idx ON {table} (event_timestamp);
+                CREATE INDEX IF NOT EXISTS {table}_profit_idx ON {table} (profit__eth__);
+                CREATE INDEX IF NOT EXISTS {table}_results_idx ON {table} USING GIN (results jsonb_path_ops);",
+                table = ARBS_TABLE
+            ))
             .await?;
 
         Ok(Self {
@@ -112,105 +306,310 @@ impl PostgresConnect {
 }
 
 #[async_trait]
-impl ArbDb for PostgresConnect {
+impl ArbWriter for PostgresConnect {
     async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
-        let handles = arbs
-            .iter()
-            .map(|arb| {
-                let txhash = format!("{:?}", arb.event.hint.hash); // must be a better way than this :\
-                let max_profit = Decimal::from_str(&format_ether(arb.max_profit))
-                    .expect("failed to encode profit");
-                let timestamp =
-                    NaiveDateTime::from_timestamp_millis(arb.event.timestamp as i64 * 1000)
-                        .expect("failed to parse timestamp");
-
-                println!(
-                    "writing arb to postgres: {} {} eth",
-                    txhash.to_string(),
-                    max_profit
-                );
-                // clone these to give to the tokio thread
-                let client = self.client.clone();
-                let arb = arb.clone();
-
-                tokio::task::spawn(async move {
-                    client
-                .execute(
-                    &format!("INSERT INTO {} (tx_hash, profit__eth__, event_block, event_timestamp)
-                        VALUES ($1, $2, $3, $4)
-                        ON CONFLICT (tx_hash) DO UPDATE SET profit__eth__ = $2",
-                        ARBS_TABLE
-                    ),
-                    &[
-                        &txhash,
-                        &max_profit,
-                        &(arb.event.block as i32),
-                        &timestamp,
-                    ],
-                )
-                .await.expect("failed to write arb to postgres");
-                })
-            })
-            .collect::<Vec<_>>();
-        join_all(handles).await;
+        let query = format!(
+            "INSERT INTO {} (tx_hash, profit__eth__, event_block, event_timestamp, event, results, failures, live, eth_usd_price, profit__usd__, placement, hint, non_hinted, hint_only)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+                ON CONFLICT (tx_hash) DO UPDATE SET profit__eth__ = $2, event = $5, results = $6, failures = $7, live = $8,
+                    eth_usd_price = $9, profit__usd__ = $10, placement = $11, hint = $12, non_hinted = $13, hint_only = $14",
+            ARBS_TABLE
+        );
+        let mut writes = vec![];
+        for arb in arbs {
+            let txhash = format!("{:?}", arb.event.hint.hash); // must be a better way than this :\
+            let max_profit = Decimal::from_str(&format_ether(arb.max_profit))?;
+            let timestamp =
+                NaiveDateTime::from_timestamp_millis(arb.event.timestamp as i64 * 1000).ok_or(
+                    anyhow::format_err!("failed to parse timestamp {}", arb.event.timestamp),
+                )?;
+            let block = arb.event.block as i32;
+            let event = serde_json::to_value(&arb.event)?;
+            let results = serde_json::to_value(&arb.results)?;
+            let failures = arb.failures as i32;
+            let live = arb.live;
+            let (eth_usd_price, profit_usd) = (arb.eth_usd_price, arb.profit_usd);
+            let placement = arb.placement.to_string();
+            let hint = arb.hint.as_ref().map(serde_json::to_value).transpose()?;
+            let non_hinted = serde_json::to_value(&arb.non_hinted)?;
+            let hint_only = arb.hint_only;
+            debug!("writing arb to postgres: {} {} eth", txhash, max_profit);
+
+            let client = self.client.clone();
+            let query = query.to_owned();
+            writes.push(async move {
+                client
+                    .execute(
+                        &query,
+                        &[
+                            &txhash,
+                            &max_profit,
+                            &block,
+                            &timestamp,
+                            &event,
+                            &results,
+                            &failures,
+                            &live,
+                            &eth_usd_price,
+                            &profit_usd,
+                            &placement,
+                            &hint,
+                            &non_hinted,
+                            &hint_only,
+                        ],
+                    )
+                    .await
+            });
+        }
+        try_join_all(writes).await?;
         Ok(())
     }
+}
 
+#[async_trait]
+impl ArbReader for PostgresConnect {
     async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
         let query = count_arbs_query(filter_params);
         let row = self.client.query_one(&query, &[]).await?;
-        let count: u32 = row.get(0);
+        let count: i64 = row.get(0);
         Ok(count as u64)
     }
 
+    async fn get_saved_events(&self, event_hashes: &[H256]) -> Result<HashSet<H256>> {
+        let event_hashes = event_hashes
+            .iter()
+            .map(|hash| format!("{:?}", hash))
+            .collect::<Vec<_>>();
+        let rows = self
+            .client
+            .query(
+                &format!("SELECT tx_hash FROM {} WHERE tx_hash = ANY($1)", ARBS_TABLE),
+                &[&event_hashes],
+            )
+            .await?;
+        rows.iter()
+            .map(|row| Ok(row.get::<_, String>(0).parse()?))
+            .collect()
+    }
+
     async fn read_arbs(
         &self,
         filter_params: &ArbFilterParams,
-        _offset: Option<u64>,
-        _limit: Option<i64>,
+        offset: Option<u64>,
+        limit: Option<i64>,
+        sort: Option<SortOrder>,
     ) -> Result<Vec<SimArbResultBatch>> {
-        let query = select_arbs_query(filter_params);
+        let query = select_arbs_query(filter_params, offset, limit, sort);
         let rows = self.client.query(&query, &[]).await?;
-        let arbs = rows
-            .into_iter()
-            .map(|row| SimArbResultBatch {
-                event: EventHistory {
-                    // TODO: change this once the rest of the fields are added to postgres
-                    block: row.get::<usize, u32>(2) as u64,
-                    timestamp: row.get::<usize, u32>(3) as u64,
-                    hint: Hint {
-                        txs: vec![],
-                        hash: H256::from_str(&row.get::<_, String>(0)).unwrap(),
-                        logs: vec![],
-                        gas_used: None,
-                        mev_gas_price: None,
-                    },
-                },
-                max_profit: parse_ether(row.get::<usize, f64>(1).to_string())
-                    .unwrap_or(U256::zero()),
-                results: vec![],
-            })
-            .collect::<Vec<_>>();
-        Ok(arbs)
+        rows.iter().map(parse_arb_row).collect()
     }
 
+    /// Gets the extrema of the blocks and timestamps of the arbs in the DB.
     async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
-        todo!()
+        let row = self
+            .client
+            .query_one(
+                &format!(
+                    "SELECT MIN(event_block), MAX(event_block),
+                        EXTRACT(EPOCH FROM MIN(event_timestamp))::BIGINT,
+                        EXTRACT(EPOCH FROM MAX(event_timestamp))::BIGINT
+                    FROM {}",
+                    ARBS_TABLE
+                ),
+                &[],
+            )
+            .await?;
+        // aggregates are NULL if there are no arbs; use the same placeholders as mongo
+        Ok(StoredArbsRanges {
+            earliest_block: row.get::<_, Option<i32>>(0).unwrap_or(1) as u64,
+            latest_block: row.get::<_, Option<i32>>(1).unwrap_or(2) as u64,
+            earliest_timestamp: row.get::<_, Option<i64>>(2).unwrap_or(1) as u64,
+            latest_timestamp: row.get::<_, Option<i64>>(3).unwrap_or(2) as u64,
+        })
+    }
+
+    async fn get_covered_ranges(&self, max_gap: u64) -> Result<Vec<BlockRange>> {
+        let rows = self
+            .client
+            .query(&covered_ranges_query(max_gap), &[])
+            .await?;
+        Ok(rows
+            .iter()
+            .map(|row| BlockRange {
+                start: row.get::<_, i32>(0) as u64,
+                end: row.get::<_, i32>(1) as u64,
+            })
+            .collect())
     }
 
     async fn export_arbs(
         &self,
-        _write_dest: WriteEngine,
-        _filter_params: &ArbFilterParams,
-    ) -> Result<()> {
-        todo!()
+        write_dest: &dyn ArbWriter,
+        filter_params: &ArbFilterParams,
+    ) -> Result<usize> {
+        let src = Arc::new(self.clone());
+        export_arbs_core(src, write_dest, filter_params).await
+    }
+
+    async fn analyze_arbs(&self, filter_params: &ArbFilterParams) -> Result<ArbReport> {
+        let events = self.get_num_arbs(filter_params).await?;
+        let profitable = where_filter(&filter_params.profitable());
+
+        let mut bucket_filters = vec![];
+        let mut min = "0".to_owned();
+        for max in PROFIT_BUCKET_BOUNDS {
+            let max = format_ether(U256::from(max));
+            bucket_filters.push(format!(
+                "COUNT(*) FILTER (WHERE profit__eth__ >= {} AND profit__eth__ < {})",
+                min, max
+            ));
+            min = max;
+        }
+        bucket_filters.push(format!("COUNT(*) FILTER (WHERE profit__eth__ >= {})", min));
+        let row = self
+            .client
+            .query_one(
+                &format!(
+                    "SELECT COUNT(*), SUM(profit__eth__),
+                        percentile_disc(0.5) WITHIN GROUP (ORDER BY profit__eth__), SUM(profit__usd__), {}
+                    FROM {}{}",
+                    bucket_filters.join(", "),
+                    ARBS_TABLE,
+                    profitable
+                ),
+                &[],
+            )
+            .await?;
+        let mut buckets = [0; PROFIT_BUCKET_BOUNDS.len() + 1];
+        for (i, bucket) in buckets.iter_mut().enumerate() {
+            *bucket = row.get::<_, i64>(4 + i) as u64;
+        }
+        let mut report = ArbReport {
+            events,
+            arbs: row.get::<_, i64>(0) as u64,
+            total_profit: eth_to_wei(row.get(1))?,
+            median_profit: eth_to_wei(row.get(2))?,
+            total_profit_usd: row.get(3),
+            profit_buckets: profit_buckets(buckets),
+            ..Default::default()
+        };
+
+        // event_timestamp is stored in UTC
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "SELECT event_timestamp::date AS day, COUNT(*), SUM(profit__eth__)
+                    FROM {}{} GROUP BY day ORDER BY day",
+                    ARBS_TABLE, profitable
+                ),
+                &[],
+            )
+            .await?;
+        for row in rows {
+            report.daily_profit.push(DailyProfit {
+                date: row.get::<_, NaiveDate>(0),
+                arbs: row.get::<_, i64>(1) as u64,
+                profit: eth_to_wei(row.get(2))?,
+            });
+        }
+
+        let best = best_results_query(filter_params);
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "{} SELECT arb->'userTrade'->'tokens'->>'token' AS token, COUNT(*), SUM(profit)
+                    FROM best GROUP BY token ORDER BY SUM(profit) DESC, token LIMIT {}",
+                    best, REPORT_TOP_N
+                ),
+                &[],
+            )
+            .await?;
+        for row in rows {
+            report.top_pairs.push(PairStats {
+                token: Address::from_str(row.get::<_, &str>(0))?,
+                arbs: row.get::<_, i64>(1) as u64,
+                profit: eth_to_wei(row.get(2))?,
+            });
+        }
+
+        let rows = self
+            .client
+            .query(
+                &format!(
+                    "{} SELECT pool, COUNT(*) FROM (
+                        SELECT arb->'backrunTrade'->>'startPool' AS pool FROM best
+                        UNION ALL SELECT arb->'backrunTrade'->>'endPool' FROM best
+                    ) pools GROUP BY pool ORDER BY COUNT(*) DESC, pool LIMIT {}",
+                    best, REPORT_TOP_N
+                ),
+                &[],
+            )
+            .await?;
+        for row in rows {
+            report.top_pools.push(PoolStats {
+                pool: Address::from_str(row.get::<_, &str>(0))?,
+                arbs: row.get::<_, i64>(1) as u64,
+            });
+        }
+
+        let row = self
+            .client
+            .query_one(
+                &format!(
+                    "{} SELECT
+                        COUNT(*) FILTER (WHERE arb->'backrunTrade'->>'startVariant' <> arb->'backrunTrade'->>'endVariant'),
+                        COUNT(*) FILTER (WHERE arb->'backrunTrade'->>'startVariant' = arb->'backrunTrade'->>'endVariant')
+                    FROM best",
+                    best
+                ),
+                &[],
+            )
+            .await?;
+        report.cross_variant_arbs = row.get::<_, i64>(0) as u64;
+        report.same_variant_arbs = row.get::<_, i64>(1) as u64;
+
+        // arbs whose user trade's impact is unknown have no priceImpactBps, so they match no bucket
+        let impact = "(arb->'userTrade'->>'priceImpactBps')::numeric";
+        let mut impact_filters = vec![];
+        let mut min = 0;
+        for max in IMPACT_BUCKET_BOUNDS_BPS {
+            let filter = format!("{} >= {} AND {} < {}", impact, min, impact, max);
+            impact_filters.push(format!(
+                "COUNT(*) FILTER (WHERE {}), SUM(profit) FILTER (WHERE {})",
+                filter, filter
+            ));
+            min = max;
+        }
+        let filter = format!("{} >= {}", impact, min);
+        impact_filters.push(format!(
+            "COUNT(*) FILTER (WHERE {}), SUM(profit) FILTER (WHERE {})",
+            filter, filter
+        ));
+        let row = self
+            .client
+            .query_one(
+                &format!("{} SELECT {} FROM best", best, impact_filters.join(", ")),
+                &[],
+            )
+            .await?;
+        let mut impacts = [(0, U256::zero()); IMPACT_BUCKET_BOUNDS_BPS.len() + 1];
+        for (i, bucket) in impacts.iter_mut().enumerate() {
+            *bucket = (
+                row.get::<_, i64>(2 * i) as u64,
+                eth_to_wei(row.get(2 * i + 1))?,
+            );
+        }
+        report.impact_buckets = impact_buckets(impacts);
+        Ok(report)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::Config;
+    use crate::{config::Config, data::arbs::coverage_gaps, interfaces::NonHintedDecision};
 
     #[tokio::test]
     async fn it_connects_postgres() -> Result<()> {
@@ -261,12 +660,204 @@ mod tests {
         Ok(())
     }
 
-    // #[tokio::test]
-    // async fn it_reads_from_db() -> Result<()> {
-    //     let config = Config::default();
-    //     let connect = PostgresConnect::new(config.postgres_url).await?;
-    //     let arbs = connect.read_arbs(ArbFilterParams::default()).await?;
-    //     println!("arbs: {:?}", arbs);
-    //     Ok(())
-    // }
+    #[tokio::test]
+    async fn it_reads_arbs_postgres() -> Result<()> {
+        let config = Config::default();
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+        })
+        .await?;
+        let mut arb = SimArbResultBatch::test_example();
+        arb.results = vec![crate::interfaces::SimArbResult::test_example()];
+        arb.placement = crate::interfaces::Placement::Pessimistic(2);
+        arb.hint = Some((&arb.event.hint).into());
+        arb.non_hinted = vec![NonHintedDecision::TradeDirection {
+            pool: arb.results[0].user_trade.pool,
+        }];
+        arb.hint_only = true;
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        let arbs = connect
+            .read_arbs(
+                &ArbFilterParams {
+                    block_start: Some(arb.event.block as u32),
+                    block_end: Some(arb.event.block as u32),
+                    timestamp_start: Some(arb.event.timestamp as u32),
+                    timestamp_end: Some(arb.event.timestamp as u32),
+                    min_profit: Some(arb.max_profit),
+                    event_tx: None,
+                    tokens: None,
+                    pools: None,
+                },
+                None,
+                None,
+                None,
+            )
+            .await?;
+        let saved = arbs
+            .iter()
+            .find(|saved| saved.event.hint.hash == arb.event.hint.hash)
+            .expect("arb not found");
+        assert_eq!(saved.max_profit, arb.max_profit);
+        assert_eq!(saved.results.len(), 1);
+        assert_eq!(saved.placement, arb.placement);
+        assert_eq!(saved.hint, arb.hint);
+        assert_eq!(saved.non_hinted, arb.non_hinted);
+        assert!(saved.hint_only);
+        let ranges = connect.get_previously_saved_ranges().await?;
+        assert!(ranges.earliest_block <= arb.event.block);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_replaces_arbs_for_saved_events_postgres() -> Result<()> {
+        let config = Config::default();
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+        })
+        .await?;
+        let mut arb = SimArbResultBatch::test_example();
+        arb.event.hint.hash = H256::random();
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        let num_arbs = connect
+            .get_num_arbs(&ArbFilterParams::event_tx(arb.event.hint.hash))
+            .await?;
+        assert_eq!(num_arbs, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_finds_coverage_gaps_postgres() -> Result<()> {
+        let config = Config::default();
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+        })
+        .await?;
+        // blocks of their own, so other tests' arbs don't end up in the window
+        let base = (rand::random::<u64>() % 900 + 1_100) * 1_000_000;
+        let arbs = (100..=200)
+            .chain(300..=400)
+            .map(|block| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::random();
+                arb.event.block = base + block;
+                arb
+            })
+            .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+        let covered = connect.get_covered_ranges(0).await?;
+        let gaps = coverage_gaps(
+            &covered,
+            BlockRange {
+                start: base + 100,
+                end: base + 400,
+            },
+            0,
+        );
+        assert_eq!(
+            gaps,
+            vec![BlockRange {
+                start: base + 201,
+                end: base + 299
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_sorts_arbs_postgres() -> Result<()> {
+        let config = Config::default();
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+        })
+        .await?;
+        // blocks of their own, so other tests' arbs aren't sorted with them
+        let base = rand::random::<u32>() % 100_000_000 + 2_000_000_000;
+        let arbs = SimArbResultBatch::sort_examples()
+            .into_iter()
+            .map(|mut arb| {
+                arb.event.hint.hash = H256::random();
+                arb.event.block += base as u64;
+                arb
+            })
+            .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+        let filter = ArbFilterParams {
+            block_start: Some(base),
+            block_end: Some(base + 3),
+            ..ArbFilterParams::none()
+        };
+        for (sort, expected) in [
+            (SortOrder::Profit, [1, 2, 0, 3]),
+            (SortOrder::AmountIn, [2, 0, 1, 3]),
+            (SortOrder::Block, [0, 2, 1, 3]),
+        ] {
+            let sorted = connect.read_arbs(&filter, None, None, Some(sort)).await?;
+            assert_eq!(
+                sorted
+                    .iter()
+                    .map(|arb| arb.event.hint.hash)
+                    .collect::<Vec<_>>(),
+                expected.map(|i| arbs[i].event.hint.hash).to_vec(),
+                "sorted by {}",
+                sort
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_analyzes_arbs_postgres() -> Result<()> {
+        let config = Config::default();
+        if config.postgres_url.is_none() {
+            println!("no postgres url, skipping test");
+            return Ok(());
+        }
+        let connect = PostgresConnect::new(PostgresConfig {
+            url: config.postgres_url.unwrap(),
+        })
+        .await?;
+        // a block of its own, so other tests' arbs don't end up in the report
+        let block = rand::random::<u32>() % 1_000_000 + 1_000_000_000;
+        let arbs = (1..4)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::random();
+                arb.event.block = block as u64;
+                arb.max_profit = U256::from(i) * U256::exp10(16);
+                arb.results = vec![crate::interfaces::SimArbResult::test_example()];
+                arb
+            })
+            .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+        let filter = ArbFilterParams {
+            block_start: Some(block),
+            block_end: Some(block),
+            ..ArbFilterParams::none()
+        };
+        let report = connect.analyze_arbs(&filter).await?;
+        assert_eq!(report.events, 3);
+        assert_eq!(report.arbs, 3);
+        assert_eq!(report.total_profit, U256::from(6) * U256::exp10(16));
+        assert_eq!(report.median_profit, U256::from(2) * U256::exp10(16));
+        assert_eq!(report.top_pools.len(), 2);
+        assert_eq!(report.cross_variant_arbs, 3);
+        Ok(())
+    }
 }
@@ -0,0 +1,499 @@
+use crate::interfaces::{PoolVariant, SimArbResultBatch};
+use chrono::{NaiveDate, NaiveDateTime};
+use ethers::{
+    types::{Address, U256},
+    utils::format_ether,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Number of token pairs & pools listed in a report.
+pub const REPORT_TOP_N: usize = 20;
+/// Upper bounds (wei) of the profit distribution's buckets: 0.001, 0.01, 0.1, 1 ETH.
+/// The last bucket has no upper bound.
+pub const PROFIT_BUCKET_BOUNDS: [u64; 4] = [
+    1_000_000_000_000_000,
+    10_000_000_000_000_000,
+    100_000_000_000_000_000,
+    1_000_000_000_000_000_000,
+];
+/// Upper bounds (basis points) of the price impact distribution's buckets: 0.1%, 0.5%, 1%, 5%.
+/// The last bucket has no upper bound.
+pub const IMPACT_BUCKET_BOUNDS_BPS: [u64; 4] = [10, 50, 100, 500];
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Number of arbs with profits in `[min, max)`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfitBucket {
+    pub min: U256,
+    /// None for the last bucket.
+    pub max: Option<U256>,
+    pub arbs: u64,
+}
+
+/// Arbs whose user trade moved its pool's price by `[min_bps, max_bps)`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImpactBucket {
+    pub min_bps: u64,
+    /// None for the last bucket.
+    pub max_bps: Option<u64>,
+    pub arbs: u64,
+    pub profit: U256,
+}
+
+/// Arbs backrunning trades of WETH/`token`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PairStats {
+    pub token: Address,
+    pub arbs: u64,
+    pub profit: U256,
+}
+
+/// Arbs that swap on `pool`.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolStats {
+    pub pool: Address,
+    pub arbs: u64,
+}
+
+/// Arbs found for events on `date` (UTC).
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyProfit {
+    pub date: NaiveDate,
+    pub arbs: u64,
+    pub profit: U256,
+}
+
+/// Aggregate stats of the arbs in a DB, produced by `ArbReader::analyze_arbs`.
+///
+/// An "arb" is an event with a profitable backrun; pair, pool & variant stats describe the most
+/// profitable backrun of each event. Profits are in wei.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArbReport {
+    /// Number of simulated events matching the filter.
+    pub events: u64,
+    /// Number of those events with a profitable arb.
+    pub arbs: u64,
+    pub total_profit: U256,
+    pub median_profit: U256,
+    /// Total profit of the arbs priced in USD (see `SimArbResultBatch::profit_usd`).
+    /// None if none of them are.
+    pub total_profit_usd: Option<f64>,
+    pub profit_buckets: Vec<ProfitBucket>,
+    /// Arbs by the price impact of the user's trade they backran. Arbs whose trade's impact is
+    /// unknown (see `UserTradeParams::price_impact_bps`) aren't counted.
+    #[serde(default)]
+    pub impact_buckets: Vec<ImpactBucket>,
+    /// Most profitable token pairs, by total profit.
+    pub top_pairs: Vec<PairStats>,
+    /// Pools with the most arbs.
+    pub top_pools: Vec<PoolStats>,
+    pub daily_profit: Vec<DailyProfit>,
+    /// Arbs between a UniswapV2 and a UniswapV3 pool.
+    pub cross_variant_arbs: u64,
+    /// Arbs between two pools of the same variant.
+    pub same_variant_arbs: u64,
+}
+
+/// Profit buckets with the given counts, which must be in the order of `PROFIT_BUCKET_BOUNDS`.
+pub fn profit_buckets(counts: [u64; PROFIT_BUCKET_BOUNDS.len() + 1]) -> Vec<ProfitBucket> {
+    counts
+        .iter()
+        .enumerate()
+        .map(|(i, arbs)| ProfitBucket {
+            min: if i == 0 {
+                U256::zero()
+            } else {
+                PROFIT_BUCKET_BOUNDS[i - 1].into()
+            },
+            max: PROFIT_BUCKET_BOUNDS.get(i).map(|&max| max.into()),
+            arbs: *arbs,
+        })
+        .collect()
+}
+
+/// Impact buckets with the given (arbs, profit), which must be in the order of `IMPACT_BUCKET_BOUNDS_BPS`.
+pub fn impact_buckets(
+    stats: [(u64, U256); IMPACT_BUCKET_BOUNDS_BPS.len() + 1],
+) -> Vec<ImpactBucket> {
+    stats
+        .iter()
+        .enumerate()
+        .map(|(i, &(arbs, profit))| ImpactBucket {
+            min_bps: if i == 0 {
+                0
+            } else {
+                IMPACT_BUCKET_BOUNDS_BPS[i - 1]
+            },
+            max_bps: IMPACT_BUCKET_BOUNDS_BPS.get(i).copied(),
+            arbs,
+            profit,
+        })
+        .collect()
+}
+
+/// The most profitable backrun of an event.
+#[derive(Clone, Debug)]
+pub struct BestArb {
+    /// Non-WETH token of the pair.
+    pub token: Address,
+    pub start_pool: Address,
+    pub end_pool: Address,
+    pub start_variant: PoolVariant,
+    pub end_variant: PoolVariant,
+    /// Price impact (bps) of the user's trade, if it's known.
+    pub price_impact_bps: Option<u64>,
+}
+
+/// The parts of a `SimArbResultBatch` that a report is built from.
+#[derive(Clone, Debug)]
+pub struct ArbSummary {
+    pub timestamp: u64,
+    pub max_profit: U256,
+    pub profit_usd: Option<f64>,
+    /// None for arbs saved without their results.
+    pub best: Option<BestArb>,
+}
+
+impl From<&SimArbResultBatch> for ArbSummary {
+    fn from(arb: &SimArbResultBatch) -> Self {
+        Self {
+            timestamp: arb.event.timestamp,
+            max_profit: arb.max_profit,
+            profit_usd: arb.profit_usd,
+            best: arb.best_result().map(|res| BestArb {
+                token: res.user_trade.tokens.token,
+                start_pool: res.backrun_trade.start_pool,
+                end_pool: res.backrun_trade.end_pool,
+                start_variant: res.backrun_trade.start_variant,
+                end_variant: res.backrun_trade.end_variant,
+                price_impact_bps: res.user_trade.price_impact_bps,
+            }),
+        }
+    }
+}
+
+/// Builds an `ArbReport` in memory, for backends that can't aggregate arbs themselves.
+#[derive(Debug, Default)]
+pub struct ArbReportBuilder {
+    profits: Vec<U256>,
+    profit_usd: Option<f64>,
+    buckets: [u64; PROFIT_BUCKET_BOUNDS.len() + 1],
+    impacts: [(u64, U256); IMPACT_BUCKET_BOUNDS_BPS.len() + 1],
+    pairs: HashMap<Address, (u64, U256)>,
+    pools: HashMap<Address, u64>,
+    days: BTreeMap<u64, (u64, U256)>,
+    cross_variant_arbs: u64,
+    same_variant_arbs: u64,
+}
+
+impl ArbReportBuilder {
+    /// Adds an arb to the report. Unprofitable arbs are skipped.
+    pub fn add(&mut self, arb: &ArbSummary) {
+        let profit = arb.max_profit;
+        if profit.is_zero() {
+            return;
+        }
+        self.profits.push(profit);
+        if let Some(profit_usd) = arb.profit_usd {
+            *self.profit_usd.get_or_insert(0.0) += profit_usd;
+        }
+        let bucket = PROFIT_BUCKET_BOUNDS
+            .iter()
+            .position(|&max| profit < max.into())
+            .unwrap_or(PROFIT_BUCKET_BOUNDS.len());
+        self.buckets[bucket] += 1;
+        let day = self
+            .days
+            .entry(arb.timestamp / SECONDS_PER_DAY)
+            .or_default();
+        *day = (day.0 + 1, day.1 + profit);
+        if let Some(best) = &arb.best {
+            let pair = self.pairs.entry(best.token).or_default();
+            *pair = (pair.0 + 1, pair.1 + profit);
+            for pool in [best.start_pool, best.end_pool] {
+                *self.pools.entry(pool).or_default() += 1;
+            }
+            if best.start_variant == best.end_variant {
+                self.same_variant_arbs += 1;
+            } else {
+                self.cross_variant_arbs += 1;
+            }
+            if let Some(impact) = best.price_impact_bps {
+                let bucket = IMPACT_BUCKET_BOUNDS_BPS
+                    .iter()
+                    .position(|&max| impact < max)
+                    .unwrap_or(IMPACT_BUCKET_BOUNDS_BPS.len());
+                let (arbs, total) = &mut self.impacts[bucket];
+                *arbs += 1;
+                *total += profit;
+            }
+        }
+    }
+
+    /// Builds the report. `events` is the number of events matching the report's filter,
+    /// including those without a profitable arb.
+    pub fn build(mut self, events: u64) -> ArbReport {
+        self.profits.sort();
+        let mut top_pairs = self
+            .pairs
+            .into_iter()
+            .map(|(token, (arbs, profit))| PairStats {
+                token,
+                arbs,
+                profit,
+            })
+            .collect::<Vec<_>>();
+        top_pairs.sort_by(|a, b| b.profit.cmp(&a.profit).then(a.token.cmp(&b.token)));
+        top_pairs.truncate(REPORT_TOP_N);
+        let mut top_pools = self
+            .pools
+            .into_iter()
+            .map(|(pool, arbs)| PoolStats { pool, arbs })
+            .collect::<Vec<_>>();
+        top_pools.sort_by(|a, b| b.arbs.cmp(&a.arbs).then(a.pool.cmp(&b.pool)));
+        top_pools.truncate(REPORT_TOP_N);
+        ArbReport {
+            events,
+            arbs: self.profits.len() as u64,
+            total_profit: self
+                .profits
+                .iter()
+                .fold(U256::zero(), |acc, profit| acc + profit),
+            // the lower of the two middle profits, like postgres' percentile_disc(0.5)
+            median_profit: self
+                .profits
+                .get(self.profits.len().saturating_sub(1) / 2)
+                .copied()
+                .unwrap_or_default(),
+            total_profit_usd: self.profit_usd,
+            profit_buckets: profit_buckets(self.buckets),
+            impact_buckets: impact_buckets(self.impacts),
+            top_pairs,
+            top_pools,
+            daily_profit: self
+                .days
+                .into_iter()
+                .filter_map(|(day, (arbs, profit))| {
+                    let date =
+                        NaiveDateTime::from_timestamp_opt((day * SECONDS_PER_DAY) as i64, 0)?
+                            .date();
+                    Some(DailyProfit { date, arbs, profit })
+                })
+                .collect(),
+            cross_variant_arbs: self.cross_variant_arbs,
+            same_variant_arbs: self.same_variant_arbs,
+        }
+    }
+}
+
+/// Formats wei as ETH, without trailing zeros.
+fn eth(wei: U256) -> String {
+    format_ether(wei)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_owned()
+}
+
+/// Prints the report as text, for `hindsight analyze`.
+impl std::fmt::Display for ArbReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "events: {}", self.events)?;
+        writeln!(f, "profitable arbs: {}", self.arbs)?;
+        match self.total_profit_usd {
+            Some(usd) => writeln!(
+                f,
+                "total profit: {} Ξ (${:.2})",
+                eth(self.total_profit),
+                usd
+            )?,
+            None => writeln!(f, "total profit: {} Ξ", eth(self.total_profit))?,
+        }
+        writeln!(f, "median profit: {} Ξ", eth(self.median_profit))?;
+
+        writeln!(f, "\nprofit distribution:")?;
+        for bucket in &self.profit_buckets {
+            match bucket.max {
+                Some(max) => {
+                    writeln!(f, "  {} - {} Ξ: {}", eth(bucket.min), eth(max), bucket.arbs)?
+                }
+                None => writeln!(f, "  >= {} Ξ: {}", eth(bucket.min), bucket.arbs)?,
+            }
+        }
+
+        if self.impact_buckets.iter().any(|bucket| bucket.arbs > 0) {
+            writeln!(f, "\nprice impact of backrun trades:")?;
+            let pct = |bps: u64| format!("{}%", bps as f64 / 100.0);
+            for bucket in &self.impact_buckets {
+                let range = match bucket.max_bps {
+                    Some(max) => format!("{} - {}", pct(bucket.min_bps), pct(max)),
+                    None => format!(">= {}", pct(bucket.min_bps)),
+                };
+                writeln!(
+                    f,
+                    "  {}: {} arbs, {} Ξ",
+                    range,
+                    bucket.arbs,
+                    eth(bucket.profit)
+                )?;
+            }
+        }
+
+        writeln!(f, "\ntop token pairs (by profit):")?;
+        for pair in &self.top_pairs {
+            writeln!(
+                f,
+                "  WETH/{:?}: {} arbs, {} Ξ",
+                pair.token,
+                pair.arbs,
+                eth(pair.profit)
+            )?;
+        }
+
+        writeln!(f, "\ntop pools (by arbs):")?;
+        for pool in &self.top_pools {
+            writeln!(f, "  {:?}: {} arbs", pool.pool, pool.arbs)?;
+        }
+
+        writeln!(f, "\nprofit per day:")?;
+        for day in &self.daily_profit {
+            writeln!(
+                f,
+                "  {}: {} arbs, {} Ξ",
+                day.date,
+                day.arbs,
+                eth(day.profit)
+            )?;
+        }
+
+        let share = |arbs: u64| {
+            let total = self.cross_variant_arbs + self.same_variant_arbs;
+            arbs as f64 / total.max(1) as f64 * 100.0
+        };
+        writeln!(f, "\npool variants:")?;
+        writeln!(
+            f,
+            "  V2 <-> V3: {} ({:.1}%)",
+            self.cross_variant_arbs,
+            share(self.cross_variant_arbs)
+        )?;
+        write!(
+            f,
+            "  same variant: {} ({:.1}%)",
+            self.same_variant_arbs,
+            share(self.same_variant_arbs)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arb(timestamp: u64, profit_finney: u64, token: u64, cross_variant: bool) -> ArbSummary {
+        ArbSummary {
+            timestamp,
+            max_profit: U256::from(profit_finney) * U256::exp10(15),
+            // priced at 2000 USD/ETH
+            profit_usd: Some(profit_finney as f64 * 2.0),
+            best: Some(BestArb {
+                token: Address::from_low_u64_be(token),
+                start_pool: Address::from_low_u64_be(100 + token),
+                end_pool: Address::from_low_u64_be(200),
+                start_variant: PoolVariant::UniswapV2,
+                end_variant: if cross_variant {
+                    PoolVariant::UniswapV3
+                } else {
+                    PoolVariant::UniswapV2
+                },
+                // bigger arbs backrun bigger trades
+                price_impact_bps: Some(profit_finney),
+            }),
+        }
+    }
+
+    #[test]
+    fn it_builds_arb_report() {
+        let mut builder = ArbReportBuilder::default();
+        // 2023-07-06, then 2023-07-07
+        let (day1, day2) = (1688601600, 1688688000);
+        builder.add(&arb(day1, 5, 1, true));
+        builder.add(&arb(day1 + 60, 50, 2, false));
+        builder.add(&arb(day2, 2000, 1, true));
+        builder.add(&arb(day2, 0, 3, true));
+        let report = builder.build(10);
+
+        assert_eq!(report.events, 10);
+        assert_eq!(report.arbs, 3);
+        assert_eq!(report.total_profit, U256::from(2055) * U256::exp10(15));
+        assert_eq!(report.median_profit, U256::from(50) * U256::exp10(15));
+        assert_eq!(
+            report
+                .profit_buckets
+                .iter()
+                .map(|bucket| bucket.arbs)
+                .collect::<Vec<_>>(),
+            vec![0, 1, 1, 0, 1]
+        );
+        assert_eq!(
+            report
+                .impact_buckets
+                .iter()
+                .map(|bucket| (bucket.arbs, bucket.profit))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, U256::from(5) * U256::exp10(15)),
+                (0, U256::zero()),
+                (1, U256::from(50) * U256::exp10(15)),
+                (0, U256::zero()),
+                (1, U256::from(2000) * U256::exp10(15)),
+            ]
+        );
+        assert!(report.to_string().contains(">= 5%: 1 arbs, 2 Ξ"));
+        assert_eq!(report.top_pairs[0].token, Address::from_low_u64_be(1));
+        assert_eq!(report.top_pairs[0].arbs, 2);
+        assert_eq!(report.top_pairs.len(), 2);
+        assert_eq!(
+            report.top_pools[0],
+            PoolStats {
+                pool: Address::from_low_u64_be(200),
+                arbs: 3
+            }
+        );
+        assert_eq!(
+            report
+                .daily_profit
+                .iter()
+                .map(|day| (day.date.to_string(), day.arbs))
+                .collect::<Vec<_>>(),
+            vec![("2023-07-06".to_owned(), 2), ("2023-07-07".to_owned(), 1)]
+        );
+        assert_eq!(report.cross_variant_arbs, 2);
+        assert_eq!(report.same_variant_arbs, 1);
+        assert_eq!(report.total_profit_usd, Some(4110.0));
+        assert!(report.to_string().contains("median profit: 0.05 Ξ"));
+        assert!(report
+            .to_string()
+            .contains("total profit: 2.055 Ξ ($4110.00)"));
+    }
+
+    #[test]
+    fn it_reads_back_json_reports() {
+        let mut builder = ArbReportBuilder::default();
+        builder.add(&arb(1688601600, 5, 1, true));
+        builder.add(&arb(1688688000, 2000, 2, false));
+        let report = builder.build(2);
+
+        let json = crate::commands::render(&report, true).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["arbs"], 2);
+        assert!(parsed["topPairs"].is_array(), "{}", json);
+        assert_eq!(serde_json::from_str::<ArbReport>(&json).unwrap(), report);
+    }
+}
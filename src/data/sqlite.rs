@@ -0,0 +1,575 @@
+use super::{
+    arbs::{analyze_arbs_core, export_arbs_core, ArbFilterParams, ArbReader, ArbWriter, SortOrder},
+    report::ArbReport,
+};
+use crate::{
+    debug,
+    interfaces::{BlockRange, SimArbResultBatch, StoredArbsRanges},
+    Result,
+};
+use async_trait::async_trait;
+use ethers::{
+    types::{Address, H256, U256},
+    utils::hex,
+};
+use rusqlite::{params, Connection};
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+const ARBS_TABLE: &'static str = "arbs";
+
+#[derive(Clone, Debug)]
+pub struct SqliteConfig {
+    pub path: PathBuf,
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        let config = crate::config::Config::default();
+        Self {
+            path: config.sqlite_path,
+        }
+    }
+}
+
+/// Talks to a local SQLite database file.
+///
+/// rusqlite is synchronous, so every query runs on tokio's blocking thread pool.
+#[derive(Clone)]
+pub struct SqliteConnect {
+    conn: Arc<Mutex<Connection>>,
+}
+
+/// U256s are stored as fixed-width hex strings, so that comparing strings compares values.
+fn u256_to_sql(value: U256) -> String {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Matches arbs with a result that has one of `addresses` at any of `paths`.
+fn any_result(paths: &[&str], addresses: &[Address]) -> String {
+    let addresses = addresses
+        .iter()
+        .map(|address| format!("'{:?}'", address))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let clauses = paths
+        .iter()
+        .map(|path| format!("json_extract(value, '{}') IN ({})", path, addresses))
+        .collect::<Vec<_>>();
+    format!(
+        "EXISTS (SELECT 1 FROM json_each(arb, '$.results') WHERE {})",
+        clauses.join(" OR ")
+    )
+}
+
+fn where_filter(filter: &ArbFilterParams) -> String {
+    let mut params = vec![];
+    if let Some(block_start) = filter.block_start {
+        params.push(format!("event_block >= {}", block_start));
+    }
+    if let Some(block_end) = filter.block_end {
+        params.push(format!("event_block <= {}", block_end));
+    }
+    if let Some(timestamp_start) = filter.timestamp_start {
+        params.push(format!("event_timestamp >= {}", timestamp_start));
+    }
+    if let Some(timestamp_end) = filter.timestamp_end {
+        params.push(format!("event_timestamp <= {}", timestamp_end));
+    }
+    if let Some(min_profit) = filter.min_profit {
+        params.push(format!("max_profit >= '{}'", u256_to_sql(min_profit)));
+    }
+    if let Some(event_tx) = filter.event_tx {
+        params.push(format!("tx_hash = '{:?}'", event_tx));
+    }
+    if let Some(tokens) = &filter.tokens {
+        params.push(any_result(
+            &["$.userTrade.tokens.token", "$.userTrade.tokens.weth"],
+            tokens,
+        ));
+    }
+    if let Some(pools) = &filter.pools {
+        params.push(any_result(
+            &["$.backrunTrade.startPool", "$.backrunTrade.endPool"],
+            pools,
+        ));
+    }
+    if params.is_empty() {
+        return "".to_owned();
+    }
+    format!(" WHERE {}", params.join(" AND "))
+}
+
+/// The amount in of an arb's most profitable result. Amounts in results are hex strings without
+/// leading zeros, so comparing their lengths, then the strings, compares their values.
+const BEST_AMOUNT_IN: &str = "(SELECT json_extract(value, '$.backrunTrade.amountIn') FROM json_each(arb, '$.results')
+    ORDER BY length(json_extract(value, '$.backrunTrade.profit')) DESC, json_extract(value, '$.backrunTrade.profit') DESC
+    LIMIT 1)";
+
+/// Orders arbs by `sort`, biggest first, or in the order they landed if unsorted.
+fn order_by(sort: Option<SortOrder>) -> String {
+    match sort {
+        None => " ORDER BY event_timestamp, tx_hash".to_owned(),
+        // max_profit is zero-padded hex, so it sorts like a number
+        Some(SortOrder::Profit) => " ORDER BY max_profit DESC, tx_hash".to_owned(),
+        Some(SortOrder::AmountIn) => format!(
+            " ORDER BY length({0}) DESC, {0} DESC, tx_hash",
+            BEST_AMOUNT_IN
+        ),
+        Some(SortOrder::Block) => " ORDER BY event_block DESC, tx_hash".to_owned(),
+    }
+}
+
+impl SqliteConnect {
+    /// Opens (or creates) the database file and creates the arbs table if needed.
+    pub async fn new(config: SqliteConfig) -> Result<Self> {
+        let conn = tokio::task::spawn_blocking(move || -> Result<Connection> {
+            let conn = Connection::open(&config.path)?;
+            // WAL lets readers (e.g. `export`) run while a scan is writing
+            conn.query_row("PRAGMA journal_mode=WAL", [], |_| Ok(()))?;
+            conn.execute_batch(&format!(
+                "CREATE TABLE IF NOT EXISTS {table} (
+                    tx_hash TEXT NOT NULL PRIMARY KEY,
+                    event_block INTEGER NOT NULL,
+                    event_timestamp INTEGER NOT NULL,
+                    max_profit TEXT NOT NULL,
+                    arb TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS {table}_event_block_idx ON {table} (event_block);
+                CREATE INDEX IF NOT EXISTS {table}_event_timestamp_idx ON {table} (event_timestamp);
+                CREATE INDEX IF NOT EXISTS {table}_max_profit_idx ON {table} (max_profit);",
+                table = ARBS_TABLE
+            ))?;
+            Ok(conn)
+        })
+        .await??;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs `f` with the connection on the blocking thread pool.
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn
+                .lock()
+                .map_err(|err| anyhow::format_err!("sqlite connection poisoned: {}", err))?;
+            f(&mut conn)
+        })
+        .await?
+    }
+}
+
+#[async_trait]
+impl ArbWriter for SqliteConnect {
+    async fn write_arbs(&self, arbs: &Vec<SimArbResultBatch>) -> Result<()> {
+        let arbs = arbs.to_owned();
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "INSERT INTO {} (tx_hash, event_block, event_timestamp, max_profit, arb)
+                        VALUES (?1, ?2, ?3, ?4, ?5)
+                        ON CONFLICT (tx_hash) DO UPDATE SET max_profit = ?4, arb = ?5",
+                    ARBS_TABLE
+                ))?;
+                for arb in arbs {
+                    let tx_hash = format!("{:?}", arb.event.hint.hash);
+                    debug!("writing arb to sqlite: {}", tx_hash);
+                    stmt.execute(params![
+                        tx_hash,
+                        arb.event.block as i64,
+                        arb.event.timestamp as i64,
+                        u256_to_sql(arb.max_profit),
+                        serde_json::to_string(&arb)?,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl ArbReader for SqliteConnect {
+    async fn read_arbs(
+        &self,
+        filter_params: &ArbFilterParams,
+        offset: Option<u64>,
+        limit: Option<i64>,
+        sort: Option<SortOrder>,
+    ) -> Result<Vec<SimArbResultBatch>> {
+        let query = format!(
+            "SELECT arb FROM {}{}{} LIMIT {} OFFSET {}",
+            ARBS_TABLE,
+            where_filter(filter_params),
+            order_by(sort),
+            // sqlite requires a LIMIT to use OFFSET; -1 means no limit
+            limit.unwrap_or(-1),
+            offset.unwrap_or(0)
+        );
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&query)?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            let mut arbs = vec![];
+            for row in rows {
+                arbs.push(serde_json::from_str(&row?)?);
+            }
+            Ok(arbs)
+        })
+        .await
+    }
+
+    async fn get_num_arbs(&self, filter_params: &ArbFilterParams) -> Result<u64> {
+        let query = format!(
+            "SELECT COUNT(*) FROM {}{}",
+            ARBS_TABLE,
+            where_filter(filter_params)
+        );
+        self.with_conn(move |conn| {
+            let count: i64 = conn.query_row(&query, [], |row| row.get(0))?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn get_saved_events(&self, event_hashes: &[H256]) -> Result<HashSet<H256>> {
+        if event_hashes.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let query = format!(
+            "SELECT tx_hash FROM {} WHERE tx_hash IN ({})",
+            ARBS_TABLE,
+            event_hashes
+                .iter()
+                .map(|hash| format!("'{:?}'", hash))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&query)?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            let mut saved = HashSet::new();
+            for row in rows {
+                saved.insert(row?.parse()?);
+            }
+            Ok(saved)
+        })
+        .await
+    }
+
+    /// Gets the extrema of the blocks and timestamps of the arbs in the DB.
+    async fn get_previously_saved_ranges(&self) -> Result<StoredArbsRanges> {
+        let query = format!(
+            "SELECT MIN(event_block), MAX(event_block), MIN(event_timestamp), MAX(event_timestamp) FROM {}",
+            ARBS_TABLE
+        );
+        self.with_conn(move |conn| {
+            let extrema = conn.query_row(&query, [], |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                    row.get::<_, Option<i64>>(3)?,
+                ))
+            })?;
+            // aggregates are NULL if there are no arbs; use the same placeholders as mongo
+            Ok(StoredArbsRanges {
+                earliest_block: extrema.0.unwrap_or(1) as u64,
+                latest_block: extrema.1.unwrap_or(2) as u64,
+                earliest_timestamp: extrema.2.unwrap_or(1) as u64,
+                latest_timestamp: extrema.3.unwrap_or(2) as u64,
+            })
+        })
+        .await
+    }
+
+    /// Groups blocks into ranges with window functions; a new range starts at every block
+    /// more than `max_gap` blocks after the previous one.
+    async fn get_covered_ranges(&self, max_gap: u64) -> Result<Vec<BlockRange>> {
+        let query = format!(
+            "SELECT MIN(event_block), MAX(event_block) FROM (
+                SELECT event_block, SUM(new_range) OVER (ORDER BY event_block) AS range_id FROM (
+                    SELECT event_block,
+                        CASE WHEN event_block - LAG(event_block) OVER (ORDER BY event_block) <= {}
+                        THEN 0 ELSE 1 END AS new_range
+                    FROM (SELECT DISTINCT event_block FROM {})
+                )
+            ) GROUP BY range_id ORDER BY MIN(event_block)",
+            max_gap + 1,
+            ARBS_TABLE
+        );
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok(BlockRange {
+                    start: row.get::<_, i64>(0)? as u64,
+                    end: row.get::<_, i64>(1)? as u64,
+                })
+            })?;
+            Ok(rows.collect::<std::result::Result<Vec<_>, _>>()?)
+        })
+        .await
+    }
+
+    async fn export_arbs(
+        &self,
+        write_dest: &dyn ArbWriter,
+        filter_params: &ArbFilterParams,
+    ) -> Result<usize> {
+        let src = Arc::new(self.clone());
+        export_arbs_core(src, write_dest, filter_params).await
+    }
+
+    async fn analyze_arbs(&self, filter_params: &ArbFilterParams) -> Result<ArbReport> {
+        analyze_arbs_core(Arc::new(self.clone()), filter_params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::arbs::coverage_gaps;
+    use rand::Rng;
+
+    async fn connect() -> Result<(SqliteConnect, PathBuf)> {
+        let rnum = rand::thread_rng().gen_range(0..1000000);
+        let path = std::env::temp_dir().join(format!("test_hindsight_{}.sqlite", rnum));
+        let connect = SqliteConnect::new(SqliteConfig { path: path.clone() }).await?;
+        Ok((connect, path))
+    }
+
+    #[tokio::test]
+    async fn it_writes_and_filters_arbs_sqlite() -> Result<()> {
+        let (connect, path) = connect().await?;
+        let arbs = (0..10)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = ethers::types::H256::from_low_u64_be(i);
+                arb.event.block = 100 + i;
+                arb.event.timestamp = 0x6464beef + (i * 12);
+                arb.max_profit = U256::from(i) * U256::exp10(18);
+                arb
+            })
+            .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+
+        let filter_params = ArbFilterParams {
+            block_start: Some(102),
+            block_end: Some(108),
+            timestamp_start: None,
+            timestamp_end: None,
+            min_profit: Some(U256::from(4) * U256::exp10(18)),
+            event_tx: None,
+            tokens: None,
+            pools: None,
+        };
+        let num_arbs = connect.get_num_arbs(&filter_params).await?;
+        let page = connect
+            .read_arbs(&filter_params, Some(1), Some(2), None)
+            .await?;
+        let ranges = connect.get_previously_saved_ranges().await?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(num_arbs, 5);
+        assert_eq!(
+            page.iter().map(|arb| arb.event.block).collect::<Vec<_>>(),
+            vec![105, 106]
+        );
+        assert_eq!(ranges.earliest_block, 100);
+        assert_eq!(ranges.latest_timestamp, 0x6464beef + 108);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_filters_arbs_by_token_and_pool_sqlite() -> Result<()> {
+        let (connect, path) = connect().await?;
+        let mut other = crate::interfaces::SimArbResult::test_example();
+        other.user_trade.tokens.token = Address::from_low_u64_be(5);
+        other.backrun_trade.end_pool = Address::from_low_u64_be(6);
+        let arbs = [
+            vec![crate::interfaces::SimArbResult::test_example()],
+            vec![other],
+            vec![],
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, results)| {
+            let mut arb = SimArbResultBatch::test_example();
+            arb.event.hint.hash = ethers::types::H256::from_low_u64_be(i as u64);
+            arb.results = results;
+            arb
+        })
+        .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+
+        let filter = |tokens: Option<u64>, pools: Option<u64>| ArbFilterParams {
+            tokens: tokens.map(|token| vec![Address::from_low_u64_be(token)]),
+            pools: pools.map(|pool| vec![Address::from_low_u64_be(pool)]),
+            ..ArbFilterParams::none()
+        };
+        let by_token = connect.get_num_arbs(&filter(Some(2), None)).await?;
+        let by_weth = connect.get_num_arbs(&filter(Some(1), None)).await?;
+        let by_pool = connect
+            .read_arbs(&filter(None, Some(6)), None, None, None)
+            .await?;
+        let by_both = connect.get_num_arbs(&filter(Some(2), Some(6))).await?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(by_token, 1);
+        assert_eq!(by_weth, 2);
+        assert_eq!(by_pool.len(), 1);
+        assert_eq!(by_pool[0].event.hint.hash, arbs[1].event.hint.hash);
+        assert_eq!(by_both, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_sorts_arbs_sqlite() -> Result<()> {
+        let (connect, path) = connect().await?;
+        let arbs = SimArbResultBatch::sort_examples();
+        connect.write_arbs(&arbs).await?;
+
+        let mut orders = vec![];
+        for sort in [SortOrder::Profit, SortOrder::AmountIn, SortOrder::Block] {
+            let sorted = connect
+                .read_arbs(&ArbFilterParams::none(), None, None, Some(sort))
+                .await?;
+            orders.push(
+                sorted
+                    .iter()
+                    .map(|arb| arb.event.hint.hash.to_low_u64_be())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        let top = connect
+            .read_arbs(
+                &ArbFilterParams::none(),
+                Some(1),
+                Some(2),
+                Some(SortOrder::Profit),
+            )
+            .await?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(
+            orders,
+            vec![vec![1, 2, 0, 3], vec![2, 0, 1, 3], vec![0, 2, 1, 3]]
+        );
+        assert_eq!(
+            top.iter()
+                .map(|arb| arb.event.hint.hash.to_low_u64_be())
+                .collect::<Vec<_>>(),
+            vec![2, 0]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_finds_coverage_gaps_sqlite() -> Result<()> {
+        let (connect, path) = connect().await?;
+        let arbs = (100..=200)
+            .chain(300..=400)
+            .map(|block| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = ethers::types::H256::from_low_u64_be(block);
+                arb.event.block = block;
+                arb
+            })
+            .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+
+        let covered = connect.get_covered_ranges(0).await?;
+        let gaps = coverage_gaps(
+            &covered,
+            BlockRange {
+                start: 100,
+                end: 400,
+            },
+            0,
+        );
+        // a gap of 98 blocks is still covered if we allow gaps that big
+        let covered_loosely = connect.get_covered_ranges(98).await?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(
+            covered,
+            vec![
+                BlockRange {
+                    start: 100,
+                    end: 200
+                },
+                BlockRange {
+                    start: 300,
+                    end: 400
+                }
+            ]
+        );
+        assert_eq!(
+            gaps,
+            vec![BlockRange {
+                start: 201,
+                end: 299
+            }]
+        );
+        assert_eq!(covered_loosely.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_replaces_arbs_for_saved_events_sqlite() -> Result<()> {
+        let (connect, path) = connect().await?;
+        let mut arb = SimArbResultBatch::test_example();
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        arb.max_profit = 42.into();
+        connect.write_arbs(&vec![arb.clone()]).await?;
+        let arbs = connect
+            .read_arbs(&ArbFilterParams::none(), None, None, None)
+            .await?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(arbs.len(), 1);
+        assert_eq!(arbs[0].max_profit, 42.into());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_analyzes_arbs_sqlite() -> Result<()> {
+        let (connect, path) = connect().await?;
+        let arbs = (0..4)
+            .map(|i| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = ethers::types::H256::from_low_u64_be(i);
+                arb.max_profit = U256::from(i) * U256::exp10(16);
+                arb.results = vec![crate::interfaces::SimArbResult::test_example()];
+                arb
+            })
+            .collect::<Vec<_>>();
+        connect.write_arbs(&arbs).await?;
+        let report = connect.analyze_arbs(&ArbFilterParams::none()).await?;
+        std::fs::remove_file(path)?;
+
+        assert_eq!(report.events, 4);
+        // the first arb isn't profitable
+        assert_eq!(report.arbs, 3);
+        assert_eq!(report.total_profit, U256::from(6) * U256::exp10(16));
+        assert_eq!(report.median_profit, U256::from(2) * U256::exp10(16));
+        assert_eq!(report.top_pairs.len(), 1);
+        assert_eq!(report.top_pairs[0].arbs, 3);
+        assert_eq!(report.cross_variant_arbs, 3);
+        assert_eq!(report.daily_profit.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{interfaces::SimArbResultBatch, Result};
+use ethers::types::{I256, U256};
+use serde_json::{Number, Value};
+use std::str::FromStr;
+
+/// Decimals of ETH (and WETH) amounts, and of pool prices (TKN per WETH).
+pub const ETH_DECIMALS: u8 = 18;
+
+/// How exports render amounts (ETH & token amounts, and pool prices), which are saved as raw
+/// integers: wei, a token's smallest unit, or prices scaled by 1e18.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AmountUnits {
+    /// Raw integers, as they're saved. JSON exports keep the saved format (hex strings), and CSV
+    /// exports write decimal strings.
+    #[default]
+    Wei,
+    /// Whole units (ETH, tokens by their decimals, or prices), as exact decimal numbers.
+    Eth,
+    /// Raw integers, each followed by its whole-unit rendering, as `<field>Units` in JSON and
+    /// `<field>_units` in CSV.
+    Both,
+}
+
+impl AmountUnits {
+    /// Whether raw amounts are written.
+    pub fn raw(&self) -> bool {
+        *self != AmountUnits::Eth
+    }
+
+    /// Whether amounts in whole units are written.
+    pub fn whole(&self) -> bool {
+        *self != AmountUnits::Wei
+    }
+}
+
+impl std::fmt::Display for AmountUnits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AmountUnits::Wei => write!(f, "wei"),
+            AmountUnits::Eth => write!(f, "eth"),
+            AmountUnits::Both => write!(f, "both"),
+        }
+    }
+}
+
+impl FromStr for AmountUnits {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "wei" => Ok(AmountUnits::Wei),
+            "eth" => Ok(AmountUnits::Eth),
+            "both" => Ok(AmountUnits::Both),
+            _ => Err(format!("invalid units: {} (expected wei, eth, or both)", s)),
+        }
+    }
+}
+
+/// Renders `amount` (in its smallest unit) in whole units of `decimals` decimals, exactly.
+/// Trailing zeros are trimmed, e.g. 1.5 ETH is `1.5`, and 2 ETH is `2`.
+pub fn format_amount(amount: U256, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    if decimals == 0 {
+        return digits;
+    }
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (int, frac) = digits.split_at(digits.len() - decimals);
+    let frac = frac.trim_end_matches('0');
+    if frac.is_empty() {
+        int.to_owned()
+    } else {
+        format!("{}.{}", int, frac)
+    }
+}
+
+/// Like `format_amount`, for signed amounts (e.g. the amounts a user trade sent to its pool).
+pub fn format_signed_amount(amount: I256, decimals: u8) -> String {
+    let (sign, abs) = amount.into_sign_and_abs();
+    let abs = format_amount(abs, decimals);
+    if sign.is_negative() {
+        format!("-{}", abs)
+    } else {
+        abs
+    }
+}
+
+/// A JSON number of a decimal rendered by `format_amount`. Its digits are kept as they are
+/// (serde_json's `arbitrary_precision`), so amounts beyond f64's precision aren't rounded.
+fn json_number(amount: String) -> Value {
+    Value::Number(Number::from_str(&amount).expect("formatted amounts are valid JSON numbers"))
+}
+
+/// Renders the field `key` of the JSON object `value` in `units`, given its amount in whole units.
+fn render_field(value: &mut Value, key: &str, amount: Option<String>, units: AmountUnits) {
+    let (Some(object), Some(amount)) = (value.as_object_mut(), amount) else {
+        return;
+    };
+    match units {
+        AmountUnits::Wei => {}
+        AmountUnits::Eth => {
+            object.insert(key.to_owned(), json_number(amount));
+        }
+        AmountUnits::Both => {
+            object.insert(format!("{}Units", key), json_number(amount));
+        }
+    }
+}
+
+/// Serializes `arb` for a JSON export w/ its amounts in `units`.
+///
+/// Amounts of a user trade's token are only rendered in whole units if its decimals are known;
+/// otherwise they're left raw.
+pub fn render_arb(arb: &SimArbResultBatch, units: AmountUnits) -> Result<Value> {
+    let mut value = serde_json::to_value(arb)?;
+    if !units.whole() {
+        return Ok(value);
+    }
+    let eth = |amount| Some(format_amount(amount, ETH_DECIMALS));
+    render_field(&mut value, "maxProfit", eth(arb.max_profit), units);
+    let Some(results) = value.get_mut("results").and_then(Value::as_array_mut) else {
+        return Ok(value);
+    };
+    for (res, value) in arb.results.iter().zip(results) {
+        let backrun = &res.backrun_trade;
+        if let Some(value) = value.get_mut("backrunTrade") {
+            for (key, amount) in [
+                ("amountIn", backrun.amount_in),
+                ("balanceEnd", backrun.balance_end),
+                ("profit", backrun.profit),
+                ("gasCost", backrun.gas_cost),
+                ("userPoolPrice", backrun.user_pool_price),
+                ("altPoolPrice", backrun.alt_pool_price),
+            ] {
+                render_field(value, key, eth(amount), units);
+            }
+        }
+        let user_trade = &res.user_trade;
+        if let Some(value) = value.get_mut("userTrade") {
+            render_field(value, "price", eth(user_trade.price), units);
+            render_field(value, "priceBefore", eth(user_trade.price_before), units);
+            let (decimals0, decimals1) = user_trade.amounts_sent_decimals();
+            for (key, amount, decimals) in [
+                ("amount0Sent", user_trade.amount0_sent, decimals0),
+                ("amount1Sent", user_trade.amount1_sent, decimals1),
+            ] {
+                let amount = decimals.map(|decimals| format_signed_amount(amount, decimals));
+                render_field(value, key, amount, units);
+            }
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+
+    #[test]
+    fn it_formats_amounts_exactly() {
+        // 2^53 + 1 wei, and ~123M ETH: neither fits in an f64
+        let odd = U256::from(9_007_199_254_740_993u64);
+        let big = U256::from_dec_str("123456789012345678901234567").unwrap();
+        assert_eq!(format_amount(odd, 18), "0.009007199254740993");
+        assert_eq!(format_amount(big, 18), "123456789.012345678901234567");
+        assert_eq!(format_amount(big, 0), "123456789012345678901234567");
+        assert_eq!(format_amount(U256::exp10(18) * 3 / 2, 18), "1.5");
+        assert_eq!(format_amount(U256::exp10(18) * 2, 18), "2");
+        assert_eq!(format_amount(U256::zero(), 6), "0");
+        assert_eq!(format_amount(U256::from(1), 6), "0.000001");
+        assert_eq!(format_amount(U256::MAX, 255).len(), 257);
+        assert_eq!(
+            format_signed_amount(-I256::from_raw(big), 6),
+            "-123456789012345678901.234567"
+        );
+    }
+
+    #[test]
+    fn it_renders_arbs_in_units() -> Result<()> {
+        let mut arb = SimArbResultBatch::test_example();
+        let mut res = SimArbResult::test_example();
+        let big = U256::from_dec_str("123456789012345678901234567").unwrap();
+        res.backrun_trade.amount_in = big;
+        res.user_trade.token0_is_weth = true;
+        res.user_trade.token_decimals = Some(6);
+        res.user_trade.amount0_sent = I256::from_raw(big);
+        res.user_trade.amount1_sent = I256::from(-1_500_000);
+        arb.results = vec![res.to_owned()];
+
+        let wei = render_arb(&arb, AmountUnits::Wei)?;
+        assert_eq!(wei, serde_json::to_value(&arb)?);
+
+        let eth = render_arb(&arb, AmountUnits::Eth)?;
+        let trade = &eth["results"][0]["backrunTrade"];
+        // compared as text, since an f64 would round them
+        assert_eq!(
+            trade["amountIn"].to_string(),
+            "123456789.012345678901234567"
+        );
+        assert_eq!(
+            trade["profit"].to_string(),
+            format_amount(res.backrun_trade.profit, 18)
+        );
+        let user_trade = &eth["results"][0]["userTrade"];
+        assert_eq!(
+            user_trade["amount0Sent"].to_string(),
+            "123456789.012345678901234567"
+        );
+        assert_eq!(user_trade["amount1Sent"].to_string(), "-1.5");
+        assert!(trade.get("amountInUnits").is_none());
+
+        let both = render_arb(&arb, AmountUnits::Both)?;
+        let trade = &both["results"][0]["backrunTrade"];
+        assert_eq!(trade["amountIn"], serde_json::to_value(big)?);
+        assert_eq!(
+            trade["amountInUnits"].to_string(),
+            "123456789.012345678901234567"
+        );
+        assert_eq!(
+            both["maxProfitUnits"].to_string(),
+            format_amount(arb.max_profit, 18)
+        );
+        // the raw fields are kept, so it still reads back as an arb
+        let read: SimArbResultBatch = serde_json::from_value(both)?;
+        assert_eq!(read.results[0].backrun_trade.amount_in, big);
+
+        // amounts of tokens w/ unknown decimals stay raw
+        arb.results[0].user_trade.token_decimals = None;
+        let eth = render_arb(&arb, AmountUnits::Eth)?;
+        let user_trade = &eth["results"][0]["userTrade"];
+        assert_eq!(
+            user_trade["amount0Sent"].to_string(),
+            "123456789.012345678901234567"
+        );
+        assert_eq!(
+            user_trade["amount1Sent"],
+            serde_json::to_value(I256::from(-1_500_000))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_parses_units() {
+        for units in [AmountUnits::Wei, AmountUnits::Eth, AmountUnits::Both] {
+            assert_eq!(units.to_string().parse(), Ok(units));
+        }
+        assert!("gwei".parse::<AmountUnits>().is_err());
+    }
+}
@@ -1,5 +1,6 @@
-use crate::Error;
-use ethers::types::{Address, H256};
+use ethers::types::{Address, Bytes, H256, U256};
+use ethers::utils::format_ether;
+use revm::primitives::Halt;
 
 #[derive(Clone, Debug)]
 pub enum HindsightError {
@@ -8,46 +9,145 @@ pub enum HindsightError {
     /// The specified transaction hash could not be found in the event map.
     EventNotCached(H256),
     /// Could not find another trading pool with same tokens as the given pool.
-    PoolNotFound(Address),
+    NoArbPool(Address),
     /// Could not find transaction onchain.
     TxNotLanded(H256),
+    /// The tx's receipt is missing long after its block, so the tx was dropped (e.g. reorged out).
+    TxDropped(H256),
     /// Failed to call smart contract.
     CallError(String),
     /// Failed to perform math operation.
     MathError(String),
     /// Failed to parse data into revm core types.
     EvmParseError(String),
+    /// A simulated swap reverted. `reason` is decoded from `output`.
+    SwapReverted {
+        reason: String,
+        output: Bytes,
+        gas_used: u64,
+    },
+    /// A simulated swap halted.
+    SwapHalted(Halt),
+    /// Token doesn't transfer exact amounts (e.g. fee-on-transfer), so it can't be arbed.
+    UnsupportedToken(Address),
+    /// The event's pools/tokens don't match the event filter, so it wasn't simulated.
+    EventFiltered(H256),
+    /// Simulating the event took longer than its time limit, so it was cancelled.
+    Timeout(H256),
+    /// Neither of the pool's tokens is WETH, so backruns (which start & end with WETH) can't arb it.
+    NonWethPair(Address),
+    /// A Swap-topic log of the pool has less data (`len` bytes) than its pool variant's Swap event.
+    MalformedSwapLog { pool: Address, len: usize },
+    /// The user's tx didn't execute successfully in the sim, so there's nothing to backrun.
+    UserTxFailed { tx_hash: H256, reason: String },
+    /// The pool holds too little WETH to arb against (`weth` is how much it holds).
+    ShallowPool { pool: Address, weth: U256 },
+    /// The tx's (max) gas price can't pay the forked block's base fee.
+    GasPriceBelowBaseFee {
+        tx_hash: H256,
+        gas_price: U256,
+        base_fee: U256,
+    },
 }
 
-impl Into<Error> for HindsightError {
-    fn into(self) -> Error {
+impl std::fmt::Display for HindsightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             HindsightError::BlockNotFound(block_number) => {
-                anyhow::format_err!("block not found (number={})", block_number)
+                write!(f, "block not found (number={})", block_number)
             }
             HindsightError::EventNotCached(tx_hash) => {
-                anyhow::format_err!("event not cached (hash={})", tx_hash)
+                write!(f, "event not cached (hash={})", tx_hash)
             }
-            HindsightError::PoolNotFound(address) => {
-                anyhow::format_err!("no other pool found, (pool={})", address)
+            HindsightError::NoArbPool(address) => {
+                write!(f, "no other pool found, (pool={})", address)
             }
             HindsightError::TxNotLanded(tx_hash) => {
-                anyhow::format_err!("tx not landed (hash={})", tx_hash)
+                write!(f, "tx not landed (hash={})", tx_hash)
             }
-            HindsightError::CallError(msg) => anyhow::format_err!("call error: {}", msg),
-            HindsightError::MathError(msg) => {
-                anyhow::format_err!("math error: {}", msg,)
+            HindsightError::TxDropped(tx_hash) => {
+                write!(f, "tx dropped (hash={})", tx_hash)
             }
-            HindsightError::EvmParseError(msg) => {
-                anyhow::format_err!("evm parse error: {}", msg,)
+            HindsightError::CallError(msg) => write!(f, "call error: {}", msg),
+            HindsightError::MathError(msg) => write!(f, "math error: {}", msg),
+            HindsightError::EvmParseError(msg) => write!(f, "evm parse error: {}", msg),
+            HindsightError::SwapReverted {
+                reason, gas_used, ..
+            } => {
+                write!(f, "swap reverted: {} (gas used: {:?})", reason, gas_used)
             }
+            HindsightError::SwapHalted(reason) => write!(f, "swap halted: {:?}", reason),
+            HindsightError::UnsupportedToken(token) => {
+                write!(f, "unsupported token (token={})", token)
+            }
+            HindsightError::EventFiltered(tx_hash) => {
+                write!(f, "event doesn't match the event filter (hash={})", tx_hash)
+            }
+            HindsightError::Timeout(tx_hash) => {
+                write!(f, "simulation timed out (hash={})", tx_hash)
+            }
+            HindsightError::NonWethPair(pool) => {
+                write!(f, "pair doesn't include WETH (pool={})", pool)
+            }
+            HindsightError::MalformedSwapLog { pool, len } => {
+                write!(f, "malformed swap log (pool={}, len={})", pool, len)
+            }
+            HindsightError::UserTxFailed { tx_hash, reason } => {
+                write!(f, "user tx failed (hash={}): {}", tx_hash, reason)
+            }
+            HindsightError::ShallowPool { pool, weth } => write!(
+                f,
+                "pool liquidity too low (pool={}, weth={})",
+                pool,
+                format_ether(*weth)
+            ),
+            HindsightError::GasPriceBelowBaseFee {
+                tx_hash,
+                gas_price,
+                base_fee,
+            } => write!(
+                f,
+                "gas price below base fee (hash={}, gas_price={}, base_fee={})",
+                tx_hash, gas_price, base_fee
+            ),
         }
     }
 }
 
+/// Lets `HindsightError` convert into `crate::Error` (anyhow), so callers can recover it with `downcast_ref`.
+impl std::error::Error for HindsightError {}
+
 #[macro_export]
 macro_rules! err {
     ($($arg:tt)*) => {
         Err(anyhow::anyhow!(format!($($arg)*)))
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn it_downcasts_hindsight_errors() {
+        let err: Error = HindsightError::SwapReverted {
+            reason: "STF".to_owned(),
+            output: Bytes::default(),
+            gas_used: 21_000,
+        }
+        .into();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::SwapReverted {
+                gas_used: 21_000,
+                ..
+            })
+        ));
+        let err: Error = HindsightError::NoArbPool(Address::zero()).into();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::NoArbPool(_))
+        ));
+    }
+}
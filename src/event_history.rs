@@ -1,7 +1,12 @@
-use crate::Result;
+use crate::{info, warn, Result};
 use mev_share_sse::{EventClient, EventHistory, EventHistoryParams};
+use std::{future::Future, time::Duration};
 
 const FLASHBOTS_EVENTS_API_URL: &'static str = "https://mev-share.flashbots.net/api/v1";
+/// Number of times a failed request to the Event History API is retried before giving up.
+const MAX_RETRIES: u32 = 5;
+/// Time to wait before the first retry; doubles on every retry.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
 
 pub fn event_history_info_url() -> String {
     format!("{}/{}", FLASHBOTS_EVENTS_API_URL, "history/info")
@@ -10,6 +15,87 @@ pub fn event_history_url() -> String {
     format!("{}/{}", FLASHBOTS_EVENTS_API_URL, "history")
 }
 
+/// Calls `request` until it succeeds, retrying up to `max_retries` times with exponential backoff.
+async fn with_retry<T, E, F, Fut>(
+    max_retries: u32,
+    initial_backoff: Duration,
+    mut request: F,
+) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let mut backoff = initial_backoff;
+    let mut retries = 0;
+    loop {
+        match request().await {
+            Ok(res) => return Ok(res),
+            Err(err) if retries < max_retries => {
+                retries += 1;
+                warn!(
+                    "event history request failed: {}. retrying in {:?} ({}/{})",
+                    err, backoff, retries, max_retries
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Pages through the Flashbots MEV-Share Event History API, passing each page of events
+/// to `on_page` as soon as it's fetched. Returns the total number of events fetched.
+///
+/// Pages hold `params.limit` events (capped at, and defaulting to, the API's max limit),
+/// starting from `params.offset`. Failed requests are retried with exponential backoff.
+pub async fn fetch_event_pages<F>(
+    client: &EventClient,
+    params: EventHistoryParams,
+    mut on_page: F,
+) -> Result<u64>
+where
+    F: FnMut(Vec<EventHistory>) -> Result<()>,
+{
+    let info_url = event_history_info_url();
+    let info = with_retry(MAX_RETRIES, INITIAL_BACKOFF, || {
+        client.event_history_info(&info_url)
+    })
+    .await?;
+    let page_size = params
+        .limit
+        .unwrap_or(info.max_limit)
+        .min(info.max_limit)
+        .max(1);
+    let history_url = event_history_url();
+    let mut offset = params.offset.unwrap_or(0);
+    let mut total = 0;
+    loop {
+        let page_params = EventHistoryParams {
+            limit: Some(page_size),
+            offset: Some(offset),
+            ..params.to_owned()
+        };
+        let page = with_retry(MAX_RETRIES, INITIAL_BACKOFF, || {
+            client.event_history(&history_url, page_params.to_owned())
+        })
+        .await?;
+        let page_len = page.len() as u64;
+        offset += page_len;
+        total += page_len;
+        info!("Fetched {} events ({} events total)", page_len, total);
+        if page_len > 0 {
+            on_page(page)?;
+        }
+        // a partial page means we've reached the end of the range (or the latest event)
+        if page_len < page_size {
+            break;
+        }
+    }
+    Ok(total)
+}
+
 /// Fetches events from the Flashbots MEV-Share SSE API. Iteratively queries for
 /// events in chunks of `info.max_limit` until all events in the specified range
 /// have been fetched.
@@ -19,33 +105,77 @@ pub async fn fetch_latest_events(
     client: &EventClient,
     params: EventHistoryParams,
 ) -> Result<Vec<EventHistory>> {
-    let mut current_offset = 0;
-    let mut done = false;
     let mut events = vec![];
-    let info = client.event_history_info(&event_history_info_url()).await?;
-    while !done {
-        let mut chunk = client
-            .event_history(
-                &event_history_url(),
-                EventHistoryParams {
-                    block_start: params.block_start,
-                    block_end: params.block_end,
-                    timestamp_start: params.timestamp_start,
-                    timestamp_end: params.timestamp_end,
-                    limit: Some(info.max_limit),
-                    offset: Some(current_offset),
-                },
-            )
-            .await?;
-        let chunk_len = chunk.len() as u64;
-        current_offset += chunk_len;
-        events.append(&mut chunk);
-        done = chunk_len < params.limit.unwrap_or(500);
-        println!(
-            "Fetched {} events ({} events total)",
-            chunk_len,
-            events.len()
+    fetch_event_pages(client, params, |mut page| {
+        events.append(&mut page);
+        Ok(())
+    })
+    .await?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[tokio::test]
+    async fn it_retries_failed_requests() -> Result<()> {
+        let attempts = AtomicU32::new(0);
+        let res = with_retry(3, Duration::from_millis(1), || async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(std::io::Error::from(std::io::ErrorKind::TimedOut))
+            } else {
+                Ok(42)
+            }
+        })
+        .await?;
+        assert_eq!(res, 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // gives up after `max_retries` retries
+        let attempts = AtomicU32::new(0);
+        let res: Result<()> = with_retry(2, Duration::from_millis(1), || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(std::io::Error::from(std::io::ErrorKind::TimedOut))
+        })
+        .await;
+        assert!(res.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_pages_through_event_history() -> Result<()> {
+        let client = EventClient::default();
+        let params = EventHistoryParams {
+            block_start: Some(17637019),
+            block_end: Some(17637019),
+            timestamp_start: None,
+            timestamp_end: None,
+            limit: None,
+            offset: None,
+        };
+        let events = fetch_latest_events(&client, params.to_owned()).await?;
+        let mut pages = vec![];
+        let total = fetch_event_pages(
+            &client,
+            EventHistoryParams {
+                limit: Some(2),
+                ..params
+            },
+            |page| {
+                assert!(page.len() <= 2);
+                pages.extend(page);
+                Ok(())
+            },
+        )
+        .await?;
+        assert_eq!(total as usize, events.len());
+        assert_eq!(
+            pages.iter().map(|e| e.hint.hash).collect::<Vec<_>>(),
+            events.iter().map(|e| e.hint.hash).collect::<Vec<_>>()
         );
+        Ok(())
     }
-    Ok(events)
 }
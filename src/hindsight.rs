@@ -1,97 +1,587 @@
 use crate::{
-    data::arbs::ArbDatabase,
+    config::Config,
+    data::{
+        arbs::{ArbDatabase, ArbDb, ArbReader, ArbWriter},
+        event_cache::EventCache,
+        failed_events::FailedEvents,
+    },
+    debug,
+    error::HindsightError,
     info,
-    sim::processor::{simulate_backrun_arbs, H256Map},
-    util::{get_ws_client, WsClient},
-    Result,
+    interfaces::SimArbResultBatch,
+    notify::Notifier,
+    sim::{
+        core::SimOptions,
+        processor::{simulate_backrun_arbs, simulate_event_backrun_arbs, H256Map},
+    },
+    util::{get_client, metadata_cache, RpcClient},
+    warn, Error, Result,
 };
-use ethers::types::Transaction;
-use futures::future;
+use ethers::types::{Transaction, H256, U256};
+use futures::{channel::mpsc, future, stream, Future, SinkExt, Stream, StreamExt};
 use mev_share_sse::EventHistory;
+use std::{
+    cell::OnceCell,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-/// Transaction processor for hindsight. Requires a websocket connection to an archive node.
-#[derive(Clone, Debug)]
+/// Log progress every this many events.
+const PROGRESS_LOG_INTERVAL: usize = 100;
+
+/// Reported to a `Hindsight`'s progress callback as orderflow is processed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OrderflowProgress {
+    /// This many more events were selected to be processed, e.g. when a scan fetches a page of events.
+    Queued(usize),
+    /// This many events were skipped w/o being simulated, e.g. because they already have results
+    /// or don't match the event filter.
+    Skipped(usize),
+    /// An event was simulated; `max_profit` (wei) is 0 if none of its arbs were profitable.
+    Simulated { max_profit: U256 },
+    /// An event's sims failed, or timed out (after their retry, if they're retried).
+    Failed,
+}
+
+/// Called w/ each `OrderflowProgress` update, from whichever task made it.
+pub type ProgressCallback = Arc<dyn Fn(OrderflowProgress) + Send + Sync>;
+
+/// Transaction processor for hindsight. Requires a connection to an archive node.
+///
+/// Built with `Hindsight::builder()`.
+#[derive(Clone)]
 pub struct Hindsight {
-    pub client: WsClient,
+    pub client: RpcClient,
+    pub sim_options: SimOptions,
+    /// Max number of txs to simulate at once.
+    pub concurrency: usize,
+    /// Where simulated results are saved. Results aren't saved if it's None.
+    pub db: Option<ArbDatabase>,
+    /// MEV-Share events saved on disk.
+    pub event_cache: EventCache,
+    /// Notified of profitable arbs as they're saved. No notifications are sent if it's None.
+    pub notifier: Option<Notifier>,
+    /// Time limit for retrying events that timed out, which are retried as they time out.
+    /// Timed-out events aren't retried if it's None.
+    pub retry_timeout: Option<Duration>,
+    /// Told how processing orderflow is going, e.g. to draw a progress bar. Progress is only logged
+    /// every `PROGRESS_LOG_INTERVAL` events if it's None.
+    pub on_progress: Option<ProgressCallback>,
+    /// Where events that don't produce results are saved, to be retried with `retry-failed`.
+    /// Failures aren't saved if it's None.
+    pub failed_events: Option<Arc<FailedEvents>>,
+}
+
+/// Builds a `Hindsight`. Anything that isn't set is derived from `Config::default()`, except
+/// the DB and notifier, which default to none (results aren't saved or notified).
+#[derive(Default)]
+pub struct HindsightBuilder {
+    client: Option<RpcClient>,
+    sim_options: Option<SimOptions>,
+    concurrency: Option<usize>,
+    db: Option<ArbDatabase>,
+    event_cache_path: Option<PathBuf>,
+    notifier: Option<Notifier>,
+    retry_timeout: Option<Option<Duration>>,
+    on_progress: Option<ProgressCallback>,
+    failed_events: Option<Arc<FailedEvents>>,
+}
+
+impl HindsightBuilder {
+    /// RPC client to fetch chain state and fork from. Defaults to a client for `RPC_URL`.
+    pub fn client(mut self, client: RpcClient) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Defaults to `Config::sim_options`.
+    pub fn sim_options(mut self, sim_options: SimOptions) -> Self {
+        self.sim_options = Some(sim_options);
+        self
+    }
+
+    /// Max number of txs to simulate at once. Defaults to `EVENT_CONCURRENCY`.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = Some(concurrency);
+        self
+    }
+
+    /// Where simulated results are saved.
+    pub fn db(mut self, db: ArbDatabase) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    /// Directory of the event cache. Defaults to `./cache`.
+    pub fn event_cache_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.event_cache_path = Some(path.into());
+        self
+    }
+
+    /// Notified of profitable arbs as they're saved.
+    pub fn notifier(mut self, notifier: Notifier) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
+
+    /// Time limit for retrying events that timed out, or None to not retry them.
+    /// Defaults to `EVENT_TIMEOUT_RETRY_SECS`.
+    pub fn retry_timeout(mut self, retry_timeout: Option<Duration>) -> Self {
+        self.retry_timeout = Some(retry_timeout);
+        self
+    }
+
+    /// Called w/ the progress of processing orderflow, as each event is processed.
+    pub fn on_progress(
+        mut self,
+        on_progress: impl Fn(OrderflowProgress) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    /// Where events that don't produce results are saved (and removed from once they do).
+    pub fn failed_events(mut self, failed_events: Arc<FailedEvents>) -> Self {
+        self.failed_events = Some(failed_events);
+        self
+    }
+
+    pub async fn build(self) -> Result<Hindsight> {
+        // only loaded if something isn't set, so a fully-built Hindsight doesn't need any env vars
+        let config = OnceCell::new();
+        let config = || config.get_or_init(Config::default);
+        let client = match self.client {
+            Some(client) => client,
+            None => get_client(Some(config().rpc_urls.to_owned())).await?,
+        };
+        let sim_options = match self.sim_options {
+            Some(sim_options) => sim_options,
+            None => config().sim_options()?,
+        };
+        Ok(Hindsight {
+            client,
+            sim_options,
+            concurrency: self
+                .concurrency
+                .unwrap_or_else(|| config().event_concurrency),
+            db: self.db,
+            event_cache: self
+                .event_cache_path
+                .map(EventCache::new)
+                .unwrap_or_default(),
+            notifier: self.notifier,
+            retry_timeout: self
+                .retry_timeout
+                .unwrap_or_else(|| config().event_timeout_retry()),
+            on_progress: self.on_progress,
+            failed_events: self.failed_events,
+        })
+    }
 }
 
 impl Hindsight {
-    pub async fn new(rpc_url_ws: String) -> Result<Self> {
-        let client = get_ws_client(Some(rpc_url_ws)).await?;
-        Ok(Self { client })
+    pub fn builder() -> HindsightBuilder {
+        HindsightBuilder::default()
     }
 
-    /// For each tx in `txs`, simulates an optimal backrun-arbitrage in a parallel thread,
-    /// caching results in batches of size `batch_size`.
+    /// Saves that the event of `tx` failed w/ `err`, if failures are saved.
+    fn record_failure(&self, tx: &Transaction, block: Option<u64>, err: &Error) {
+        if let Some(failed_events) = &self.failed_events {
+            if let Err(save_err) = failed_events.record(tx.hash, block, err) {
+                warn!("failed to save failed event {:?}: {}", tx.hash, save_err);
+            }
+        }
+    }
+
+    /// Reports `progress` to the progress callback, if there is one.
+    pub fn report(&self, progress: OrderflowProgress) {
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(progress);
+        }
+    }
+
+    /// Simulates an optimal backrun-arbitrage for a single event, whose tx is `tx`, with
+    /// `self.sim_options`. The result isn't saved or notified.
     ///
-    /// Saves results into `db` after each batch is processed. Returns when all txs are processed.
+    /// Fails with `HindsightError::EventFiltered` if the event doesn't match the event filter,
+    /// and with `HindsightError::Timeout` if it takes longer than the event timeout.
+    pub async fn simulate_event(
+        &self,
+        event: &EventHistory,
+        tx: &Transaction,
+    ) -> Result<SimArbResultBatch> {
+        simulate_event_backrun_arbs(&self.client, tx.to_owned(), event, self.sim_options.clone())
+            .await
+    }
+
+    /// Returns the txs in `txs` whose events don't have results saved in `self.db` (all of them if
+    /// there's no DB).
+    pub async fn unsimulated(&self, txs: &Vec<Transaction>) -> Result<Vec<Transaction>> {
+        match self.db.as_deref() {
+            Some(db) => filter_unsimulated(db, txs).await,
+            None => Ok(txs.to_owned()),
+        }
+    }
+
+    /// Simulates an optimal backrun-arbitrage for each tx in `txs`, running up to
+    /// `self.concurrency` sims at once in parallel threads.
+    ///
+    /// Results are passed to a single writer as they complete, which saves them into `self.db`
+    /// `batch_size` at a time, then passes them to `self.notifier`. Sims that fail are skipped. Returns all simulated results
+    /// when all txs are processed.
+    ///
+    /// Txs are simulated in order of their blocks, so events on the same block can share its fork.
+    /// Txs whose events already have results in `self.db` are skipped, unless `force` is set,
+    /// as are txs whose events don't match `self.sim_options.event_filter`. Txs whose sims time out
+    /// are retried with `self.retry_timeout`, if it's set.
+    ///
+    /// Each tx's outcome is reported to `self.on_progress`; the txs aren't reported as `Queued`,
+    /// since the caller knows how many it passed. Events that fail (other than those skipped by
+    /// the event filter) are saved to `self.failed_events`, and removed from it once they're simulated.
     pub async fn process_orderflow(
         self,
         txs: &Vec<Transaction>,
         batch_size: usize,
-        db: Option<ArbDatabase>,
         event_map: H256Map<EventHistory>,
-    ) -> Result<()> {
+        force: bool,
+    ) -> Result<Vec<SimArbResultBatch>> {
         info!("loaded {} transactions total...", txs.len());
-        let mut processed_txs = 0;
-        while processed_txs < txs.len() {
-            let mut handlers = vec![];
-            let txs_batch = txs
-                .iter()
-                .skip(processed_txs)
-                .take(batch_size)
-                .map(|tx| tx.to_owned())
-                .collect::<Vec<Transaction>>();
-            processed_txs += txs_batch.len();
-            info!("processing {} txs", txs_batch.len());
-            for tx in txs_batch {
-                let event_map = event_map.clone();
-                let client = self.client.clone();
-                handlers.push(tokio::task::spawn(async move {
-                    simulate_backrun_arbs(&client, tx, &event_map).await.ok()
-                }));
+        let mut txs = if force {
+            txs.to_owned()
+        } else {
+            let unsimulated = self.unsimulated(txs).await?;
+            info!(
+                "skipping {} events already simulated, simulating {} events",
+                txs.len() - unsimulated.len(),
+                unsimulated.len()
+            );
+            if unsimulated.len() < txs.len() {
+                self.report(OrderflowProgress::Skipped(txs.len() - unsimulated.len()));
+            }
+            unsimulated
+        };
+        txs.sort_by_key(|tx| tx.block_number);
+        let total = Arc::new(AtomicUsize::new(txs.len()));
+        let txs = txs.into_iter().map(|tx| {
+            let event = event_map.get(&tx.hash).cloned();
+            (tx, event)
+        });
+        let mut all_results = vec![];
+        self.process_orderflow_stream(stream::iter(txs), total, batch_size, |batch| {
+            all_results.extend(batch.into_iter().filter_map(|processed| processed.result));
+            future::ready(Ok(()))
+        })
+        .await?;
+        Ok(all_results)
+    }
+
+    /// Simulates an optimal backrun-arbitrage for each tx of `txs` (w/ its event, if it has one)
+    /// as they arrive, running up to `self.concurrency` sims at once in parallel threads, until
+    /// `txs` ends. Like `process_orderflow`, but for txs that are still being fetched, e.g. the
+    /// whole range of a scan, which it processes in one pipeline.
+    ///
+    /// Txs aren't filtered for saved results. Txs whose sims time out are retried w/
+    /// `self.retry_timeout` (if it's set) as they time out, alongside the other txs.
+    ///
+    /// Each tx is passed to a single writer once it's done, whether it produced results or not,
+    /// which saves the results into `self.db` & passes them to `self.notifier` `batch_size` txs at a
+    /// time, then passes the batch to `on_written`, e.g. to checkpoint it.
+    ///
+    /// `total` is the number of txs queued so far, which progress is logged against.
+    pub async fn process_orderflow_stream<S, F, Fut>(
+        self,
+        txs: S,
+        total: Arc<AtomicUsize>,
+        batch_size: usize,
+        mut on_written: F,
+    ) -> Result<()>
+    where
+        S: Stream<Item = (Transaction, Option<EventHistory>)> + Unpin,
+        F: FnMut(Vec<ProcessedTx>) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let db = self.db.clone();
+        let db = db.as_deref();
+        let notifier = self.notifier.clone();
+        let failed_events = self.failed_events.clone();
+        let batch_size = batch_size.max(1);
+        let (mut results_tx, results_rx) = mpsc::channel(batch_size);
+
+        let sims = async move {
+            let started = Instant::now();
+            let (retry_tx, retry_rx) = mpsc::unbounded();
+            // dropped once `txs` ends & no retries are left, which ends the pipeline
+            let mut retry_tx = self.retry_timeout.map(|_| retry_tx);
+            let retry_options = SimOptions {
+                event_timeout: self.retry_timeout,
+                ..self.sim_options.clone()
+            };
+            // `None` marks the end of `txs`
+            let inputs = stream::select(
+                txs.map(|(tx, event)| Some((tx, event, false)))
+                    .chain(stream::once(future::ready(None))),
+                retry_rx.map(|(tx, event)| Some((tx, event, true))),
+            );
+            let mut results = inputs
+                .map(|input| {
+                    let client = self.client.clone();
+                    let sim_options = self.sim_options.clone();
+                    let retry_options = retry_options.clone();
+                    async move {
+                        let (tx, event, retry) = input?;
+                        let block = event
+                            .as_ref()
+                            .map(|event| event.block)
+                            .or(tx.block_number.map(|block| block.as_u64()));
+                        let sim_options = if retry { retry_options } else { sim_options };
+                        let res = tokio::task::spawn({
+                            let (tx, event) = (tx.clone(), event.clone());
+                            async move {
+                                match &event {
+                                    Some(event) => {
+                                        simulate_event_backrun_arbs(&client, tx, event, sim_options)
+                                            .await
+                                    }
+                                    // fails (& is audited) as an event that isn't cached
+                                    None => {
+                                        let no_events = H256Map::default();
+                                        simulate_backrun_arbs(&client, tx, &no_events, sim_options)
+                                            .await
+                                    }
+                                }
+                            }
+                        })
+                        .await
+                        .map_err(Error::from)
+                        .and_then(|res| res);
+                        Some((tx, event, block, retry, res))
+                    }
+                })
+                .buffer_unordered(self.concurrency.max(1));
+            let mut processed = 0;
+            let mut filtered = 0;
+            let mut non_weth = 0;
+            let mut dropped = 0;
+            let mut timed_out = 0;
+            let mut inputs_done = false;
+            let mut pending_retries = 0;
+            while let Some(outcome) = results.next().await {
+                let Some((tx, event, block, retry, res)) = outcome else {
+                    inputs_done = true;
+                    if pending_retries == 0 {
+                        retry_tx = None;
+                    }
+                    continue;
+                };
+                if retry {
+                    pending_retries -= 1;
+                } else {
+                    processed += 1;
+                    if processed % PROGRESS_LOG_INTERVAL == 0 {
+                        if self.on_progress.is_none() {
+                            let total = total.load(Ordering::SeqCst).max(processed);
+                            let (rate, eta) = progress(processed, total, started.elapsed());
+                            info!(
+                                "processed {}/{} events ({:.1} events/min, ETA {:?})",
+                                processed, total, rate, eta
+                            );
+                        }
+                        (*self.client).as_ref().inner().log_health();
+                    }
+                }
+                let result = match res {
+                    Ok(res) => {
+                        self.report(OrderflowProgress::Simulated {
+                            max_profit: res.max_profit,
+                        });
+                        Some(res)
+                    }
+                    Err(err) if retry => {
+                        warn!("retry failed: {}", err);
+                        self.record_failure(&tx, block, &err);
+                        self.report(OrderflowProgress::Failed);
+                        None
+                    }
+                    Err(err) => match err.downcast_ref::<HindsightError>() {
+                        Some(HindsightError::EventFiltered(_)) => {
+                            filtered += 1;
+                            self.report(OrderflowProgress::Skipped(1));
+                            None
+                        }
+                        Some(HindsightError::NonWethPair(_)) => {
+                            non_weth += 1;
+                            self.record_failure(&tx, block, &err);
+                            self.report(OrderflowProgress::Skipped(1));
+                            None
+                        }
+                        Some(HindsightError::TxDropped(_)) => {
+                            dropped += 1;
+                            self.record_failure(&tx, block, &err);
+                            self.report(OrderflowProgress::Skipped(1));
+                            None
+                        }
+                        Some(HindsightError::Timeout(_)) => {
+                            warn!("{}", err);
+                            timed_out += 1;
+                            // reported (& saved) once it's retried
+                            if let Some(retry_tx) = &retry_tx {
+                                // can't fail, since the pipeline holds the receiver
+                                let _ = retry_tx.unbounded_send((tx, event));
+                                pending_retries += 1;
+                                continue;
+                            }
+                            self.record_failure(&tx, block, &err);
+                            self.report(OrderflowProgress::Failed);
+                            None
+                        }
+                        _ => {
+                            self.record_failure(&tx, block, &err);
+                            self.report(OrderflowProgress::Failed);
+                            None
+                        }
+                    },
+                };
+                if inputs_done && pending_retries == 0 {
+                    retry_tx = None;
+                }
+                let processed = ProcessedTx {
+                    tx_hash: tx.hash,
+                    result,
+                };
+                // the writer only hangs up if it failed, in which case there's no point going on
+                if results_tx.send(processed).await.is_err() {
+                    break;
+                }
+            }
+            if filtered > 0 {
+                info!(
+                    "skipped {} events that don't match the event filter",
+                    filtered
+                );
+            }
+            if non_weth > 0 {
+                info!(
+                    "skipped {} events that only swap pairs without WETH",
+                    non_weth
+                );
             }
-            let results = future::join_all(handlers).await;
-            let results = results
-                // TODO: can this be cleaned up? so ugly
-                .into_iter()
-                .filter(|res| res.is_ok())
-                .map(|res| res.unwrap())
-                .filter(|res| res.is_some())
-                .map(|res| res.unwrap())
-                .collect::<Vec<_>>();
-            info!("batch results: {:#?}", results);
-            if let Some(db) = db.to_owned() {
-                // can't do && with a `let` in the conditional
-                if !results.is_empty() {
-                    db.to_owned().write_arbs(&results).await?;
+            if dropped > 0 {
+                info!("skipped {} events whose txs were dropped", dropped);
+            }
+            if timed_out > 0 {
+                warn!("{} events timed out", timed_out);
+            }
+        };
+
+        let writer = async {
+            // full batches are written as they fill up, then whatever's left when the sims finish
+            let mut batches = results_rx.chunks(batch_size);
+            while let Some(batch) = batches.next().await {
+                let results = batch
+                    .iter()
+                    .filter_map(|processed| processed.result.to_owned())
+                    .collect::<Vec<_>>();
+                info!("batch results: {:#?}", results);
+                if let Some(db) = db {
+                    db.write_arbs(&results).await?;
+                }
+                if let Some(notifier) = &notifier {
+                    for res in &results {
+                        notifier.notify(res);
+                    }
+                }
+                if let Some(failed_events) = &failed_events {
+                    let simulated = results
+                        .iter()
+                        .map(|res| res.event.hint.hash)
+                        .collect::<Vec<_>>();
+                    match failed_events.remove(&simulated) {
+                        Ok(0) => {}
+                        Ok(removed) => info!("simulated {} events that had failed before", removed),
+                        Err(err) => warn!("failed to update failed events: {}", err),
+                    }
+                }
+                let (hits, misses) = metadata_cache().stats();
+                debug!("metadata cache: {} hits, {} misses", hits, misses);
+                if let Err(err) = metadata_cache().save() {
+                    warn!("failed to save metadata cache: {}", err);
                 }
+                on_written(batch).await?;
             }
-        }
-        Ok(())
+            Ok::<_, Error>(())
+        };
+
+        let (_, written) = tokio::join!(sims, writer);
+        written
     }
 }
 
+/// A tx passed to `Hindsight::process_orderflow_stream`, once it's done being processed.
+#[derive(Clone, Debug)]
+pub struct ProcessedTx {
+    pub tx_hash: H256,
+    /// The results of the tx's sims, or None if they failed or the tx was skipped.
+    pub result: Option<SimArbResultBatch>,
+}
+
+/// Returns the throughput (events/min) and the estimated time until `total` events are processed.
+pub fn progress(processed: usize, total: usize, elapsed: Duration) -> (f64, Duration) {
+    let rate = processed as f64 / elapsed.as_secs_f64().max(f64::EPSILON) * 60.0;
+    let remaining = total.saturating_sub(processed) as f64;
+    (rate, Duration::from_secs((remaining / rate * 60.0) as u64))
+}
+
+/// Returns the txs in `txs` whose events don't have results saved in `db`.
+async fn filter_unsimulated(db: &dyn ArbDb, txs: &Vec<Transaction>) -> Result<Vec<Transaction>> {
+    let tx_hashes = txs.iter().map(|tx| tx.hash).collect::<Vec<_>>();
+    let saved = db.get_saved_events(&tx_hashes).await?;
+    Ok(txs
+        .iter()
+        .filter(|tx| !saved.contains(&tx.hash))
+        .cloned()
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
-    use ethers::{providers::Middleware, types::H256};
+    use ethers::{
+        providers::{Middleware, Provider},
+        types::H256,
+    };
     use serde_json::json;
+    use std::sync::Arc;
 
     use crate::{
-        config::Config,
+        client::{FailoverPolicy, FailoverTransport, RetryPolicy, RetryTransport, RpcTransport},
         data::{
-            arbs::ArbFilterParams,
+            arbs::{ArbFilterParams, ArbReader},
             db::{Db, DbEngine},
-            MongoConfig,
+            MongoConfig, SqliteConfig,
         },
     };
 
     use super::*;
 
+    #[test]
+    fn it_estimates_progress() {
+        let (rate, eta) = progress(100, 400, Duration::from_secs(120));
+        assert_eq!(rate, 50.0);
+        assert_eq!(eta, Duration::from_secs(360));
+        let (_, eta) = progress(400, 400, Duration::from_secs(480));
+        assert_eq!(eta, Duration::ZERO);
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
     async fn it_processes_orderflow() -> Result<()> {
-        let config = Config::default();
-        let hindsight = Hindsight::new(config.rpc_url_ws).await?;
+        let test_db = Db::new(DbEngine::Mongo(MongoConfig::default())).await;
+        let hindsight = Hindsight::builder()
+            .db(test_db.connect.clone())
+            .build()
+            .await?;
 
         // data from an actual juicy event
         let juicy_event: EventHistory = serde_json::from_value(json!({
@@ -107,7 +597,8 @@ mod tests {
                   "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
                   "0x0000000000000000000000000000000000000000000000000000000000000000",
                   "0x0000000000000000000000000000000000000000000000000000000000000000"
-                ]
+                ],
+                "data": "0x"
               },
               {
                 "address": "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640",
@@ -115,7 +606,8 @@ mod tests {
                   "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
                   "0x0000000000000000000000000000000000000000000000000000000000000000",
                   "0x0000000000000000000000000000000000000000000000000000000000000000"
-                ]
+                ],
+                "data": "0x"
               },
               {
                 "address": "0x36bcf57291a291a6e0e0bff7b12b69b556bcd9ed",
@@ -123,14 +615,15 @@ mod tests {
                   "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
                   "0x0000000000000000000000000000000000000000000000000000000000000000",
                   "0x0000000000000000000000000000000000000000000000000000000000000000"
-                ]
+                ],
+                "data": "0x"
               }
             ]
           }
         }))?;
         let juicy_tx_hash: H256 =
             "0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a".parse::<H256>()?;
-        let juicy_tx = get_ws_client(None)
+        let juicy_tx = get_client(None)
             .await?
             .get_transaction(juicy_tx_hash)
             .await?
@@ -139,22 +632,16 @@ mod tests {
             .iter()
             .map(|event| (event.hint.hash, event.to_owned()))
             .collect::<H256Map<EventHistory>>();
-        let test_db = Db::new(DbEngine::Mongo(MongoConfig::default())).await;
 
         // run the sim, it will save a result to the "test" DB
         hindsight
-            .process_orderflow(
-                vec![juicy_tx].as_ref(),
-                1,
-                Some(test_db.connect.clone()),
-                event_map,
-            )
+            .process_orderflow(vec![juicy_tx].as_ref(), 1, event_map, true)
             .await?;
 
         // check DB for result
         let arbs = test_db
             .connect
-            .read_arbs(&ArbFilterParams::none(), None, None)
+            .read_arbs(&ArbFilterParams::none(), None, None, None)
             .await?;
         assert!(arbs
             .into_iter()
@@ -163,4 +650,86 @@ mod tests {
             .contains(&juicy_tx_hash));
         Ok(())
     }
+
+    /// A client for an endpoint that's never up, for tests that mustn't touch the RPC.
+    async fn offline_client() -> Result<RpcClient> {
+        let rpc_url = "http://127.0.0.1:1";
+        let transport = RpcTransport::connect(rpc_url, 0).await?;
+        Ok(Arc::new(Provider::new(RetryTransport::new(
+            FailoverTransport::new(
+                vec![(rpc_url.to_owned(), transport)],
+                FailoverPolicy::default(),
+            ),
+            RetryPolicy::default(),
+        ))))
+    }
+
+    #[tokio::test]
+    async fn it_skips_events_saved_in_injected_db() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "test_hindsight_orderflow_{}.sqlite",
+            rand::random::<u32>()
+        ));
+        let db = Db::new(DbEngine::Sqlite(SqliteConfig { path: path.clone() }))
+            .await
+            .connect;
+        let arb = SimArbResultBatch::test_example();
+        db.write_arbs(&vec![arb.clone()]).await?;
+        let reported = Arc::new(std::sync::Mutex::new(vec![]));
+        let hindsight = Hindsight::builder()
+            .client(offline_client().await?)
+            .sim_options(SimOptions::default())
+            .concurrency(1)
+            .db(db.clone())
+            .on_progress({
+                let reported = reported.clone();
+                move |progress| reported.lock().unwrap().push(progress)
+            })
+            .build()
+            .await?;
+
+        let tx = Transaction {
+            hash: arb.event.hint.hash,
+            ..Default::default()
+        };
+        let results = hindsight
+            .process_orderflow(&vec![tx], 1, H256Map::default(), false)
+            .await?;
+        assert!(results.is_empty());
+        assert_eq!(db.get_num_arbs(&ArbFilterParams::none()).await?, 1);
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![OrderflowProgress::Skipped(1)]
+        );
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_filters_unsimulated_txs_in_one_lookup() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "test_hindsight_unsimulated_{}.sqlite",
+            rand::random::<u32>()
+        ));
+        let db = Db::new(DbEngine::Sqlite(SqliteConfig { path: path.clone() }))
+            .await
+            .connect;
+        let arb = SimArbResultBatch::test_example();
+        db.write_arbs(&vec![arb.clone()]).await?;
+
+        let saved = Transaction {
+            hash: arb.event.hint.hash,
+            ..Default::default()
+        };
+        let unsaved = Transaction {
+            hash: H256::from_low_u64_be(1),
+            ..Default::default()
+        };
+        let txs = vec![unsaved.clone(), saved.clone(), unsaved.clone()];
+        let unsimulated = filter_unsimulated(db.as_ref(), &txs).await?;
+        assert_eq!(unsimulated, vec![unsaved.clone(), unsaved]);
+        assert!(filter_unsimulated(db.as_ref(), &vec![]).await?.is_empty());
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
 }
@@ -1,12 +1,38 @@
-use ethers::types::{Address, I256, U256};
-use mev_share_sse::EventHistory;
+use crate::util::wei_to_eth;
+use ethers::types::{Address, Bytes, H256, I256, U256};
+use mev_share_sse::{EventHistory, Hint};
 use serde::{self, Deserialize, Serialize};
+use std::str::FromStr;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SimArbResult {
     pub user_trade: UserTradeParams,
     pub backrun_trade: BackrunResult,
+    /// Signed backrun txs (buy, then sell; or one per swap of a route), if a bundle signer was configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backrun_txs: Option<Vec<Bytes>>,
+    /// Every amount simulated by the search for `backrun_trade`, if tracing was enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub search_trace: Option<SearchTrace>,
+}
+
+/// Amounts simulated while searching for a backrun's most profitable `amount_in`, for plotting
+/// profit curves. Sims that failed aren't included.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchTrace {
+    pub samples: Vec<SearchSample>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchSample {
+    /// Grid pass (or golden-section/ternary iteration) that simulated the amount, from 0.
+    pub depth: usize,
+    pub amount_in: U256,
+    /// WETH balance of the backrun contract after the backrun, before paying for gas.
+    pub balance_out: U256,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -14,19 +40,323 @@ pub struct SimArbResult {
 pub struct BackrunResult {
     pub amount_in: U256,
     pub balance_end: U256,
+    /// Profit after paying for gas.
     pub profit: U256,
+    /// `profit` in ETH, for quick filtering. Lossy; 0 for arbs saved before it was added.
+    #[serde(default)]
+    pub profit_eth: f64,
+    /// Gas used by the backrun's swaps.
+    #[serde(default)]
+    pub gas_used: u64,
+    /// Gas used by the backrun's first swap (WETH -> TKN).
+    #[serde(default)]
+    pub gas_used_buy: u64,
+    /// Gas used by the backrun's swaps after the first (TKN -> WETH, or the rest of its `route`).
+    #[serde(default)]
+    pub gas_used_sell: u64,
+    /// Gas price (wei) the backrun is assumed to pay; the block's base fee plus the configured priority fee.
+    #[serde(default)]
+    pub effective_gas_price: U256,
+    /// Gas cost (wei) of the backrun, at the block's base fee plus the configured priority fee.
+    #[serde(default)]
+    pub gas_cost: U256,
     pub start_pool: Address,
     pub end_pool: Address,
     pub start_variant: PoolVariant,
     pub end_variant: PoolVariant,
+    /// Fee tier (in hundredths of a bip) of `start_pool`, if it's a UniswapV3 pool. None for arbs
+    /// saved before it was recorded.
+    #[serde(default)]
+    pub start_fee: Option<u32>,
+    /// Fee tier (in hundredths of a bip) of `end_pool`, if it's a UniswapV3 pool.
+    #[serde(default)]
+    pub end_fee: Option<u32>,
+    /// Every pool a multi-hop arb swaps on, in order, from `start_pool` to `end_pool`.
+    /// Empty for arbs that only swap on `start_pool` & `end_pool`.
+    #[serde(default)]
+    pub route: Vec<(Address, PoolVariant)>,
+    /// Tokens swapped along `route`, from WETH back to WETH; `route[i]` swaps `route_tokens[i]`
+    /// for `route_tokens[i + 1]`.
+    #[serde(default)]
+    pub route_tokens: Vec<Address>,
+    /// Where `amount_in` sits in the searched range. None for arbs saved before it was recorded.
+    #[serde(default)]
+    pub search_bound: Option<SearchBound>,
+    /// Whether the search stopped early because its event's time budget (`EVENT_BUDGET_SECS`) was
+    /// spent, so `amount_in` is the best found so far; re-simulate w/ a bigger budget to finish it.
+    #[serde(default)]
+    pub search_truncated: bool,
+    /// The search's result before it was refined (see `REFINE_MIN_PROFIT_ETH`), if it was: the
+    /// amounts above are the refinement's, which are at least as profitable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coarse: Option<CoarseSearch>,
+    /// Price of the user's pool after the user's tx, which picked the arb's direction. Like every
+    /// pool price, it's TKN per WETH in whole tokens, as an 18-decimal fixed-point number (1e18
+    /// is 1 TKN/WETH). Zero for arbs saved before it was recorded.
+    #[serde(default)]
+    pub user_pool_price: U256,
+    /// Price of the other pool of the user's pair, before the backrun, like `user_pool_price`.
+    /// Zero for routed arbs, which aren't picked by comparing prices.
+    #[serde(default)]
+    pub alt_pool_price: U256,
+}
+
+/// Best amount found by a backrun's search, kept when a refinement re-simulates around it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoarseSearch {
+    pub amount_in: U256,
+    pub balance_end: U256,
+    /// Profit after paying for gas.
+    pub profit: U256,
+}
+
+impl BackrunResult {
+    /// Returns the swaps the backrun makes: along its `route`, or buying `tokens.token` on
+    /// `start_pool` & selling it on `end_pool`.
+    pub fn legs(&self, tokens: &TokenPair) -> Vec<SwapLeg> {
+        if self.route.is_empty() {
+            return SwapLeg::round_trip(
+                tokens,
+                (self.start_pool, self.start_variant),
+                (self.end_pool, self.end_variant),
+            );
+        }
+        self.route
+            .iter()
+            .zip(self.route_tokens.windows(2))
+            .map(|(&(pool, variant), tokens)| SwapLeg {
+                pool,
+                variant,
+                token_in: tokens[0],
+                token_out: tokens[1],
+            })
+            .collect()
+    }
+}
+
+/// One swap of a backrun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwapLeg {
+    pub pool: Address,
+    pub variant: PoolVariant,
+    pub token_in: Address,
+    pub token_out: Address,
+}
+
+impl SwapLeg {
+    /// Returns the legs of an arb that buys `tokens.token` w/ WETH on `start` & sells it on `end`.
+    pub fn round_trip(
+        tokens: &TokenPair,
+        start: (Address, PoolVariant),
+        end: (Address, PoolVariant),
+    ) -> Vec<Self> {
+        vec![
+            Self {
+                pool: start.0,
+                variant: start.1,
+                token_in: tokens.weth,
+                token_out: tokens.token,
+            },
+            Self {
+                pool: end.0,
+                variant: end.1,
+                token_in: tokens.token,
+                token_out: tokens.weth,
+            },
+        ]
+    }
+}
+
+/// The swaps of a backrun to search, w/ the fee tiers of its first & last pools (for V3 pools).
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArbRoute {
+    pub legs: Vec<SwapLeg>,
+    pub start_fee: Option<u32>,
+    pub end_fee: Option<u32>,
+}
+
+impl ArbRoute {
+    /// Returns the route that buys `tokens.token` w/ WETH on `start` & sells it on `end`, each
+    /// given as (address, variant, fee tier).
+    pub fn round_trip(
+        tokens: &TokenPair,
+        start: (Address, PoolVariant, Option<u32>),
+        end: (Address, PoolVariant, Option<u32>),
+    ) -> Self {
+        Self {
+            legs: SwapLeg::round_trip(tokens, (start.0, start.1), (end.0, end.1)),
+            start_fee: start.2,
+            end_fee: end.2,
+        }
+    }
+}
+
+/// Where the best `amount_in` found by a backrun search sits in the range it searched.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum SearchBound {
+    /// Between the range's ends; the search found the profit curve's peak.
+    Interior,
+    /// At zero; no amount was profitable.
+    Lower,
+    /// At the top of the range, which couldn't be expanded any further (or the search ran out
+    /// of depth while expanding it). The most profitable amount may be larger.
+    Upper,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
+#[non_exhaustive]
 pub struct SimArbResultBatch {
+    /// The mev-share event that triggered the sims; carries the block number,
+    /// timestamp, and hint (incl. tx hash) that backends store and filter on.
     pub event: EventHistory,
     pub results: Vec<SimArbResult>,
     pub max_profit: U256,
+    /// Number of candidate arb pools whose sims failed.
+    #[serde(default)]
+    pub failures: usize,
+    /// Whether the event was simulated as it streamed in (`listen`) rather than from history (`scan`).
+    #[serde(default)]
+    pub live: bool,
+    /// ETH price (USD) at the simulated block, read from a Chainlink price feed.
+    /// None if USD pricing is disabled or the feed couldn't be read.
+    #[serde(default)]
+    pub eth_usd_price: Option<f64>,
+    /// `max_profit` in USD, at `eth_usd_price`.
+    #[serde(default)]
+    pub profit_usd: Option<f64>,
+    /// Where in its block the backruns were simulated. Arbs saved before it was recorded
+    /// were all simulated at the top of the block.
+    #[serde(default)]
+    pub placement: Placement,
+    /// What the event's hint shared, i.e. what a searcher would've known in real time.
+    /// None for arbs saved before it was recorded.
+    #[serde(default)]
+    pub hint: Option<HintSummary>,
+    /// Decisions deriving the user's trades made from data the hint didn't share.
+    #[serde(default)]
+    pub non_hinted: Vec<NonHintedDecision>,
+    /// Whether the user's trades were derived w/o the tx's receipt (`--hint-only`), i.e. only from
+    /// the hint & simulating the user's tx.
+    #[serde(default)]
+    pub hint_only: bool,
+}
+
+/// The parts of a mev-share hint that tell what it shared, w/o the data of its logs or txs.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintSummary {
+    pub hash: H256,
+    pub logs: Vec<HintedLog>,
+    /// Whether any of the hinted txs shared its calldata.
+    pub calldata: bool,
+    /// Whether any of the hinted txs shared its function selector.
+    pub function_selector: bool,
+    #[serde(default)]
+    pub gas_used: Option<U256>,
+    #[serde(default)]
+    pub mev_gas_price: Option<U256>,
+}
+
+/// A hinted log's address & topics.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HintedLog {
+    pub address: Address,
+    pub topics: Vec<H256>,
+    /// Whether the log's data was shared.
+    pub data: bool,
+}
+
+impl From<&Hint> for HintSummary {
+    fn from(hint: &Hint) -> Self {
+        Self {
+            hash: hint.hash,
+            logs: hint
+                .logs
+                .iter()
+                .map(|log| HintedLog {
+                    address: log.address,
+                    topics: log.topics.to_owned(),
+                    data: !log.data.is_empty(),
+                })
+                .collect(),
+            calldata: hint.txs.iter().any(|tx| tx.calldata.is_some()),
+            function_selector: hint.txs.iter().any(|tx| tx.function_selector.is_some()),
+            gas_used: hint.gas_used,
+            mev_gas_price: hint.mev_gas_price,
+        }
+    }
+}
+
+/// A decision that deriving a user's trade on `pool` made from the tx's receipt (or a fork),
+/// since the hint didn't share the data it needed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase", tag = "decision")]
+pub enum NonHintedDecision {
+    /// The direction & amounts of the user's trade, from the pool's swap logs.
+    TradeDirection { pool: Address },
+    /// The pool's price after the user's trade, which picks the direction of its backruns.
+    PoolPrice { pool: Address },
+}
+
+/// Number of txs following the user's tx that `Placement::Pessimistic` commits by default.
+pub const DEFAULT_FOLLOWING_TXS: usize = 3;
+
+/// Where in the user's block a backrun is simulated.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Placement {
+    /// On the state before the block, right after the user's tx; as if it were the block's first tx.
+    #[default]
+    TopOfBlock,
+    /// Right after the user's tx, on the state after the txs that landed before it in its block.
+    InBlock,
+    /// Like `InBlock`, but the given number of txs that landed after the user's tx are committed
+    /// before the backrun, as if other txs (or searchers) got in first.
+    Pessimistic(usize),
+}
+
+impl std::fmt::Display for Placement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Placement::TopOfBlock => write!(f, "top-of-block"),
+            Placement::InBlock => write!(f, "in-block"),
+            Placement::Pessimistic(following_txs) => write!(f, "pessimistic:{}", following_txs),
+        }
+    }
+}
+
+/// Parses `top-of-block`, `in-block`, `pessimistic` or `pessimistic:<following txs>`.
+impl FromStr for Placement {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "top-of-block" => Ok(Placement::TopOfBlock),
+            None if s == "in-block" => Ok(Placement::InBlock),
+            None if s == "pessimistic" => Ok(Placement::Pessimistic(DEFAULT_FOLLOWING_TXS)),
+            Some(("pessimistic", following_txs)) => following_txs
+                .parse()
+                .map(Placement::Pessimistic)
+                .map_err(|_| format!("invalid number of following txs: {}", following_txs)),
+            _ => Err(format!("invalid placement: {}", s)),
+        }
+    }
+}
+
+impl SimArbResultBatch {
+    /// The most profitable result, if there are any.
+    pub fn best_result(&self) -> Option<&SimArbResult> {
+        self.results
+            .iter()
+            .max_by_key(|res| res.backrun_trade.profit)
+    }
+
+    /// Converts `wei` to USD at the event's ETH price, if it has one.
+    pub fn to_usd(&self, wei: U256) -> Option<f64> {
+        self.eth_usd_price.map(|price| wei_to_eth(wei) * price)
+    }
 }
 
 /// Information derived from user's trade tx.
@@ -34,15 +364,57 @@ pub struct SimArbResultBatch {
 #[serde(rename_all = "camelCase")]
 pub struct UserTradeParams {
     pub pool_variant: PoolVariant,
+    /// Fee tier (in hundredths of a bip) of `pool`, if it's a UniswapV3 pool.
+    #[serde(default)]
+    pub pool_fee: Option<u32>,
     pub token_in: Address,
     pub token_out: Address,
     pub amount0_sent: I256,
     pub amount1_sent: I256,
     pub token0_is_weth: bool,
     pub pool: Address,
+    /// Price (TKN per WETH, 18 decimals) of `pool` after the user's tx.
     pub price: U256,
+    /// Price of `pool` before the user's tx, like `price`. Zero if it couldn't be read, or for
+    /// trades saved before it was recorded.
+    #[serde(default)]
+    pub price_before: U256,
+    /// How far (in basis points) the user's tx moved `pool`'s price, in either direction; see
+    /// `util::price_impact_bps`. None if `price_before` is unknown.
+    #[serde(default)]
+    pub price_impact_bps: Option<u64>,
     pub tokens: TokenPair,
     pub arb_pools: Vec<PairPool>,
+    /// Pools to arb the user's pool against through an intermediate token; only looked up when
+    /// there are no `arb_pools`.
+    #[serde(default)]
+    pub intermediate_pools: Vec<IntermediatePools>,
+    /// DEX of the pool the user traded on.
+    #[serde(default)]
+    pub dex: Option<Dex>,
+    /// Symbol of `tokens.token`, if it has one.
+    #[serde(default)]
+    pub token_symbol: Option<String>,
+    /// Decimals of `tokens.token`.
+    #[serde(default)]
+    pub token_decimals: Option<u8>,
+    /// Whether `tokens.token` is worth about 1 ETH (e.g. wstETH; see
+    /// `ChainConfig::eth_equivalent_tokens`), so either side of the trade is valued 1:1 in WETH.
+    #[serde(default)]
+    pub eth_equivalent: bool,
+}
+
+impl UserTradeParams {
+    /// Decimals of `amount0_sent` & `amount1_sent`: 18 for WETH, and `token_decimals` for the
+    /// other token, if they're known.
+    pub fn amounts_sent_decimals(&self) -> (Option<u8>, Option<u8>) {
+        let weth = Some(crate::data::units::ETH_DECIMALS);
+        if self.token0_is_weth {
+            (weth, self.token_decimals)
+        } else {
+            (self.token_decimals, weth)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -52,10 +424,26 @@ pub struct TokenPair {
     pub token: Address,
 }
 
+/// Pools that connect WETH to the user's token through `token` (e.g. USDC), for two-hop arbs.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IntermediatePools {
+    pub token: Address,
+    /// Pools trading WETH for `token`.
+    pub weth_pools: Vec<PairPool>,
+    /// Pools trading `token` for the user's token.
+    pub token_pools: Vec<PairPool>,
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct PairPool {
     pub variant: PoolVariant,
     pub address: Address,
+    /// Fee tier (in hundredths of a bip) of a UniswapV3 pool; `None` for UniswapV2 pairs.
+    #[serde(default)]
+    pub fee: Option<u32>,
+    #[serde(default)]
+    pub dex: Option<Dex>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -67,10 +455,54 @@ pub struct StoredArbsRanges {
     pub latest_block: u64,
 }
 
+/// An inclusive range of blocks.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BlockRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// How a pool trades. A UniswapV3 pool's fee tier is kept next to its variant (e.g. `PairPool::fee`).
+///
+/// This is the only pool variant used in hindsight; rusty-sando's is converted to & from it
+/// wherever they meet.
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
 pub enum PoolVariant {
     UniswapV2,
     UniswapV3,
+    /// A Curve stableswap pool w/ 2 coins, one of them ETH (or WETH). Only arbed against; users'
+    /// trades on Curve aren't derived.
+    Curve,
+}
+
+impl TryFrom<PoolVariant> for rusty_sando::prelude::PoolVariant {
+    type Error = anyhow::Error;
+
+    fn try_from(variant: PoolVariant) -> Result<Self, Self::Error> {
+        match variant {
+            PoolVariant::UniswapV2 => Ok(Self::UniswapV2),
+            PoolVariant::UniswapV3 => Ok(Self::UniswapV3),
+            PoolVariant::Curve => Err(anyhow::anyhow!("rusty-sando doesn't support Curve pools")),
+        }
+    }
+}
+
+impl From<rusty_sando::prelude::PoolVariant> for PoolVariant {
+    fn from(variant: rusty_sando::prelude::PoolVariant) -> Self {
+        match variant {
+            rusty_sando::prelude::PoolVariant::UniswapV2 => Self::UniswapV2,
+            rusty_sando::prelude::PoolVariant::UniswapV3 => Self::UniswapV3,
+        }
+    }
+}
+
+/// The DEX a pool belongs to. V2-style DEXes (e.g. SushiSwap) trade like `PoolVariant::UniswapV2`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Dex {
+    UniswapV2,
+    UniswapV3,
+    SushiSwap,
+    Curve,
 }
 
 #[cfg(test)]
@@ -79,6 +511,137 @@ mod test {
     use ethers::types::H256;
     use mev_share_sse::Hint;
     use rand::Rng;
+
+    #[test]
+    fn it_parses_placements() {
+        for placement in [
+            Placement::TopOfBlock,
+            Placement::InBlock,
+            Placement::Pessimistic(5),
+        ] {
+            assert_eq!(placement.to_string().parse(), Ok(placement));
+        }
+        assert_eq!(
+            "pessimistic".parse(),
+            Ok(Placement::Pessimistic(DEFAULT_FOLLOWING_TXS))
+        );
+        assert!("pessimistic:lots".parse::<Placement>().is_err());
+        assert!("bottom-of-block".parse::<Placement>().is_err());
+    }
+
+    #[test]
+    fn it_summarizes_hints() -> anyhow::Result<()> {
+        let address = Address::from_low_u64_be(1);
+        let topic = H256::from_low_u64_be(2);
+        let hint: Hint = serde_json::from_value(serde_json::json!({
+            "txs": [{ "functionSelector": "0x12345678" }],
+            "hash": H256::from_low_u64_be(3),
+            "logs": [
+                { "address": address, "topics": [topic], "data": "0x" },
+                { "address": address, "topics": [topic], "data": "0x01" },
+            ],
+            "mevGasPrice": "0x2a",
+        }))?;
+        let summary = HintSummary::from(&hint);
+        assert_eq!(summary.hash, hint.hash);
+        assert_eq!(
+            summary.logs,
+            vec![
+                HintedLog {
+                    address,
+                    topics: vec![topic],
+                    data: false,
+                },
+                HintedLog {
+                    address,
+                    topics: vec![topic],
+                    data: true,
+                },
+            ]
+        );
+        assert!(!summary.calldata);
+        assert!(summary.function_selector);
+        assert_eq!(summary.mev_gas_price, Some(42.into()));
+        assert_eq!(summary.gas_used, None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_batches_saved_without_hints() -> anyhow::Result<()> {
+        let mut json = serde_json::to_value(SimArbResultBatch::test_example())?;
+        let batch = json.as_object_mut().expect("batch is an object");
+        batch.remove("hint");
+        batch.remove("nonHinted");
+        let batch: SimArbResultBatch = serde_json::from_value(json)?;
+        assert_eq!(batch.hint, None);
+        assert!(batch.non_hinted.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_reads_results_saved_without_fee_tiers() -> anyhow::Result<()> {
+        let mut json = serde_json::to_value(SimArbResult::test_example())?;
+        json["userTrade"]
+            .as_object_mut()
+            .expect("user trade is an object")
+            .remove("poolFee");
+        let backrun = json["backrunTrade"]
+            .as_object_mut()
+            .expect("backrun is an object");
+        backrun.remove("startFee");
+        backrun.remove("endFee");
+        let res: SimArbResult = serde_json::from_value(json)?;
+        assert_eq!(res.user_trade.pool_fee, None);
+        assert_eq!(res.backrun_trade.start_fee, None);
+        assert_eq!(res.backrun_trade.start_variant, PoolVariant::UniswapV3);
+        Ok(())
+    }
+
+    #[test]
+    fn it_lists_backrun_legs() {
+        let res = SimArbResult::test_example();
+        let tokens = &res.user_trade.tokens;
+        let mut backrun = res.backrun_trade;
+        assert_eq!(
+            backrun.legs(tokens),
+            SwapLeg::round_trip(
+                tokens,
+                (backrun.start_pool, PoolVariant::UniswapV3),
+                (backrun.end_pool, PoolVariant::UniswapV2)
+            )
+        );
+
+        // WETH -> USDC -> TKN -> WETH
+        let usdc = Address::from_low_u64_be(5);
+        let (weth_usdc, usdc_token) = (Address::from_low_u64_be(6), Address::from_low_u64_be(7));
+        backrun.route = vec![
+            (weth_usdc, PoolVariant::UniswapV3),
+            (usdc_token, PoolVariant::UniswapV2),
+            (res.user_trade.pool, PoolVariant::UniswapV2),
+        ];
+        backrun.route_tokens = vec![tokens.weth, usdc, tokens.token, tokens.weth];
+        let legs = backrun.legs(tokens);
+        assert_eq!(legs.len(), 3);
+        assert_eq!(
+            legs[1],
+            SwapLeg {
+                pool: usdc_token,
+                variant: PoolVariant::UniswapV2,
+                token_in: usdc,
+                token_out: tokens.token,
+            }
+        );
+        assert_eq!(legs[2].token_out, tokens.weth);
+    }
+
+    #[test]
+    fn it_converts_pool_variants_to_rusty_sando() {
+        for variant in [PoolVariant::UniswapV2, PoolVariant::UniswapV3] {
+            let sando = rusty_sando::prelude::PoolVariant::try_from(variant).unwrap();
+            assert_eq!(PoolVariant::from(sando), variant);
+        }
+        assert!(rusty_sando::prelude::PoolVariant::try_from(PoolVariant::Curve).is_err());
+    }
     impl SimArbResultBatch {
         pub fn test_example() -> Self {
             // get random u64
@@ -98,6 +661,108 @@ mod test {
                 },
                 results: vec![],
                 max_profit: 0x1337.into(),
+                failures: 0,
+                live: false,
+                placement: Placement::TopOfBlock,
+                eth_usd_price: Some(2000.0),
+                profit_usd: Some(2000.0 * 0x1337 as f64 / 1e18),
+                hint: None,
+                non_hinted: vec![],
+                hint_only: false,
+            }
+        }
+
+        /// Arbs that `SortOrder`s put in different orders. Hex amounts of different lengths
+        /// sort differently as strings than as numbers, and arb 0's largest backrun isn't its best.
+        ///
+        /// By profit: [1, 2, 0, 3]; by amount in: [2, 0, 1, 3]; by block: [0, 2, 1, 3].
+        pub fn sort_examples() -> Vec<Self> {
+            let result = |profit: u64, amount_in: u64| {
+                let mut res = SimArbResult::test_example();
+                res.backrun_trade.profit = profit.into();
+                res.backrun_trade.amount_in = amount_in.into();
+                res
+            };
+            [
+                (3, vec![result(0x9, 0x100), result(0x5, 0xffff)]),
+                (1, vec![result(0x10, 0x9)]),
+                (2, vec![result(0xa, 0x1000)]),
+                (0, vec![]),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, (block, results))| {
+                let mut arb = SimArbResultBatch::test_example();
+                arb.event.hint.hash = H256::from_low_u64_be(i as u64);
+                arb.event.block = block;
+                arb.results = results;
+                arb.max_profit = arb
+                    .best_result()
+                    .map_or(U256::zero(), |res| res.backrun_trade.profit);
+                arb
+            })
+            .collect()
+        }
+    }
+
+    impl SimArbResult {
+        pub fn test_example() -> Self {
+            let weth = Address::from_low_u64_be(1);
+            let token = Address::from_low_u64_be(2);
+            let pool = Address::from_low_u64_be(3);
+            let other_pool = Address::from_low_u64_be(4);
+            Self {
+                user_trade: UserTradeParams {
+                    pool_variant: PoolVariant::UniswapV2,
+                    pool_fee: None,
+                    token_in: weth,
+                    token_out: token,
+                    amount0_sent: 1_000_000.into(),
+                    amount1_sent: 0.into(),
+                    token0_is_weth: true,
+                    pool,
+                    price: 42.into(),
+                    price_before: 44.into(),
+                    price_impact_bps: Some(454),
+                    tokens: TokenPair { weth, token },
+                    arb_pools: vec![PairPool {
+                        variant: PoolVariant::UniswapV3,
+                        address: other_pool,
+                        fee: Some(3000),
+                        dex: Some(Dex::UniswapV3),
+                    }],
+                    intermediate_pools: vec![],
+                    dex: Some(Dex::UniswapV2),
+                    token_symbol: Some("TKN".to_owned()),
+                    token_decimals: Some(18),
+                    eth_equivalent: false,
+                },
+                backrun_trade: BackrunResult {
+                    amount_in: U256::from(10).pow(17.into()),
+                    balance_end: U256::from(10).pow(18.into()) * 420 + 1337,
+                    profit: 1337.into(),
+                    profit_eth: 1.337e-15,
+                    gas_used: 180_000,
+                    gas_used_buy: 100_000,
+                    gas_used_sell: 80_000,
+                    effective_gas_price: 30_000_000_000u64.into(),
+                    gas_cost: U256::from(180_000) * 30_000_000_000u64,
+                    start_pool: other_pool,
+                    end_pool: pool,
+                    start_variant: PoolVariant::UniswapV3,
+                    end_variant: PoolVariant::UniswapV2,
+                    start_fee: Some(3000),
+                    end_fee: None,
+                    route: vec![],
+                    route_tokens: vec![],
+                    search_bound: Some(SearchBound::Interior),
+                    search_truncated: false,
+                    coarse: None,
+                    user_pool_price: U256::exp10(18) * 42,
+                    alt_pool_price: U256::exp10(18) * 41,
+                },
+                backrun_txs: None,
+                search_trace: None,
             }
         }
     }
@@ -1,3 +1,5 @@
+pub mod chain;
+pub mod client;
 pub mod commands;
 pub mod config;
 pub mod data;
@@ -5,8 +7,15 @@ pub mod error;
 pub mod event_history;
 pub mod hindsight;
 pub mod interfaces;
+pub mod notify;
 pub mod sim;
+#[cfg(all(test, feature = "integration-tests"))]
+mod test_chain;
 pub mod util;
 
 pub use anyhow::{Error, Result};
-pub use tracing::{debug, error as log_error, info, warn};
+pub use data::arbs::ArbFilterParams;
+pub use hindsight::{Hindsight, HindsightBuilder, OrderflowProgress};
+pub use interfaces::SimArbResultBatch;
+pub use sim::core::{SearchConfig, SimOptions};
+pub use tracing::{debug, error as log_error, info, trace, warn};
@@ -1,37 +1,193 @@
-use ethers::types::U256;
+use ethers::types::{Address, U256};
 use hindsight::{
     commands::{self},
     config::Config,
     data::{
-        arbs::{ArbFilterParams, WriteEngine},
+        arbs::{coverage_gaps, ArbFilterParams, ArbReader, WriteEngine},
+        checkpoint::Checkpoint,
         db::Db,
+        event_cache::EventCache,
+        failed_events::FailedEvents,
+        units::AmountUnits,
     },
     // debug,
     hindsight::Hindsight,
     info,
-    util::get_ws_client,
+    interfaces::BlockRange,
+    sim::snapshot,
+    util::{get_client, init_metadata_cache},
+    warn,
 };
 use mev_share_sse::EventClient;
 use revm::primitives::bitvec::macros::internal::funty::Fundamental;
-use std::thread::available_parallelism;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tracing_subscriber::EnvFilter;
 mod cli;
-use cli::{Cli, Commands};
+mod progress;
+use cli::{
+    AuditCommands, CacheCommands, Cli, Commands, ConfigCommands, ExportFormat, ReportFormat,
+};
+use progress::ScanProgress;
+
+/// Initializes the global tracing subscriber.
+///
+/// `RUST_LOG` takes precedence if set; otherwise `verbosity` picks the level for hindsight's logs.
+/// Logs go to stderr when `to_stderr` is set, so they don't mix with `--output json` on stdout.
+fn init_logging(verbosity: u8, json: bool, to_stderr: bool) {
+    let level = match verbosity {
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("info,hindsight={}", level)));
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match (json, to_stderr) {
+        (true, true) => subscriber.json().with_writer(std::io::stderr).init(),
+        (true, false) => subscriber.json().init(),
+        (false, true) => subscriber.with_writer(std::io::stderr).init(),
+        (false, false) => subscriber.init(),
+    }
+}
+
+/// Converts a `--min-profit` arg (ETH) to wei.
+fn parse_min_profit(min_profit: Option<f64>) -> U256 {
+    let min_profit = min_profit.unwrap_or(0f64);
+    if min_profit < 0f64 {
+        panic!("min_profit must be >= 0");
+    } else if min_profit > 0.0 && min_profit * 1e9 < 1.0 {
+        panic!("min_profit must be >= 1e9 wei");
+    }
+    U256::from((min_profit * 1e9) as u64) * U256::from(1e9.as_u64())
+}
+
+/// Converts repeated `--token`/`--pool` args to an arb filter; no args means no filter.
+fn address_filter(addresses: &[Address]) -> Option<Vec<Address>> {
+    if addresses.is_empty() {
+        None
+    } else {
+        Some(addresses.to_vec())
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    let config = Config::default();
     let cli = Cli::parse_args();
+    let json = matches!(cli.output, ReportFormat::Json);
+    init_logging(cli.debug, cli.log_json, json);
+    let mut config = Config::default();
+    config.search_intervals = cli.search.intervals.unwrap_or(config.search_intervals);
+    config.search_depth = cli.search.depth.unwrap_or(config.search_depth);
+    if let Some(tolerance_gwei) = cli.search.tolerance_gwei {
+        config.search_tolerance = U256::from(tolerance_gwei) * U256::exp10(9);
+    }
+    config.search_trace |= cli.search.trace;
+    config.hint_only |= cli.hint_only;
+    if !cli.filter.pools.is_empty() {
+        config.event_filter.pools = cli.filter.pools.iter().copied().collect();
+    }
+    if !cli.filter.tokens.is_empty() {
+        config.event_filter.tokens = cli.filter.tokens.iter().copied().collect();
+    }
+    if !cli.filter.deny_tokens.is_empty() {
+        config.event_filter.deny_tokens = cli.filter.deny_tokens.iter().copied().collect();
+    }
+    init_metadata_cache(config.metadata_cache_path.to_owned())?;
 
+    // `scan` stops gracefully once its queued events are saved; everything else (or a second ctrl-c) exits now.
+    let graceful = matches!(cli.command, Some(Commands::Scan { .. }));
+    let shutdown = Arc::new(AtomicBool::new(false));
+    let shutdown_handle = shutdown.clone();
     ctrlc::set_handler(move || {
-        println!("\nstopping hindsight!");
-        std::process::exit(0);
+        if graceful && !shutdown_handle.swap(true, Ordering::SeqCst) {
+            eprintln!(
+                "\nstopping hindsight once the queued events are saved... (ctrl-c again to force quit)"
+            );
+        } else {
+            eprintln!("\nstopping hindsight!");
+            std::process::exit(0);
+        }
     })
     .expect("Error setting Ctrl-C handler");
 
-    let ws_client = get_ws_client(None).await?;
+    // doesn't need an RPC connection, so it can be used to debug one
+    if let Some(Commands::Config {
+        command: ConfigCommands::Show,
+    }) = cli.command
+    {
+        println!("{}", config);
+        return Ok(());
+    }
+    // maintains the local event cache; doesn't need an RPC connection (only `refresh` fetches events)
+    if let Some(Commands::Cache { command }) = &cli.command {
+        let cache = EventCache::default();
+        match command {
+            CacheCommands::Stats => commands::cache::stats(&cache, json)?,
+            CacheCommands::Refresh {
+                block_start,
+                block_end,
+            } => {
+                if block_start > block_end {
+                    anyhow::bail!("block_start must be <= block_end");
+                }
+                commands::cache::refresh(*block_start, *block_end, &EventClient::default(), &cache)
+                    .await?
+            }
+            CacheCommands::Evict { before } => commands::cache::evict(*before, &cache)?,
+            CacheCommands::Repair => commands::cache::repair(&cache)?,
+        }
+        return Ok(());
+    }
+    // reads the audit log; doesn't need an RPC connection
+    if let Some(Commands::Audit {
+        command: AuditCommands::Grep { event_hash, path },
+    }) = &cli.command
+    {
+        let path = path
+            .to_owned()
+            .or(config.audit_log_path.to_owned())
+            .ok_or(anyhow::anyhow!(
+                "no audit log; set AUDIT_LOG_PATH or --path"
+            ))?;
+        commands::audit::grep(&path, *event_hash)?;
+        return Ok(());
+    }
+
+    // serves saved arbs; doesn't need an RPC connection
+    if let Some(Commands::Serve { addr, read_db }) = &cli.command {
+        #[cfg(feature = "serve")]
+        {
+            let read_db = Db::new(read_db.to_owned().unwrap_or_default())
+                .await
+                .connect;
+            commands::serve::run(read_db, *addr).await?;
+            return Ok(());
+        }
+        #[cfg(not(feature = "serve"))]
+        {
+            let _ = (addr, read_db);
+            anyhow::bail!("`serve` isn't supported by this build; rebuild with `--features serve`");
+        }
+    }
+
+    // re-simulates from a state snapshot, without an RPC connection
+    if let Some(Commands::SimulateTx {
+        tx_hash,
+        offline: true,
+        ..
+    }) = cli.command
+    {
+        commands::simulate_tx::run_offline(tx_hash, config.sim_options()?, json).await?;
+        return Ok(());
+    }
+
+    config.validate()?;
+
+    let rpc_client = get_client(None).await?;
     let mevshare = EventClient::default();
-    let hindsight = Hindsight::new(config.rpc_url_ws).await?;
 
     match cli.command {
         Some(Commands::Scan {
@@ -42,6 +198,10 @@ async fn main() -> anyhow::Result<()> {
             timestamp_start,
             batch_size,
             db_engine,
+            resume,
+            max_gap,
+            force,
+            record_state,
         }) => {
             /* If no start/end params are defined,
                 refine params based on ranges present in DB.
@@ -52,30 +212,82 @@ async fn main() -> anyhow::Result<()> {
                 then we know we've scanned & simulated up to that point.
                 Timestamp is evaluated by default, falls back to block.
             */
-            let db_engine = db_engine.unwrap_or_default();
+            let db_engine = db_engine
+                .unwrap_or_default()
+                .allowing_duplicates(cli.allow_duplicates);
             let db = Db::new(db_engine.to_owned()).await;
-            let (block_start, timestamp_start) =
-                if block_start.is_none() && timestamp_start.is_none() {
-                    let db_ranges = db.connect.get_previously_saved_ranges().await?;
-                    info!("previously saved event ranges: {:?}", db_ranges);
-                    let block_start = db_ranges.latest_block;
-                    let timestamp_start = db_ranges.latest_timestamp;
-                    (block_start as u32, timestamp_start as u32)
-                } else {
-                    if block_start.is_some() && timestamp_start.is_some() {
-                        panic!("cannot specify both block_start and timestamp_start");
-                    }
-                    // use whichever is specified; the other (being 1) will not alter the selection
-                    (block_start.unwrap_or(1), timestamp_start.unwrap_or(1))
-                };
+            // with a start block, --resume fills the gaps in the saved arbs rather than using the checkpoint
+            let gaps = match (resume, block_start) {
+                (true, Some(window_start)) => {
+                    let covered = db.connect.get_covered_ranges(max_gap).await?;
+                    let window = BlockRange {
+                        start: window_start as u64,
+                        end: block_end.map(|end| end as u64).unwrap_or(
+                            covered
+                                .last()
+                                .map_or(window_start as u64, |range| range.end),
+                        ),
+                    };
+                    let gaps = coverage_gaps(&covered, window, max_gap);
+                    info!(
+                        "found {} gaps in saved arbs in blocks {}..={}: {:?}",
+                        gaps.len(),
+                        window.start,
+                        window.end,
+                        gaps
+                    );
+                    Some(gaps)
+                }
+                _ => None,
+            };
+            let checkpoint = if resume && gaps.is_none() {
+                let checkpoint = Checkpoint::load().await?;
+                if checkpoint.is_none() {
+                    warn!("no checkpoint found, falling back to previously saved ranges");
+                }
+                checkpoint
+            } else {
+                None
+            };
+            let (block_start, timestamp_start) = if let Some(checkpoint) = checkpoint {
+                info!("resuming from checkpoint: {:?}", checkpoint);
+                // add 1 to skip the last event we processed
+                (1, checkpoint.timestamp as u32 + 1)
+            } else if gaps.is_some() || (block_start.is_none() && timestamp_start.is_none()) {
+                let db_ranges = db.connect.get_previously_saved_ranges().await?;
+                info!("previously saved event ranges: {:?}", db_ranges);
+                let block_start = db_ranges.latest_block;
+                let timestamp_start = db_ranges.latest_timestamp;
+                (block_start as u32, timestamp_start as u32)
+            } else {
+                if block_start.is_some() && timestamp_start.is_some() {
+                    panic!("cannot specify both block_start and timestamp_start");
+                }
+                // use whichever is specified; the other (being 1) will not alter the selection
+                (block_start.unwrap_or(1), timestamp_start.unwrap_or(1))
+            };
 
-            let batch_size = batch_size.unwrap_or(
-                available_parallelism()
-                    .map(|n| usize::from(n) / 2)
-                    .unwrap_or(4)
-                    .max(1),
-            );
+            let batch_size = batch_size.unwrap_or(config.event_concurrency);
             info!("batch size: {}", batch_size);
+            let mut sim_options = config.sim_options()?;
+            if record_state {
+                sim_options.record_state = Some(snapshot::default_dir());
+            }
+            let progress = Arc::new(ScanProgress::new(cli.log_json || json));
+            let mut builder = Hindsight::builder()
+                .client(rpc_client.clone())
+                .sim_options(sim_options)
+                .concurrency(batch_size)
+                .db(db.connect.clone())
+                .failed_events(Arc::new(FailedEvents::open(FailedEvents::default_path())?))
+                .on_progress({
+                    let progress = progress.clone();
+                    move |update| progress.update(update)
+                });
+            if let Some(notifier) = config.notifier()? {
+                builder = builder.notifier(notifier);
+            }
+            let hindsight = builder.build().await?;
             let scan_options = commands::scan::ScanOptions {
                 block_start,
                 block_end,
@@ -83,13 +295,111 @@ async fn main() -> anyhow::Result<()> {
                 timestamp_end,
                 batch_size,
                 db_engine,
+                force,
             };
-            commands::scan::run(
-                scan_options.to_owned(),
-                &ws_client,
+            let mut summary = commands::scan::ScanSummary::default();
+            if let Some(gaps) = &gaps {
+                summary += commands::scan::run_gaps(
+                    scan_options.to_owned(),
+                    gaps,
+                    &rpc_client,
+                    &mevshare,
+                    &hindsight,
+                    shutdown.clone(),
+                )
+                .await?;
+            }
+            // a gap-filling scan of a closed window is done once its gaps are scanned
+            let window_done = gaps.is_some() && block_end.is_some();
+            if !window_done && !shutdown.load(Ordering::SeqCst) {
+                summary += commands::scan::run(
+                    scan_options.to_owned(),
+                    &rpc_client,
+                    &mevshare,
+                    &hindsight,
+                    shutdown,
+                )
+                .await?;
+            }
+            progress.finish();
+            // text mode already logged the totals
+            if json {
+                commands::print_result(&summary, json)?;
+            }
+        }
+        Some(Commands::RetryFailed {
+            batch_size,
+            db_engine,
+        }) => {
+            let db = Db::new(
+                db_engine
+                    .unwrap_or_default()
+                    .allowing_duplicates(cli.allow_duplicates),
+            )
+            .await;
+            let batch_size = batch_size.unwrap_or(config.event_concurrency);
+            let failed_events = Arc::new(FailedEvents::open(FailedEvents::default_path())?);
+            let mut builder = Hindsight::builder()
+                .client(rpc_client.clone())
+                .sim_options(config.sim_options()?)
+                .concurrency(batch_size)
+                .db(db.connect.clone())
+                .failed_events(failed_events.clone());
+            if let Some(notifier) = config.notifier()? {
+                builder = builder.notifier(notifier);
+            }
+            commands::retry_failed::run(
+                &rpc_client,
+                &mevshare,
+                builder.build().await?,
+                &failed_events,
+                batch_size,
+                json,
+            )
+            .await?;
+        }
+        Some(Commands::FetchEvents {
+            block_start,
+            timestamp_start,
+            block_end,
+            timestamp_end,
+            page_size,
+        }) => {
+            commands::fetch_events::run(
+                commands::fetch_events::FetchEventsOptions {
+                    block_start,
+                    block_end,
+                    timestamp_start,
+                    timestamp_end,
+                    page_size,
+                },
+                &mevshare,
+                &EventCache::default(),
+            )
+            .await?;
+        }
+        Some(Commands::Listen {
+            batch_size,
+            queue_size,
+            db_engine,
+        }) => {
+            let db = Db::new(
+                db_engine
+                    .unwrap_or_default()
+                    .allowing_duplicates(cli.allow_duplicates),
+            )
+            .await;
+            let batch_size = batch_size.unwrap_or(config.event_concurrency);
+            commands::listen::run(
+                commands::listen::ListenOptions {
+                    batch_size,
+                    queue_size,
+                },
+                &rpc_client,
                 &mevshare,
-                &hindsight,
+                config.sim_options()?,
                 &db.connect,
+                config.notifier()?.as_ref(),
             )
             .await?;
         }
@@ -103,26 +413,29 @@ async fn main() -> anyhow::Result<()> {
             min_profit,
             read_db,
             write_db,
+            format,
+            units,
+            eth_units,
         }) => {
-            let min_profit = min_profit.unwrap_or(0f64);
-            if min_profit < 0f64 {
-                panic!("min_profit must be >= 0");
-            } else if min_profit > 0.0 && min_profit * 1e9 < 1.0 {
-                panic!("min_profit must be >= 1e9 wei");
-            }
-            let umin_profit = U256::from((min_profit * 1e9) as u64) * U256::from(1e9.as_u64());
+            let umin_profit = parse_min_profit(min_profit);
 
             let db_engine = read_db.unwrap_or_default();
             let read_db = Db::new(db_engine.to_owned()).await.connect;
             // if filename is specified, use that, otherwise try write_db
             // if filename & write_db are both None, use file exporter & default filename
+            let file_dest = |filename: Option<String>| match format {
+                ExportFormat::Json => WriteEngine::File(filename),
+                ExportFormat::JsonGz => WriteEngine::FileGz(filename),
+                ExportFormat::Parquet => WriteEngine::Parquet(filename),
+                ExportFormat::Csv => WriteEngine::Csv(filename),
+            };
             let write_dest = if filename.is_some() {
-                WriteEngine::File(filename)
+                file_dest(filename)
             } else {
                 if let Some(write_db) = write_db {
                     WriteEngine::Db(write_db)
                 } else {
-                    WriteEngine::File(None)
+                    file_dest(None)
                 }
             };
 
@@ -133,12 +446,153 @@ async fn main() -> anyhow::Result<()> {
                     timestamp_end,
                     timestamp_start,
                     min_profit: Some(umin_profit),
+                    event_tx: None,
+                    tokens: address_filter(&cli.filter.tokens),
+                    pools: address_filter(&cli.filter.pools),
                 },
                 &read_db,
                 write_dest,
+                cli.allow_duplicates,
+                units
+                    .or(eth_units.then_some(AmountUnits::Eth))
+                    .or(config.export_units),
+            )
+            .await?;
+        }
+        Some(Commands::ExportBundles {
+            filename,
+            block_end,
+            block_start,
+            timestamp_end,
+            timestamp_start,
+            from_db,
+        }) => {
+            let read_db = Db::new(from_db.unwrap_or_default()).await.connect;
+            commands::export_bundles::run(
+                &rpc_client,
+                ArbFilterParams {
+                    block_end,
+                    block_start,
+                    timestamp_end,
+                    timestamp_start,
+                    min_profit: None,
+                    event_tx: None,
+                    tokens: address_filter(&cli.filter.tokens),
+                    pools: address_filter(&cli.filter.pools),
+                },
+                &read_db,
+                filename,
+            )
+            .await?;
+        }
+        Some(Commands::Analyze {
+            timestamp_start,
+            timestamp_end,
+            block_start,
+            block_end,
+            min_profit,
+            read_db,
+        }) => {
+            let read_db = Db::new(read_db.unwrap_or_default()).await.connect;
+            commands::analyze::run(
+                ArbFilterParams {
+                    block_end,
+                    block_start,
+                    timestamp_end,
+                    timestamp_start,
+                    min_profit: Some(parse_min_profit(min_profit)),
+                    event_tx: None,
+                    tokens: address_filter(&cli.filter.tokens),
+                    pools: address_filter(&cli.filter.pools),
+                },
+                &read_db,
+                json,
+            )
+            .await?;
+        }
+        Some(Commands::TopArbs {
+            limit,
+            sort,
+            timestamp_start,
+            timestamp_end,
+            block_start,
+            block_end,
+            min_profit,
+            read_db,
+        }) => {
+            let read_db = Db::new(read_db.unwrap_or_default()).await.connect;
+            commands::top_arbs::run(
+                ArbFilterParams {
+                    block_end,
+                    block_start,
+                    timestamp_end,
+                    timestamp_start,
+                    min_profit: Some(parse_min_profit(min_profit)),
+                    event_tx: None,
+                    tokens: address_filter(&cli.filter.tokens),
+                    pools: address_filter(&cli.filter.pools),
+                },
+                &read_db,
+                sort,
+                limit,
+                json,
+            )
+            .await?;
+        }
+        Some(Commands::Replay {
+            timestamp_start,
+            timestamp_end,
+            block_start,
+            block_end,
+            min_profit,
+            limit,
+            tolerance_bps,
+            read_db,
+        }) => {
+            let read_db = Db::new(read_db.unwrap_or_default()).await.connect;
+            commands::replay::run(
+                &rpc_client,
+                &config.chain,
+                config.braindance(),
+                ArbFilterParams {
+                    block_end,
+                    block_start,
+                    timestamp_end,
+                    timestamp_start,
+                    min_profit: Some(parse_min_profit(min_profit)),
+                    event_tx: None,
+                    tokens: address_filter(&cli.filter.tokens),
+                    pools: address_filter(&cli.filter.pools),
+                },
+                &read_db,
+                limit,
+                tolerance_bps,
+                config.event_concurrency,
+                json,
+            )
+            .await?;
+        }
+        Some(Commands::SimulateTx {
+            tx_hash,
+            block,
+            offline: false,
+        }) => {
+            commands::simulate_tx::run(
+                &rpc_client,
+                &mevshare,
+                tx_hash,
+                block,
+                config.sim_options()?,
+                json,
             )
             .await?;
         }
+        // handled before connecting to the RPC
+        Some(Commands::Config { .. })
+        | Some(Commands::Cache { .. })
+        | Some(Commands::Audit { .. })
+        | Some(Commands::Serve { .. })
+        | Some(Commands::SimulateTx { offline: true, .. }) => {}
         None => {
             let program = std::env::args().next().unwrap_or("hindsight".to_owned());
             println!("for usage, run: {} --help", program);
@@ -0,0 +1,289 @@
+use crate::{
+    client::redact_url,
+    config::Secret,
+    debug,
+    interfaces::{SimArbResultBatch, TokenPair},
+    warn, Result,
+};
+use ethers::{
+    types::{Address, H256, U256},
+    utils::format_ether,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{str::FromStr, sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// Max number of times a notification is sent before it's dropped.
+const MAX_DELIVERY_ATTEMPTS: u32 = 3;
+/// Time to wait before the first retry; doubles on every failed attempt.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shape of the JSON body POSTed to the webhook.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum WebhookFormat {
+    /// An `ArbNotification`, as-is.
+    #[default]
+    Json,
+    /// A Discord message (`{"content": ...}`) summarizing the arb.
+    Discord,
+}
+
+impl std::fmt::Display for WebhookFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookFormat::Json => write!(f, "json"),
+            WebhookFormat::Discord => write!(f, "discord"),
+        }
+    }
+}
+
+impl FromStr for WebhookFormat {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(WebhookFormat::Json),
+            "discord" => Ok(WebhookFormat::Discord),
+            _ => Err(format!("invalid webhook format: {}", s)),
+        }
+    }
+}
+
+/// Where & when to send notifications about profitable arbs.
+#[derive(Clone, Debug)]
+pub struct NotifyConfig {
+    /// Webhook that notifications are POSTed to. Webhook URLs embed their own credentials, so it's secret.
+    pub webhook_url: Secret<String>,
+    pub format: WebhookFormat,
+    /// Only arbs more profitable than this (wei) are notified.
+    pub min_profit: U256,
+}
+
+/// A profitable arb, as sent to the webhook (`WebhookFormat::Json`).
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArbNotification {
+    pub event_hash: H256,
+    pub block: u64,
+    pub tokens: TokenPair,
+    /// Pools the backrun swaps on: (buy pool, sell pool).
+    pub pools: Vec<Address>,
+    /// WETH (wei) swapped by the backrun.
+    pub amount_in: U256,
+    /// Profit (wei) after paying for gas.
+    pub profit: U256,
+    pub live: bool,
+}
+
+impl ArbNotification {
+    /// Describes the most profitable arb in `batch`. None if the batch has no results.
+    pub fn from_batch(batch: &SimArbResultBatch) -> Option<Self> {
+        let best = batch.best_result()?;
+        Some(Self {
+            event_hash: batch.event.hint.hash,
+            block: batch.event.block,
+            tokens: best.user_trade.tokens.to_owned(),
+            pools: vec![best.backrun_trade.start_pool, best.backrun_trade.end_pool],
+            amount_in: best.backrun_trade.amount_in,
+            profit: best.backrun_trade.profit,
+            live: batch.live,
+        })
+    }
+
+    fn to_discord(&self) -> serde_json::Value {
+        json!({
+            "content": format!(
+                "**arb found: {} ETH profit**\nevent: `{:?}` (block {})\ntoken: `{:?}`\npools: {}\namount in: {} ETH",
+                format_ether(self.profit),
+                self.event_hash,
+                self.block,
+                self.tokens.token,
+                self.pools
+                    .iter()
+                    .map(|pool| format!("`{:?}`", pool))
+                    .collect::<Vec<_>>()
+                    .join(" -> "),
+                format_ether(self.amount_in),
+            )
+        })
+    }
+}
+
+/// Sends webhook notifications for arbs above a profit threshold.
+///
+/// Notifications are delivered in the background, so a slow or failing webhook never holds up
+/// (or fails) the sims; undeliverable notifications are dropped with a warning.
+#[derive(Clone, Debug)]
+pub struct Notifier {
+    http: reqwest::Client,
+    config: Arc<NotifyConfig>,
+}
+
+impl Notifier {
+    pub fn new(config: NotifyConfig) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()?,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Sends a notification for `batch` if its best arb is more profitable than the threshold.
+    /// Returns the handle of the delivery task, if one was started.
+    pub fn notify(&self, batch: &SimArbResultBatch) -> Option<JoinHandle<()>> {
+        if batch.max_profit <= self.config.min_profit {
+            return None;
+        }
+        let notification = ArbNotification::from_batch(batch)?;
+        let body = match self.config.format {
+            WebhookFormat::Json => serde_json::to_value(&notification).ok()?,
+            WebhookFormat::Discord => notification.to_discord(),
+        };
+        let notifier = self.clone();
+        Some(tokio::task::spawn(async move {
+            notifier.deliver(notification.event_hash, body).await
+        }))
+    }
+
+    async fn deliver(&self, event_hash: H256, body: serde_json::Value) {
+        let mut backoff = INITIAL_BACKOFF;
+        for attempt in 1..=MAX_DELIVERY_ATTEMPTS {
+            let res = self
+                .http
+                .post(self.config.webhook_url.expose())
+                .json(&body)
+                .send()
+                .await
+                .and_then(|res| res.error_for_status());
+            match res {
+                Ok(_) => {
+                    debug!("sent notification for event {:?}", event_hash);
+                    return;
+                }
+                Err(err) if attempt < MAX_DELIVERY_ATTEMPTS => {
+                    // reqwest errors quote the URL, which holds the webhook's credentials
+                    debug!(
+                        "failed to send notification to {} (attempt {}): {}",
+                        redact_url(self.config.webhook_url.expose()),
+                        attempt,
+                        err.without_url()
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(err) => warn!(
+                    "dropping notification for event {:?} after {} attempts: {}",
+                    event_hash,
+                    attempt,
+                    err.without_url()
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::SimArbResult;
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// Serves HTTP requests on a local port, replying 200 to each and passing on their JSON bodies.
+    fn mock_webhook() -> (String, mpsc::Receiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/webhook", listener.local_addr().unwrap());
+        let (bodies_tx, bodies_rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                    .unwrap();
+                let _ = bodies_tx.send(serde_json::from_slice(&body).unwrap());
+            }
+        });
+        (url, bodies_rx)
+    }
+
+    fn profitable_batch() -> SimArbResultBatch {
+        let mut batch = SimArbResultBatch::test_example();
+        let mut best = SimArbResult::test_example();
+        best.backrun_trade.profit = U256::exp10(17);
+        batch.results = vec![SimArbResult::test_example(), best];
+        batch.max_profit = U256::exp10(17);
+        batch
+    }
+
+    #[tokio::test]
+    async fn it_posts_profitable_arbs_to_webhook() -> Result<()> {
+        let (url, bodies) = mock_webhook();
+        let notifier = |format| {
+            Notifier::new(NotifyConfig {
+                webhook_url: Secret::new(url.to_owned()),
+                format,
+                min_profit: U256::exp10(16),
+            })
+        };
+        let batch = profitable_batch();
+
+        notifier(WebhookFormat::Json)?
+            .notify(&batch)
+            .expect("arb is above the threshold")
+            .await?;
+        let body = bodies.recv_timeout(Duration::from_secs(5))?;
+        let expected = SimArbResult::test_example();
+        assert_eq!(
+            body,
+            json!({
+                "eventHash": batch.event.hint.hash,
+                "block": 9001,
+                "tokens": expected.user_trade.tokens,
+                "pools": [expected.backrun_trade.start_pool, expected.backrun_trade.end_pool],
+                "amountIn": expected.backrun_trade.amount_in,
+                "profit": U256::exp10(17),
+                "live": false,
+            })
+        );
+
+        notifier(WebhookFormat::Discord)?
+            .notify(&batch)
+            .expect("arb is above the threshold")
+            .await?;
+        let body = bodies.recv_timeout(Duration::from_secs(5))?;
+        let content = body["content"].as_str().unwrap();
+        assert!(
+            content.contains("0.100000000000000000 ETH profit"),
+            "{}",
+            content
+        );
+
+        // below the threshold, nothing is sent
+        let mut batch = SimArbResultBatch::test_example();
+        batch.results = vec![SimArbResult::test_example()];
+        assert!(notifier(WebhookFormat::Json)?.notify(&batch).is_none());
+        Ok(())
+    }
+}
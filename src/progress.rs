@@ -0,0 +1,122 @@
+use ethers::{types::U256, utils::format_ether};
+use hindsight::{hindsight::progress, info, OrderflowProgress};
+use indicatif::{HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    io::IsTerminal,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How often a progress line is logged when there's no progress bar.
+const PROGRESS_LINE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Running totals of the events a scan processed.
+#[derive(Debug, Default)]
+struct Totals {
+    queued: usize,
+    processed: usize,
+    arbs: usize,
+    profit: U256,
+    failures: usize,
+}
+
+impl Totals {
+    fn summary(&self, elapsed: Duration) -> String {
+        let (rate, eta) = progress(self.processed, self.queued, elapsed);
+        let mut summary = format!(
+            "{} arbs found, {} ETH simulated profit, {} failed, {:.1} events/min",
+            self.arbs,
+            format_ether(self.profit),
+            self.failures,
+            rate
+        );
+        if self.processed > 0 {
+            summary.push_str(&format!(", ETA {}", HumanDuration(eta)));
+        }
+        summary
+    }
+}
+
+/// Shows the progress of a scan, from the `OrderflowProgress` it reports: as a progress bar if
+/// stdout is a terminal, or else as a log line every `PROGRESS_LINE_INTERVAL` (e.g. in CI or
+/// under nohup).
+pub struct ScanProgress {
+    bar: Option<ProgressBar>,
+    totals: Mutex<Totals>,
+    started: Instant,
+    last_line: Mutex<Instant>,
+}
+
+impl ScanProgress {
+    /// Draws a progress bar unless stdout isn't a terminal or `plain` is set (e.g. for JSON logs).
+    pub fn new(plain: bool) -> Self {
+        let bar = (!plain && std::io::stdout().is_terminal()).then(|| {
+            let bar = ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::stdout());
+            bar.set_style(
+                ProgressStyle::with_template(
+                    "{elapsed_precise} [{wide_bar}] {pos}/{len} events\n{msg}",
+                )
+                .expect("invalid progress bar template")
+                .progress_chars("=> "),
+            );
+            bar
+        });
+        let now = Instant::now();
+        Self {
+            bar,
+            totals: Mutex::new(Totals::default()),
+            started: now,
+            last_line: Mutex::new(now),
+        }
+    }
+
+    pub fn update(&self, update: OrderflowProgress) {
+        let mut totals = self.totals.lock().expect("scan progress poisoned");
+        match update {
+            OrderflowProgress::Queued(events) => totals.queued += events,
+            OrderflowProgress::Skipped(events) => totals.processed += events,
+            OrderflowProgress::Simulated { max_profit } => {
+                totals.processed += 1;
+                if !max_profit.is_zero() {
+                    totals.arbs += 1;
+                    totals.profit += max_profit;
+                }
+            }
+            OrderflowProgress::Failed => {
+                totals.processed += 1;
+                totals.failures += 1;
+            }
+        }
+        let summary = totals.summary(self.started.elapsed());
+        match &self.bar {
+            Some(bar) => {
+                bar.set_length(totals.queued as u64);
+                bar.set_position(totals.processed as u64);
+                bar.set_message(summary);
+            }
+            None => {
+                let mut last_line = self.last_line.lock().expect("scan progress poisoned");
+                if last_line.elapsed() >= PROGRESS_LINE_INTERVAL {
+                    *last_line = Instant::now();
+                    info!(
+                        "processed {}/{} events: {}",
+                        totals.processed, totals.queued, summary
+                    );
+                }
+            }
+        }
+    }
+
+    /// Leaves the final totals on screen (or logs them).
+    pub fn finish(&self) {
+        let totals = self.totals.lock().expect("scan progress poisoned");
+        let summary = totals.summary(self.started.elapsed());
+        match &self.bar {
+            Some(bar) => bar.finish_with_message(summary),
+            None => info!(
+                "processed {}/{} events: {}",
+                totals.processed, totals.queued, summary
+            ),
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    interfaces::SimArbResult,
+    sim::{
+        core::SimForks,
+        evm::{braindance_swap_data, commit_braindance_swap, commit_user_tx},
+    },
+    Result,
+};
+use ethers::{
+    signers::{LocalWallet, Signer},
+    types::{
+        transaction::eip2718::TypedTransaction, Bytes, Eip1559TransactionRequest, Transaction,
+        U256, U64,
+    },
+};
+use rusty_sando::{simulate::braindance_address, types::BlockInfo};
+use serde::{Deserialize, Serialize};
+
+/// A backrun bundle, in the shape of the params of Flashbots' `eth_sendBundle`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Bundle {
+    /// Signed txs; the user's tx first, then the backrun's.
+    pub txs: Vec<Bytes>,
+    /// The block that the bundle targets.
+    pub block_number: U64,
+}
+
+impl Bundle {
+    /// Bundles the user's (landed) tx with the signed backrun txs of one of its arbs,
+    /// targeting the block that the user's tx landed in.
+    pub fn new(user_tx: &Transaction, result: &SimArbResult, block_number: U64) -> Option<Self> {
+        let backrun_txs = result.backrun_txs.as_ref()?;
+        let mut txs = vec![user_tx.rlp()];
+        txs.extend(backrun_txs.iter().cloned());
+        Some(Self { txs, block_number })
+    }
+}
+
+/// Builds & signs the txs that would have executed `result`'s backrun after `user_tx`:
+/// a buy on the start pool, then a sell on the end pool (or a swap on each pool of its route).
+///
+/// Every tx calls the braindance contract, which only exists on sim forks; a live bot would swap
+/// through its own contract. The amount of tokens each swap after the first trades is found by
+/// re-simulating the swaps before it. Each tx gets the gas limit of the sims' swaps on `forks`.
+///
+/// Fails for routes through Curve pools, which braindance can't swap on.
+pub async fn sign_backrun(
+    forks: &SimForks,
+    block_info: &BlockInfo,
+    user_tx: &Transaction,
+    result: &SimArbResult,
+    signer: &LocalWallet,
+    nonce: U256,
+    priority_fee: U256,
+) -> Result<Vec<Bytes>> {
+    let backrun = &result.backrun_trade;
+    let legs = backrun.legs(&result.user_trade.tokens);
+    let (mut evm, _sim_permit) = forks.evm(block_info).await;
+    commit_user_tx(&mut evm, user_tx.to_owned()).await?;
+    let gas_limit = forks.braindance().swap_gas_limit;
+    let mut swaps = vec![];
+    let mut amount_in = backrun.amount_in;
+    for (i, leg) in legs.iter().enumerate() {
+        swaps.push(braindance_swap_data(
+            leg.variant,
+            amount_in,
+            leg.pool,
+            leg.token_in,
+            leg.token_out,
+        )?);
+        // the last swap's output isn't traded on
+        if i + 1 < legs.len() {
+            amount_in = commit_braindance_swap(
+                &mut evm,
+                leg.variant,
+                amount_in,
+                leg.pool,
+                leg.token_in,
+                leg.token_out,
+                block_info.base_fee,
+                None,
+                gas_limit,
+            )?
+            .balance;
+        }
+    }
+
+    let mut signed_txs = vec![];
+    for (i, data) in swaps.into_iter().enumerate() {
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .from(signer.address())
+            .to(braindance_address())
+            .data(data)
+            .nonce(nonce + i)
+            .gas(gas_limit)
+            .max_fee_per_gas(backrun.effective_gas_price)
+            .max_priority_fee_per_gas(priority_fee)
+            .chain_id(signer.chain_id())
+            .into();
+        let signature = signer.sign_transaction_sync(&tx)?;
+        signed_txs.push(tx.rlp_signed(&signature));
+    }
+    Ok(signed_txs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::{PoolVariant, TokenPair};
+    use crate::util::{get_block_info, test::get_test_client, ETH, WETH};
+    use ethers::{
+        providers::Middleware,
+        types::H256,
+        utils::rlp::{Decodable, Rlp},
+    };
+    use std::str::FromStr;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_signs_backrun_txs() -> Result<()> {
+        let client = get_test_client().await?;
+        let tx_hash =
+            H256::from_str("0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a")?;
+        let user_tx = client.get_transaction(tx_hash).await?.unwrap();
+        let block_info =
+            get_block_info(&client, user_tx.block_number.unwrap().as_u64() - 1).await?;
+        let forks = SimForks::new(&client, &block_info, None).await?;
+        let signer = LocalWallet::new(&mut rand::thread_rng());
+        // buy USDC on the V3 pool the user traded on, sell it on the V2 pair
+        let mut result = SimArbResult::test_example();
+        result.user_trade.tokens = TokenPair {
+            weth: WETH,
+            token: "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".parse()?,
+        };
+        let backrun = &mut result.backrun_trade;
+        backrun.amount_in = ETH;
+        backrun.start_pool = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640".parse()?;
+        backrun.start_variant = PoolVariant::UniswapV3;
+        backrun.end_pool = "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc".parse()?;
+        backrun.end_variant = PoolVariant::UniswapV2;
+        backrun.effective_gas_price = block_info.base_fee;
+
+        let backrun_txs = sign_backrun(
+            &forks,
+            &block_info,
+            &user_tx,
+            &result,
+            &signer,
+            7.into(),
+            1_000_000_000.into(),
+        )
+        .await?;
+        assert_eq!(backrun_txs.len(), 2);
+        for (i, raw_tx) in backrun_txs.iter().enumerate() {
+            let tx = Transaction::decode(&Rlp::new(raw_tx))?;
+            assert_eq!(tx.recover_from()?, signer.address());
+            assert_eq!(tx.nonce, U256::from(7 + i));
+            assert_eq!(tx.to, Some(braindance_address()));
+        }
+
+        result.backrun_txs = Some(backrun_txs);
+        let bundle = Bundle::new(&user_tx, &result, user_tx.block_number.unwrap())
+            .expect("signed arbs should be bundled");
+        assert_eq!(bundle.txs.len(), 3);
+        assert_eq!(bundle.txs[0], user_tx.rlp());
+        Ok(())
+    }
+}
@@ -1,68 +1,774 @@
+use crate::chain::ChainConfig;
+use crate::data::audit::AuditLog;
+use crate::data::blocklist::TokenBlocklist;
 use crate::error::HindsightError;
 use crate::interfaces::{
-    BackrunResult, PairPool, PoolVariant, SimArbResult, TokenPair, UserTradeParams,
+    ArbRoute, BackrunResult, CoarseSearch, Dex, NonHintedDecision, PairPool, Placement,
+    PoolVariant, SearchBound, SearchSample, SearchTrace, SimArbResult, SwapLeg, TokenPair,
+    UserTradeParams,
 };
-use crate::sim::evm::{commit_braindance_swap, sim_bundle, sim_price_v2, sim_price_v3};
+use crate::sim::bundle::sign_backrun;
+use crate::sim::curve::{curve_trader, sim_price_curve, DirectSwaps};
+use crate::sim::event_filter::EventFilter;
+use crate::sim::evm::{
+    commit_braindance_swap, commit_user_tx, sim_balance_of, sim_bundle, sim_eth_usd_price,
+    sim_price_v2, sim_price_v3, sim_weth_depth, SwapOutcome,
+};
+use crate::sim::fork_cache::BlockForkCache;
+use crate::sim::snapshot::{offline_fork_db, EventSnapshot, Recording, StateSnapshot};
 use crate::util::{
-    get_all_trading_pools, get_decimals, get_pair_tokens, get_price_v2, get_price_v3, WsClient,
+    fork_provider, get_all_trading_pools, get_decimals, get_intermediate_pools, get_pair_tokens,
+    get_price_v2, get_price_v3, get_receipts, get_token_metadata, get_v2_dex, prefetch_pair_tokens,
+    prefetch_token_metadata, price_impact_bps, wei_to_eth, RpcClient,
 };
-use crate::{debug, info};
+use crate::{debug, info, trace, warn};
 use crate::{Error, Result};
 use async_recursion::async_recursion;
-use ethers::providers::Middleware;
-use ethers::types::{AccountDiff, Address, BlockNumber, Transaction, H160, H256, I256, U256};
-use futures::future;
-use mev_share_sse::{EventHistory, EventTransactionLog};
-use revm::primitives::U256 as rU256;
+use ethers::abi::{self, Token};
+use ethers::providers::{Middleware, Provider, Ws};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{
+    AccountDiff, Address, BlockNumber, Log, Transaction, TransactionReceipt, H160, H256, I256, U256,
+};
+use ethers::utils::keccak256;
+use futures::{future, FutureExt};
+use mev_share_sse::{EventHistory, EventTransactionLog, Hint};
+use revm::db::{CacheDB, DatabaseRef, EmptyDB};
+use revm::primitives::{AccountInfo, KECCAK_EMPTY, U256 as rU256};
 use revm::EVM;
 use rusty_sando::prelude::fork_db::ForkDB;
 use rusty_sando::simulate::{
-    attach_braindance_module, braindance_starting_balance, setup_block_state,
+    attach_braindance_module, braindance_address, braindance_starting_balance, setup_block_state,
 };
 use rusty_sando::types::BlockInfo;
 use rusty_sando::{forked_db::fork_factory::ForkFactory, utils::state_diff};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
 
-const MAX_DEPTH: usize = 7;
-const STEP_INTERVALS: usize = 15;
+/// Default max number of grid passes for `SearchStrategy::Grid`.
+pub const DEFAULT_MAX_DEPTH: usize = 8;
+/// Default number of amounts simulated per grid pass.
+pub const DEFAULT_STEP_INTERVALS: usize = 15;
+/// Default number of amounts re-simulated by a `Refinement`.
+pub const DEFAULT_REFINE_POINTS: usize = 50;
+/// Default half-width of a `Refinement`'s window: 2% of the search's best `amount_in`.
+pub const DEFAULT_REFINE_WINDOW_BPS: u64 = 200;
+/// Max number of range-narrowing iterations for `GoldenSection` and `Ternary` searches.
+const MAX_SEARCH_ITERATIONS: usize = 24;
+/// 1/φ, scaled by `GOLDEN_RATIO_SCALE`, for integer golden-section math.
+const INV_GOLDEN_RATIO: u64 = 618_034;
+const GOLDEN_RATIO_SCALE: u64 = 1_000_000;
+/// Amount of WETH (0.01 ETH) swapped to probe a token for non-standard transfer behavior.
+const PROBE_AMOUNT_IN: u64 = 10_000_000_000_000_000;
+/// Max number of arb pools to simulate concurrently for a single tx.
+const MAX_CONCURRENT_POOL_SIMS: usize = 8;
 
-/// Return an evm instance forked from the provided block info and client state
+/// Strategy used to search for the most profitable backrun `amount_in`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SearchStrategy {
+    /// Simulate `SearchConfig::intervals` evenly-spaced amounts and recurse around the best one.
+    #[default]
+    Grid,
+    /// Golden-section search; assumes a unimodal profit curve and reuses one sim per iteration.
+    GoldenSection,
+    /// Ternary search; assumes a unimodal profit curve and sims two new amounts per iteration.
+    Ternary,
+}
+
+/// Tuning for the search for the most profitable backrun `amount_in`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub struct SearchConfig {
+    /// Max number of grid passes; each pass narrows the range around the best amount.
+    pub max_depth: usize,
+    /// Number of amounts simulated per grid pass.
+    pub intervals: usize,
+    /// The search stops once its range is narrower than this (in wei), or than 0.1% of its lower bound.
+    pub tolerance: U256,
+    /// Max amount of WETH to search; the search range starts at the user's trade size, and is
+    /// also capped by the braindance contract's balance.
+    pub max_amount_in: U256,
+    /// Record every amount simulated by the search in the result's `search_trace`.
+    pub trace: bool,
+    /// Re-simulate profitable arbs' best amounts at a finer step once they're found.
+    pub refine: Option<Refinement>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            intervals: DEFAULT_STEP_INTERVALS,
+            tolerance: U256::zero(),
+            max_amount_in: braindance_starting_balance(),
+            trace: false,
+            refine: None,
+        }
+    }
+}
+
+impl SearchConfig {
+    pub fn new(max_depth: usize, intervals: usize, tolerance: U256) -> Result<Self> {
+        if max_depth < 1 {
+            return Err(anyhow::anyhow!("search depth must be at least 1"));
+        }
+        if intervals < 2 {
+            return Err(anyhow::anyhow!("search intervals must be at least 2"));
+        }
+        Ok(Self {
+            max_depth,
+            intervals,
+            tolerance,
+            ..Default::default()
+        })
+    }
+
+    /// A trace to record the search's sims in, if it should be traced.
+    fn new_trace(&self) -> Option<Arc<Mutex<SearchTrace>>> {
+        self.trace.then(Default::default)
+    }
+
+    /// Whether the range `[lo, hi]` is tight enough to stop searching.
+    fn is_tight(&self, lo: U256, hi: U256) -> bool {
+        let width = hi - lo;
+        width <= lo / 1000 || width <= self.tolerance
+    }
+}
+
+/// A second pass over the best amount a search found: `points` evenly-spaced amounts within
+/// `window_bps` of it are simulated, and the most profitable one replaces it. A search's steps
+/// are coarse, so near the optimum its profit may be off by a few percent; refining only the
+/// arbs worth at least `min_profit` keeps the extra sims to the few that matter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Refinement {
+    /// Only arbs whose profit (wei, after gas) is at least this are refined.
+    pub min_profit: U256,
+    /// Number of amounts simulated.
+    pub points: usize,
+    /// Amounts are simulated up to this many bps of the best `amount_in` either side of it.
+    pub window_bps: u64,
+}
+
+impl Refinement {
+    /// Amounts to simulate around `amount_in`, up to `max_amount_in`.
+    fn amounts(&self, amount_in: U256, max_amount_in: U256) -> Vec<U256> {
+        let window = amount_in * self.window_bps / 10_000;
+        let lo = amount_in.saturating_sub(window);
+        let hi = amount_in.saturating_add(window).min(max_amount_in);
+        if hi <= lo || self.points < 2 {
+            return vec![];
+        }
+        let step = (hi - lo) / (self.points - 1);
+        (0..self.points).map(|i| lo + step * i).collect()
+    }
+}
+
+/// State shared by every pass of one search: when to stop it, & what it recorded.
+#[derive(Clone, Debug, Default)]
+struct SearchContext {
+    /// Stops the search with an error once cancelled, e.g. when its event times out.
+    cancel: CancellationToken,
+    /// Once this passes, the search returns the best amount it found after its current pass,
+    /// rather than starting another.
+    deadline: Option<Instant>,
+    /// Records the search's sims, if it's being traced.
+    trace: Option<Arc<Mutex<SearchTrace>>>,
+    /// Set once the search stopped early at its `deadline`.
+    truncated: Arc<AtomicBool>,
+}
+
+impl SearchContext {
+    fn new(
+        cancel: CancellationToken,
+        deadline: Option<Instant>,
+        trace: Option<Arc<Mutex<SearchTrace>>>,
+    ) -> Self {
+        Self {
+            cancel,
+            deadline,
+            trace,
+            truncated: Default::default(),
+        }
+    }
+
+    /// Whether the search is past its deadline.
+    fn past_deadline(&self) -> bool {
+        self.deadline
+            .map(|deadline| Instant::now() >= deadline)
+            .unwrap_or(false)
+    }
+
+    /// Marks the search as stopped early at its deadline.
+    fn mark_truncated(&self) {
+        self.truncated.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the search stopped early at its deadline.
+    fn truncated(&self) -> bool {
+        self.truncated.load(Ordering::Relaxed)
+    }
+}
+
+/// How long to wait for the receipt of an event's tx, if the node doesn't have it.
+///
+/// A recent tx's receipt may still be propagating to the node, so it's retried w/ exponential
+/// backoff; an older tx without one was dropped, so it isn't.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReceiptRetry {
+    /// Txs that landed within this many blocks of the head are retried.
+    pub recent_blocks: u64,
+    /// Number of retries before giving up on a recent tx.
+    pub attempts: u32,
+    /// Delay before the first retry; it doubles after each.
+    pub backoff: Duration,
+}
+
+impl Default for ReceiptRetry {
+    fn default() -> Self {
+        Self {
+            recent_blocks: 10,
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// The braindance contract that backruns are simulated from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Braindance {
+    /// WETH the contract starts each sim w/, which caps the backrun's `amount_in`; profits are
+    /// measured against it.
+    pub starting_balance: U256,
+    /// Gas limit of each of its swaps; V3 swaps crossing many ticks may need more than the default.
+    pub swap_gas_limit: u64,
+}
+
+impl Default for Braindance {
+    fn default() -> Self {
+        Self {
+            starting_balance: braindance_starting_balance(),
+            swap_gas_limit: 700_000,
+        }
+    }
+}
+
+/// WETH9 keeps balances in a mapping at slot 3.
+const WETH_BALANCES_SLOT: u64 = 3;
+
+/// Returns the storage slot of `owner`'s balance in WETH9's balances mapping.
+fn weth_balance_slot(owner: Address) -> rU256 {
+    let key = abi::encode(&[
+        Token::Address(owner),
+        Token::Uint(U256::from(WETH_BALANCES_SLOT)),
+    ]);
+    rU256::from_be_bytes(keccak256(key))
+}
+
+/// Returns the WETH balances that `fund_braindance` sets: the braindance contract's, and the
+/// Curve trader's, which swaps the routes braindance can't.
+fn braindance_weth_slots() -> [rU256; 2] {
+    [
+        weth_balance_slot(braindance_address()),
+        weth_balance_slot(curve_trader()),
+    ]
+}
+
+/// Gas used by each leg (swap) of a backrun.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct BackrunGas {
+    buy: u64,
+    sell: u64,
+}
+
+impl BackrunGas {
+    fn total(&self) -> u64 {
+        self.buy + self.sell
+    }
+}
+
+/// Options that control how backrun-arbs are simulated.
+#[derive(Clone, Debug, Default)]
+pub struct SimOptions {
+    pub search_strategy: SearchStrategy,
+    pub search: SearchConfig,
+    /// Only return the most profitable arb for each tx, rather than one for every arb pool.
+    pub best_arb_only: bool,
+    /// Priority fee (wei per gas) that a backrun is assumed to pay, on top of the base fee.
+    pub priority_fee: U256,
+    /// Tokens to skip; unsupported tokens found while simulating are added to it.
+    pub token_blocklist: Option<Arc<TokenBlocklist>>,
+    /// Limits the number of sims running at once, across all txs using these options.
+    pub sim_permits: Option<Arc<Semaphore>>,
+    /// Signs the txs of profitable backruns, so they can be exported as bundles.
+    pub bundle_signer: Option<LocalWallet>,
+    /// Pools & tokens to simulate (or skip).
+    pub event_filter: Arc<EventFilter>,
+    /// Chainlink ETH/USD price feed that profits are priced in USD with, at the simulated block.
+    /// Profits aren't priced in USD if it's None.
+    pub eth_usd_feed: Option<Address>,
+    /// Max time to spend simulating one event; it fails with `HindsightError::Timeout` after that.
+    pub event_timeout: Option<Duration>,
+    /// Soft time limit for simulating one event: once it's spent, each search returns the best
+    /// amount it found after its current pass, marked `search_truncated`.
+    pub event_budget: Option<Duration>,
+    /// Stops the searches of an event's sims when cancelled, e.g. when the event times out.
+    pub cancel: CancellationToken,
+    /// Where in the user's block backruns are simulated.
+    pub placement: Placement,
+    /// Candidate arb pools holding less WETH than this (wei) are skipped without searching.
+    pub min_pool_weth: U256,
+    /// The chain being simulated; its WETH & factories are used to derive trades & find arb pools.
+    pub chain: ChainConfig,
+    /// Directory to save the state each event's sims touch to, as `EventSnapshot`s, so they can
+    /// be re-simulated without a node. Nothing's saved if it's None.
+    pub record_state: Option<PathBuf>,
+    /// Where a record of each event's sims is appended, whether they succeed or not.
+    pub audit_log: Option<Arc<AuditLog>>,
+    /// How long to wait for the receipt of a tx that the node doesn't have yet.
+    pub receipt_retry: ReceiptRetry,
+    /// WETH balance & swap gas limit of the braindance contract that backruns are simulated from.
+    pub braindance: Braindance,
+    /// Derive users' trades w/o their receipts: only from hinted log data, or else by simulating
+    /// their txs & diffing the pools' balances.
+    pub hint_only: bool,
+    /// Fork factories shared by the events on the same block. Each event builds its own if it's None.
+    pub fork_cache: Option<Arc<BlockForkCache>>,
+}
+
+impl std::fmt::Display for SearchStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchStrategy::Grid => write!(f, "grid"),
+            SearchStrategy::GoldenSection => write!(f, "golden-section"),
+            SearchStrategy::Ternary => write!(f, "ternary"),
+        }
+    }
+}
+
+impl FromStr for SearchStrategy {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(SearchStrategy::Grid),
+            "golden-section" => Ok(SearchStrategy::GoldenSection),
+            "ternary" => Ok(SearchStrategy::Ternary),
+            _ => Err(format!("invalid search strategy: {}", s)),
+        }
+    }
+}
+
+/// Return a fork factory for the provided block info and client state
 /// with braindance module initialized.
-/// Braindance contracts starts w/ braindance_starting_balance, which is 420 WETH.
-pub async fn fork_evm(client: &WsClient, block_info: &BlockInfo) -> Result<EVM<ForkDB>> {
+/// Braindance contracts starts w/ braindance_starting_balance, which is 420 WETH, unless it's
+/// funded w/ `fund_braindance`.
+///
+/// Sandbox forks made by the same factory share its backend, so any state fetched
+/// by one fork is cached for the others.
+///
+/// `txs` are applied on top of the block's state, e.g. the txs that landed before the user's tx.
+pub async fn new_fork_factory(
+    client: &RpcClient,
+    block_info: &BlockInfo,
+    txs: &Vec<Transaction>,
+) -> Result<ForkFactory> {
     let fork_block_num = BlockNumber::Number(block_info.number);
     let fork_block = Some(ethers::types::BlockId::Number(fork_block_num));
 
-    let state_diffs =
-        if let Some(sd) = state_diff::get_from_txs(&client, &vec![], fork_block_num).await {
-            sd
-        } else {
-            BTreeMap::<H160, AccountDiff>::new()
-        };
-    let initial_db = state_diff::to_cache_db(&state_diffs, fork_block, &client).await?;
-    let mut fork_factory = ForkFactory::new_sandbox_factory(client.clone(), initial_db, fork_block);
+    let client = fork_provider(client).await?;
+    let initial_db = txs_state_db(&client, block_info, txs).await?;
+    let mut fork_factory = ForkFactory::new_sandbox_factory(client, initial_db, fork_block);
     attach_braindance_module(&mut fork_factory);
+    Ok(fork_factory)
+}
+
+/// Returns the state that `txs` change when applied on top of `block_info`'s block, for a fork
+/// of the block to start from. It's empty if there are no `txs`.
+pub async fn txs_state_db(
+    client: &Arc<Provider<Ws>>,
+    block_info: &BlockInfo,
+    txs: &Vec<Transaction>,
+) -> Result<CacheDB<EmptyDB>> {
+    let fork_block_num = BlockNumber::Number(block_info.number);
+    let fork_block = Some(ethers::types::BlockId::Number(fork_block_num));
+    let state_diffs = if let Some(sd) = state_diff::get_from_txs(client, txs, fork_block_num).await
+    {
+        sd
+    } else if txs.is_empty() {
+        BTreeMap::<H160, AccountDiff>::new()
+    } else {
+        // forking without them would silently simulate at a different placement
+        return Err(anyhow::anyhow!(
+            "failed to trace {} txs on block {}",
+            txs.len(),
+            block_info.number
+        ));
+    };
+    Ok(state_diff::to_cache_db(&state_diffs, fork_block, client).await?)
+}
+
+/// Sets the braindance contract's (& the Curve trader's) balance of `weth` in `fork_factory`'s
+/// forks to `balance`.
+pub fn fund_braindance(fork_factory: &mut ForkFactory, weth: Address, balance: U256) -> Result<()> {
+    for slot in braindance_weth_slots() {
+        fork_factory
+            .insert_account_storage(weth.0.into(), slot, balance.into())
+            .map_err(|err| anyhow::anyhow!("failed to fund braindance contract: {:?}", err))?;
+    }
+    Ok(())
+}
+
+/// An account's balance (wei) & nonce to set on a fork, as (address, balance, nonce); e.g. for an
+/// EOA whose signed txs are simulated w/ `sim_signed_bundle`.
+pub type AccountOverride = (Address, U256, u64);
 
+/// Sets the balance & nonce of each of `accounts` in `fork_factory`'s forks, keeping their code.
+pub fn override_accounts(
+    fork_factory: &mut ForkFactory,
+    accounts: &[AccountOverride],
+) -> Result<()> {
+    let fork = fork_factory.new_sandbox_fork();
+    let db_err = |err| anyhow::anyhow!("failed to read account to override: {:?}", err);
+    for &(address, balance, nonce) in accounts {
+        let info = DatabaseRef::basic(&fork, address.0.into())
+            .map_err(db_err)?
+            .unwrap_or_default();
+        // the factory's db doesn't know the code of accounts it hasn't loaded yet
+        let code = match info.code {
+            None if info.code_hash != KECCAK_EMPTY => {
+                Some(DatabaseRef::code_by_hash(&fork, info.code_hash).map_err(db_err)?)
+            }
+            code => code,
+        };
+        fork_factory.insert_account_info(
+            address.0.into(),
+            AccountInfo {
+                balance: balance.into(),
+                nonce,
+                code,
+                ..info
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Return an evm instance on a new sandbox fork from `fork_factory`.
+pub fn sandbox_evm(fork_factory: &ForkFactory, block_info: &BlockInfo) -> EVM<ForkDB> {
     let mut evm = EVM::new();
     evm.database(fork_factory.new_sandbox_fork());
     setup_block_state(&mut evm, block_info);
+    evm
+}
+
+/// Return an evm instance forked from the provided block info and client state
+/// with braindance module initialized (as `braindance` configures), executing txs as `chain`.
+pub async fn fork_evm(
+    client: &RpcClient,
+    block_info: &BlockInfo,
+    chain: &ChainConfig,
+    braindance: &Braindance,
+) -> Result<EVM<ForkDB>> {
+    fork_evm_with_accounts(client, block_info, chain, braindance, vec![]).await
+}
+
+/// Like `fork_evm`, w/ the balances & nonces of `accounts` overridden, so txs signed from them can
+/// be simulated even if they couldn't have landed at the forked block.
+pub async fn fork_evm_with_accounts(
+    client: &RpcClient,
+    block_info: &BlockInfo,
+    chain: &ChainConfig,
+    braindance: &Braindance,
+    accounts: Vec<AccountOverride>,
+) -> Result<EVM<ForkDB>> {
+    let mut fork_factory = new_fork_factory(client, block_info, &vec![]).await?;
+    fund_braindance(&mut fork_factory, chain.weth, braindance.starting_balance)?;
+    override_accounts(&mut fork_factory, &accounts)?;
+    let mut evm = sandbox_evm(&fork_factory, block_info);
+    set_chain_id(&mut evm, chain.chain_id);
     Ok(evm)
 }
 
+/// Sets the chain id that `evm` runs as; txs signed for other chains are rejected.
+fn set_chain_id(evm: &mut EVM<ForkDB>, chain_id: u64) {
+    evm.env.cfg.chain_id = rU256::from(chain_id);
+}
+
+/// Return an evm instance on the state recorded in `snapshot`, without a node.
+/// Executing anything that reads state the snapshot doesn't have fails.
+pub fn fork_evm_from_snapshot(snapshot: &StateSnapshot) -> EVM<ForkDB> {
+    let mut evm = EVM::new();
+    evm.database(snapshot.fork_db());
+    setup_block_state(&mut evm, &snapshot.block_info());
+    evm
+}
+
+/// Where `SimForks` gets the state it forks.
+enum ForkSource {
+    /// A node, through rusty-sando's fork backend.
+    Node(ForkFactory),
+    /// A recorded `StateSnapshot`.
+    Snapshot(CacheDB<EmptyDB>),
+}
+
+/// Sandbox forks for all the sims of a single tx, which fork the same state.
+///
+/// The number of sims running at once is limited by `sim_permits`, which may be shared
+/// by many `SimForks`.
+pub struct SimForks {
+    source: ForkSource,
+    sim_permits: Option<Arc<Semaphore>>,
+    /// Txs committed after the user's tx, before each backrun.
+    following_txs: Vec<Transaction>,
+    /// Chain id the forks' evms run as; mainnet's unless set with `on_chain`.
+    chain_id: u64,
+    /// UniswapV3's SwapRouter, which routes through Curve pools swap on V3 pools with; mainnet's
+    /// unless set with `on_chain`.
+    v3_router: Option<Address>,
+    /// The braindance contract the forks' sims swap from; the default unless set w/ `with_braindance`.
+    braindance: Braindance,
+    /// Number of forks handed out.
+    num_sims: AtomicUsize,
+}
+
+impl SimForks {
+    /// Forks the state before `block_info`'s block, to simulate backruns at the top of the block.
+    pub async fn new(
+        client: &RpcClient,
+        block_info: &BlockInfo,
+        sim_permits: Option<Arc<Semaphore>>,
+    ) -> Result<Self> {
+        Self::placed(client, block_info, sim_permits, &vec![], vec![]).await
+    }
+
+    /// Forks the state after `preceding_txs`, the txs that landed before the user's tx in its block.
+    /// Backrun sims commit `following_txs` after the user's tx.
+    pub async fn placed(
+        client: &RpcClient,
+        block_info: &BlockInfo,
+        sim_permits: Option<Arc<Semaphore>>,
+        preceding_txs: &Vec<Transaction>,
+        following_txs: Vec<Transaction>,
+    ) -> Result<Self> {
+        Ok(Self::from_fork_factory(
+            new_fork_factory(client, block_info, preceding_txs).await?,
+            sim_permits,
+            following_txs,
+        ))
+    }
+
+    /// Forks the state of `fork_factory`, e.g. one shared by a `BlockForkCache`.
+    /// Backrun sims commit `following_txs` after the user's tx.
+    pub fn from_fork_factory(
+        fork_factory: ForkFactory,
+        sim_permits: Option<Arc<Semaphore>>,
+        following_txs: Vec<Transaction>,
+    ) -> Self {
+        Self {
+            source: ForkSource::Node(fork_factory),
+            sim_permits,
+            following_txs,
+            chain_id: ChainConfig::default().chain_id,
+            v3_router: ChainConfig::default().uniswap_v3_router,
+            braindance: Braindance::default(),
+            num_sims: AtomicUsize::new(0),
+        }
+    }
+
+    /// Forks the state that `user_tx`'s backruns are simulated on at `placement`, like
+    /// `find_optimal_backrun_amount_in_out` does.
+    pub async fn for_placement(
+        client: &RpcClient,
+        user_tx: &Transaction,
+        block_info: &BlockInfo,
+        sim_permits: Option<Arc<Semaphore>>,
+        placement: Placement,
+    ) -> Result<Self> {
+        let (preceding_txs, following_txs) = placement_txs(client, user_tx, placement).await?;
+        Self::placed(
+            client,
+            block_info,
+            sim_permits,
+            &preceding_txs,
+            following_txs,
+        )
+        .await
+    }
+
+    /// Forks the state recorded in `snapshot`, without a node; sims fail if they read state the
+    /// snapshot doesn't have. Backrun sims commit `following_txs` after the user's tx.
+    pub fn from_snapshot(
+        snapshot: &StateSnapshot,
+        sim_permits: Option<Arc<Semaphore>>,
+        following_txs: Vec<Transaction>,
+    ) -> Self {
+        Self {
+            source: ForkSource::Snapshot(snapshot.cache_db()),
+            sim_permits,
+            following_txs,
+            chain_id: ChainConfig::default().chain_id,
+            v3_router: ChainConfig::default().uniswap_v3_router,
+            braindance: Braindance::default(),
+            num_sims: AtomicUsize::new(0),
+        }
+    }
+
+    /// Runs the forks' evms as `chain`, so they accept txs signed for it, and swaps on its
+    /// UniswapV3 router.
+    pub fn on_chain(mut self, chain: &ChainConfig) -> Self {
+        self.chain_id = chain.chain_id;
+        self.v3_router = chain.uniswap_v3_router;
+        self
+    }
+
+    /// Funds the forks' braindance contract w/ `braindance`'s starting balance of `weth`, & swaps
+    /// from it w/ its gas limit.
+    pub fn with_braindance(mut self, braindance: Braindance, weth: Address) -> Result<Self> {
+        match &mut self.source {
+            ForkSource::Node(fork_factory) => {
+                fund_braindance(fork_factory, weth, braindance.starting_balance)?
+            }
+            ForkSource::Snapshot(db) => {
+                for slot in braindance_weth_slots() {
+                    db.insert_account_storage(
+                        weth.0.into(),
+                        slot,
+                        braindance.starting_balance.into(),
+                    )
+                    .map_err(|err| {
+                        anyhow::anyhow!("failed to fund braindance contract: {:?}", err)
+                    })?;
+                }
+            }
+        }
+        self.braindance = braindance;
+        Ok(self)
+    }
+
+    /// Returns the braindance contract the forks' sims swap from.
+    pub fn braindance(&self) -> Braindance {
+        self.braindance
+    }
+
+    /// Returns an evm on a new sandbox fork, without waiting for a permit or counting it as a sim.
+    fn sandbox(&self, block_info: &BlockInfo) -> EVM<ForkDB> {
+        let mut evm = match &self.source {
+            ForkSource::Node(fork_factory) => sandbox_evm(fork_factory, block_info),
+            ForkSource::Snapshot(db) => {
+                let mut evm = EVM::new();
+                evm.database(offline_fork_db(db.clone()));
+                setup_block_state(&mut evm, block_info);
+                evm
+            }
+        };
+        set_chain_id(&mut evm, self.chain_id);
+        evm
+    }
+
+    /// Starts recording the state that sims on these forks touch.
+    pub fn start_recording(&self, block_info: &BlockInfo) -> Recording {
+        Recording::start(self.sandbox(block_info).env.block.number)
+    }
+
+    /// Finishes `recording`, snapshotting the state it recorded as these forks have it.
+    pub fn finish_recording(
+        &self,
+        recording: Recording,
+        block_info: &BlockInfo,
+    ) -> Result<StateSnapshot> {
+        let evm = self.sandbox(block_info);
+        recording.finish(evm.db.as_ref().expect("sandbox evm has a db"), block_info)
+    }
+
+    /// Waits for a sim permit, then returns an evm on a new sandbox fork.
+    /// The permit should be held until the sim is done.
+    pub async fn evm(&self, block_info: &BlockInfo) -> (EVM<ForkDB>, Option<OwnedSemaphorePermit>) {
+        let permit = match &self.sim_permits {
+            Some(sim_permits) => {
+                let wait_start = Instant::now();
+                let permit = sim_permits
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("sim semaphore closed");
+                let waited = wait_start.elapsed();
+                if waited > Duration::from_millis(10) {
+                    debug!("waited {:?} for a sim permit", waited);
+                }
+                Some(permit)
+            }
+            None => None,
+        };
+        self.num_sims.fetch_add(1, Ordering::Relaxed);
+        (self.sandbox(block_info), permit)
+    }
+
+    /// Returns the number of sims run on these forks so far.
+    pub fn num_sims(&self) -> usize {
+        self.num_sims.load(Ordering::Relaxed)
+    }
+}
+
+/// Returns the txs that land before `user_tx` in its block, and those that land after it
+/// which are committed before the backrun, as `placement` dictates.
+async fn placement_txs(
+    client: &RpcClient,
+    user_tx: &Transaction,
+    placement: Placement,
+) -> Result<(Vec<Transaction>, Vec<Transaction>)> {
+    let num_following = match placement {
+        Placement::TopOfBlock => return Ok((vec![], vec![])),
+        Placement::InBlock => 0,
+        Placement::Pessimistic(num_following) => num_following,
+    };
+    let block_num = user_tx
+        .block_number
+        .ok_or::<Error>(HindsightError::TxNotLanded(user_tx.hash).into())?;
+    let tx_index = user_tx
+        .transaction_index
+        .ok_or::<Error>(HindsightError::TxNotLanded(user_tx.hash).into())?
+        .as_usize();
+    let mut txs = client
+        .get_block_with_txs(block_num)
+        .await?
+        .ok_or::<Error>(HindsightError::BlockNotFound(block_num.as_u64()).into())?
+        .transactions;
+    if tx_index >= txs.len() {
+        return Err(anyhow::anyhow!(
+            "tx {:?} not found at index {} of block {}",
+            user_tx.hash,
+            tx_index,
+            block_num
+        ));
+    }
+    let following = txs.split_off(tx_index + 1);
+    txs.truncate(tx_index);
+    Ok((txs, following.into_iter().take(num_following).collect()))
+}
+
 /// Returns None if trade params can't be derived.
 ///
-/// May derive multiple trades from a single tx.
-async fn derive_trade_params(
-    client: &WsClient,
+/// May derive multiple trades from a single tx, each between `chain`'s WETH & another token.
+/// Only needs `client` to fetch the tx's receipt (unless `ctx` has it) and make calls; `forks` is
+/// only used to read pools' prices before the user's tx, and a V2 pool's reserves after it when
+/// the pool's Sync log is missing from the receipt. W/o `forks`, prices before the tx are unknown.
+async fn derive_trade_params<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    forks: Option<&SimForks>,
+    block_info: &BlockInfo,
     tx: Transaction,
     event: &EventHistory,
+    ctx: &EventContext,
 ) -> Result<Vec<UserTradeParams>> {
     // Swap(address,address,int256,int256,uint160,uint128,int24)
     let univ3_topic =
         H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")?;
-    let sync_topic =
-        H256::from_str("0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1")?;
     let uniswap_topics = vec![
         // univ3
         H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67")?,
@@ -72,37 +778,72 @@ async fn derive_trade_params(
     ];
 
     // get potential pool addresses from event, relying on mev-share hints
+    // a pool may be hinted more than once if the tx swapped on it several times
+    let mut hinted_pools = HashSet::new();
     let swap_logs = event
         .hint
         .logs
         .iter()
         .filter(|log| uniswap_topics.contains(&log.topics[0]))
+        .filter(|log| hinted_pools.insert((log.address, log.topics[0])))
         .map(|log| log.to_owned())
         .collect::<Vec<EventTransactionLog>>();
     debug!("swap logs {:?}", swap_logs);
-    // derive trade direction from (full) tx logs
-    let tx_receipt = client
-        .get_transaction_receipt(tx.hash)
-        .await?
-        .ok_or::<Error>(HindsightError::TxNotLanded(tx.hash).into())?;
+
+    // fetch the tokens (& their metadata) of every hinted pool up front, in as few calls as possible
+    let pools = swap_logs.iter().map(|log| log.address).collect::<Vec<_>>();
+    prefetch_pair_tokens(client, chain, &pools).await?;
+    let mut tokens = vec![];
+    let mut non_weth_pools = vec![];
+    for pool in pools {
+        let (token0, token1) = get_pair_tokens(client, pool).await?;
+        if token0 == chain.weth || token1 == chain.weth {
+            tokens.extend([token0, token1]);
+        } else {
+            non_weth_pools.push(pool);
+        }
+    }
+    // backruns start & end with WETH, so they can't arb pairs without it
+    let swap_logs = swap_logs
+        .into_iter()
+        .filter(|log| !non_weth_pools.contains(&log.address))
+        .collect::<Vec<_>>();
+    if !non_weth_pools.is_empty() {
+        debug!("skipping pools without WETH: {:?}", non_weth_pools);
+        if swap_logs.is_empty() {
+            return Err(HindsightError::NonWethPair(non_weth_pools[0]).into());
+        }
+    }
+    prefetch_token_metadata(client, chain, &tokens).await?;
+
+    // derive trade direction from (full) tx logs; in hint-only mode, only logs whose data was hinted
+    let tx_logs = if ctx.hint_only() {
+        event
+            .hint
+            .logs
+            .iter()
+            .filter(|log| !log.data.is_empty())
+            .map(|log| Log {
+                address: log.address,
+                topics: log.topics.to_owned(),
+                data: log.data.to_owned(),
+                ..Default::default()
+            })
+            .collect()
+    } else {
+        ctx.receipt(client, tx.hash, event.block).await?.logs
+    };
 
     // collect trade params for each pair derived from swap logs
     let mut trade_params = vec![];
+    // the first pool skipped b/c its swap logs were malformed, if any
+    let mut malformed_pool = None;
     for swap_log in swap_logs {
         let pool_address = swap_log.address;
         let swap_topic = swap_log.topics[0]; // MEV-Share puts the swap topic in the 0th position, following txs are zeroed out by default
         debug!("pool address: {:?}", pool_address);
         debug!("swap topic: {:?}", swap_topic);
 
-        let swap_log = tx_receipt
-            .logs
-            .iter()
-            .find(|log| log.topics.contains(&swap_topic) && log.address == pool_address)
-            .ok_or(anyhow::format_err!(
-                "no swap logs found for tx {:?}",
-                tx.hash
-            ))?;
-
         // derive pool variant from event log topics
         let pool_variant = if swap_topic == univ3_topic {
             PoolVariant::UniswapV3
@@ -111,200 +852,815 @@ async fn derive_trade_params(
         };
         debug!("pool variant: {:?}", pool_variant);
 
+        // aggregators may split a trade into several swaps on the same pool
+        // nonstandard pools may emit Swap-topic logs w/ less data than the canonical event; skip those
+        let mut malformed = None;
+        let pool_swap_logs = tx_logs
+            .iter()
+            .filter(|log| log.topics.contains(&swap_topic) && log.address == pool_address)
+            .filter(
+                |log| match check_swap_log(pool_address, pool_variant, &log.data) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        warn!("skipping swap log of tx {:?}: {}", tx.hash, err);
+                        malformed.get_or_insert(err);
+                        false
+                    }
+                },
+            )
+            .collect::<Vec<_>>();
+        // in hint-only mode, the user's trade is simulated instead
+        if pool_swap_logs.is_empty() && !ctx.hint_only() {
+            if let Some(err) = malformed {
+                malformed_pool.get_or_insert(err);
+                continue;
+            }
+            return Err(anyhow::format_err!(
+                "no swap logs found for tx {:?}",
+                tx.hash
+            ));
+        }
+        debug!("{} swap logs on pool", pool_swap_logs.len());
+        // V2 clones (e.g. SushiSwap) emit the same events; tell them apart by the pool's factory
+        let dex = match pool_variant {
+            PoolVariant::UniswapV3 => Dex::UniswapV3,
+            PoolVariant::UniswapV2 => get_v2_dex(client, chain, pool_address).await?,
+            PoolVariant::Curve => Dex::Curve,
+        };
+
         // get token addrs from pool address
         // tokens may vary per swap log -- many swaps can happen in one tx
         let (token0, token1) = get_pair_tokens(client, pool_address).await?;
         debug!("token0\t{:?}\ntoken1\t{:?}", token0, token1);
-        let token0_is_weth =
-            token0 == "0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2".parse::<H160>()?;
-        let token0_decimals = get_decimals(client, token0).await?;
+        let token0_is_weth = token0 == chain.weth;
+        let token_decimals = (
+            get_decimals(client, token0).await?,
+            get_decimals(client, token1).await?,
+        );
 
-        // if a Sync event (UniV2) is detected from the tx logs, it can be used to get the new price
-        let sync_log: Option<_> = tx_receipt
-            .logs
-            .iter()
-            .find(|log| log.topics[0] == sync_topic && log.address == pool_address);
-
-        // derive user's trade amounts & post-tx price from log data
-        let (amount0_sent, amount1_sent, new_price) = match pool_variant {
-            PoolVariant::UniswapV3 => {
-                let amount0 = I256::from_raw(U256::from_big_endian(&swap_log.data[0..32]));
-                let amount1 = I256::from_raw(U256::from_big_endian(&swap_log.data[32..64]));
-                let sqrt_price = U256::from_big_endian(&swap_log.data[64..96]); // u160
-                let liquidity = U256::from_big_endian(&swap_log.data[96..128]); // u128
-                let new_price = get_price_v3(liquidity, sqrt_price, token0_decimals)?;
-                (
-                    /* amount0_sent */
-                    if amount0.le(&0.into()) {
-                        0.into()
-                    } else {
-                        amount0
-                    },
-                    /* amount1_sent */
-                    if amount1.le(&0.into()) {
-                        0.into()
-                    } else {
-                        amount1
-                    },
-                    /* new_price */
-                    new_price,
-                )
+        let (amount0_sent, amount1_sent, price_before, new_price) = match pool_swap_logs.last() {
+            Some(swap_log) => {
+                // derive user's trade amounts (net of all swaps on the pool) & post-tx price
+                let (amount0_sent, amount1_sent) = parse_amounts_sent(
+                    pool_variant,
+                    &pool_swap_logs
+                        .iter()
+                        .map(|log| log.data.as_ref())
+                        .collect::<Vec<_>>(),
+                );
+                let new_price = match pool_variant {
+                    PoolVariant::UniswapV3 => {
+                        let sqrt_price = swap_log_word(&swap_log.data, 2); // u160
+                        let liquidity = swap_log_word(&swap_log.data, 3); // u128
+                        get_price_v3(liquidity, sqrt_price, token_decimals, token0_is_weth)?
+                    }
+                    PoolVariant::UniswapV2 => {
+                        let amount0_out = swap_log_word(&swap_log.data, 2);
+                        let amount1_out = swap_log_word(&swap_log.data, 3);
+                        debug!("amounts out: ({:?}, {:?})", amount0_out, amount1_out);
+                        match sync_price_v2(&tx_logs, pool_address, token_decimals, token0_is_weth)?
+                        {
+                            Some(price) => price,
+                            None => {
+                                debug!(
+                                    "no sync log for pool {:?}, reading reserves after user tx",
+                                    pool_address
+                                );
+                                let forks = forks.ok_or(anyhow::format_err!(
+                                    "no sync log for pool {:?}, and no fork to read its reserves from",
+                                    pool_address
+                                ))?;
+                                sim_post_trade_price_v2(
+                                    forks,
+                                    block_info,
+                                    &tx,
+                                    pool_address,
+                                    (token0, token1),
+                                    chain.weth,
+                                )
+                                .await?
+                            }
+                        }
+                    }
+                    PoolVariant::Curve => {
+                        return Err(anyhow::anyhow!(
+                            "can't price Curve pool {:?} from its swap logs",
+                            pool_address
+                        ))
+                    }
+                };
+                // research only, so a pool whose price can't be read isn't worth failing over
+                let price_before = match forks {
+                    Some(forks) => sim_pre_trade_price(
+                        forks,
+                        block_info,
+                        pool_address,
+                        pool_variant,
+                        (token0, token1),
+                        chain.weth,
+                    )
+                    .await
+                    .unwrap_or_else(|err| {
+                        debug!(
+                            "failed to read price of pool {:?} before user tx: {}",
+                            pool_address, err
+                        );
+                        U256::zero()
+                    }),
+                    None => U256::zero(),
+                };
+                (amount0_sent, amount1_sent, price_before, new_price)
             }
-            PoolVariant::UniswapV2 => {
-                let amount0_out = I256::from_raw(U256::from_big_endian(&swap_log.data[64..96]));
-                let amount1_out = I256::from_raw(U256::from_big_endian(&swap_log.data[96..128]));
-                let mut new_price = U256::zero();
-                if let Some(sync_log) = sync_log {
-                    let reserve0 = U256::from_big_endian(&sync_log.data[0..32]);
-                    let reserve1 = U256::from_big_endian(&sync_log.data[32..64]);
-                    new_price = get_price_v2(reserve0, reserve1, token0_decimals)?;
-                }
-                (amount0_out, amount1_out, new_price)
+            // hint-only, and the hint didn't share the swap's data
+            None => {
+                debug!(
+                    "no hinted swap data for pool {:?}, simulating user tx",
+                    pool_address
+                );
+                let forks = forks.ok_or(anyhow::format_err!(
+                    "no hinted swap data for pool {:?}, and no fork to simulate the user tx on",
+                    pool_address
+                ))?;
+                sim_user_trade(
+                    forks,
+                    block_info,
+                    &tx,
+                    pool_address,
+                    pool_variant,
+                    (token0, token1),
+                    chain.weth,
+                )
+                .await?
             }
         };
 
-        let swap_0_for_1 = amount0_sent.gt(&0.into());
-        debug!(
-            "***\nuser swaps {} for {}\n***",
-            if swap_0_for_1 { token0 } else { token1 },
-            if swap_0_for_1 { token1 } else { token0 }
-        );
-        let token_in = if swap_0_for_1 { token0 } else { token1 };
-        let token_out = if swap_0_for_1 { token1 } else { token0 };
+        let token = if token0_is_weth { token1 } else { token0 };
+        let token_metadata = get_token_metadata(client, token).await?;
+
+        let (token_in, token_out) = trade_direction(amount0_sent, (token0, token1));
+        debug!("***\nuser swaps {} for {}\n***", token_in, token_out);
         // find all pairs that aren't the one that the user swapped on
-        let arb_pools: Vec<PairPool> = get_all_trading_pools(client, (token_in, token_out))
-            .await?
-            .into_iter()
-            .filter(|pool| !pool.address.is_zero())
-            .filter(|pool| pool.address != pool_address)
-            .collect();
+        let (user_pools, arb_pools): (Vec<PairPool>, Vec<PairPool>) =
+            get_all_trading_pools(client, chain, (token_in, token_out))
+                .await?
+                .into_iter()
+                .filter(|pool| !pool.address.is_zero())
+                .partition(|pool| pool.address == pool_address);
+        // the factories also return the user's pool, w/ its fee tier if it's a V3 pool
+        let pool_fee = user_pools.first().and_then(|pool| pool.fee);
+        // w/o another pool to arb against, look for routes through other tokens instead
+        let intermediate_pools = if arb_pools.is_empty() {
+            get_intermediate_pools(client, chain, token).await?
+        } else {
+            vec![]
+        };
         trade_params.push(UserTradeParams {
             pool_variant,
+            pool_fee,
             token_in,
             token_out,
             amount0_sent,
             amount1_sent,
             pool: pool_address,
             arb_pools,
+            intermediate_pools,
             price: new_price,
+            price_before,
+            price_impact_bps: price_impact_bps(price_before, new_price),
             token0_is_weth,
+            dex: Some(dex),
             tokens: TokenPair {
                 weth: if token0_is_weth { token0 } else { token1 },
-                token: if token0_is_weth { token1 } else { token0 },
+                token,
             },
+            token_symbol: token_metadata.symbol,
+            // ERC20 decimals are a uint8; anything bigger is junk
+            token_decimals: (token_metadata.decimals <= U256::from(u8::MAX))
+                .then(|| token_metadata.decimals.low_u32() as u8),
+            eth_equivalent: chain.eth_equivalent_tokens.contains(&token),
         })
     }
+    // no trade could be derived from the other pools either
+    if let (true, Some(err)) = (trade_params.is_empty(), malformed_pool) {
+        return Err(err);
+    }
     Ok(trade_params)
 }
 
-/// Recursively finds the best possible arbitrage trade for a given set of params.
-#[async_recursion]
-async fn step_arb(
-    client: WsClient,
-    user_tx: Transaction,
-    block_info: BlockInfo,
-    params: UserTradeParams,
-    best_amount_in_out: Option<(U256, U256)>,
-    range: [U256; 2],
-    intervals: usize,
-    depth: Option<usize>,
-    start_pair_variant: (Address, PoolVariant),
-    end_pair_variant: (Address, PoolVariant),
-) -> Result<(U256, U256)> {
-    info!(
-        "step_arb
-        best (weth_in, weth_bal)\t{:?}
-        depth:\t{:?}
-        range:\t{:?}
-        user_tx:\t{:?}
-        (start_pair, variant):\t{:?}
-        (end_pair, variant):\t{:?}
-    ",
-        best_amount_in_out, depth, range, user_tx.hash, start_pair_variant, end_pair_variant
-    );
-    // unwrap current best result or assign defaults for init case
-    let (mut best_amount_in, mut best_amount_out) =
-        best_amount_in_out.unwrap_or((0.into(), braindance_starting_balance()));
+/// Max number of an event's receipts fetched at once.
+const RECEIPT_CONCURRENCY: usize = 8;
 
-    // convenience closures for stop cases
-    let done_unprofitable = || return Ok((0.into(), braindance_starting_balance()));
-    let done_profitable = || return Ok((best_amount_in, best_amount_out));
+/// State shared while processing a single event; dropped w/ it, so its caches stay small.
+#[derive(Debug, Default)]
+pub struct EventContext {
+    receipt_retry: ReceiptRetry,
+    /// Receipts may not be read; see `SimOptions::hint_only`.
+    hint_only: bool,
+    receipts: Mutex<HashMap<H256, TransactionReceipt>>,
+    /// Number of receipts asked for, whether cached or not.
+    receipt_lookups: AtomicUsize,
+    /// Number of RPC calls made to fetch receipts.
+    rpc_calls: AtomicUsize,
+}
 
-    /*  ============================================================
-    ======================== STOP CASES ============================
-    ============================================================  */
-    if params.arb_pools.len() == 0 {
-        // returning an error here will halt the whole sim branch
-        return Err(HindsightError::PoolNotFound(params.pool).into());
+impl EventContext {
+    pub fn new(receipt_retry: ReceiptRetry) -> Self {
+        Self {
+            receipt_retry,
+            ..Default::default()
+        }
     }
-    // if the ranges get tight enough together, we can quit early
-    // we'll call a 0.1% difference "tight enough"
-    if (range[1] - range[0]) <= (range[0] / 1000) {
-        info!("range tight enough, finishing early {:?}", range);
-        return done_profitable();
+
+    /// Forbids reading receipts, so trades are derived like a searcher would in real time.
+    pub fn with_hint_only(mut self, hint_only: bool) -> Self {
+        self.hint_only = hint_only;
+        self
     }
-    /*  INIT CASE:
-       User possibly passed None for best_amount_in_out, so we use our catch-all values defined above and recurse.
-    */
-    if depth.is_none() {
-        return step_arb(
-            client,
-            user_tx,
-            block_info,
-            params,
-            Some((best_amount_in, best_amount_out)),
-            range,
-            intervals,
-            Some(0),
-            start_pair_variant,
-            end_pair_variant,
-        )
-        .await;
+
+    pub fn hint_only(&self) -> bool {
+        self.hint_only
     }
-    // this could just be unwrapped, but paranoia is good when it's just in your code.
-    let depth = depth.expect("depth should have been defined (recursively) by this point.");
 
-    // stop case: we have recursed three times and the range minimum is STILL 0, AND no profit
-    if range[0] == 0.into() && depth >= 3 && best_amount_out <= braindance_starting_balance() {
-        // Return (0, start_balance) to indicate that there was no arbitrage opportunity,
-        // but the arb params (tokens, pools, etc) were still valid.
-        // This ensures that the attempt is logged in the DB.
-        info!("amount_in trending towards zero, quitting sim.");
-        return done_unprofitable();
+    /// Returns the hashes of the txs whose receipts processing `event` (of `tx`) needs.
+    pub fn event_tx_hashes(event: &EventHistory, tx: &Transaction) -> Vec<H256> {
+        // MEV-Share doesn't share the hashes of the other txs in a bundle, only the event's
+        let mut tx_hashes = vec![event.hint.hash];
+        if tx.hash != event.hint.hash {
+            tx_hashes.push(tx.hash);
+        }
+        tx_hashes
+    }
+
+    /// Fetches the receipts of `tx_hashes` that aren't cached yet, concurrently.
+    pub async fn prefetch_receipts<M: Middleware + 'static>(
+        &self,
+        client: &Arc<M>,
+        tx_hashes: &[H256],
+    ) {
+        if self.hint_only {
+            return;
+        }
+        let missing = {
+            let receipts = self.receipts.lock().expect("receipt cache poisoned");
+            tx_hashes
+                .iter()
+                .filter(|tx_hash| !receipts.contains_key(tx_hash))
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        if missing.is_empty() {
+            return;
+        }
+        self.rpc_calls.fetch_add(missing.len(), Ordering::Relaxed);
+        let fetched = get_receipts(client, &missing, RECEIPT_CONCURRENCY).await;
+        self.receipts
+            .lock()
+            .expect("receipt cache poisoned")
+            .extend(fetched);
+    }
+
+    /// Returns the receipt of `tx_hash`, which landed in `block`, fetching it if it isn't cached.
+    ///
+    /// If the node doesn't have it and `block` is recent, it's retried per the context's
+    /// `ReceiptRetry`, then fails w/ `HindsightError::TxNotLanded`. If `block` isn't recent, the
+    /// tx was dropped, so it fails w/ `HindsightError::TxDropped` right away. Always fails in
+    /// hint-only mode.
+    pub async fn receipt<M: Middleware + 'static>(
+        &self,
+        client: &Arc<M>,
+        tx_hash: H256,
+        block: u64,
+    ) -> Result<TransactionReceipt> {
+        if self.hint_only {
+            return Err(anyhow::format_err!(
+                "can't read the receipt of tx {:?} in hint-only mode",
+                tx_hash
+            ));
+        }
+        self.receipt_lookups.fetch_add(1, Ordering::Relaxed);
+        let cached = self
+            .receipts
+            .lock()
+            .expect("receipt cache poisoned")
+            .get(&tx_hash)
+            .cloned();
+        if let Some(receipt) = cached {
+            return Ok(receipt);
+        }
+        let receipt = self.fetch_receipt(client, tx_hash, block).await?;
+        self.receipts
+            .lock()
+            .expect("receipt cache poisoned")
+            .insert(tx_hash, receipt.to_owned());
+        Ok(receipt)
+    }
+
+    async fn fetch_receipt<M: Middleware + 'static>(
+        &self,
+        client: &Arc<M>,
+        tx_hash: H256,
+        block: u64,
+    ) -> Result<TransactionReceipt> {
+        let retry = &self.receipt_retry;
+        self.rpc_calls.fetch_add(1, Ordering::Relaxed);
+        if let Some(receipt) = client.get_transaction_receipt(tx_hash).await? {
+            return Ok(receipt);
+        }
+        self.rpc_calls.fetch_add(1, Ordering::Relaxed);
+        let head = client.get_block_number().await?.as_u64();
+        if head.saturating_sub(block) > retry.recent_blocks {
+            return Err(HindsightError::TxDropped(tx_hash).into());
+        }
+        let mut backoff = retry.backoff;
+        for attempt in 1..=retry.attempts {
+            debug!(
+                "no receipt for tx {:?} yet, retrying in {:?} ({}/{})",
+                tx_hash, backoff, attempt, retry.attempts
+            );
+            tokio::time::sleep(backoff).await;
+            self.rpc_calls.fetch_add(1, Ordering::Relaxed);
+            if let Some(receipt) = client.get_transaction_receipt(tx_hash).await? {
+                return Ok(receipt);
+            }
+            backoff *= 2;
+        }
+        Err(HindsightError::TxNotLanded(tx_hash).into())
+    }
+
+    /// Returns the number of receipts asked for, & the number of RPC calls made to fetch them.
+    pub fn receipt_stats(&self) -> (usize, usize) {
+        (
+            self.receipt_lookups.load(Ordering::Relaxed),
+            self.rpc_calls.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Returns the decisions that deriving `params` made from data `hint` didn't share. Hints rarely
+/// share logs' data, so trade directions & post-trade prices usually come from the tx's receipt.
+pub fn non_hinted_decisions(hint: &Hint, params: &[UserTradeParams]) -> Vec<NonHintedDecision> {
+    let sync_topic = H256::from(keccak256("Sync(uint112,uint112)"));
+    let hinted_data = |pool: Address, sync: bool| {
+        hint.logs.iter().any(|log| {
+            log.address == pool
+                && !log.data.is_empty()
+                && (log.topics.first() == Some(&sync_topic)) == sync
+        })
+    };
+    let mut seen_pools = HashSet::new();
+    let mut decisions = vec![];
+    for params in params
+        .iter()
+        .filter(|params| seen_pools.insert(params.pool))
+    {
+        let pool = params.pool;
+        let swap_hinted = hinted_data(pool, false);
+        if !swap_hinted {
+            decisions.push(NonHintedDecision::TradeDirection { pool });
+        }
+        // V3 swap logs carry the pool's price; V2 pools' comes from their Sync logs
+        let price_hinted = match params.pool_variant {
+            PoolVariant::UniswapV3 => swap_hinted,
+            PoolVariant::UniswapV2 => hinted_data(pool, true),
+            PoolVariant::Curve => false,
+        };
+        if !price_hinted {
+            decisions.push(NonHintedDecision::PoolPrice { pool });
+        }
+    }
+    decisions
+}
+
+/// Returns the price (TKN/WETH) of a UniV2 `pool` after a tx, from the last Sync event the pool emitted
+/// in the tx's `logs`, which reflects its reserves after all of the tx's swaps.
+///
+/// Returns None if the pool didn't emit a Sync event.
+fn sync_price_v2(
+    logs: &[Log],
+    pool: Address,
+    token_decimals: (U256, U256),
+    token0_is_weth: bool,
+) -> Result<Option<U256>> {
+    // Sync(uint112 reserve0, uint112 reserve1)
+    let sync_topic =
+        H256::from_str("0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1")?;
+    // a malformed sync log is treated as missing
+    let sync_log = logs
+        .iter()
+        .filter(|log| log.topics.first() == Some(&sync_topic) && log.address == pool)
+        .filter(|log| log.data.len() >= 64)
+        .last();
+    sync_log
+        .map(|sync_log| {
+            let reserve0 = U256::from_big_endian(&sync_log.data[0..32]);
+            let reserve1 = U256::from_big_endian(&sync_log.data[32..64]);
+            get_price_v2(reserve0, reserve1, token_decimals, token0_is_weth)
+        })
+        .transpose()
+}
+
+/// Derives the user's trade on `pool` w/o its logs, by simulating `user_tx` on a fork & diffing
+/// the pool's balances of `(token0, token1)` around it.
+///
+/// Returns `(amount0_sent, amount1_sent, price_before, price)`, like the trade's swap logs would,
+/// w/ the pool's prices (TKN/WETH) before & after the tx.
+async fn sim_user_trade(
+    forks: &SimForks,
+    block_info: &BlockInfo,
+    user_tx: &Transaction,
+    pool: Address,
+    pool_variant: PoolVariant,
+    (token0, token1): (Address, Address),
+    weth: Address,
+) -> Result<(I256, I256, U256, U256)> {
+    let (mut evm, _sim_permit) = forks.evm(block_info).await;
+    let balances_before = (
+        sim_balance_of(&mut evm, token0, pool)?,
+        sim_balance_of(&mut evm, token1, pool)?,
+    );
+    let price_before = sim_pool_price(&mut evm, pool, pool_variant, (token0, token1), weth).await?;
+    commit_user_tx(&mut evm, user_tx.to_owned()).await?;
+    let balances_after = (
+        sim_balance_of(&mut evm, token0, pool)?,
+        sim_balance_of(&mut evm, token1, pool)?,
+    );
+    debug!(
+        "pool {:?} balances: {:?} -> {:?}",
+        pool, balances_before, balances_after
+    );
+    // net amounts the user received are 0, as w/ `parse_amounts_sent`
+    let sent =
+        |before: U256, after: U256| (I256::from_raw(after) - I256::from_raw(before)).max(0.into());
+    let price = sim_pool_price(&mut evm, pool, pool_variant, (token0, token1), weth).await?;
+    Ok((
+        sent(balances_before.0, balances_after.0),
+        sent(balances_before.1, balances_after.1),
+        price_before,
+        price,
+    ))
+}
+
+/// Returns the price (TKN/WETH) of `pool` in `evm`'s state, w/o committing anything.
+async fn sim_pool_price(
+    evm: &mut EVM<ForkDB>,
+    pool: Address,
+    pool_variant: PoolVariant,
+    (token0, token1): (Address, Address),
+    weth: Address,
+) -> Result<U256> {
+    match pool_variant {
+        PoolVariant::UniswapV3 => sim_price_v3(pool, token0, token1, weth, evm).await,
+        PoolVariant::UniswapV2 => sim_price_v2(pool, token0, token1, weth, evm).await,
+        PoolVariant::Curve => sim_price_curve(evm, pool, (token0, token1), weth),
+    }
+}
+
+/// Returns the price (TKN/WETH) of `pool` before the user's tx, by reading it on a fork where
+/// the tx hasn't been executed yet.
+async fn sim_pre_trade_price(
+    forks: &SimForks,
+    block_info: &BlockInfo,
+    pool: Address,
+    pool_variant: PoolVariant,
+    tokens: (Address, Address),
+    weth: Address,
+) -> Result<U256> {
+    let (mut evm, _sim_permit) = forks.evm(block_info).await;
+    sim_pool_price(&mut evm, pool, pool_variant, tokens, weth).await
+}
+
+/// Returns the price (TKN/WETH) of a UniV2 `pool` after `user_tx`, by reading its reserves
+/// on a fork after executing the user's tx.
+async fn sim_post_trade_price_v2(
+    forks: &SimForks,
+    block_info: &BlockInfo,
+    user_tx: &Transaction,
+    pool: Address,
+    (token0, token1): (Address, Address),
+    weth: Address,
+) -> Result<U256> {
+    let (mut evm, _sim_permit) = forks.evm(block_info).await;
+    commit_user_tx(&mut evm, user_tx.to_owned()).await?;
+    sim_price_v2(pool, token0, token1, weth, &mut evm).await
+}
+
+/// Returns the value (in WETH) of the amount the user sent to the pool, clamped to `max`.
+///
+/// Without a `client`, the token's decimals must be in `params`.
+async fn user_amount_in_weth(
+    client: Option<&RpcClient>,
+    params: &UserTradeParams,
+    max: U256,
+) -> Result<U256> {
+    let token_in_is_token0 = (params.token_in == params.tokens.weth) == params.token0_is_weth;
+    let amount_sent = if token_in_is_token0 {
+        params.amount0_sent
+    } else {
+        params.amount1_sent
+    }
+    .into_raw();
+    if params.token_in == params.tokens.weth {
+        return Ok(amount_sent.min(max));
+    }
+    // token_in is `params.tokens.token`
+    let decimals = match (params.token_decimals, client) {
+        (Some(decimals), _) => decimals.into(),
+        (None, Some(client)) => get_decimals(client, params.token_in).await?,
+        (None, None) => return Err(anyhow::anyhow!("decimals of {:?} unknown", params.token_in)),
+    };
+    if params.eth_equivalent {
+        // worth about 1 WETH each; the pool's price (w/ the user's impact) would only skew it
+        return Ok(token_amount_in_weth(
+            amount_sent,
+            decimals,
+            U256::exp10(18),
+            max,
+        ));
+    }
+    Ok(token_amount_in_weth(
+        amount_sent,
+        decimals,
+        params.price,
+        max,
+    ))
+}
+
+/// Converts `amount` of a token with `decimals` into WETH (wei) at `price` (TKN per WETH, 18 decimals),
+/// clamped to `max`. Intermediate math is 512-bit, so it can't overflow for any token amount.
+fn token_amount_in_weth(amount: U256, decimals: U256, price: U256, max: U256) -> U256 {
+    if price.is_zero() || decimals > 77.into() {
+        return max;
+    }
+    // weth = amount / 10^decimals / (price / 1e18) * 1e18
+    let ten = U256::from(10);
+    let weth = amount.full_mul(ten.pow(36.into())) / price.full_mul(ten.pow(decimals));
+    U256::try_from(weth).unwrap_or(U256::MAX).min(max)
+}
+
+/// Returns the net amounts of (token0, token1) the user sent to the pool, summed over
+/// the data of each of the user's swap logs on that pool. Net amounts the user received are returned as 0.
+fn parse_amounts_sent(pool_variant: PoolVariant, swap_logs_data: &[&[u8]]) -> (I256, I256) {
+    let (amount0, amount1) =
+        swap_logs_data
+            .iter()
+            .fold((I256::zero(), I256::zero()), |(amount0, amount1), data| {
+                let (delta0, delta1) = parse_amount_deltas(pool_variant, data);
+                (amount0 + delta0, amount1 + delta1)
+            });
+    (amount0.max(0.into()), amount1.max(0.into()))
+}
+
+/// Number of 32-byte words in the data of each variant's Swap event; see `parse_amount_deltas`.
+fn swap_log_words(pool_variant: PoolVariant) -> usize {
+    match pool_variant {
+        // amount0, amount1, sqrtPriceX96, liquidity, tick
+        PoolVariant::UniswapV3 => 5,
+        // amount0In, amount1In, amount0Out, amount1Out
+        PoolVariant::UniswapV2 => 4,
+        // TokenExchange: sold_id, tokens_sold, bought_id, tokens_bought
+        PoolVariant::Curve => 4,
+    }
+}
+
+/// Checks that the data of a swap log of `pool` is long enough to decode as `pool_variant`'s Swap
+/// event, so decoding it can't panic. Longer data is fine; only its leading words are read.
+fn check_swap_log(pool: Address, pool_variant: PoolVariant, swap_log_data: &[u8]) -> Result<()> {
+    if swap_log_data.len() < swap_log_words(pool_variant) * 32 {
+        return Err(HindsightError::MalformedSwapLog {
+            pool,
+            len: swap_log_data.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns the `i`th 32-byte word of a swap log's data, which must have passed `check_swap_log`.
+fn swap_log_word(swap_log_data: &[u8], i: usize) -> U256 {
+    U256::from_big_endian(&swap_log_data[i * 32..(i + 1) * 32])
+}
+
+/// Returns the amounts of (token0, token1) sent to the pool in a single swap log;
+/// negative when received from it. The log must have passed `check_swap_log`.
+fn parse_amount_deltas(pool_variant: PoolVariant, swap_log_data: &[u8]) -> (I256, I256) {
+    let word = |i: usize| I256::from_raw(swap_log_word(swap_log_data, i));
+    match pool_variant {
+        // Swap(address,address,int256 amount0,int256 amount1,uint160,uint128,int24)
+        PoolVariant::UniswapV3 => (word(0), word(1)),
+        // Swap(address,uint256 amount0In,uint256 amount1In,uint256 amount0Out,uint256 amount1Out,address)
+        PoolVariant::UniswapV2 => (word(0) - word(2), word(1) - word(3)),
+        // TokenExchange(address,int128 sold_id,uint256 tokens_sold,int128 bought_id,uint256 tokens_bought)
+        PoolVariant::Curve if word(0).is_zero() => (word(1), -word(3)),
+        PoolVariant::Curve => (-word(3), word(1)),
+    }
+}
+
+/// Returns (token_in, token_out) for a swap on a pool with tokens `(token0, token1)`.
+fn trade_direction(amount0_sent: I256, (token0, token1): (Address, Address)) -> (Address, Address) {
+    if amount0_sent.gt(&0.into()) {
+        (token0, token1)
+    } else {
+        (token1, token0)
+    }
+}
+
+/// Simulates a backrun swapping the given amount of WETH, returning `(amount_in, balance_out, gas_used)`.
+type BackrunSim =
+    Arc<dyn Fn(U256) -> future::BoxFuture<'static, Result<(U256, U256, BackrunGas)>> + Send + Sync>;
+
+/// Finds the best arbitrage trade along `legs` with a grid search (see `grid_search`).
+async fn step_arb(
+    forks: Arc<SimForks>,
+    user_tx: Transaction,
+    block_info: BlockInfo,
+    params: UserTradeParams,
+    range: [U256; 2],
+    search: SearchConfig,
+    ctx: SearchContext,
+    legs: Vec<SwapLeg>,
+) -> Result<(U256, U256, BackrunGas, SearchBound)> {
+    if legs.len() < 2 {
+        // returning an error here will halt the whole sim branch
+        return Err(HindsightError::NoArbPool(params.pool).into());
+    }
+    info!(
+        "step_arb
+        user_tx:\t{:?}
+        legs:\t{:?}
+    ",
+        user_tx.hash, legs
+    );
+    let start_balance = forks.braindance().starting_balance;
+    let sim = backrun_sim(forks, user_tx, block_info, legs);
+    grid_search(sim, start_balance, None, range, search, ctx, None).await
+}
+
+/// Sims the backrun of `user_tx` along `legs`, on a new sandbox fork from `forks` for each amount.
+fn backrun_sim(
+    forks: Arc<SimForks>,
+    user_tx: Transaction,
+    block_info: BlockInfo,
+    legs: Vec<SwapLeg>,
+) -> BackrunSim {
+    Arc::new(move |amount_in: U256| {
+        let forks = forks.clone();
+        let user_tx = user_tx.clone();
+        let block_info = block_info.clone();
+        let legs = legs.clone();
+        async move { sim_arb_forked(&forks, user_tx, &block_info, amount_in, &legs).await }.boxed()
+    })
+}
+
+/// Sims `amounts` (see `Refinement::amounts`) concurrently, returning the best of them, or
+/// `best` (the search's best amount, ending balance & gas) if none beat it. Failed sims are skipped.
+async fn refine_search(
+    sim: BackrunSim,
+    best: (U256, U256, BackrunGas),
+    amounts: Vec<U256>,
+) -> (U256, U256, BackrunGas) {
+    let handles = amounts
+        .into_iter()
+        .map(|amount_in| tokio::task::spawn(sim(amount_in)));
+    let mut best = best;
+    for res in future::join_all(handles).await {
+        match res {
+            Ok(Ok(res)) if res.1 > best.1 => best = res,
+            Ok(Err(err)) => debug!("refinement sim failed: {}", err),
+            _ => {}
+        }
+    }
+    best
+}
+
+/// Adds a sim's result to `trace`, if the search is being traced.
+fn record_sample(
+    trace: &Option<Arc<Mutex<SearchTrace>>>,
+    depth: usize,
+    amount_in: U256,
+    balance_out: U256,
+) {
+    if let Some(trace) = trace {
+        trace
+            .lock()
+            .expect("search trace poisoned")
+            .samples
+            .push(SearchSample {
+                depth,
+                amount_in,
+                balance_out,
+            });
+    }
+}
+
+/// Recursively finds the most profitable amount to `sim`.
+///
+/// Each pass sims `search.intervals` evenly-spaced amounts, then narrows the range around the best one.
+/// If the best amount is the range's last one, the optimum may be past the range, so its width is
+/// doubled upwards instead (up to `search.max_amount_in`). Stops with an error once `ctx` is cancelled,
+/// or with the best amount so far once it's past its deadline (after the first pass). Successful
+/// sims are recorded in `ctx`'s trace, if it's set.
+///
+/// `start_balance` is the WETH balance each sim starts with, which caps `amount_in`.
+#[async_recursion]
+async fn grid_search(
+    sim: BackrunSim,
+    start_balance: U256,
+    best_amount_in_out: Option<(U256, U256, BackrunGas, SearchBound)>,
+    range: [U256; 2],
+    search: SearchConfig,
+    ctx: SearchContext,
+    depth: Option<usize>,
+) -> Result<(U256, U256, BackrunGas, SearchBound)> {
+    info!(
+        "grid_search
+        best (weth_in, weth_bal, gas_used, bound)\t{:?}
+        depth:\t{:?}
+        range:\t{:?}
+    ",
+        best_amount_in_out, depth, range
+    );
+    // unwrap current best result or assign defaults for init case
+    let (mut best_amount_in, mut best_amount_out, mut best_gas_used, mut best_bound) =
+        best_amount_in_out.unwrap_or((
+            0.into(),
+            start_balance,
+            BackrunGas::default(),
+            SearchBound::Lower,
+        ));
+
+    // convenience closures for stop cases
+    let done_unprofitable = || {
+        return Ok((
+            0.into(),
+            start_balance,
+            BackrunGas::default(),
+            SearchBound::Lower,
+        ));
+    };
+    let done_profitable = || {
+        return Ok((best_amount_in, best_amount_out, best_gas_used, best_bound));
+    };
+
+    /*  ============================================================
+    ======================== STOP CASES ============================
+    ============================================================  */
+    // if the ranges get tight enough together, we can quit early
+    if search.is_tight(range[0], range[1]) {
+        info!("range tight enough, finishing early {:?}", range);
+        return done_profitable();
+    }
+    /*  INIT CASE:
+       User possibly passed None for best_amount_in_out, so we use our catch-all values defined above and recurse.
+    */
+    if depth.is_none() {
+        return grid_search(
+            sim,
+            start_balance,
+            Some((best_amount_in, best_amount_out, best_gas_used, best_bound)),
+            range,
+            search,
+            ctx,
+            Some(0),
+        )
+        .await;
+    }
+    // this could just be unwrapped, but paranoia is good when it's just in your code.
+    let depth = depth.expect("depth should have been defined (recursively) by this point.");
+
+    // stop case: we have recursed three times and the range minimum is STILL 0, AND no profit
+    if range[0] == 0.into() && depth >= 3 && best_amount_out <= start_balance {
+        // Return (0, start_balance) to indicate that there was no arbitrage opportunity,
+        // but the arb params (tokens, pools, etc) were still valid.
+        // This ensures that the attempt is logged in the DB.
+        info!("amount_in trending towards zero, quitting sim.");
+        return done_unprofitable();
     }
     // stop case: we hit the max depth, or the best amount of WETH in is lower than the gas cost of the backrun tx
-    if depth > MAX_DEPTH {
+    if depth >= search.max_depth {
         info!("depth limit reached, quitting sim.");
         return done_profitable();
     }
+    // stop case: the event's time budget is spent; keep what the passes so far found
+    if depth > 0 && ctx.past_deadline() {
+        ctx.mark_truncated();
+        info!("time budget spent at depth {}, quitting sim.", depth);
+        return done_profitable();
+    }
 
     /*  ============================================================
     ============== PARALLEL SIMULATION PROCESSING ==================
     ============================================================  */
     // run sims with current params
     let mut handles = vec![];
-    let band_width = (range[1] - range[0]) / U256::from(intervals);
-    for i in 0..intervals {
-        // prep data for consumption by async task
+    let band_width = (range[1] - range[0]) / U256::from(search.intervals);
+    for i in 0..search.intervals {
+        // the event timed out (or the run was stopped); don't start any more sims for it
+        if ctx.cancel.is_cancelled() {
+            return Err(anyhow::anyhow!("search cancelled at depth {}", depth));
+        }
         let amount_in = range[0] + band_width * U256::from(i);
-        let user_tx = user_tx.clone();
-        let block_info = block_info.clone();
-        let params = params.clone();
-        let client = client.clone();
         // spawn the task, hold on to its handle
-        handles.push(tokio::task::spawn(async move {
-            let evm = fork_evm(&client, &block_info).await?;
-            sim_arb_single(
-                evm,
-                user_tx,
-                &block_info,
-                &params,
-                amount_in,
-                start_pair_variant,
-                end_pair_variant,
-            )
-            .await
-        }));
+        handles.push(tokio::task::spawn(sim(amount_in)));
     }
 
     /*  ============================================================
@@ -313,28 +1669,40 @@ async fn step_arb(
     let revenues = future::join_all(handles).await;
     let revenue_len = revenues.len();
     let mut num_reverts = 0;
+    let mut revert_reasons = BTreeSet::new();
+    // index of this pass's best amount, if it beat the previous best
+    let mut best_interval = None;
     // pick best result and update best_amount_in & best_amount_out
-    for result in revenues {
+    for (i, result) in revenues.into_iter().enumerate() {
         if let Ok(result) = result {
             if let Ok(result) = result {
-                let (amount_in, balance_out) = result;
+                let (amount_in, balance_out, gas_used) = result;
+                record_sample(&ctx.trace, depth, amount_in, balance_out);
                 if balance_out > best_amount_out {
                     best_amount_in = amount_in;
                     best_amount_out = balance_out;
+                    best_gas_used = gas_used;
+                    best_interval = Some(i);
                     debug!(
                         "new best (amount_in, balance_out): {:?}",
-                        best_amount_in_out
+                        (best_amount_in, best_amount_out)
                     );
                 }
             } else {
-                // TODO: use real error types, not this garbage
-                let err = result.as_ref().unwrap_err().to_string();
+                let err = result.as_ref().unwrap_err();
                 debug!("{}", err);
-                if err.contains("no other pool found") {
+                match err.downcast_ref::<HindsightError>() {
                     // fail the whole batch by returning this error immediately
-                    return result;
-                } else if err.contains("swap reverted") {
-                    num_reverts += 1;
+                    Some(HindsightError::NoArbPool(_) | HindsightError::UserTxFailed { .. }) => {
+                        return result.map(|(amount_in, balance_out, gas_used)| {
+                            (amount_in, balance_out, gas_used, best_bound)
+                        })
+                    }
+                    Some(HindsightError::SwapReverted { reason, .. }) => {
+                        num_reverts += 1;
+                        revert_reasons.insert(reason.to_owned());
+                    }
+                    _ => {}
                 }
             }
         } else {
@@ -344,333 +1712,3150 @@ async fn step_arb(
             ));
         }
         if num_reverts == revenue_len {
-            return Err(anyhow::anyhow!("all swaps reverted"));
+            return Err(anyhow::anyhow!(
+                "all swaps reverted: {}",
+                revert_reasons.into_iter().collect::<Vec<_>>().join(", ")
+            ));
         }
     }
 
     /*  ============================================================
     ===================== IM RECURSIIIIING =========================
     ============================================================  */
-    let r_amount: rU256 = best_amount_in.into();
-    let range = [
-        if best_amount_in < band_width {
-            0.into()
-        } else {
-            best_amount_in - band_width
-        },
-        if U256::MAX - r_amount < band_width.into() {
-            U256::MAX.into()
-        } else {
-            best_amount_in + band_width
-        },
-    ];
-    return step_arb(
-        client,
-        user_tx,
-        block_info,
-        params,
-        Some((best_amount_in, best_amount_out)),
+    let max_amount_in = search.max_amount_in.min(start_balance);
+    let at_top = best_interval == Some(search.intervals - 1);
+    best_bound = if best_amount_in.is_zero() {
+        SearchBound::Lower
+    } else if at_top || best_amount_in.saturating_add(band_width) >= max_amount_in {
+        SearchBound::Upper
+    } else {
+        SearchBound::Interior
+    };
+    let lower = best_amount_in.saturating_sub(band_width);
+    let range = if at_top && range[1] < max_amount_in {
+        // the best amount is at the top of the range; double the range's width to look past it
+        let upper = range[1]
+            .saturating_add(range[1] - range[0])
+            .min(max_amount_in);
+        debug!(
+            "best amount at top of range, expanding range to {:?}",
+            [lower, upper]
+        );
+        [lower, upper]
+    } else {
+        // narrow the range around the best amount; if that's zero, this tightens the range towards zero
+        [lower, best_amount_in.saturating_add(band_width)]
+    };
+    return grid_search(
+        sim,
+        start_balance,
+        Some((best_amount_in, best_amount_out, best_gas_used, best_bound)),
         range,
-        intervals,
+        search,
+        ctx,
         Some(depth + 1),
-        start_pair_variant,
-        end_pair_variant,
     )
     .await;
 }
 
-/// Find the optimal backrun for a given tx.
-pub async fn find_optimal_backrun_amount_in_out(
-    client: &WsClient,
-    user_tx: Transaction,
-    event: &EventHistory,
+/// Finds the best arbitrage trade for a given set of params, assuming that profit is
+/// unimodal over `amount_in`: narrows `range` around the maximum by simulating two
+/// interior points per iteration, as dictated by `strategy`.
+async fn search_arb(
+    forks: &SimForks,
+    user_tx: &Transaction,
     block_info: &BlockInfo,
-) -> Result<Vec<SimArbResult>> {
-    let start_balance = braindance_starting_balance();
-    let params = derive_trade_params(client, user_tx.to_owned(), event).await?;
-    info!("params {:?}", params);
-
-    // look at price (TKN/ETH) on each exchange to determine which exchange to arb on
-    // if priceA > priceB after user tx creates price impact, then buy TKN on exchange B and sell on exchange A
-
-    let mut pool_handles = vec![];
-    /*
-     Δ
-    Δ Δ Branch for each pool.
-                                             user_event
-                                                / \  \
-                                               /   \  \
-                                              /     \  ...
-                                         params     params
-                                           / \       / \ \
-                                          /   \     /   \ \
-                                         /     \   /     \ ...
-    [pool_handles] <--bg thread <-- ... pool,pool,pool,pool
-                                          |
-                                        step_arb
-                                         / \
-                                        /   ..STEP_INTERVALS
-                                    sim_arb_single()
-
-    Simulate an arb for every pool and throw out the ones that
-    don't turn a profit.
+    params: &UserTradeParams,
+    range: [U256; 2],
+    strategy: SearchStrategy,
+    search: SearchConfig,
+    ctx: &SearchContext,
+    legs: &[SwapLeg],
+) -> Result<(U256, U256, BackrunGas, SearchBound)> {
+    if legs.len() < 2 {
+        return Err(HindsightError::NoArbPool(params.pool).into());
+    }
+    let start_balance = forks.braindance().starting_balance;
+    let [mut lo, mut hi] = range;
+    let mut best = (U256::zero(), start_balance, BackrunGas::default());
+    let mut num_sims = 0;
+    let mut num_reverts = 0;
+    let mut revert_reasons = BTreeSet::new();
 
-    `pool_handles` will hold the joinable background thread handlers,
-    each of which will return a result. Each handle is responsible for
-    determining whether it was profitable, and terminating its execution
-    early if it finds a failure case.
-    When we join the results, we'll filter out the error/null values,
-    which leaves us with only the profitable sims.
-    */
-    for params in params {
-        if params.arb_pools.len() == 0 {
-            debug!("skipping this set of params, no arb pools found.");
-            continue;
+    // sims `amount_in`, returning the ending balance; a reverted sim is treated as a total loss
+    let sim = |amount_in: U256| {
+        let user_tx = user_tx.clone();
+        async move {
+            let res = sim_arb_forked(forks, user_tx, block_info, amount_in, legs).await;
+            (amount_in, res)
         }
-        for other_pool in params.arb_pools.to_owned() {
-            let client = client.clone();
-            let user_tx = user_tx.clone();
-            let block_info = block_info.clone();
-            let params = params.clone();
-            /* SPAWN A NEW (GREEN) THREAD */
-            let handle = tokio::task::spawn(async move {
-                let mut evm = fork_evm(&client, &block_info)
-                    .await
-                    .expect("failed to fork evm");
-
-                // find price on other exchange
-                let alt_price = match other_pool.variant {
-                    PoolVariant::UniswapV2 => sim_price_v2(
-                        other_pool.address,
-                        params.token_in,
-                        params.token_out,
-                        &mut evm,
-                    )
-                    .await
-                    .expect(&format!(
-                        "sim_price_v2 panicked. address={:?} token_in={:?} token_out={:?}",
-                        other_pool.address, params.token_in, params.token_out
-                    )),
-                    PoolVariant::UniswapV3 => sim_price_v3(
-                        other_pool.address,
-                        params.token_in,
-                        params.token_out,
-                        &mut evm,
-                    )
-                    .await
-                    .expect(&format!(
-                        "sim_price_v3 panicked. address={:?} token_in={:?} token_out={:?}",
-                        other_pool.address, params.token_in, params.token_out
-                    )),
-                };
-                debug!("alt price {:?}", alt_price);
+    };
+    // pick the interior points for the current range
+    let golden_points = |lo: U256, hi: U256| {
+        let step = (hi - lo) * INV_GOLDEN_RATIO / GOLDEN_RATIO_SCALE;
+        (hi - step, lo + step)
+    };
+    let ternary_points = |lo: U256, hi: U256| {
+        let step = (hi - lo) / 3;
+        (lo + step, hi - step)
+    };
+    let points = |lo, hi| match strategy {
+        SearchStrategy::Ternary => ternary_points(lo, hi),
+        _ => golden_points(lo, hi),
+    };
 
-                let (start_pool, start_pool_variant, end_pool, end_pool_variant) =
-                    if params.token0_is_weth {
-                        // if tkn0 is weth, then price is denoted in tkn1/eth, so look for highest price
-                        if params.price.gt(&alt_price) {
-                            (
-                                params.pool,
-                                params.pool_variant,
-                                other_pool.address,
-                                other_pool.variant,
-                            )
-                        } else {
-                            (
-                                other_pool.address,
-                                other_pool.variant,
-                                params.pool,
-                                params.pool_variant,
-                            )
-                        }
-                    } else {
-                        // else if tkn1 is weth, then price is denoted in eth/tkn0, so look for lowest price
-                        if params.price.gt(&alt_price) {
-                            (
-                                other_pool.address,
-                                other_pool.variant,
-                                params.pool,
-                                params.pool_variant,
-                            )
-                        } else {
-                            (
-                                params.pool,
-                                params.pool_variant,
-                                other_pool.address,
-                                other_pool.variant,
-                            )
+    let (mut x1, mut x2) = points(lo, hi);
+    let (r1, r2) = future::join(sim(x1), sim(x2)).await;
+    let mut evaluated = vec![r1, r2];
+    let (mut f1, mut f2) = (U256::zero(), U256::zero());
+    for iteration in 0..MAX_SEARCH_ITERATIONS {
+        // record results of the sims run since the last iteration
+        for (amount_in, res) in evaluated.drain(..) {
+            num_sims += 1;
+            let (balance_out, gas_used) = match res {
+                Ok((_, balance_out, gas_used)) => {
+                    record_sample(&ctx.trace, iteration, amount_in, balance_out);
+                    (balance_out, gas_used)
+                }
+                Err(err) => {
+                    debug!("{}", err);
+                    match err.downcast_ref::<HindsightError>() {
+                        Some(
+                            HindsightError::NoArbPool(_) | HindsightError::UserTxFailed { .. },
+                        ) => return Err(err),
+                        Some(HindsightError::SwapReverted { reason, .. }) => {
+                            num_reverts += 1;
+                            revert_reasons.insert(reason.to_owned());
                         }
-                    };
-
-                // set amount_in_start to the arb contract balance; ours has 420 WETH
-                let initial_range = [0.into(), braindance_starting_balance()];
-
-                // a new EVM is spawned inside this function, where the user tx is executed on a fresh fork before our backrun
-                let res = step_arb(
-                    client.clone(),
-                    user_tx,
-                    block_info,
-                    params.to_owned(),
-                    None,
-                    initial_range,
-                    STEP_INTERVALS,
-                    None,
-                    (start_pool, start_pool_variant),
-                    (end_pool, end_pool_variant),
-                )
-                .await;
-                debug!("*** step_arb complete: {:?}", res);
-                if let Ok(res) = res {
-                    Some(SimArbResult {
-                        user_trade: params,
-                        backrun_trade: BackrunResult {
-                            amount_in: res.0,
-                            balance_end: res.1,
-                            profit: if res.1 > start_balance {
-                                res.1 - start_balance
-                            } else {
-                                0.into()
-                            },
-                            start_pool: start_pool,
-                            end_pool: end_pool,
-                            start_variant: start_pool_variant,
-                            end_variant: end_pool_variant,
-                        },
-                    })
-                } else {
-                    None
+                        _ => {}
+                    }
+                    (U256::zero(), BackrunGas::default())
                 }
-            });
-            pool_handles.push(handle);
+            };
+            if balance_out > best.1 {
+                best = (amount_in, balance_out, gas_used);
+                debug!("new best (amount_in, balance_out, gas_used): {:?}", best);
+            }
+            if amount_in == x1 {
+                f1 = balance_out;
+            }
+            if amount_in == x2 {
+                f2 = balance_out;
+            }
         }
-    }
-
-    // Collect all the results for this batch, filter out any errors or empty results before returning.
-    let results: Vec<_> = future::join_all(pool_handles).await;
-    Ok(results
-        .into_iter()
-        .filter(|res| res.is_ok())
-        .map(|res| res.unwrap())
-        .filter(|res| res.is_some())
-        .map(|res| res.to_owned().unwrap())
-        .collect::<Vec<_>>())
-}
+        trace!(
+            "search_arb iteration {}: range [{:?}, {:?}], sims {:?} -> {:?}",
+            iteration,
+            lo,
+            hi,
+            (x1, x2),
+            (f1, f2)
+        );
 
-/// Simulate a two-step arbitrage on a forked EVM with fixed trade amount & path.
-///
-/// 1. Buy `amount_in` WETH worth of token on start_pair
+        // narrow the range around the better point
+        if f1 >= f2 {
+            hi = x2;
+        } else {
+            lo = x1;
+        }
+        // if the ranges get tight enough together, we can quit early
+        if search.is_tight(lo, hi) || hi - lo < 3.into() {
+            break;
+        }
+
+        if ctx.cancel.is_cancelled() {
+            return Err(anyhow::anyhow!(
+                "search cancelled after {} iterations",
+                iteration + 1
+            ));
+        }
+        if ctx.past_deadline() {
+            ctx.mark_truncated();
+            info!(
+                "time budget spent after {} iterations, quitting search.",
+                iteration + 1
+            );
+            break;
+        }
+
+        // pick next points; golden-section keeps one of the previous points
+        match strategy {
+            SearchStrategy::Ternary | SearchStrategy::Grid => {
+                (x1, x2) = points(lo, hi);
+                let (r1, r2) = future::join(sim(x1), sim(x2)).await;
+                evaluated = vec![r1, r2];
+            }
+            SearchStrategy::GoldenSection => {
+                if hi == x2 {
+                    (x2, f2) = (x1, f1);
+                    x1 = points(lo, hi).0;
+                    evaluated = vec![sim(x1).await];
+                } else {
+                    (x1, f1) = (x2, f2);
+                    x2 = points(lo, hi).1;
+                    evaluated = vec![sim(x2).await];
+                }
+            }
+        }
+    }
+
+    info!(
+        "search_arb ({}) finished after {} sims. best (amount_in, balance_out): {:?}",
+        strategy, num_sims, best
+    );
+    if num_reverts == num_sims {
+        return Err(anyhow::anyhow!(
+            "all swaps reverted: {}",
+            revert_reasons.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    let bound = if best.0.is_zero() {
+        SearchBound::Lower
+    } else if hi == range[1] {
+        // the range's upper bound never moved, so the optimum may be past it
+        SearchBound::Upper
+    } else {
+        SearchBound::Interior
+    };
+    let (amount_in, balance_out, gas_used) = best;
+    Ok((amount_in, balance_out, gas_used, bound))
+}
+
+/// Outcome of searching for a backrun-arb against each candidate pool for a tx.
+#[derive(Debug, Default)]
+pub struct ArbSearchOutcome {
+    pub results: Vec<SimArbResult>,
+    /// Candidate pools whose search failed, and the error that failed it.
+    pub failures: Vec<(Address, Error)>,
+    /// ETH price (USD) at the simulated block, if it was read (see `SimOptions::eth_usd_feed`).
+    pub eth_usd_price: Option<f64>,
+    /// Trade params derived from the user's tx, which the searches started from.
+    pub params: Vec<UserTradeParams>,
+    /// Number of sims run on the tx's forks.
+    pub num_sims: usize,
+}
+
+impl ArbSearchOutcome {
+    /// Sorts the result of each pool's search into results or failures.
+    fn collect(pool_results: impl IntoIterator<Item = (Address, Result<SimArbResult>)>) -> Self {
+        let mut outcome = Self::default();
+        for (pool, res) in pool_results {
+            match res {
+                Ok(res) => outcome.results.push(res),
+                Err(err) => outcome.failures.push((pool, err)),
+            }
+        }
+        outcome
+    }
+
+    /// Summarizes the failures, e.g. "2 failed (0x12..: all swaps reverted: STF)".
+    pub fn failure_summary(&self) -> String {
+        format!(
+            "{} failed ({})",
+            self.failures.len(),
+            self.failures
+                .iter()
+                .map(|(pool, err)| format!("{:?}: {}", pool, err))
+                .collect::<Vec<_>>()
+                .join("; ")
+        )
+    }
+}
+
+/// Find the optimal backrun for a given tx.
 ///
-/// 2. Sell balance of token on end_pair for WETH, completing the arb.
-async fn sim_arb_single(
-    mut evm: EVM<ForkDB>,
+/// With `options.record_state`, the state the search touches is saved there as an
+/// `EventSnapshot`, which `replay_backruns` can re-simulate without a node.
+pub async fn find_optimal_backrun_amount_in_out(
+    client: &RpcClient,
     user_tx: Transaction,
+    event: &EventHistory,
     block_info: &BlockInfo,
+    options: SimOptions,
+) -> Result<ArbSearchOutcome> {
+    let start_time = Instant::now();
+    // all sims for this tx fork the same state, so we only need to fetch it once
+    let (preceding_txs, following_txs) = placement_txs(client, &user_tx, options.placement).await?;
+    let fork_factory = match &options.fork_cache {
+        Some(fork_cache) => {
+            fork_cache
+                .fork_factory(client, block_info, &preceding_txs)
+                .await?
+        }
+        None => new_fork_factory(client, block_info, &preceding_txs).await?,
+    };
+    let forks = Arc::new(
+        SimForks::from_fork_factory(
+            fork_factory,
+            options.sim_permits.clone(),
+            following_txs.to_owned(),
+        )
+        .on_chain(&options.chain)
+        .with_braindance(options.braindance, options.chain.weth)?,
+    );
+    debug!(
+        "fork factory for tx {:?} ready in {:?} (shared: {:?})",
+        user_tx.hash,
+        start_time.elapsed(),
+        options
+            .fork_cache
+            .as_ref()
+            .and_then(|fork_cache| fork_cache.stats(block_info.number.as_u64()))
+    );
+    let recording = options
+        .record_state
+        .is_some()
+        .then(|| forks.start_recording(block_info));
+    let ctx = EventContext::new(options.receipt_retry).with_hint_only(options.hint_only);
+    ctx.prefetch_receipts(client, &EventContext::event_tx_hashes(event, &user_tx))
+        .await;
+    let params = derive_trade_params(
+        client,
+        &options.chain,
+        Some(&*forks),
+        block_info,
+        user_tx.to_owned(),
+        event,
+        &ctx,
+    )
+    .await?;
+    let (receipt_lookups, receipt_calls) = ctx.receipt_stats();
+    debug!(
+        "tx {:?}: {} receipt lookups took {} RPC calls",
+        user_tx.hash, receipt_lookups, receipt_calls
+    );
+    info!("params {:?}", params);
+    let outcome = search_backruns(
+        Some(client),
+        forks.clone(),
+        user_tx.to_owned(),
+        params.to_owned(),
+        block_info,
+        &options,
+        start_time,
+    )
+    .await;
+
+    // saved even if the search failed, so the failure can be reproduced
+    if let (Some(dir), Some(recording)) = (&options.record_state, recording) {
+        let snapshot = forks
+            .finish_recording(recording, block_info)
+            .map(|state| EventSnapshot {
+                event: event.to_owned(),
+                tx: user_tx.to_owned(),
+                params,
+                following_txs,
+                state,
+            });
+        match snapshot.and_then(|snapshot| snapshot.save(dir)) {
+            Ok(path) => debug!("saved state of tx {:?} to {}", user_tx.hash, path.display()),
+            Err(err) => warn!("failed to save state of tx {:?}: {}", user_tx.hash, err),
+        }
+    }
+    outcome
+}
+
+/// Re-simulates the backruns of the event in `snapshot`, on the state it recorded, without a node.
+///
+/// Backruns aren't signed, since that needs the signer's nonce from a node.
+pub async fn replay_backruns(
+    snapshot: &EventSnapshot,
+    options: SimOptions,
+) -> Result<ArbSearchOutcome> {
+    let start_time = Instant::now();
+    let block_info = snapshot.state.block_info();
+    let forks = Arc::new(
+        SimForks::from_snapshot(
+            &snapshot.state,
+            options.sim_permits.clone(),
+            snapshot.following_txs.to_owned(),
+        )
+        .on_chain(&options.chain)
+        .with_braindance(options.braindance, options.chain.weth)?,
+    );
+    let options = SimOptions {
+        bundle_signer: None,
+        ..options
+    };
+    search_backruns(
+        None,
+        forks,
+        snapshot.tx.to_owned(),
+        snapshot.params.to_owned(),
+        &block_info,
+        &options,
+        start_time,
+    )
+    .await
+}
+
+/// Returns the round trip between the user's pool & `other_pool`, whose price is `alt_price`,
+/// that the prices say is profitable.
+///
+/// Prices are denoted in TKN/ETH (whichever of the pool's tokens is WETH), so TKN is bought where
+/// its price is highest & sold where it's lowest.
+fn select_arb_route(params: &UserTradeParams, other_pool: &PairPool, alt_price: U256) -> ArbRoute {
+    let user_pool = (params.pool, params.pool_variant, params.pool_fee);
+    let alt_pool = (other_pool.address, other_pool.variant, other_pool.fee);
+    if params.price.gt(&alt_price) {
+        ArbRoute::round_trip(&params.tokens, user_pool, alt_pool)
+    } else {
+        ArbRoute::round_trip(&params.tokens, alt_pool, user_pool)
+    }
+}
+
+/// Returns the round trips to search between the user's pool & `other_pool`: the one
+/// `select_arb_route` picks, or both for ETH-equivalent tokens, which trade so close to 1:1 that
+/// the user's impact may not tell which way the arb goes.
+fn arb_round_trips(
     params: &UserTradeParams,
-    amount_in: U256,
-    start_pair_variant: (Address, PoolVariant),
-    end_pair_variant: (Address, PoolVariant),
-) -> Result<(U256, U256)> {
-    let (start_pool, start_variant) = start_pair_variant;
-    let (end_pool, end_variant) = end_pair_variant;
-    sim_bundle(&mut evm, vec![user_tx.to_owned()]).await?;
+    other_pool: &PairPool,
+    alt_price: U256,
+) -> Vec<ArbRoute> {
+    if !params.eth_equivalent {
+        return vec![select_arb_route(params, other_pool, alt_price)];
+    }
+    let user_pool = (params.pool, params.pool_variant, params.pool_fee);
+    let alt_pool = (other_pool.address, other_pool.variant, other_pool.fee);
+    vec![
+        ArbRoute::round_trip(&params.tokens, user_pool, alt_pool),
+        ArbRoute::round_trip(&params.tokens, alt_pool, user_pool),
+    ]
+}
+
+/// Returns the range of WETH amounts to search backruns of the user's trade over: up to what the
+/// user's trade was worth, since the best backrun rarely needs more, or up to `max_amount_in` if
+/// it can't be valued.
+async fn estimate_search_range(
+    client: Option<&RpcClient>,
+    params: &UserTradeParams,
+    max_amount_in: U256,
+) -> [U256; 2] {
+    let amount_in_max = match user_amount_in_weth(client, params, max_amount_in).await {
+        Ok(amount) if !amount.is_zero() => amount,
+        Ok(_) => max_amount_in,
+        Err(err) => {
+            debug!(
+                "failed to value user's trade, searching full range: {}",
+                err
+            );
+            max_amount_in
+        }
+    };
+    [0.into(), amount_in_max]
+}
+
+/// Returns the more profitable of two searches of the same pools, preferring any success to a
+/// failure.
+fn keep_best(best: Result<SimArbResult>, res: Result<SimArbResult>) -> Result<SimArbResult> {
+    match (&res, &best) {
+        (Ok(new), Ok(old)) if new.backrun_trade.profit <= old.backrun_trade.profit => best,
+        (Err(_), Ok(_)) => best,
+        _ => res,
+    }
+}
+
+/// Searches for the best backrun of `user_tx` on each of `params`' arb pools.
+///
+/// `client` is only needed to sign backruns, & to value the user's trade if its token's decimals
+/// aren't in `params`.
+async fn search_backruns(
+    client: Option<&RpcClient>,
+    forks: Arc<SimForks>,
+    user_tx: Transaction,
+    params: Vec<UserTradeParams>,
+    block_info: &BlockInfo,
+    options: &SimOptions,
+    start_time: Instant,
+) -> Result<ArbSearchOutcome> {
+    let strategy = options.search_strategy;
+    let search = options.search;
+    let priority_fee = options.priority_fee;
+    let min_pool_weth = options.min_pool_weth;
+    let start_balance = forks.braindance().starting_balance;
+    let trade_params = params.to_owned();
+    // searches still running once the event's budget is spent settle for what they've found
+    let deadline = options.event_budget.map(|budget| start_time + budget);
 
+    // look at price (TKN/ETH) on each exchange to determine which exchange to arb on
+    // if priceA > priceB after user tx creates price impact, then buy TKN on exchange B and sell on exchange A
+
+    let mut pool_handles = vec![];
+    // limits the number of pools being simulated at once
+    let pool_permits = Arc::new(Semaphore::new(MAX_CONCURRENT_POOL_SIMS));
+    // (user's pool, other pool) pairs that are already being simulated
+    let mut seen_pools = HashSet::new();
     /*
-    - if the price is denoted in TKN/ETH, we want to buy where the price is highest
-    - if the price is denoted in ETH/TKN, we want to buy where the price is lowest
-    - price is always denoted in tkn1/tkn0
+     Δ
+    Δ Δ Branch for each pool.
+                                             user_event
+                                                / \  \
+                                               /   \  \
+                                              /     \  ...
+                                         params     params
+                                           / \       / \ \
+                                          /   \     /   \ \
+                                         /     \   /     \ ...
+    [pool_handles] <--bg thread <-- ... pool,pool,pool,pool
+                                          |
+                                        step_arb
+                                         / \
+                                        /   ..search.intervals
+                                    sim_arb_single()
+
+    Simulate an arb for every pool and throw out the ones that
+    don't turn a profit.
+
+    `pool_handles` will hold the joinable background thread handlers,
+    each of which will return a result. Each handle is responsible for
+    determining whether it was profitable, and terminating its execution
+    early if it finds a failure case.
+    When we join the results, we'll filter out the error/null values,
+    which leaves us with only the profitable sims.
     */
+    let mut unsupported_token = None;
+    for params in params {
+        if params.arb_pools.is_empty() && params.intermediate_pools.is_empty() {
+            debug!("skipping this set of params, no arb pools found.");
+            continue;
+        }
+        if !options
+            .event_filter
+            .allows_pool(params.pool, (params.tokens.weth, params.tokens.token))
+        {
+            debug!(
+                "skipping this set of params, pool {:?} doesn't match the event filter.",
+                params.pool
+            );
+            continue;
+        }
+        if let Err(err) = check_token(
+            &forks,
+            block_info,
+            &params,
+            options.token_blocklist.as_deref(),
+        )
+        .await
+        {
+            debug!("skipping this set of params: {}", err);
+            unsupported_token = Some(err);
+            continue;
+        }
+        let range =
+            estimate_search_range(client, &params, search.max_amount_in.min(start_balance)).await;
+        for other_pool in params.arb_pools.to_owned() {
+            // the same pair may be swapped on by multiple logs in a tx; only count the opportunity once
+            if !seen_pools.insert((params.pool, other_pool.address)) {
+                debug!(
+                    "skipping duplicate arb pool {:?} for pool {:?}",
+                    other_pool.address, params.pool
+                );
+                continue;
+            }
+            let pool_permits = pool_permits.clone();
+            let forks = forks.clone();
+            let user_tx = user_tx.clone();
+            let block_info = block_info.clone();
+            let params = params.clone();
+            let cancel = options.cancel.clone();
+            /* SPAWN A NEW (GREEN) THREAD */
+            let handle = tokio::task::spawn(async move {
+                let _permit = pool_permits.acquire().await.expect("pool semaphore closed");
+                if cancel.is_cancelled() {
+                    return Err(anyhow::anyhow!("search cancelled before it started"));
+                }
+                let (mut evm, sim_permit) = forks.evm(&block_info).await;
+                // the backrun pays the same base fee as the user, plus our priority fee
+                let gas_price = block_info.base_fee + priority_fee;
 
-    /* Buy tokens on one exchange. */
-    let res = commit_braindance_swap(
+                check_pool_depth(&mut evm, &other_pool, &params.tokens, min_pool_weth)?;
+
+                // find price on other exchange; a pool whose price can't be read is skipped
+                let alt_price = sim_pool_price(
+                    &mut evm,
+                    other_pool.address,
+                    other_pool.variant,
+                    (params.token_in, params.token_out),
+                    params.tokens.weth,
+                )
+                .await
+                .map_err(|err| {
+                    warn!(
+                        "skipping pool {:?}, failed to read its price: {}",
+                        other_pool.address, err
+                    );
+                    anyhow::anyhow!(
+                        "failed to read price of pool {:?}: {}",
+                        other_pool.address,
+                        err
+                    )
+                })?;
+                debug!("alt price {:?}", alt_price);
+                // release the permit so that the search's sims can use it
+                drop(sim_permit);
+
+                // a new EVM is spawned for each sim, where the user tx is executed on a fresh sandbox fork before our backrun
+                let mut best: Result<SimArbResult> =
+                    Err(anyhow::anyhow!("no routes via {:?}", other_pool.address));
+                for route in arb_round_trips(&params, &other_pool, alt_price) {
+                    let res = search_route(
+                        forks.clone(),
+                        user_tx.clone(),
+                        block_info.clone(),
+                        params.clone(),
+                        route,
+                        range,
+                        strategy,
+                        search,
+                        cancel.clone(),
+                        deadline,
+                        gas_price,
+                    )
+                    .await;
+                    best = keep_best(best, res);
+                }
+                best.map(|mut res| {
+                    res.backrun_trade.alt_pool_price = alt_price;
+                    res
+                })
+            });
+            pool_handles.push((other_pool.address, handle));
+        }
+        for intermediate in params.intermediate_pools.to_owned() {
+            for token_pool in intermediate.token_pools.to_owned() {
+                // routes through the same pools may be found from multiple logs in a tx, too
+                if !seen_pools.insert((params.pool, token_pool.address)) {
+                    continue;
+                }
+                let pool_permits = pool_permits.clone();
+                let forks = forks.clone();
+                let user_tx = user_tx.clone();
+                let block_info = block_info.clone();
+                let params = params.clone();
+                let intermediate = intermediate.clone();
+                let cancel = options.cancel.clone();
+                let handle = tokio::task::spawn(async move {
+                    let _permit = pool_permits.acquire().await.expect("pool semaphore closed");
+                    if cancel.is_cancelled() {
+                        return Err(anyhow::anyhow!("search cancelled before it started"));
+                    }
+                    let gas_price = block_info.base_fee + priority_fee;
+                    let (mut evm, sim_permit) = forks.evm(&block_info).await;
+                    // WETH is traded for the intermediate token on its deepest pool
+                    let weth_tokens = TokenPair {
+                        weth: params.tokens.weth,
+                        token: intermediate.token,
+                    };
+                    let weth_pool = deepest_pool(&mut evm, &intermediate.weth_pools, &weth_tokens)
+                        .ok_or(HindsightError::NoArbPool(params.pool))?;
+                    check_pool_depth(&mut evm, &weth_pool, &weth_tokens, min_pool_weth)?;
+                    drop(sim_permit);
+
+                    let (weth, token, via) =
+                        (weth_tokens.weth, params.tokens.token, intermediate.token);
+                    let leg = |pool: Address, variant, token_in, token_out| SwapLeg {
+                        pool,
+                        variant,
+                        token_in,
+                        token_out,
+                    };
+                    let (user_pool, user_variant) = (params.pool, params.pool_variant);
+                    // there's no price to compare the route to, so search it in both directions
+                    let routes = [
+                        ArbRoute {
+                            legs: vec![
+                                leg(weth_pool.address, weth_pool.variant, weth, via),
+                                leg(token_pool.address, token_pool.variant, via, token),
+                                leg(user_pool, user_variant, token, weth),
+                            ],
+                            start_fee: weth_pool.fee,
+                            end_fee: params.pool_fee,
+                        },
+                        ArbRoute {
+                            legs: vec![
+                                leg(user_pool, user_variant, weth, token),
+                                leg(token_pool.address, token_pool.variant, token, via),
+                                leg(weth_pool.address, weth_pool.variant, via, weth),
+                            ],
+                            start_fee: params.pool_fee,
+                            end_fee: weth_pool.fee,
+                        },
+                    ];
+                    let mut best: Result<SimArbResult> =
+                        Err(anyhow::anyhow!("no routes through {:?}", via));
+                    for route in routes {
+                        let res = search_route(
+                            forks.clone(),
+                            user_tx.clone(),
+                            block_info.clone(),
+                            params.clone(),
+                            route,
+                            range,
+                            strategy,
+                            search,
+                            cancel.clone(),
+                            deadline,
+                            gas_price,
+                        )
+                        .await;
+                        best = keep_best(best, res);
+                    }
+                    best
+                });
+                pool_handles.push((token_pool.address, handle));
+            }
+        }
+    }
+
+    if pool_handles.is_empty() {
+        if let Some(err) = unsupported_token {
+            return Err(err);
+        }
+    }
+
+    // Collect all the results for this batch, separating the failures from the results.
+    let (pools, handles): (Vec<_>, Vec<_>) = pool_handles.into_iter().unzip();
+    let results = future::join_all(handles).await.into_iter().map(|res| {
+        res.map_err(|err| anyhow::anyhow!("sim task failed: {}", err))
+            .and_then(|res| res)
+    });
+    let mut outcome = ArbSearchOutcome::collect(pools.into_iter().zip(results));
+    outcome.params = trade_params;
+    outcome.num_sims = forks.num_sims();
+    info!(
+        "simulated backruns for tx {:?} in {:?} ({} sims)",
+        user_tx.hash,
+        start_time.elapsed(),
+        forks.num_sims()
+    );
+    debug!(
+        "found {} arbs for tx {:?}: {:?}",
+        outcome.results.len(),
+        user_tx.hash,
+        outcome
+            .results
+            .iter()
+            .map(|res| (res.backrun_trade.end_pool, res.backrun_trade.profit))
+            .collect::<Vec<_>>()
+    );
+    if options.best_arb_only {
+        outcome.results = outcome
+            .results
+            .into_iter()
+            .max_by_key(|res| res.backrun_trade.profit)
+            .into_iter()
+            .collect();
+    }
+    if let (Some(signer), Some(client)) = (&options.bundle_signer, client) {
+        let nonce = client
+            .get_transaction_count(
+                signer.address(),
+                Some(BlockNumber::Number(block_info.number).into()),
+            )
+            .await?;
+        for res in outcome
+            .results
+            .iter_mut()
+            .filter(|res| !res.backrun_trade.profit.is_zero())
+        {
+            match sign_backrun(
+                &forks,
+                block_info,
+                &user_tx,
+                res,
+                signer,
+                nonce,
+                priority_fee,
+            )
+            .await
+            {
+                Ok(backrun_txs) => res.backrun_txs = Some(backrun_txs),
+                Err(err) => warn!("failed to sign backrun for tx {:?}: {}", user_tx.hash, err),
+            }
+        }
+    }
+    if let Some(feed) = options.eth_usd_feed {
+        let (mut evm, _sim_permit) = forks.evm(block_info).await;
+        match sim_eth_usd_price(&mut evm, feed) {
+            Ok(price) => outcome.eth_usd_price = Some(price),
+            Err(err) => warn!(
+                "failed to read ETH/USD price for tx {:?} from {:?}: {}",
+                user_tx.hash, feed, err
+            ),
+        }
+    }
+    Ok(outcome)
+}
+
+/// Searches for the best backrun of `user_tx` along `route`, and prices its gas at `gas_price`.
+async fn search_route(
+    forks: Arc<SimForks>,
+    user_tx: Transaction,
+    block_info: BlockInfo,
+    params: UserTradeParams,
+    route: ArbRoute,
+    range: [U256; 2],
+    strategy: SearchStrategy,
+    search: SearchConfig,
+    cancel: CancellationToken,
+    deadline: Option<Instant>,
+    gas_price: U256,
+) -> Result<SimArbResult> {
+    let ArbRoute {
+        legs,
+        start_fee,
+        end_fee,
+    } = route;
+    let ctx = SearchContext::new(cancel, deadline, search.new_trace());
+    let start_balance = forks.braindance().starting_balance;
+    let res = match strategy {
+        SearchStrategy::Grid => {
+            step_arb(
+                forks.clone(),
+                user_tx.clone(),
+                block_info.clone(),
+                params.to_owned(),
+                range,
+                search,
+                ctx.clone(),
+                legs.to_owned(),
+            )
+            .await
+        }
+        SearchStrategy::GoldenSection | SearchStrategy::Ternary => {
+            search_arb(
+                &forks,
+                &user_tx,
+                &block_info,
+                &params,
+                range,
+                strategy,
+                search,
+                &ctx,
+                &legs,
+            )
+            .await
+        }
+    };
+    debug!("*** step_arb complete: {:?}", res);
+    let (mut amount_in, mut balance_end, mut gas, bound) = res?;
+    let profit_of = |balance_end: U256, gas: &BackrunGas| {
+        let gas_cost = U256::from(gas.total()) * gas_price;
+        balance_end
+            .saturating_sub(start_balance)
+            .saturating_sub(gas_cost)
+    };
+    // the search's result is kept next to the refined one, to tell how far off it was
+    let mut coarse = None;
+    if let Some(refine) = search.refine {
+        let profit = profit_of(balance_end, &gas);
+        if !amount_in.is_zero()
+            && profit >= refine.min_profit
+            && !ctx.cancel.is_cancelled()
+            && !ctx.past_deadline()
+        {
+            coarse = Some(CoarseSearch {
+                amount_in,
+                balance_end,
+                profit,
+            });
+            let amounts = refine.amounts(amount_in, search.max_amount_in.min(start_balance));
+            // the sims share the event's forks (& so its block's fork factory) w/ the search
+            let sim = backrun_sim(forks, user_tx, block_info, legs.to_owned());
+            (amount_in, balance_end, gas) =
+                refine_search(sim, (amount_in, balance_end, gas), amounts).await;
+            debug!(
+                "refined amount_in {:?} -> {:?}",
+                coarse.map(|coarse| coarse.amount_in),
+                amount_in
+            );
+        }
+    }
+    let gas_cost = U256::from(gas.total()) * gas_price;
+    let profit = profit_of(balance_end, &gas);
+    let (first_leg, last_leg) = (legs[0], legs[legs.len() - 1]);
+    // arbs between two pools of the user's pair are described by their start & end pools alone
+    let (route, route_tokens) = if legs.len() > 2 {
+        (
+            legs.iter().map(|leg| (leg.pool, leg.variant)).collect(),
+            std::iter::once(first_leg.token_in)
+                .chain(legs.iter().map(|leg| leg.token_out))
+                .collect(),
+        )
+    } else {
+        (vec![], vec![])
+    };
+    Ok(SimArbResult {
+        user_trade: params,
+        backrun_trade: BackrunResult {
+            amount_in,
+            balance_end,
+            profit,
+            profit_eth: wei_to_eth(profit),
+            gas_used: gas.total(),
+            gas_used_buy: gas.buy,
+            gas_used_sell: gas.sell,
+            effective_gas_price: gas_price,
+            gas_cost,
+            start_pool: first_leg.pool,
+            end_pool: last_leg.pool,
+            start_variant: first_leg.variant,
+            end_variant: last_leg.variant,
+            start_fee,
+            end_fee,
+            route,
+            route_tokens,
+            search_bound: Some(bound),
+            search_truncated: ctx.truncated(),
+            coarse,
+            user_pool_price: params.price,
+            alt_pool_price: U256::zero(),
+        },
+        backrun_txs: None,
+        search_trace: ctx.trace.map(|trace| {
+            let trace = trace.lock().expect("search trace poisoned");
+            trace.to_owned()
+        }),
+    })
+}
+
+/// Returns the pool in `pools` that holds the most WETH, skipping pools whose depth can't be read.
+fn deepest_pool(evm: &mut EVM<ForkDB>, pools: &[PairPool], tokens: &TokenPair) -> Option<PairPool> {
+    pools
+        .iter()
+        .filter_map(|pool| {
+            sim_weth_depth(evm, pool.address, pool.variant, tokens)
+                .map(|depth| (depth, *pool))
+                .ok()
+        })
+        .max_by_key(|(depth, _)| *depth)
+        .map(|(_, pool)| pool)
+}
+
+/// Returns `HindsightError::ShallowPool` if `pool` holds less than `min_weth`, since every
+/// swap on a pool that only holds dust would revert or return nothing.
+fn check_pool_depth(
+    evm: &mut EVM<ForkDB>,
+    pool: &PairPool,
+    tokens: &TokenPair,
+    min_weth: U256,
+) -> Result<()> {
+    if min_weth.is_zero() {
+        return Ok(());
+    }
+    let weth = sim_weth_depth(evm, pool.address, pool.variant, tokens)?;
+    if weth < min_weth {
+        return Err(HindsightError::ShallowPool {
+            pool: pool.address,
+            weth,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Returns `HindsightError::UnsupportedToken` if the token in `params` is blocklisted or
+/// doesn't transfer exact amounts. Newly-found unsupported tokens are added to `blocklist`.
+async fn check_token(
+    forks: &SimForks,
+    block_info: &BlockInfo,
+    params: &UserTradeParams,
+    blocklist: Option<&TokenBlocklist>,
+) -> Result<()> {
+    let token = params.tokens.token;
+    if blocklist.map_or(false, |blocklist| blocklist.contains(&token)) {
+        return Err(HindsightError::UnsupportedToken(token).into());
+    }
+    let (mut evm, _sim_permit) = forks.evm(block_info).await;
+    match probe_token(
         &mut evm,
-        start_variant,
-        amount_in,
-        start_pool,
+        block_info,
+        params,
+        forks.braindance().swap_gas_limit,
+    ) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            info!("token {:?} doesn't transfer exact amounts", token);
+            if let Some(blocklist) = blocklist {
+                if let Err(err) = blocklist.insert(token) {
+                    warn!("failed to save token blocklist: {}", err);
+                }
+            }
+            Err(HindsightError::UnsupportedToken(token).into())
+        }
+        Err(err) => {
+            // inconclusive; let the search find out
+            debug!("failed to probe token {:?}: {}", token, err);
+            Ok(())
+        }
+    }
+}
+
+/// Buys a small amount of the token in `params` on the user's pool and sells it back.
+/// Returns false if the token behaves non-standardly (e.g. takes a fee on transfer).
+fn probe_token(
+    evm: &mut EVM<ForkDB>,
+    block_info: &BlockInfo,
+    params: &UserTradeParams,
+    gas_limit: u64,
+) -> Result<bool> {
+    let buy: SwapOutcome = commit_braindance_swap(
+        evm,
+        params.pool_variant,
+        PROBE_AMOUNT_IN.into(),
+        params.pool,
         params.tokens.weth,
         params.tokens.token,
         block_info.base_fee,
         None,
-    );
-    debug!("braindance 1 completed. {:?}", res);
-    let amount_received = res.unwrap_or(0.into());
-    debug!("amount received {:?}", amount_received);
-
-    /* Sell them on other exchange. */
-    let res = commit_braindance_swap(
-        &mut evm,
-        end_variant,
-        amount_received,
-        end_pool,
+        gas_limit,
+    )?;
+    // we started with none of the token, so we should have exactly what the pool sent
+    if buy.balance != buy.amount_out {
+        debug!(
+            "probe: pool sent {:?} tokens, received {:?}",
+            buy.amount_out, buy.balance
+        );
+        return Ok(false);
+    }
+    let sell = commit_braindance_swap(
+        evm,
+        params.pool_variant,
+        buy.balance,
+        params.pool,
         params.tokens.token,
         params.tokens.weth,
-        block_info.base_fee + (block_info.base_fee * 2500) / 10000,
+        block_info.base_fee,
         None,
-    )?;
-    debug!("braindance 2 completed. {:?}", res);
-    Ok((amount_in, res))
+        gas_limit,
+    );
+    match sell {
+        Ok(_) => Ok(true),
+        // the pool receives less than we sent if the token takes a fee, which fails the pool's invariant check
+        Err(err) => match err.downcast_ref::<HindsightError>() {
+            Some(HindsightError::SwapReverted { .. }) => Ok(false),
+            _ => Err(err),
+        },
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::util::{get_all_trading_pools, get_block_info, test::get_test_ws_client, ETH};
-    use anyhow::Result;
-    use ethers::providers::Middleware;
-
-    async fn setup_test_evm(client: &WsClient, block_num: u64) -> Result<EVM<ForkDB>> {
-        let block_info = get_block_info(&client, block_num).await?;
-        fork_evm(&client, &block_info).await
-    }
+/// Simulates an arb with `sim_arb_single` on a new sandbox fork from `forks`.
+async fn sim_arb_forked(
+    forks: &SimForks,
+    user_tx: Transaction,
+    block_info: &BlockInfo,
+    amount_in: U256,
+    legs: &[SwapLeg],
+) -> Result<(U256, U256, BackrunGas)> {
+    let (evm, _sim_permit) = forks.evm(block_info).await;
+    sim_arb_single(
+        evm,
+        user_tx,
+        &forks.following_txs,
+        block_info,
+        amount_in,
+        legs,
+        forks.v3_router,
+        forks.braindance.swap_gas_limit,
+    )
+    .await
+}
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn it_simulates_tx() -> Result<()> {
-        let client = get_test_ws_client().await?;
-        let tx_hash =
-            H256::from_str("0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a")
-                .unwrap();
-        let tx = client.get_transaction(tx_hash).await?.unwrap();
-        let block_num = tx.block_number.unwrap() - 1;
-        let mut evm = setup_test_evm(&client, block_num.as_u64()).await?;
-        let res = sim_bundle(&mut evm, vec![tx]).await;
-        assert!(res.is_ok());
-        let res = res.unwrap();
-        assert!(res[0].is_success());
-        Ok(())
-    }
+/// Re-simulates the backrun of a saved `result` at its `amount_in`, after `user_tx` on a new
+/// sandbox fork from `forks`. Returns the WETH balance it ends with, comparable to its `balance_end`.
+pub async fn resim_backrun(
+    forks: &SimForks,
+    user_tx: Transaction,
+    block_info: &BlockInfo,
+    result: &SimArbResult,
+) -> Result<U256> {
+    let backrun = &result.backrun_trade;
+    let (_, balance_end, _) = sim_arb_forked(
+        forks,
+        user_tx,
+        block_info,
+        backrun.amount_in,
+        &backrun.legs(&result.user_trade.tokens),
+    )
+    .await?;
+    Ok(balance_end)
+}
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn it_simulates_swaps() -> Result<()> {
-        let client = get_test_ws_client().await?;
-        let block_num = client.get_block_number().await?;
-        let mut evm = setup_test_evm(&client, block_num.as_u64() - 4).await?;
-        let weth = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2".parse::<Address>()?;
-        let tkn = "0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE".parse::<Address>()?; // SHIB (mainnet)
-        let pools = get_all_trading_pools(&client, (weth, tkn)).await?;
-        let gas_price = U256::from(1_000_000_000) * 420; // 420 gwei
-
-        // buy 69 ETH worth of SHIB on exchange 0
-        let res = commit_braindance_swap(
-            &mut evm,
-            pools[0].variant,
-            ETH * 69,
-            pools[0].address,
-            weth,
-            tkn,
-            gas_price,
+/// Simulate an arbitrage along `legs` on a forked EVM with a fixed trade amount, after
+/// the user's tx and `following_txs`.
+///
+/// 1. Buy `amount_in` WETH worth of token on the first leg's pool
+///
+/// 2. Swap the balance received on each following leg, selling for WETH on the last one to
+///    complete the arb.
+///
+/// Each swap may use up to `gas_limit`. Routes through Curve pools are swapped directly from the
+/// Curve trader (see `DirectSwaps`), w/ V3 legs going through `v3_router`.
+async fn sim_arb_single(
+    mut evm: EVM<ForkDB>,
+    user_tx: Transaction,
+    following_txs: &[Transaction],
+    block_info: &BlockInfo,
+    amount_in: U256,
+    legs: &[SwapLeg],
+    v3_router: Option<Address>,
+    gas_limit: u64,
+) -> Result<(U256, U256, BackrunGas)> {
+    let (first_leg, next_legs) = legs
+        .split_first()
+        .ok_or(anyhow::anyhow!("arb has no swaps"))?;
+    let direct = legs
+        .iter()
+        .any(|leg| leg.variant == PoolVariant::Curve)
+        .then(|| DirectSwaps {
+            trader: curve_trader(),
+            weth: first_leg.token_in,
+            v3_router,
+            gas_limit,
+        });
+    let swap = |evm: &mut EVM<ForkDB>, leg: &SwapLeg, amount_in: U256, base_fee: U256| match &direct
+    {
+        Some(direct) => direct.swap(evm, leg, amount_in, base_fee),
+        None => commit_braindance_swap(
+            evm,
+            leg.variant,
+            amount_in,
+            leg.pool,
+            leg.token_in,
+            leg.token_out,
+            base_fee,
             None,
-        )?;
-        assert!(res > 0.into());
-        // sell all the SHIB on exchange 1
-        let _ = commit_braindance_swap(
+            gas_limit,
+        ),
+    };
+    let user_res = commit_user_tx(&mut evm, user_tx.to_owned()).await?;
+    trace!(
+        "user tx {:?} execution result: {:?}",
+        user_tx.hash,
+        user_res
+    );
+    // txs that landed after the user's may fail here (e.g. if they depended on an earlier tx);
+    // they're simulated anyway, like they'd be onchain
+    for (tx, res) in following_txs
+        .iter()
+        .zip(sim_bundle(&mut evm, following_txs.to_vec()).await)
+    {
+        if let Err(err) = res {
+            debug!("following tx {:?} failed: {}", tx.hash, err);
+        }
+    }
+
+    /*
+    - price is always denoted in TKN/ETH, so we want to buy where the price is highest
+    */
+
+    /* Buy tokens on one exchange. */
+    let res = swap(&mut evm, first_leg, amount_in, block_info.base_fee);
+    match &res {
+        Ok(res) => debug!("braindance 1 completed. {:?}", res),
+        Err(err) => debug!("braindance 1 failed: {}", err),
+    }
+    let (mut amount_received, buy_gas_used) = res
+        .map(|res| (res.balance, res.gas_used))
+        .unwrap_or((0.into(), 0));
+    debug!("amount received {:?}", amount_received);
+
+    /* Swap them along the rest of the route, selling them on the last exchange. */
+    let mut sell_gas_used = 0;
+    for (i, leg) in next_legs.iter().enumerate() {
+        let res = swap(
             &mut evm,
-            pools[1].variant,
-            res,
-            pools[1].address,
-            tkn,
-            weth,
-            gas_price,
-            None,
+            leg,
+            amount_received,
+            block_info.base_fee + (block_info.base_fee * 2500) / 10000,
+        )
+        .map_err(|err| {
+            debug!("braindance {} failed: {}", i + 2, err);
+            err
+        })?;
+        debug!("braindance {} completed. {:?}", i + 2, res);
+        amount_received = res.balance;
+        sell_gas_used += res.gas_used;
+    }
+    let gas = BackrunGas {
+        buy: buy_gas_used,
+        sell: sell_gas_used,
+    };
+    Ok((amount_in, amount_received, gas))
+}
+
+/// Entry points for `benches/arb_search.rs`, which times the search's internals on recorded
+/// state instead of a node.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod bench {
+    use super::*;
+    use crate::util::get_block_info;
+    use serde::{Deserialize, Serialize};
+
+    /// An arb search to benchmark: the user's tx, its trade params, the pools to arb between, and
+    /// the fork state that searching it touches.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct ArbFixture {
+        pub tx: Transaction,
+        pub params: UserTradeParams,
+        pub start_pool: (Address, PoolVariant),
+        pub end_pool: (Address, PoolVariant),
+        pub snapshot: StateSnapshot,
+    }
+
+    /// The search configs to benchmark `step_arb` with, by name.
+    pub fn search_configs() -> Vec<(&'static str, SearchConfig)> {
+        let config = |depth, intervals| {
+            SearchConfig::new(depth, intervals, U256::zero()).expect("valid search config")
+        };
+        vec![
+            ("default", SearchConfig::default()),
+            ("shallow", config(2, DEFAULT_STEP_INTERVALS)),
+            ("coarse", config(DEFAULT_MAX_DEPTH, 5)),
+            ("fine", config(DEFAULT_MAX_DEPTH, 30)),
+        ]
+    }
+
+    impl ArbFixture {
+        /// Records a fixture for the arb between the pool of `event`'s first trade & its first arb
+        /// pool, running every search in `search_configs` to record the state they touch.
+        pub async fn record(
+            client: &RpcClient,
+            tx: Transaction,
+            event: &EventHistory,
+        ) -> Result<Self> {
+            let block_info = get_block_info(client, event.block - 1).await?;
+            let forks = Arc::new(SimForks::new(client, &block_info, None).await?);
+            let chain = ChainConfig::default();
+            let params = derive_trade_params(
+                client,
+                &chain,
+                Some(&*forks),
+                &block_info,
+                tx.clone(),
+                event,
+                &EventContext::default(),
+            )
+            .await?
+            .into_iter()
+            .find(|params| !params.arb_pools.is_empty())
+            .ok_or::<Error>(HindsightError::NoArbPool(event.hint.logs[0].address).into())?;
+            let other_pool = &params.arb_pools[0];
+            let (mut evm, _) = forks.evm(&block_info).await;
+            let alt_price = sim_pool_price(
+                &mut evm,
+                other_pool.address,
+                other_pool.variant,
+                (params.token_in, params.token_out),
+                params.tokens.weth,
+            )
+            .await?;
+            // same direction as `find_optimal_backrun_amount_in_out`
+            let user_pool = (params.pool, params.pool_variant);
+            let other_pool = (other_pool.address, other_pool.variant);
+            let (start_pool, end_pool) = if params.price > alt_price {
+                (user_pool, other_pool)
+            } else {
+                (other_pool, user_pool)
+            };
+
+            let mut fixture = Self {
+                tx,
+                params,
+                start_pool,
+                end_pool,
+                snapshot: StateSnapshot::new(&block_info),
+            };
+            let recording = forks.start_recording(&block_info);
+            let mut res = Ok(());
+            for (_, search) in search_configs() {
+                res = fixture.step_arb(forks.clone(), search).await.map(|_| ());
+                if res.is_err() {
+                    break;
+                }
+            }
+            let recorded = forks.finish_recording(recording, &block_info);
+            res?;
+            fixture.snapshot = recorded?;
+            Ok(fixture)
+        }
+
+        /// Forks the fixture's recorded state.
+        pub fn forks(&self) -> Arc<SimForks> {
+            Arc::new(SimForks::from_snapshot(&self.snapshot, None, vec![]))
+        }
+
+        /// Simulates one arb of `amount_in` WETH, like each interval of a search.
+        /// Returns the WETH it ends with.
+        pub async fn sim_arb(&self, forks: &SimForks, amount_in: U256) -> Result<U256> {
+            let block_info = self.snapshot.block_info();
+            let (_, balance_out, _) = sim_arb_forked(
+                forks,
+                self.tx.to_owned(),
+                &block_info,
+                amount_in,
+                &self.legs(),
+            )
+            .await?;
+            Ok(balance_out)
+        }
+
+        /// Searches for the best arb with `search`, over all of `search.max_amount_in`.
+        /// Returns the best amount in & the WETH it ends with.
+        pub async fn step_arb(
+            &self,
+            forks: Arc<SimForks>,
+            search: SearchConfig,
+        ) -> Result<(U256, U256)> {
+            let (amount_in, balance_out, _, _) = step_arb(
+                forks,
+                self.tx.to_owned(),
+                self.snapshot.block_info(),
+                self.params.to_owned(),
+                [0.into(), search.max_amount_in],
+                search,
+                SearchContext::default(),
+                self.legs(),
+            )
+            .await?;
+            Ok((amount_in, balance_out))
+        }
+
+        /// The swaps of the fixture's arb.
+        fn legs(&self) -> Vec<SwapLeg> {
+            SwapLeg::round_trip(&self.params.tokens, self.start_pool, self.end_pool)
+        }
+    }
+}
+
+/// Fixtures of `derive_trade_params`: a tx's receipt & the calls deriving its trade params makes,
+/// recorded from a node so its tests don't need one. See `fixtures/derive_trade_params`, and
+/// `examples/record_trade_fixture.rs`, which records them.
+#[cfg(any(test, feature = "test-utils"))]
+pub mod trade_fixture {
+    use super::*;
+    use crate::client::{FailoverError, RpcTransportError};
+    use async_trait::async_trait;
+    use ethers::providers::{JsonRpcClient, MockError};
+    use ethers::types::Bytes;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use serde_json::Value;
+    use std::fmt::Debug;
+
+    /// A tx's MEV-Share event & receipt, and the `eth_call`s needed to derive its trade params.
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct TradeFixture {
+        pub event: EventHistory,
+        pub receipt: Value,
+        pub calls: Vec<FixtureCall>,
+    }
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    pub struct FixtureCall {
+        pub to: Address,
+        pub data: Bytes,
+        pub result: Bytes,
+    }
+
+    impl TradeFixture {
+        /// Records the fixture of the tx `tx_hash` from `client`. Its event hints the tx's swap
+        /// logs as MEV-Share does: by pool & event signature, w/o their data.
+        ///
+        /// Lookups are cached for the whole process, so record each fixture in a fresh one, or the
+        /// calls of lookups made before aren't recorded.
+        pub async fn record(client: &RpcClient, tx_hash: H256) -> Result<Self> {
+            let receipt = client
+                .get_transaction_receipt(tx_hash)
+                .await?
+                .ok_or(anyhow::anyhow!("no receipt for tx {:?}", tx_hash))?;
+            let block = receipt
+                .block_number
+                .ok_or(anyhow::anyhow!("tx {:?} is pending", tx_hash))?;
+            let timestamp = client
+                .get_block(block)
+                .await?
+                .ok_or(anyhow::anyhow!("block {} not found", block))?
+                .timestamp;
+            let swap_topics = [
+                // Swap(address,address,int256,int256,uint160,uint128,int24)
+                H256::from_str(
+                    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
+                )?,
+                // Swap(address,uint256,uint256,uint256,uint256,address)
+                H256::from_str(
+                    "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822",
+                )?,
+            ];
+            let event = EventHistory {
+                block: block.as_u64(),
+                timestamp: timestamp.as_u64(),
+                hint: Hint {
+                    txs: vec![],
+                    hash: tx_hash,
+                    logs: receipt
+                        .logs
+                        .iter()
+                        .filter(|log| {
+                            log.topics
+                                .first()
+                                .map_or(false, |topic| swap_topics.contains(topic))
+                        })
+                        .map(|log| EventTransactionLog {
+                            address: log.address,
+                            topics: vec![log.topics[0]],
+                            data: Default::default(),
+                        })
+                        .collect(),
+                    gas_used: None,
+                    mev_gas_price: None,
+                },
+            };
+
+            let recorder = Arc::new(Provider::new(RecordingTransport {
+                client: client.clone(),
+                receipt: Default::default(),
+                calls: Default::default(),
+            }));
+            let (tx, block_info, chain) = replay_inputs(&event);
+            derive_trade_params(
+                &recorder,
+                &chain,
+                None,
+                &block_info,
+                tx,
+                &event,
+                &EventContext::default(),
+            )
+            .await?;
+            let transport = (*recorder).as_ref();
+            let receipt = transport
+                .receipt
+                .lock()
+                .expect("recorded receipt poisoned")
+                .to_owned();
+            let calls = transport
+                .calls
+                .lock()
+                .expect("recorded calls poisoned")
+                .to_owned();
+            Ok(Self {
+                event,
+                receipt,
+                calls,
+            })
+        }
+
+        /// Derives the trade params of the fixture's tx, without a node.
+        pub async fn trade_params(self, ctx: &EventContext) -> Result<Vec<UserTradeParams>> {
+            let event = self.event.to_owned();
+            let client = Arc::new(Provider::new(FixtureTransport { fixture: self }));
+            let (tx, block_info, chain) = replay_inputs(&event);
+            derive_trade_params(&client, &chain, None, &block_info, tx, &event, ctx).await
+        }
+    }
+
+    /// The tx, block & chain that a fixture's trade params are derived with, both when it's
+    /// recorded & when it's replayed, so they make the same calls.
+    fn replay_inputs(event: &EventHistory) -> (Transaction, BlockInfo, ChainConfig) {
+        let tx = Transaction {
+            hash: event.hint.hash,
+            block_number: Some(event.block.into()),
+            ..Default::default()
+        };
+        let block_info = BlockInfo {
+            number: (event.block - 1).into(),
+            timestamp: event.timestamp.into(),
+            base_fee: 0.into(),
+        };
+        // the fixture has no multicall (or Curve pool) responses
+        let chain = ChainConfig {
+            multicall: None,
+            curve_pools: vec![],
+            ..Default::default()
+        };
+        (tx, block_info, chain)
+    }
+
+    /// The address & calldata of an `eth_call`'s tx.
+    fn call_of(tx: &Value) -> serde_json::Result<(Address, Bytes)> {
+        let to = serde_json::from_value(tx["to"].to_owned())?;
+        let data = serde_json::from_value(
+            tx.get("data")
+                .or(tx.get("input"))
+                .cloned()
+                .unwrap_or_default(),
         )?;
+        Ok((to, data))
+    }
+
+    /// Answers requests from a `TradeFixture`: the tx's receipt, and calls matching its `calls`
+    /// by address & calldata. Anything else fails (e.g. `eth_chainId`, so multicall isn't used).
+    #[derive(Debug)]
+    struct FixtureTransport {
+        fixture: TradeFixture,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for FixtureTransport {
+        type Error = MockError;
+
+        async fn request<P, R>(&self, method: &str, params: P) -> Result<R, MockError>
+        where
+            P: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            let params = serde_json::to_value(params)?;
+            let response = match method {
+                "eth_getTransactionReceipt" => self.fixture.receipt.to_owned(),
+                "eth_call" => {
+                    let (to, data) = call_of(&params[0])?;
+                    let call = self
+                        .fixture
+                        .calls
+                        .iter()
+                        .find(|call| call.to == to && call.data == data)
+                        .ok_or(MockError::EmptyResponses)?;
+                    serde_json::to_value(&call.result)?
+                }
+                _ => return Err(MockError::EmptyResponses),
+            };
+            Ok(serde_json::from_value(response)?)
+        }
+    }
+
+    /// Forwards requests to a node, keeping the receipt & the calls a `TradeFixture` needs.
+    #[derive(Debug)]
+    struct RecordingTransport {
+        client: RpcClient,
+        receipt: Mutex<Value>,
+        calls: Mutex<Vec<FixtureCall>>,
+    }
+
+    #[async_trait]
+    impl JsonRpcClient for RecordingTransport {
+        type Error = FailoverError<RpcTransportError>;
+
+        async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+        where
+            P: Debug + Serialize + Send + Sync,
+            R: DeserializeOwned + Send,
+        {
+            let params = serde_json::to_value(params).map_err(FailoverError::Serialize)?;
+            let response: Value = (*self.client).as_ref().request(method, &params).await?;
+            match method {
+                "eth_getTransactionReceipt" => {
+                    *self.receipt.lock().expect("recorded receipt poisoned") = response.to_owned()
+                }
+                "eth_call" => {
+                    let (to, data) = call_of(&params[0]).map_err(FailoverError::Serialize)?;
+                    let result = serde_json::from_value(response.to_owned())
+                        .map_err(FailoverError::Serialize)?;
+                    let mut calls = self.calls.lock().expect("recorded calls poisoned");
+                    if !calls.iter().any(|call| call.to == to && call.data == data) {
+                        calls.push(FixtureCall { to, data, result });
+                    }
+                }
+                _ => {}
+            }
+            serde_json::from_value(response).map_err(FailoverError::Serialize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sim::core::trade_fixture::{FixtureCall, TradeFixture};
+    use crate::sim::evm::commit_tx;
+    use crate::sim::fork_cache::ForkCacheStats;
+    use crate::util::{get_all_trading_pools, get_block_info, test::get_test_client, ETH, WETH};
+    use anyhow::Result;
+    use ethers::providers::{Middleware, Provider};
+    use ethers::types::{Bytes, U64};
+
+    async fn setup_test_evm(client: &RpcClient, block_num: u64) -> Result<EVM<ForkDB>> {
+        let block_info = get_block_info(&client, block_num).await?;
+        fork_evm(
+            &client,
+            &block_info,
+            &ChainConfig::default(),
+            &Braindance::default(),
+        )
+        .await
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_simulates_tx() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, _, block_info, _) = usdc_weth_fixture(&client).await?;
+        let mut evm = setup_test_evm(&client, block_info.number.as_u64()).await?;
+        let res = sim_bundle(&mut evm, vec![tx]).await;
+        assert_eq!(res.len(), 1);
+        assert!(res[0].as_ref().map_or(false, |res| res.is_success()));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_aborts_search_when_user_tx_fails() -> Result<()> {
+        let client = get_test_client().await?;
+        let (mut tx, event, block_info, _) = usdc_weth_fixture(&client).await?;
+        let tx_hash = tx.hash;
+        // a nonce the sender hasn't reached yet, so the tx can't execute
+        tx.nonce += 1.into();
+
+        let mut evm = sandbox_evm(
+            &new_fork_factory(&client, &block_info, &vec![]).await?,
+            &block_info,
+        );
+        let res = sim_bundle(&mut evm, vec![tx.clone()]).await;
+        assert!(res[0].is_err());
+
+        let outcome = find_optimal_backrun_amount_in_out(
+            &client,
+            tx,
+            &event,
+            &block_info,
+            Default::default(),
+        )
+        .await?;
+        assert!(outcome.results.is_empty());
+        assert!(!outcome.failures.is_empty());
+        assert!(outcome.failures.iter().all(|(_, err)| matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::UserTxFailed { tx_hash: hash, .. }) if *hash == tx_hash
+        )));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_simulates_access_list_tx_gas() -> Result<()> {
+        let client = get_test_client().await?;
+        // the first successful type-2 tx that declares an access list in a fixed (historical)
+        // range of blocks, so it's always the same tx
+        let blocks = 17637010u64..=17637019;
+        let mut found = None;
+        'blocks: for block_num in blocks.clone() {
+            let block = client
+                .get_block_with_txs(block_num)
+                .await?
+                .ok_or(anyhow::anyhow!("block {} not found", block_num))?;
+            for tx in block.transactions {
+                let has_access_list = tx
+                    .access_list
+                    .as_ref()
+                    .map(|list| !list.0.is_empty())
+                    .unwrap_or(false);
+                if tx.transaction_type != Some(2.into()) || !has_access_list {
+                    continue;
+                }
+                let receipt = client
+                    .get_transaction_receipt(tx.hash)
+                    .await?
+                    .ok_or(anyhow::anyhow!("no receipt for {:?}", tx.hash))?;
+                if receipt.status == Some(1.into()) {
+                    found = Some((tx, receipt, block.base_fee_per_gas.unwrap_or_default()));
+                    break 'blocks;
+                }
+            }
+        }
+        let (tx, receipt, base_fee) =
+            found.ok_or(anyhow::anyhow!("no access-list txs in blocks {:?}", blocks))?;
+
+        // fork right before the tx, in its own block's env
+        let (preceding_txs, _) = placement_txs(&client, &tx, Placement::InBlock).await?;
+        let block_info = get_block_info(&client, tx.block_number.unwrap().as_u64() - 1).await?;
+        let fork_factory = new_fork_factory(&client, &block_info, &preceding_txs).await?;
+        let mut evm = sandbox_evm(&fork_factory, &block_info);
+        evm.env.block.basefee = base_fee.into();
+
+        let res = commit_tx(&mut evm, tx).await?;
+        assert!(res.is_success());
+        let gas_used = res.gas_used();
+        let onchain_gas_used = receipt.gas_used.unwrap().as_u64();
+        let diff = gas_used.abs_diff(onchain_gas_used);
+        assert!(
+            diff * 100 <= onchain_gas_used * 3,
+            "{:?} used {} gas, but {} onchain",
+            receipt.transaction_hash,
+            gas_used,
+            onchain_gas_used
+        );
+        Ok(())
+    }
+
+    /// Event for a tx that swapped on the USDC/WETH 0.05% UniswapV3 pool.
+    fn usdc_weth_v3_event(tx: &Transaction) -> Result<EventHistory> {
+        Ok(EventHistory {
+            block: tx.block_number.unwrap().as_u64(),
+            timestamp: 0,
+            hint: mev_share_sse::Hint {
+                txs: vec![],
+                hash: tx.hash,
+                logs: vec![EventTransactionLog {
+                    address: "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640".parse()?,
+                    topics: vec![H256::from_str(
+                        "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
+                    )?],
+                    data: Default::default(),
+                }],
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        })
+    }
+
+    /// A tx that swapped on the USDC/WETH 0.05% UniswapV3 pool, w/ its event, the info of the
+    /// block before it (which sims run on top of), and forks of the state at the top of its block.
+    async fn usdc_weth_fixture(
+        client: &RpcClient,
+    ) -> Result<(Transaction, EventHistory, BlockInfo, Arc<SimForks>)> {
+        let tx_hash =
+            H256::from_str("0xf00df02ad86f04a8b32d9f738394ee1b7ff791647f753923c60522363132f84a")?;
+        let tx = client.get_transaction(tx_hash).await?.unwrap();
+        let event = usdc_weth_v3_event(&tx)?;
+        let block_info = get_block_info(client, event.block - 1).await?;
+        let forks = Arc::new(SimForks::new(client, &block_info, None).await?);
+        Ok((tx, event, block_info, forks))
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn depth_of_one_runs_one_grid_pass() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, forks) = usdc_weth_fixture(&client).await?;
+        let chain = ChainConfig::default();
+        let params = derive_trade_params(
+            &client,
+            &chain,
+            Some(&*forks),
+            &block_info,
+            tx.clone(),
+            &event,
+            &EventContext::default(),
+        )
+        .await?
+        .remove(0);
+        let other_pool = params.arb_pools[0].to_owned();
+        let sims_before = forks.num_sims();
+
+        let search = SearchConfig::new(1, 5, U256::zero())?;
+        step_arb(
+            forks.clone(),
+            tx,
+            block_info,
+            params.to_owned(),
+            [0.into(), braindance_starting_balance()],
+            search,
+            SearchContext::default(),
+            SwapLeg::round_trip(
+                &params.tokens,
+                (params.pool, params.pool_variant),
+                (other_pool.address, other_pool.variant),
+            ),
+        )
+        .await?;
+        assert_eq!(forks.num_sims() - sims_before, search.intervals);
+        Ok(())
+    }
+
+    /// A sim whose profit peaks at `peak` wei of WETH in, falling off linearly to zero either side.
+    fn peaked_sim(peak: U256) -> BackrunSim {
+        Arc::new(move |amount_in: U256| {
+            let distance = if amount_in > peak {
+                amount_in - peak
+            } else {
+                peak - amount_in
+            };
+            let balance_out = braindance_starting_balance() + peak.saturating_sub(distance);
+            async move { Ok::<_, Error>((amount_in, balance_out, BackrunGas::default())) }.boxed()
+        })
+    }
+
+    #[tokio::test]
+    async fn grid_search_expands_range_past_best_amount() -> Result<()> {
+        let peak = U256::exp10(18) * 30;
+        let range = [0.into(), U256::exp10(19)];
+        let (amount_in, _, _, bound) = grid_search(
+            peaked_sim(peak),
+            braindance_starting_balance(),
+            None,
+            range,
+            SearchConfig::default(),
+            SearchContext::default(),
+            None,
+        )
+        .await?;
+        // found within 0.1% of the peak, past the initial range
+        let diff = if amount_in > peak {
+            amount_in - peak
+        } else {
+            peak - amount_in
+        };
+        assert!(
+            diff <= peak / 1000,
+            "found {} instead of {}",
+            amount_in,
+            peak
+        );
+        assert_eq!(bound, SearchBound::Interior);
+        Ok(())
+    }
+
+    #[test]
+    fn it_picks_refinement_amounts_around_the_best_amount() {
+        let refine = Refinement {
+            min_profit: U256::zero(),
+            points: 5,
+            window_bps: 200,
+        };
+        let amount_in = U256::exp10(18) * 10;
+        let tenths = |n: u64| U256::exp10(17) * n;
+        assert_eq!(
+            refine.amounts(amount_in, U256::MAX),
+            vec![
+                tenths(98),
+                tenths(99),
+                tenths(100),
+                tenths(101),
+                tenths(102)
+            ]
+        );
+        // never past the max
+        let amounts = refine.amounts(amount_in, amount_in);
+        assert_eq!(amounts.first(), Some(&tenths(98)));
+        assert_eq!(amounts.last(), Some(&amount_in));
+        assert!(refine.amounts(U256::zero(), U256::MAX).is_empty());
+    }
+
+    #[tokio::test]
+    async fn refinement_finds_amounts_between_the_search_steps() -> Result<()> {
+        // 10.07 ETH
+        let peak = U256::exp10(15) * 10_070;
+        let sim = peaked_sim(peak);
+        let refine = Refinement {
+            min_profit: U256::zero(),
+            points: 51,
+            window_bps: 200,
+        };
+        let coarse_amount_in = U256::exp10(18) * 10;
+        let coarse = sim(coarse_amount_in).await?;
+        let amounts = refine.amounts(coarse_amount_in, U256::MAX);
+        let (amount_in, balance_out, _) = refine_search(sim.clone(), coarse, amounts).await;
+        // 0.008 ETH steps from 9.8 ETH; the closest to the peak is 10.072 ETH
+        assert_eq!(amount_in, U256::exp10(15) * 10_072);
+        assert!(balance_out > coarse.1);
+
+        // the search's amount is kept if nothing around it is better
+        let best = sim(peak).await?;
+        let amounts = refine.amounts(coarse_amount_in, U256::MAX);
+        assert_eq!(refine_search(sim, best, amounts).await, best);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn grid_search_reports_range_clamped_by_max_amount_in() -> Result<()> {
+        let search = SearchConfig {
+            max_amount_in: U256::exp10(18) * 50,
+            ..Default::default()
+        };
+        let range = [0.into(), U256::exp10(19)];
+        let (amount_in, _, _, bound) = grid_search(
+            peaked_sim(U256::exp10(20)),
+            braindance_starting_balance(),
+            None,
+            range,
+            search,
+            SearchContext::default(),
+            None,
+        )
+        .await?;
+        assert!(amount_in <= search.max_amount_in);
+        assert!(amount_in > search.max_amount_in * 99 / 100);
+        assert_eq!(bound, SearchBound::Upper);
+
+        // no amount is profitable
+        let (amount_in, _, _, bound) = grid_search(
+            peaked_sim(0.into()),
+            braindance_starting_balance(),
+            None,
+            range,
+            search,
+            SearchContext::default(),
+            None,
+        )
+        .await?;
+        assert_eq!(amount_in, 0.into());
+        assert_eq!(bound, SearchBound::Lower);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn grid_search_traces_every_sim() -> Result<()> {
+        let search = SearchConfig::new(3, 5, U256::zero())?;
+        let trace = Arc::new(Mutex::new(SearchTrace::default()));
+        grid_search(
+            peaked_sim(ETH * 3),
+            braindance_starting_balance(),
+            None,
+            [0.into(), ETH * 10],
+            search,
+            SearchContext::new(CancellationToken::new(), None, Some(trace.clone())),
+            None,
+        )
+        .await?;
+        let samples = trace.lock().unwrap().samples.to_owned();
+        assert_eq!(samples.len(), search.intervals * search.max_depth);
+        for depth in 0..search.max_depth {
+            let pass = samples.iter().filter(|sample| sample.depth == depth);
+            assert_eq!(pass.count(), search.intervals);
+        }
+        // the first pass spans the whole range
+        let first_pass = samples[..search.intervals]
+            .iter()
+            .map(|sample| sample.amount_in)
+            .collect::<Vec<_>>();
+        assert_eq!(first_pass, [0, 2, 4, 6, 8].map(|eth| ETH * eth));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn grid_search_stops_when_cancelled() {
+        let cancel = CancellationToken::new();
+        let num_sims = Arc::new(AtomicUsize::new(0));
+        let sim: BackrunSim = {
+            let cancel = cancel.clone();
+            let num_sims = num_sims.clone();
+            let sim = peaked_sim(ETH);
+            // cancels the search as soon as its first sim starts
+            Arc::new(move |amount_in: U256| {
+                num_sims.fetch_add(1, Ordering::Relaxed);
+                cancel.cancel();
+                sim(amount_in)
+            })
+        };
+        let range = [0.into(), ETH * 10];
+        let res = grid_search(
+            sim,
+            braindance_starting_balance(),
+            None,
+            range,
+            SearchConfig::default(),
+            SearchContext::new(cancel, None, None),
+            None,
+        )
+        .await;
+        assert!(res.is_err());
+        assert_eq!(num_sims.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn search_finished_before_deadline_isnt_truncated() -> Result<()> {
+        let deadline = Instant::now() + Duration::from_millis(200);
+        let ctx = SearchContext::new(CancellationToken::new(), Some(deadline), None);
+        grid_search(
+            peaked_sim(ETH * 3),
+            braindance_starting_balance(),
+            None,
+            [0.into(), ETH * 10],
+            SearchConfig::new(2, 5, U256::zero())?,
+            ctx.clone(),
+            None,
+        )
+        .await?;
+        assert!(
+            !ctx.past_deadline(),
+            "the search should finish well before its deadline"
+        );
+
+        // the deadline passes while its result is built (e.g. before refining it)
+        tokio::time::sleep_until(deadline.into()).await;
+        assert!(ctx.past_deadline());
+        assert!(!ctx.truncated());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn grid_search_returns_best_amount_once_past_deadline() -> Result<()> {
+        let search = SearchConfig::new(3, 5, U256::zero())?;
+        let trace = Arc::new(Mutex::new(SearchTrace::default()));
+        // the deadline has already passed, so only the first pass runs
+        let ctx = SearchContext::new(
+            CancellationToken::new(),
+            Some(Instant::now()),
+            Some(trace.clone()),
+        );
+        let (amount_in, balance_out, _, _) = grid_search(
+            peaked_sim(ETH * 3),
+            braindance_starting_balance(),
+            None,
+            [0.into(), ETH * 10],
+            search,
+            ctx.clone(),
+            None,
+        )
+        .await?;
+        assert!(ctx.truncated());
+        assert_eq!(trace.lock().unwrap().samples.len(), search.intervals);
+        // the first pass's best amount, on its 2 ETH grid
+        assert_eq!(amount_in, ETH * 2);
+        assert!(balance_out > braindance_starting_balance());
+        Ok(())
+    }
+
+    #[test]
+    fn it_values_token_amounts_in_weth() {
+        let max = ETH * 1000;
+        // 3000 USDC (6 decimals) at 3000 USDC/WETH
+        let usdc = U256::from(3000) * U256::exp10(6);
+        assert_eq!(token_amount_in_weth(usdc, 6.into(), ETH * 3000, max), ETH);
+        // 1e12 meme tokens (18 decimals) at 1e12 TKN/WETH
+        let meme = U256::exp10(30);
+        let price = U256::exp10(30);
+        assert_eq!(token_amount_in_weth(meme, 18.into(), price, max), ETH);
+        // a huge amount of a token that's nearly worthless would overflow 256-bit math
+        let price = U256::from(1);
+        assert_eq!(
+            token_amount_in_weth(U256::MAX / 2, 18.into(), price, max),
+            max
+        );
+        // ... as would a huge amount of a big-decimals token
+        assert_eq!(token_amount_in_weth(U256::MAX, 24.into(), ETH, max), max);
+        // no price, no idea
+        assert_eq!(token_amount_in_weth(meme, 18.into(), 0.into(), max), max);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_reports_gas_used_by_each_leg() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, _) = usdc_weth_fixture(&client).await?;
+        let outcome = find_optimal_backrun_amount_in_out(
+            &client,
+            tx,
+            &event,
+            &block_info,
+            SimOptions::default(),
+        )
+        .await?;
+        // V3 -> V2 (or V2 -> V3)
+        let backrun = outcome
+            .results
+            .iter()
+            .map(|res| &res.backrun_trade)
+            .find(|backrun| backrun.start_variant != backrun.end_variant && backrun.gas_used > 0)
+            .expect("should simulate an arb against the USDC/WETH V2 pair");
+        assert!(backrun.gas_used > 100_000 && backrun.gas_used < 400_000);
+        assert_eq!(
+            backrun.gas_used,
+            backrun.gas_used_buy + backrun.gas_used_sell
+        );
+        assert!(backrun.gas_used_buy > 0 && backrun.gas_used_sell > 0);
+        assert_eq!(backrun.effective_gas_price, block_info.base_fee);
+        Ok(())
+    }
+
+    #[test]
+    fn it_validates_search_config() {
+        assert!(SearchConfig::new(0, DEFAULT_STEP_INTERVALS, U256::zero()).is_err());
+        assert!(SearchConfig::new(DEFAULT_MAX_DEPTH, 1, U256::zero()).is_err());
+        assert!(SearchConfig::new(1, 2, U256::zero()).is_ok());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_arbs_between_v3_fee_tiers() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, _) = usdc_weth_fixture(&client).await?;
+
+        let outcome = find_optimal_backrun_amount_in_out(
+            &client,
+            tx,
+            &event,
+            &block_info,
+            SimOptions::default(),
+        )
+        .await?;
+        let v3_arb = outcome
+            .results
+            .iter()
+            .map(|res| &res.backrun_trade)
+            .find(|backrun| {
+                backrun.start_variant == PoolVariant::UniswapV3
+                    && backrun.end_variant == PoolVariant::UniswapV3
+            })
+            .expect("should simulate an arb against another USDC/WETH fee tier");
+        assert_ne!(v3_arb.start_pool, v3_arb.end_pool);
+        assert!(v3_arb.balance_end > 0.into());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn sim_limit_doesnt_change_results() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, _) = usdc_weth_fixture(&client).await?;
+
+        let mut results = vec![];
+        for limit in [1, 16] {
+            let options = SimOptions {
+                sim_permits: Some(Arc::new(Semaphore::new(limit))),
+                ..Default::default()
+            };
+            let res = find_optimal_backrun_amount_in_out(
+                &client,
+                tx.clone(),
+                &event,
+                &block_info,
+                options,
+            )
+            .await?
+            .results;
+            let mut res = res
+                .into_iter()
+                .map(|res| {
+                    let backrun = res.backrun_trade;
+                    (backrun.end_pool, backrun.amount_in, backrun.profit)
+                })
+                .collect::<Vec<_>>();
+            res.sort();
+            results.push(res);
+        }
+        assert_eq!(results[0], results[1]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn fork_cache_doesnt_change_results() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, _) = usdc_weth_fixture(&client).await?;
+
+        let fork_cache = Arc::new(BlockForkCache::new(1));
+        for placement in [Placement::TopOfBlock, Placement::InBlock] {
+            let mut results = vec![];
+            for fork_cache in [None, Some(fork_cache.clone())] {
+                let options = SimOptions {
+                    placement,
+                    fork_cache,
+                    ..Default::default()
+                };
+                let res = find_optimal_backrun_amount_in_out(
+                    &client,
+                    tx.clone(),
+                    &event,
+                    &block_info,
+                    options,
+                )
+                .await?
+                .results;
+                let mut res = res
+                    .into_iter()
+                    .map(|res| {
+                        let backrun = res.backrun_trade;
+                        (backrun.end_pool, backrun.amount_in, backrun.profit)
+                    })
+                    .collect::<Vec<_>>();
+                res.sort();
+                results.push(res);
+            }
+            assert_eq!(results[0], results[1], "{:?}", placement);
+        }
+        // the in-block sims layered the preceding txs' state on the top-of-block fork
+        assert_eq!(
+            fork_cache.stats(block_info.number.as_u64()),
+            Some(ForkCacheStats {
+                backends_built: 1,
+                reused: 1
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_collects_failures_separately() {
+        let ok_pool = Address::from_low_u64_be(1);
+        let bad_pool = Address::from_low_u64_be(2);
+        let outcome = ArbSearchOutcome::collect(vec![
+            (ok_pool, Ok(SimArbResult::test_example())),
+            (
+                bad_pool,
+                Err(HindsightError::SwapReverted {
+                    reason: "STF".to_owned(),
+                    output: Default::default(),
+                    gas_used: 0,
+                }
+                .into()),
+            ),
+        ]);
+        assert_eq!(outcome.results.len(), 1);
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].0, bad_pool);
+        assert!(matches!(
+            outcome.failures[0].1.downcast_ref::<HindsightError>(),
+            Some(HindsightError::SwapReverted { .. })
+        ));
+    }
+
+    /// Encodes `words` as the data of a swap log.
+    fn log_data(words: &[U256]) -> Vec<u8> {
+        let mut data = vec![0u8; 32 * words.len()];
+        for (i, word) in words.iter().enumerate() {
+            word.to_big_endian(&mut data[i * 32..(i + 1) * 32]);
+        }
+        data
+    }
+
+    #[test]
+    fn it_derives_v2_trade_direction() {
+        let token0 = Address::from_low_u64_be(0);
+        let token1 = Address::from_low_u64_be(1);
+
+        // user sends 1.5 token0, receives 2997 token1
+        let data = log_data(&[
+            U256::from(15) * ETH / 10,
+            0.into(),
+            0.into(),
+            U256::from(2997) * ETH,
+        ]);
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(PoolVariant::UniswapV2, &[&data]);
+        assert_eq!(amount0_sent, I256::from_raw(U256::from(15) * ETH / 10));
+        assert_eq!(amount1_sent, 0.into());
+        assert_eq!(
+            trade_direction(amount0_sent, (token0, token1)),
+            (token0, token1)
+        );
+
+        // user sends 2997 token1, receives 1.5 token0
+        let data = log_data(&[
+            0.into(),
+            U256::from(2997) * ETH,
+            U256::from(15) * ETH / 10,
+            0.into(),
+        ]);
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(PoolVariant::UniswapV2, &[&data]);
+        assert_eq!(amount0_sent, 0.into());
+        assert_eq!(amount1_sent, I256::from_raw(U256::from(2997) * ETH));
+        assert_eq!(
+            trade_direction(amount0_sent, (token0, token1)),
+            (token1, token0)
+        );
+    }
+
+    #[test]
+    fn it_derives_v3_trade_direction() {
+        let token0 = Address::from_low_u64_be(0);
+        let token1 = Address::from_low_u64_be(1);
+        // user sends 1.5 token0, receives 2997 token1 (negative amount)
+        let amount1 = I256::from_raw(U256::from(2997) * ETH);
+        let data = log_data(&[
+            U256::from(15) * ETH / 10,
+            (-amount1).into_raw(),
+            0.into(),
+            0.into(),
+        ]);
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(PoolVariant::UniswapV3, &[&data]);
+        assert_eq!(amount1_sent, 0.into());
+        assert_eq!(
+            trade_direction(amount0_sent, (token0, token1)),
+            (token0, token1)
+        );
+    }
+
+    #[test]
+    fn it_derives_curve_trade_direction() {
+        let token0 = Address::from_low_u64_be(0);
+        let token1 = Address::from_low_u64_be(1);
+        // user sells 2 of coin 1 for 1.99 of coin 0
+        let data = log_data(&[
+            1.into(),
+            U256::from(2) * ETH,
+            0.into(),
+            U256::from(199) * ETH / 100,
+        ]);
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(PoolVariant::Curve, &[&data]);
+        assert_eq!(amount0_sent, 0.into());
+        assert_eq!(amount1_sent, I256::from_raw(U256::from(2) * ETH));
+        assert_eq!(
+            trade_direction(amount0_sent, (token0, token1)),
+            (token1, token0)
+        );
+    }
+
+    #[test]
+    fn it_rejects_short_swap_logs_without_panicking() {
+        let pool = Address::from_low_u64_be(1);
+        for pool_variant in [
+            PoolVariant::UniswapV2,
+            PoolVariant::UniswapV3,
+            PoolVariant::Curve,
+        ] {
+            let min_len = swap_log_words(pool_variant) * 32;
+            // truncated, canonical & oversized data
+            for len in 0..=min_len + 64 {
+                let data = (0..len).map(|i| (i as u8) ^ 0xa5).collect::<Vec<_>>();
+                match check_swap_log(pool, pool_variant, &data) {
+                    Ok(()) => {
+                        assert!(len >= min_len, "{:?} log of {} bytes", pool_variant, len);
+                        parse_amounts_sent(pool_variant, &[&data]);
+                        for i in 0..swap_log_words(pool_variant) {
+                            swap_log_word(&data, i);
+                        }
+                    }
+                    Err(err) => {
+                        assert!(len < min_len, "{:?} log of {} bytes", pool_variant, len);
+                        assert!(matches!(
+                            err.downcast_ref::<HindsightError>(),
+                            Some(HindsightError::MalformedSwapLog { len: short, .. }) if *short == len
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_marks_decisions_made_without_hinted_data() {
+        let (v2_pool, v3_pool) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let params = |pool, pool_variant| UserTradeParams {
+            pool,
+            pool_variant,
+            ..SimArbResult::test_example().user_trade
+        };
+        let log = |address, topic: H256, data: &[u8]| EventTransactionLog {
+            address,
+            topics: vec![topic],
+            data: data.to_vec().into(),
+        };
+        let swap_topic = H256::from_low_u64_be(0x5a);
+        let sync_topic = H256::from(keccak256("Sync(uint112,uint112)"));
+        let mut hint = Hint {
+            txs: vec![],
+            hash: H256::zero(),
+            logs: vec![log(v2_pool, swap_topic, &[]), log(v3_pool, swap_topic, &[])],
+            gas_used: None,
+            mev_gas_price: None,
+        };
+        let params = [
+            params(v2_pool, PoolVariant::UniswapV2),
+            params(v3_pool, PoolVariant::UniswapV3),
+            // pools swapped on more than once are only marked once
+            params(v3_pool, PoolVariant::UniswapV3),
+        ];
+        // w/o any hinted data, everything's read from the receipt
+        assert_eq!(
+            non_hinted_decisions(&hint, &params),
+            vec![
+                NonHintedDecision::TradeDirection { pool: v2_pool },
+                NonHintedDecision::PoolPrice { pool: v2_pool },
+                NonHintedDecision::TradeDirection { pool: v3_pool },
+                NonHintedDecision::PoolPrice { pool: v3_pool },
+            ]
+        );
+
+        // a V2 pool's price comes from its Sync log; a V3 pool's from its swap log
+        hint.logs = vec![
+            log(v2_pool, sync_topic, &[1]),
+            log(v3_pool, swap_topic, &[1]),
+        ];
+        assert_eq!(
+            non_hinted_decisions(&hint, &params),
+            vec![NonHintedDecision::TradeDirection { pool: v2_pool }]
+        );
+    }
+
+    #[test]
+    fn it_reads_v2_price_from_last_sync_log() -> Result<()> {
+        let pool = Address::from_low_u64_be(1);
+        let sync_topic =
+            H256::from_str("0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1")?;
+        let decimals = (U256::from(18), U256::from(18));
+        let sync_log = |reserve0: U256, reserve1: U256| Log {
+            address: pool,
+            topics: vec![sync_topic],
+            data: log_data(&[reserve0, reserve1]).into(),
+            ..Default::default()
+        };
+        let logs = vec![
+            sync_log(ETH * 100, ETH * 1000),
+            sync_log(ETH * 100, ETH * 2000),
+        ];
+        assert_eq!(
+            sync_price_v2(&logs, pool, decimals, true)?,
+            Some(get_price_v2(ETH * 100, ETH * 2000, decimals, true)?)
+        );
+
+        // a receipt w/o a Sync log for the pool has no price
+        let logs = vec![Log {
+            address: Address::from_low_u64_be(2),
+            topics: vec![sync_topic],
+            data: log_data(&[ETH, ETH]).into(),
+            ..Default::default()
+        }];
+        assert_eq!(sync_price_v2(&logs, pool, decimals, true)?, None);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_falls_back_to_v2_reserves_after_user_tx() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, _, block_info, forks) = usdc_weth_fixture(&client).await?;
+        let usdc = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".parse::<Address>()?;
+        let v2_pool = get_all_trading_pools(&client, &ChainConfig::default(), (WETH, usdc))
+            .await?
+            .into_iter()
+            .find(|pool| pool.dex == Some(Dex::UniswapV2))
+            .expect("USDC/WETH pool should exist on UniswapV2");
+
+        // the user's tx doesn't emit a Sync log for this pool
+        let (token0, token1) = get_pair_tokens(&client, v2_pool.address).await?;
+        let price =
+            sim_post_trade_price_v2(&forks, &block_info, &tx, v2_pool.address, (token0, token1))
+                .await?;
+        // USDC per WETH, around the time of the tx
+        assert!(price > U256::from(1000) * ETH && price < U256::from(5000) * ETH);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_finds_txs_around_user_tx() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, ..) = usdc_weth_fixture(&client).await?;
+        let tx_index = tx.transaction_index.unwrap().as_usize();
+
+        let (preceding, following) = placement_txs(&client, &tx, Placement::TopOfBlock).await?;
+        assert!(preceding.is_empty() && following.is_empty());
+        let (preceding, following) = placement_txs(&client, &tx, Placement::InBlock).await?;
+        assert_eq!(preceding.len(), tx_index);
+        assert!(following.is_empty());
+        let (preceding, following) = placement_txs(&client, &tx, Placement::Pessimistic(2)).await?;
+        assert_eq!(preceding.len(), tx_index);
+        assert_eq!(following.len(), 2);
+        assert!(following
+            .iter()
+            .all(|following| following.transaction_index.unwrap().as_usize() > tx_index));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_skips_pools_without_a_price() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, forks) = usdc_weth_fixture(&client).await?;
+        let chain = ChainConfig::default();
+        let mut params = derive_trade_params(
+            &client,
+            &chain,
+            Some(&*forks),
+            &block_info,
+            tx.clone(),
+            &event,
+            &EventContext::default(),
+        )
+        .await?;
+        // an EOA has no reserves or slot0 to read a price from
+        let eoa = Address::random();
+        params[0].arb_pools.push(PairPool {
+            variant: PoolVariant::UniswapV3,
+            address: eoa,
+            fee: Some(3000),
+            dex: Some(Dex::UniswapV3),
+        });
+
+        let outcome = search_backruns(
+            Some(&client),
+            forks,
+            tx,
+            params,
+            &block_info,
+            &SimOptions {
+                search: SearchConfig::new(1, 5, U256::zero())?,
+                min_pool_weth: U256::zero(),
+                ..Default::default()
+            },
+            Instant::now(),
+        )
+        .await?;
+        assert!(!outcome.results.is_empty());
+        assert_eq!(outcome.failures.len(), 1);
+        assert_eq!(outcome.failures[0].0, eoa);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_retries_receipts_of_recent_txs() -> Result<()> {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        let tx_hash = H256::random();
+        let receipt = TransactionReceipt {
+            transaction_hash: tx_hash,
+            ..Default::default()
+        };
+        // responses are popped from the back: no receipt, the head (a block later), no receipt
+        // again, then the receipt
+        mock.push(receipt.to_owned())?;
+        mock.push::<Option<TransactionReceipt>, _>(None)?;
+        mock.push(U64::from(101))?;
+        mock.push::<Option<TransactionReceipt>, _>(None)?;
+        let retry = ReceiptRetry {
+            backoff: Duration::ZERO,
+            ..Default::default()
+        };
+        let ctx = EventContext::new(retry);
+        assert_eq!(ctx.receipt(&client, tx_hash, 100).await?, receipt);
+        assert_eq!(ctx.receipt_stats(), (1, 4));
+
+        // gives up after the last attempt
+        for _ in 0..retry.attempts {
+            mock.push::<Option<TransactionReceipt>, _>(None)?;
+        }
+        mock.push(U64::from(101))?;
+        mock.push::<Option<TransactionReceipt>, _>(None)?;
+        let err = EventContext::new(retry)
+            .receipt(&client, tx_hash, 100)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::TxNotLanded(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_doesnt_retry_receipts_of_old_txs() -> Result<()> {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        let tx_hash = H256::random();
+        mock.push(U64::from(100 + ReceiptRetry::default().recent_blocks + 1))?;
+        mock.push::<Option<TransactionReceipt>, _>(None)?;
+        let err = EventContext::default()
+            .receipt(&client, tx_hash, 100)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::TxDropped(hash)) if *hash == tx_hash
+        ));
+        // dropped without retrying
+        mock.assert_request("eth_getTransactionReceipt", [tx_hash])?;
+        mock.assert_request("eth_blockNumber", ())?;
+        assert!(mock
+            .assert_request("eth_getTransactionReceipt", [tx_hash])
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_caches_prefetched_receipts() -> Result<()> {
+        let (provider, mock) = Provider::mocked();
+        let client = Arc::new(provider);
+        let tx_hash = H256::random();
+        let receipt = TransactionReceipt {
+            transaction_hash: tx_hash,
+            ..Default::default()
+        };
+        mock.push(receipt.to_owned())?;
+        let ctx = EventContext::default();
+        ctx.prefetch_receipts(&client, &[tx_hash]).await;
+        // already cached, so neither fetches it again
+        ctx.prefetch_receipts(&client, &[tx_hash]).await;
+        assert_eq!(ctx.receipt(&client, tx_hash, 100).await?, receipt);
+        assert_eq!(ctx.receipt(&client, tx_hash, 100).await?, receipt);
+        assert_eq!(ctx.receipt_stats(), (2, 1));
+        mock.assert_request("eth_getTransactionReceipt", [tx_hash])?;
+        assert!(mock
+            .assert_request("eth_getTransactionReceipt", [tx_hash])
+            .is_err());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_skips_shallow_pools() -> Result<()> {
+        let client = get_test_client().await?;
+        let block_num = client.get_block_number().await?.as_u64() - 1;
+        let mut evm = setup_test_evm(&client, block_num).await?;
+        let usdc = "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48".parse::<Address>()?;
+        let pools = get_all_trading_pools(&client, &ChainConfig::default(), (WETH, usdc)).await?;
+        // UniswapV2's & UniswapV3's (0.05%) USDC/WETH pools
+        let deep_pools = [
+            "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc".parse::<Address>()?,
+            "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse::<Address>()?,
+        ];
+        let pools = pools
+            .iter()
+            .filter(|pool| deep_pools.contains(&pool.address))
+            .collect::<Vec<_>>();
+        assert_eq!(pools.len(), 2);
+        let tokens = TokenPair {
+            weth: WETH,
+            token: usdc,
+        };
+        for pool in pools {
+            let weth = sim_weth_depth(&mut evm, pool.address, pool.variant, &tokens)?;
+            assert!(weth > ETH * 100);
+            check_pool_depth(&mut evm, pool, &tokens, ETH)?;
+
+            // the same pool is skipped once the minimum is above what it holds
+            let err = check_pool_depth(&mut evm, pool, &tokens, weth + 1).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<HindsightError>(),
+                Some(HindsightError::ShallowPool { pool: shallow, .. }) if *shallow == pool.address
+            ));
+        }
+        Ok(())
+    }
+
+    /// Derives the trade params of a fixture's tx, without a node.
+    async fn fixture_trade_params(fixture: &str) -> Result<Vec<UserTradeParams>> {
+        let fixture: TradeFixture = serde_json::from_str(fixture)?;
+        fixture.trade_params(&EventContext::default()).await
+    }
+
+    fn usdc() -> Address {
+        "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+            .parse()
+            .unwrap()
+    }
+
+    /// Every USDC/WETH pool on UniswapV3 (by fee tier) & the V2 DEXes.
+    fn usdc_weth_pools() -> Vec<(Address, PoolVariant, Option<u32>, Option<Dex>)> {
+        let v3 = |address: &str, fee| {
+            let address = address.parse().unwrap();
+            (
+                address,
+                PoolVariant::UniswapV3,
+                Some(fee),
+                Some(Dex::UniswapV3),
+            )
+        };
+        let v2 = |address: &str, dex| {
+            (
+                address.parse().unwrap(),
+                PoolVariant::UniswapV2,
+                None,
+                Some(dex),
+            )
+        };
+        vec![
+            v3("0xE0554a476A092703abdB3Ef35c80e0D76d32939F", 100),
+            v3("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640", 500),
+            v3("0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8", 3000),
+            v3("0x7BeA39867e4169DBe237d55C8242a8f2fcDcc387", 10000),
+            v2("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc", Dex::UniswapV2),
+            v2("0x397FF1542f962076d0BFE58eA045FfA2d347ACa0", Dex::SushiSwap),
+        ]
+    }
+
+    /// Asserts that `params` trade USDC/WETH on `pool`, with every other USDC/WETH pool to arb on.
+    fn assert_usdc_weth_params(params: &UserTradeParams, pool: Address, dex: Dex) {
+        assert_eq!(params.pool, pool);
+        assert_eq!(params.dex, Some(dex));
+        assert!(!params.token0_is_weth);
+        assert_eq!(params.tokens.weth, WETH);
+        assert_eq!(params.tokens.token, usdc());
+        assert_eq!(params.token_symbol.as_deref(), Some("USDC"));
+        assert_eq!(params.token_decimals, Some(6));
+        let arb_pools = params
+            .arb_pools
+            .iter()
+            .map(|pool| (pool.address, pool.variant, pool.fee, pool.dex))
+            .collect::<Vec<_>>();
+        let expected = usdc_weth_pools()
+            .into_iter()
+            .filter(|(address, ..)| *address != pool)
+            .collect::<Vec<_>>();
+        assert_eq!(arb_pools, expected);
+    }
+
+    #[tokio::test]
+    async fn it_derives_v2_trade_params_from_fixture() -> Result<()> {
+        let params = fixture_trade_params(include_str!(
+            "../../fixtures/derive_trade_params/v2_swap.json"
+        ))
+        .await?;
+        assert_eq!(params.len(), 1);
+        let params = &params[0];
+        // 10 WETH -> 18,500 USDC
+        assert_eq!(params.pool_variant, PoolVariant::UniswapV2);
+        assert_eq!((params.token_in, params.token_out), (WETH, usdc()));
+        assert_eq!(params.amount0_sent, 0.into());
+        assert_eq!(params.amount1_sent, I256::from_raw(ETH * 10));
+        // priced from the pair's Sync log
+        let reserves = (
+            U256::from(40_000_000) * U256::exp10(6),
+            U256::from(21_600) * ETH,
+        );
+        assert_eq!(
+            params.price,
+            get_price_v2(reserves.0, reserves.1, (6.into(), 18.into()), false)?
+        );
+        let pool = "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc".parse()?;
+        assert_usdc_weth_params(params, pool, Dex::UniswapV2);
+        Ok(())
+    }
+
+    /// Truncates the data of the fixture receipt's `i`th log to `len` bytes.
+    fn truncate_receipt_log(fixture: &mut TradeFixture, i: usize, len: usize) -> Result<()> {
+        let log = &mut fixture.receipt["logs"][i];
+        let data: Bytes = serde_json::from_value(log["data"].to_owned())?;
+        log["data"] = serde_json::to_value(Bytes::from(data[..len].to_vec()))?;
+        Ok(())
+    }
+
+    /// Indexes of the fixture receipt's logs emitted by `pool`.
+    fn receipt_logs_of(fixture: &TradeFixture, pool: Address) -> Result<Vec<usize>> {
+        let logs: Vec<Log> = serde_json::from_value(fixture.receipt["logs"].to_owned())?;
+        Ok(logs
+            .iter()
+            .enumerate()
+            .filter(|(_, log)| log.address == pool)
+            .map(|(i, _)| i)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn it_skips_malformed_swap_logs() -> Result<()> {
+        let aggregator_swap =
+            include_str!("../../fixtures/derive_trade_params/aggregator_swap.json");
+        let v3_pool: Address = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse()?;
+
+        // a short fill is skipped, & the pool's trade is derived from its other fill
+        let mut fixture: TradeFixture = serde_json::from_str(aggregator_swap)?;
+        let v3_logs = receipt_logs_of(&fixture, v3_pool)?;
+        assert_eq!(v3_logs.len(), 2);
+        truncate_receipt_log(&mut fixture, v3_logs[0], 100)?;
+        let params = fixture.trade_params(&EventContext::default()).await?;
+        assert_eq!(params.len(), 2);
+        assert_eq!(params[0].pool, v3_pool);
+        assert_eq!(params[0].amount1_sent, I256::from_raw(ETH * 3));
+
+        // a pool w/o a well-formed fill is skipped, but the event's other pools aren't
+        let mut fixture: TradeFixture = serde_json::from_str(aggregator_swap)?;
+        for i in receipt_logs_of(&fixture, v3_pool)? {
+            truncate_receipt_log(&mut fixture, i, 96)?;
+        }
+        let params = fixture.trade_params(&EventContext::default()).await?;
+        assert_eq!(params.len(), 1);
+        assert_eq!(params[0].pool_variant, PoolVariant::UniswapV2);
+
+        // w/ no other pool, the event fails w/ the malformed log, rather than panicking
+        let mut fixture: TradeFixture = serde_json::from_str(include_str!(
+            "../../fixtures/derive_trade_params/v3_swap.json"
+        ))?;
+        for i in receipt_logs_of(&fixture, v3_pool)? {
+            truncate_receipt_log(&mut fixture, i, 128)?;
+        }
+        let err = fixture
+            .trade_params(&EventContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::MalformedSwapLog { pool, len: 128 }) if *pool == v3_pool
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_derives_hint_only_trade_params_from_hinted_logs() -> Result<()> {
+        let fixture = include_str!("../../fixtures/derive_trade_params/v2_swap.json");
+        let expected = fixture_trade_params(fixture).await?;
+        let ctx = EventContext::default().with_hint_only(true);
+
+        // w/o hinted log data, the user's tx must be simulated, which needs a fork
+        let err = serde_json::from_str::<TradeFixture>(fixture)?
+            .trade_params(&ctx)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("no fork"), "{}", err);
+
+        // a hint that shares its logs' data yields the same trade, w/o reading the receipt
+        let mut fixture: TradeFixture = serde_json::from_str(fixture)?;
+        let logs: Vec<Log> = serde_json::from_value(fixture.receipt["logs"].to_owned())?;
+        fixture.event.hint.logs = logs
+            .into_iter()
+            .map(|log| EventTransactionLog {
+                address: log.address,
+                topics: log.topics,
+                data: log.data,
+            })
+            .collect();
+        fixture.receipt = serde_json::Value::Null;
+        let params = fixture.trade_params(&ctx).await?;
+        assert_eq!(
+            serde_json::to_value(params)?,
+            serde_json::to_value(expected)?
+        );
+        assert_eq!(ctx.receipt_stats(), (0, 0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_derives_v3_trade_params_from_fixture() -> Result<()> {
+        let params = fixture_trade_params(include_str!(
+            "../../fixtures/derive_trade_params/v3_swap.json"
+        ))
+        .await?;
+        assert_eq!(params.len(), 1);
+        let params = &params[0];
+        // 50,000 USDC -> 27 WETH
+        assert_eq!(params.pool_variant, PoolVariant::UniswapV3);
+        assert_eq!((params.token_in, params.token_out), (usdc(), WETH));
+        assert_eq!(params.amount0_sent, I256::from(50_000_000_000_i64));
+        assert_eq!(params.amount1_sent, 0.into());
+        let sqrt_price = U256::from_dec_str("1842382168473146718391734927654321")?;
+        let liquidity = U256::from_dec_str("19875432198765432101")?;
+        assert_eq!(
+            params.price,
+            get_price_v3(liquidity, sqrt_price, (6.into(), 18.into()), false)?
+        );
+        let pool = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse()?;
+        assert_usdc_weth_params(params, pool, Dex::UniswapV3);
+        Ok(())
+    }
+
+    /// The calls that read the tokens of UniswapV3's USDC/USDT (0.01%) pool, `pool`.
+    fn usdc_usdt_pool_calls(pool: Address) -> Result<Vec<FixtureCall>> {
+        let usdt: Address = "0xdAC17F958D2ee523a2206206994597C13D831ec7".parse()?;
+        let call = |selector: &str, token| -> Result<FixtureCall> {
+            Ok(FixtureCall {
+                to: pool,
+                data: selector.parse()?,
+                result: abi::encode(&[Token::Address(token)]).into(),
+            })
+        };
+        // token0() & token1()
+        Ok(vec![call("0x0dfe1681", usdc())?, call("0xd21220a7", usdt)?])
+    }
+
+    #[tokio::test]
+    async fn it_skips_pairs_without_weth() -> Result<()> {
+        let stable_pool = "0x3416cF6C708Da44DB2624D63ea0AAef7113527C6".parse::<Address>()?;
+        let stable_swap = EventTransactionLog {
+            address: stable_pool,
+            topics: vec![H256::from_str(
+                "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67",
+            )?],
+            data: Default::default(),
+        };
+        let v3_swap = include_str!("../../fixtures/derive_trade_params/v3_swap.json");
+
+        // a stable-stable swap can't be backrun
+        let mut fixture: TradeFixture = serde_json::from_str(v3_swap)?;
+        fixture.event.hint.logs = vec![stable_swap.to_owned()];
+        fixture.calls.extend(usdc_usdt_pool_calls(stable_pool)?);
+        let err = fixture
+            .trade_params(&EventContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::NonWethPair(pool)) if *pool == stable_pool
+        ));
+
+        // a route from USDT through USDC into WETH still has its WETH swap derived
+        let mut fixture: TradeFixture = serde_json::from_str(v3_swap)?;
+        fixture.event.hint.logs.insert(0, stable_swap);
+        fixture.calls.extend(usdc_usdt_pool_calls(stable_pool)?);
+        let params = fixture.trade_params(&EventContext::default()).await?;
+        assert_eq!(params.len(), 1);
+        let usdc_weth_pool = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse()?;
+        assert_usdc_weth_params(&params[0], usdc_weth_pool, Dex::UniswapV3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_derives_aggregator_trade_params_from_fixture() -> Result<()> {
+        let params = fixture_trade_params(include_str!(
+            "../../fixtures/derive_trade_params/aggregator_swap.json"
+        ))
+        .await?;
+        // the V3 pool is hinted twice, but its fills are netted into one trade
+        assert_eq!(params.len(), 2);
+
+        // 5 + 3 WETH -> 9,000 + 5,400 USDC, priced after the last fill
+        let v3 = &params[0];
+        assert_eq!(v3.pool_variant, PoolVariant::UniswapV3);
+        assert_eq!((v3.token_in, v3.token_out), (WETH, usdc()));
+        assert_eq!(v3.amount0_sent, 0.into());
+        assert_eq!(v3.amount1_sent, I256::from_raw(ETH * 8));
+        let sqrt_price = U256::from_dec_str("1842445000000000000000000000000000")?;
+        let liquidity = U256::from_dec_str("19875432198765432101")?;
+        assert_eq!(
+            v3.price,
+            get_price_v3(liquidity, sqrt_price, (6.into(), 18.into()), false)?
+        );
+        let pool = "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640".parse()?;
+        assert_usdc_weth_params(v3, pool, Dex::UniswapV3);
+
+        // 2 WETH -> 3,600 USDC
+        let v2 = &params[1];
+        assert_eq!(v2.pool_variant, PoolVariant::UniswapV2);
+        assert_eq!((v2.token_in, v2.token_out), (WETH, usdc()));
+        assert_eq!(v2.amount0_sent, 0.into());
+        assert_eq!(v2.amount1_sent, I256::from_raw(ETH * 2));
+        let reserves = (
+            U256::from(39_996_400) * U256::exp10(6),
+            U256::from(21_602) * ETH,
+        );
+        assert_eq!(
+            v2.price,
+            get_price_v2(reserves.0, reserves.1, (6.into(), 18.into()), false)?
+        );
+        let pool = "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc".parse()?;
+        assert_usdc_weth_params(v2, pool, Dex::UniswapV2);
+        Ok(())
+    }
+
+    #[test]
+    fn it_selects_arb_routes_by_price() -> Result<()> {
+        let (weth, token) = (Address::from_low_u64_be(1), Address::from_low_u64_be(2));
+        let (user_pool, alt_pool) = (Address::from_low_u64_be(3), Address::from_low_u64_be(4));
+        let other_pool = PairPool {
+            variant: PoolVariant::UniswapV3,
+            address: alt_pool,
+            fee: Some(500),
+            dex: Some(Dex::UniswapV3),
+        };
+        // (token0_is_weth, TKN in the user's pool, TKN in the other pool, pool TKN is bought on);
+        // each pool holds 100 WETH, so the one w/ more TKN sells it cheapest
+        let cases = [
+            (true, 2100, 2000, user_pool),
+            (true, 2000, 2100, alt_pool),
+            (false, 2100, 2000, user_pool),
+            (false, 2000, 2100, alt_pool),
+            // w/o a price difference, the user's pool is sold on
+            (true, 2000, 2000, alt_pool),
+        ];
+        for (token0_is_weth, user_tkn, alt_tkn, start) in cases {
+            let price = |tkn: u64| {
+                let (weth_reserve, tkn_reserve) = (ETH * 100, ETH * tkn);
+                if token0_is_weth {
+                    get_price_v2(weth_reserve, tkn_reserve, (18.into(), 18.into()), true)
+                } else {
+                    get_price_v2(tkn_reserve, weth_reserve, (18.into(), 18.into()), false)
+                }
+            };
+            let params = UserTradeParams {
+                pool: user_pool,
+                pool_variant: PoolVariant::UniswapV2,
+                pool_fee: None,
+                token0_is_weth,
+                price: price(user_tkn)?,
+                tokens: TokenPair { weth, token },
+                eth_equivalent: false,
+                ..SimArbResult::test_example().user_trade
+            };
+            let route = select_arb_route(&params, &other_pool, price(alt_tkn)?);
+            let case = (token0_is_weth, user_tkn, alt_tkn);
+            assert_eq!(route.legs.len(), 2, "{:?}", case);
+            assert_eq!(route.legs[0].pool, start, "{:?}", case);
+            assert_eq!(
+                (route.legs[0].token_in, route.legs[0].token_out),
+                (weth, token)
+            );
+            assert_eq!(
+                (route.legs[1].token_in, route.legs[1].token_out),
+                (token, weth)
+            );
+            let end = if start == user_pool {
+                alt_pool
+            } else {
+                user_pool
+            };
+            assert_eq!(route.legs[1].pool, end, "{:?}", case);
+            let fees = if start == user_pool {
+                (None, Some(500))
+            } else {
+                (Some(500), None)
+            };
+            assert_eq!((route.start_fee, route.end_fee), fees, "{:?}", case);
+            assert_eq!(
+                arb_round_trips(&params, &other_pool, price(alt_tkn)?),
+                vec![route]
+            );
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_estimates_search_ranges_from_the_user_trade() {
+        let max = ETH * 50;
+        let trade = SimArbResult::test_example().user_trade;
+        let weth_in = UserTradeParams {
+            token_in: trade.tokens.weth,
+            token_out: trade.tokens.token,
+            token0_is_weth: true,
+            amount0_sent: I256::from_raw(ETH * 3),
+            ..trade.to_owned()
+        };
+        let nothing_in = UserTradeParams {
+            amount0_sent: 0.into(),
+            ..weth_in.to_owned()
+        };
+        let token_in = UserTradeParams {
+            token_in: trade.tokens.token,
+            token_out: trade.tokens.weth,
+            amount1_sent: I256::from_raw(ETH * 84),
+            price: ETH * 42,
+            ..weth_in.to_owned()
+        };
+        let unknown_decimals = UserTradeParams {
+            token_decimals: None,
+            ..token_in.to_owned()
+        };
+        let cases = [
+            (&weth_in, ETH * 3),
+            (&nothing_in, max),
+            (&token_in, ETH * 2),
+            (&unknown_decimals, max),
+        ];
+        for (params, amount_in_max) in cases {
+            assert_eq!(
+                estimate_search_range(None, params, max).await,
+                [0.into(), amount_in_max]
+            );
+        }
+        // clamped to the max
+        assert_eq!(
+            estimate_search_range(None, &weth_in, ETH).await,
+            [0.into(), ETH]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_values_eth_equivalent_trades_one_to_one() -> Result<()> {
+        let params = fixture_trade_params(include_str!(
+            "../../fixtures/derive_trade_params/wsteth_weth_v3_swap.json"
+        ))
+        .await?;
+        assert_eq!(params.len(), 1);
+        let params = &params[0];
+        // 10 wstETH -> 11.4 WETH
+        let wsteth: Address = "0x7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0".parse()?;
+        assert_eq!((params.token_in, params.token_out), (wsteth, WETH));
+        assert_eq!(params.amount0_sent, I256::from_raw(ETH * 10));
+        assert!(!params.token0_is_weth && params.eth_equivalent);
+        assert_eq!(params.arb_pools.len(), 1);
+        let max = ETH * 1000;
+        assert_eq!(user_amount_in_weth(None, params, max).await?, ETH * 10);
+        // priced, it'd be worth the WETH the user got for it
+        let priced = UserTradeParams {
+            eth_equivalent: false,
+            ..params.to_owned()
+        };
+        assert!(user_amount_in_weth(None, &priced, max).await? > ETH * 11);
+
+        // the arb is searched both ways, starting w/ WETH either way
+        let other_pool = &params.arb_pools[0];
+        let routes = arb_round_trips(params, other_pool, params.price);
+        assert_eq!(routes.len(), 2);
+        for route in &routes {
+            assert_eq!(route.legs[0].token_in, WETH);
+            assert_eq!(route.legs[1].token_out, WETH);
+        }
+        assert_eq!(routes[0].legs[0].pool, params.pool);
+        assert_eq!(routes[1].legs[0].pool, other_pool.address);
+        assert_eq!(
+            (routes[0].start_fee, routes[0].end_fee),
+            (Some(100), Some(500))
+        );
+        assert_eq!(arb_round_trips(&priced, other_pool, params.price).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn it_nets_split_swaps_on_one_pool() {
+        // user's order is split into two fills on the same pool (e.g. by an aggregator)
+        let (in0, out0) = (U256::from(15) * ETH / 10, U256::from(2997) * ETH);
+        let (in1, out1) = (U256::from(5) * ETH / 10, U256::from(996) * ETH);
+        let v2_logs = [
+            log_data(&[in0, 0.into(), 0.into(), out0]),
+            log_data(&[in1, 0.into(), 0.into(), out1]),
+        ];
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(
+            PoolVariant::UniswapV2,
+            &v2_logs
+                .iter()
+                .map(|data| data.as_slice())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(amount0_sent, I256::from_raw(in0 + in1));
+        assert_eq!(amount1_sent, 0.into());
+
+        let v3_logs = [
+            log_data(&[in0, (-I256::from_raw(out0)).into_raw(), 0.into(), 0.into()]),
+            log_data(&[in1, (-I256::from_raw(out1)).into_raw(), 0.into(), 0.into()]),
+        ];
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(
+            PoolVariant::UniswapV3,
+            &v3_logs
+                .iter()
+                .map(|data| data.as_slice())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(amount0_sent, I256::from_raw(in0 + in1));
+        assert_eq!(amount1_sent, 0.into());
+
+        // a fill in the opposite direction is netted out
+        let v3_logs = [
+            log_data(&[in0, (-I256::from_raw(out0)).into_raw(), 0.into(), 0.into()]),
+            log_data(&[(-I256::from_raw(in1)).into_raw(), out1, 0.into(), 0.into()]),
+        ];
+        let (amount0_sent, amount1_sent) = parse_amounts_sent(
+            PoolVariant::UniswapV3,
+            &v3_logs
+                .iter()
+                .map(|data| data.as_slice())
+                .collect::<Vec<_>>(),
+        );
+        assert_eq!(amount0_sent, I256::from_raw(in0 - in1));
+        assert_eq!(amount1_sent, 0.into());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn search_strategies_converge() -> Result<()> {
+        let client = get_test_client().await?;
+        let (tx, event, block_info, _) = usdc_weth_fixture(&client).await?;
+
+        let grid = find_optimal_backrun_amount_in_out(
+            &client,
+            tx.clone(),
+            &event,
+            &block_info,
+            SimOptions {
+                search_strategy: SearchStrategy::Grid,
+                priority_fee: 0.into(),
+                ..Default::default()
+            },
+        )
+        .await?
+        .results;
+        let golden = find_optimal_backrun_amount_in_out(
+            &client,
+            tx,
+            &event,
+            &block_info,
+            SimOptions {
+                search_strategy: SearchStrategy::GoldenSection,
+                priority_fee: 0.into(),
+                ..Default::default()
+            },
+        )
+        .await?
+        .results;
+        assert!(!grid.is_empty());
+        for res in grid {
+            let grid_res = res.backrun_trade;
+            let golden_res = golden
+                .iter()
+                .map(|res| &res.backrun_trade)
+                .find(|res| {
+                    res.start_pool == grid_res.start_pool && res.end_pool == grid_res.end_pool
+                })
+                .expect("golden-section search should find the same arb");
+            let diff = if grid_res.amount_in > golden_res.amount_in {
+                grid_res.amount_in - golden_res.amount_in
+            } else {
+                golden_res.amount_in - grid_res.amount_in
+            };
+            // amounts should be within 1% of each other
+            assert!(diff <= grid_res.amount_in / 100);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,512 @@
+//! Curve stableswap pools, which backruns may arb against: their coins, price & depth as read
+//! from a fork, and the direct swaps that routes through them are simulated with.
+
+use crate::{
+    error::HindsightError,
+    interfaces::{PoolVariant, SwapLeg},
+    sim::evm::{call_function, commit_call, sim_balance_of, sim_tx_request, SwapOutcome},
+    Error, Result,
+};
+use ethers::{
+    abi::{self, ParamType, Token},
+    types::{Address, Bytes, TransactionRequest, H160, U256, U64},
+    utils::id,
+};
+use revm::EVM;
+use rusty_sando::{
+    prelude::fork_db::ForkDB, simulate::braindance_controller_address,
+    utils::constants::get_eth_dev,
+};
+use uniswap_v3_math::full_math::mul_div;
+
+/// The address Curve pools list as the coin of native ETH.
+pub const ETH_COIN: Address = H160([0xee; 20]);
+
+/// WETH (wei) that a Curve pool's price is quoted for; small, so the quote stays close to the
+/// pool's spot price.
+const PRICE_PROBE_AMOUNT: u64 = 10_000_000_000_000_000;
+
+/// Returns the EOA that routes through Curve pools are swapped from: braindance's controller,
+/// which has ETH for gas on every fork, and WETH once `fund_braindance` funds it.
+pub fn curve_trader() -> Address {
+    Address::from(braindance_controller_address().0)
+}
+
+/// Returns the calldata of a call to `signature` w/ `args`.
+fn calldata(signature: &str, args: &[Token]) -> Bytes {
+    let mut data = id(signature).to_vec();
+    data.extend(abi::encode(args));
+    data.into()
+}
+
+/// Calls `contract` w/ `data` in the forked EVM, w/o committing anything.
+fn view(evm: &mut EVM<ForkDB>, contract: Address, data: Bytes) -> Result<Bytes> {
+    sim_tx_request(
+        evm,
+        TransactionRequest {
+            from: Some(get_eth_dev()),
+            to: Some(contract.into()),
+            gas: Some(U256::from(900_000_u64)),
+            gas_price: Some(U256::from(1000_000_000_000_u64)),
+            data: Some(data),
+            chain_id: Some(U64::from(1)),
+            ..Default::default()
+        },
+    )
+}
+
+/// Decodes the output of a call returning a single `param`.
+fn decode_one(output: &[u8], param: ParamType, call: &str) -> Result<Token> {
+    abi::decode(&[param], output)?
+        .pop()
+        .ok_or::<Error>(HindsightError::CallError(format!("{} returned nothing", call)).into())
+}
+
+fn decode_uint(output: &[u8], call: &str) -> Result<U256> {
+    decode_one(output, ParamType::Uint(256), call)?
+        .into_uint()
+        .ok_or::<Error>(HindsightError::CallError(format!("{} didn't return a uint", call)).into())
+}
+
+/// Calls the getter `name` for the `i`th coin of Curve `pool`. Newer pools index their coins w/
+/// a uint256, older ones w/ an int128, so both are tried.
+fn call_coin_getter(evm: &mut EVM<ForkDB>, pool: Address, name: &str, i: usize) -> Result<Bytes> {
+    let i = U256::from(i);
+    view(
+        evm,
+        pool,
+        calldata(&format!("{}(uint256)", name), &[Token::Uint(i)]),
+    )
+    .or_else(|_| {
+        view(
+            evm,
+            pool,
+            calldata(&format!("{}(int128)", name), &[Token::Int(i)]),
+        )
+    })
+}
+
+/// Returns the coins of a 2-coin Curve `pool` in the forked EVM.
+pub fn sim_curve_coins(evm: &mut EVM<ForkDB>, pool: Address) -> Result<[Address; 2]> {
+    let mut coins = [Address::zero(); 2];
+    for (i, coin) in coins.iter_mut().enumerate() {
+        let output = call_coin_getter(evm, pool, "coins", i)?;
+        *coin = decode_one(&output, ParamType::Address, "coins")?
+            .into_address()
+            .ok_or::<Error>(
+                HindsightError::CallError(format!("coins({}) of {:?}", i, pool)).into(),
+            )?;
+    }
+    Ok(coins)
+}
+
+/// Returns the index of the coin that `token` trades as in `coins`; ETH pools trade WETH as ETH.
+pub fn coin_index(coins: &[Address], token: Address, weth: Address) -> Option<usize> {
+    coins
+        .iter()
+        .position(|&coin| coin == token || (token == weth && coin == ETH_COIN))
+}
+
+/// Returns the coins of Curve `pool`, and the indexes of `token_in` & `token_out` in them.
+fn sim_coin_indexes(
+    evm: &mut EVM<ForkDB>,
+    pool: Address,
+    token_in: Address,
+    token_out: Address,
+    weth: Address,
+) -> Result<([Address; 2], usize, usize)> {
+    let coins = sim_curve_coins(evm, pool)?;
+    match (
+        coin_index(&coins, token_in, weth),
+        coin_index(&coins, token_out, weth),
+    ) {
+        (Some(i), Some(j)) if i != j => Ok((coins, i, j)),
+        _ => Err(anyhow::anyhow!(
+            "Curve pool {:?} doesn't trade {:?} for {:?}",
+            pool,
+            token_in,
+            token_out
+        )),
+    }
+}
+
+/// Returns how much of its `j`th coin Curve `pool` would send for `dx` of its `i`th.
+fn sim_get_dy(evm: &mut EVM<ForkDB>, pool: Address, i: usize, j: usize, dx: U256) -> Result<U256> {
+    let output = view(
+        evm,
+        pool,
+        calldata(
+            "get_dy(int128,int128,uint256)",
+            &[Token::Int(i.into()), Token::Int(j.into()), Token::Uint(dx)],
+        ),
+    )?;
+    decode_uint(&output, "get_dy")
+}
+
+/// Returns the price (TKN per WETH, 18 decimals) of Curve `pool` in the forked EVM, quoted w/
+/// `get_dy` for a small amount of WETH, so it's comparable to the prices of Uniswap pools.
+/// One of the tokens must be `weth`.
+pub fn sim_price_curve(
+    evm: &mut EVM<ForkDB>,
+    pool: Address,
+    (token_a, token_b): (Address, Address),
+    weth: Address,
+) -> Result<U256> {
+    let token = if token_a == weth { token_b } else { token_a };
+    let (_, i, j) = sim_coin_indexes(evm, pool, weth, token, weth)?;
+    let probe = U256::from(PRICE_PROBE_AMOUNT);
+    let dy = sim_get_dy(evm, pool, i, j, probe)?;
+    let output = call_function(evm, "0x313ce567", token)?; // decimals()
+    let decimals = decode_uint(&output, "decimals")?;
+    if decimals > 36.into() {
+        return Err(HindsightError::MathError(format!(
+            "{:?} has too many decimals ({})",
+            token, decimals
+        ))
+        .into());
+    }
+    // (dy / 10^decimals) / (probe / 1e18), in 18 decimals
+    Ok(mul_div(
+        dy,
+        U256::exp10(36),
+        probe * U256::exp10(decimals.as_usize()),
+    )?)
+}
+
+/// Returns Curve `pool`'s balance of ETH (or WETH) in the forked EVM.
+pub fn sim_curve_weth_depth(evm: &mut EVM<ForkDB>, pool: Address, weth: Address) -> Result<U256> {
+    let coins = sim_curve_coins(evm, pool)?;
+    let i = coin_index(&coins, weth, weth)
+        .ok_or(anyhow::anyhow!("Curve pool {:?} doesn't trade ETH", pool))?;
+    let output = call_coin_getter(evm, pool, "balances", i)?;
+    decode_uint(&output, "balances")
+}
+
+/// Swaps the legs of a backrun directly from `trader`, an EOA holding WETH, by calling each pool
+/// (or router) itself & approving what it pays them on the fork.
+///
+/// Braindance can only swap on Uniswap pools, so every leg of a route through a Curve pool is
+/// swapped this way. Each call counts towards the leg's gas.
+#[derive(Clone, Copy, Debug)]
+pub struct DirectSwaps {
+    pub trader: Address,
+    pub weth: Address,
+    /// UniswapV3's SwapRouter, which V3 legs are swapped through; they fail without it.
+    pub v3_router: Option<Address>,
+    /// Gas limit of each call.
+    pub gas_limit: u64,
+}
+
+impl DirectSwaps {
+    fn call(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        to: Address,
+        data: Bytes,
+        value: U256,
+        base_fee: U256,
+    ) -> Result<u64> {
+        let (_, gas_used) = commit_call(
+            evm,
+            self.trader,
+            to,
+            data,
+            value,
+            base_fee,
+            None,
+            self.gas_limit,
+        )?;
+        Ok(gas_used)
+    }
+
+    fn approve(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        token: Address,
+        spender: Address,
+        amount: U256,
+        base_fee: U256,
+    ) -> Result<u64> {
+        self.call(
+            evm,
+            token,
+            calldata(
+                "approve(address,uint256)",
+                &[Token::Address(spender), Token::Uint(amount)],
+            ),
+            U256::zero(),
+            base_fee,
+        )
+    }
+
+    /// Swaps `amount_in` of `leg.token_in` for `leg.token_out` on `leg.pool`, committing it to the
+    /// fork. The outcome's balance is the trader's balance of `leg.token_out`.
+    pub fn swap(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        leg: &SwapLeg,
+        amount_in: U256,
+        base_fee: U256,
+    ) -> Result<SwapOutcome> {
+        let balance_before = sim_balance_of(evm, leg.token_out, self.trader)?;
+        let gas_used = match leg.variant {
+            PoolVariant::UniswapV2 => self.swap_v2(evm, leg, amount_in, base_fee)?,
+            PoolVariant::UniswapV3 => self.swap_v3(evm, leg, amount_in, base_fee)?,
+            PoolVariant::Curve => self.swap_curve(evm, leg, amount_in, base_fee)?,
+        };
+        let balance = sim_balance_of(evm, leg.token_out, self.trader)?;
+        Ok(SwapOutcome {
+            amount_out: balance.saturating_sub(balance_before),
+            balance,
+            gas_used,
+        })
+    }
+
+    /// Sends `amount_in` to the pair, then takes out what its reserves give for it, less its 0.3% fee.
+    fn swap_v2(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        leg: &SwapLeg,
+        amount_in: U256,
+        base_fee: U256,
+    ) -> Result<u64> {
+        let output = call_function(evm, "0x0902f1ac", leg.pool)?; // getReserves()
+        let reserves = abi::decode(
+            &[
+                ParamType::Uint(112), // reserve0
+                ParamType::Uint(112), // reserve1
+                ParamType::Uint(32),  // blockTimestampLast
+            ],
+            &output,
+        )?;
+        let reserve = |i: usize| reserves[i].clone().into_uint().unwrap_or_default();
+        let token0_in = leg.token_in < leg.token_out;
+        let (reserve_in, reserve_out) = if token0_in {
+            (reserve(0), reserve(1))
+        } else {
+            (reserve(1), reserve(0))
+        };
+        let amount_in_with_fee = amount_in * 997;
+        let amount_out = mul_div(
+            amount_in_with_fee,
+            reserve_out,
+            reserve_in * 1000 + amount_in_with_fee,
+        )?;
+        let (amount0_out, amount1_out) = if token0_in {
+            (U256::zero(), amount_out)
+        } else {
+            (amount_out, U256::zero())
+        };
+        let transfer_gas = self.call(
+            evm,
+            leg.token_in,
+            calldata(
+                "transfer(address,uint256)",
+                &[Token::Address(leg.pool), Token::Uint(amount_in)],
+            ),
+            U256::zero(),
+            base_fee,
+        )?;
+        let swap_gas = self.call(
+            evm,
+            leg.pool,
+            calldata(
+                "swap(uint256,uint256,address,bytes)",
+                &[
+                    Token::Uint(amount0_out),
+                    Token::Uint(amount1_out),
+                    Token::Address(self.trader),
+                    Token::Bytes(vec![]),
+                ],
+            ),
+            U256::zero(),
+            base_fee,
+        )?;
+        Ok(transfer_gas + swap_gas)
+    }
+
+    /// Swaps through the SwapRouter, since the pool pays out before calling back for its payment,
+    /// which an EOA can't answer.
+    fn swap_v3(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        leg: &SwapLeg,
+        amount_in: U256,
+        base_fee: U256,
+    ) -> Result<u64> {
+        let router = self.v3_router.ok_or(anyhow::anyhow!(
+            "no UniswapV3 router to swap on pool {:?} with",
+            leg.pool
+        ))?;
+        let output = call_function(evm, "0xddca3f43", leg.pool)?; // fee()
+        let fee = decode_uint(&output, "fee")?;
+        let approve_gas = self.approve(evm, leg.token_in, router, amount_in, base_fee)?;
+        let swap_gas = self.call(
+            evm,
+            router,
+            calldata(
+                "exactInputSingle((address,address,uint24,address,uint256,uint256,uint256,uint160))",
+                &[Token::Tuple(vec![
+                    Token::Address(leg.token_in),
+                    Token::Address(leg.token_out),
+                    Token::Uint(fee),
+                    Token::Address(self.trader),
+                    Token::Uint(U256::MAX), // deadline
+                    Token::Uint(amount_in),
+                    Token::Uint(U256::zero()), // amountOutMinimum
+                    Token::Uint(U256::zero()), // sqrtPriceLimitX96
+                ])],
+            ),
+            U256::zero(),
+            base_fee,
+        )?;
+        Ok(approve_gas + swap_gas)
+    }
+
+    /// Swaps w/ the pool's `exchange`, unwrapping WETH that the pool takes as ETH, and wrapping
+    /// the ETH it sends.
+    fn swap_curve(
+        &self,
+        evm: &mut EVM<ForkDB>,
+        leg: &SwapLeg,
+        amount_in: U256,
+        base_fee: U256,
+    ) -> Result<u64> {
+        let (coins, i, j) =
+            sim_coin_indexes(evm, leg.pool, leg.token_in, leg.token_out, self.weth)?;
+        // quoted up front, since the ETH received can't be read from the trader's balance, which pays for gas
+        let amount_out = sim_get_dy(evm, leg.pool, i, j, amount_in)?;
+        let mut gas_used = 0;
+        let value = if coins[i] == ETH_COIN {
+            gas_used += self.call(
+                evm,
+                self.weth,
+                calldata("withdraw(uint256)", &[Token::Uint(amount_in)]),
+                U256::zero(),
+                base_fee,
+            )?;
+            amount_in
+        } else {
+            gas_used += self.approve(evm, leg.token_in, leg.pool, amount_in, base_fee)?;
+            U256::zero()
+        };
+        gas_used += self.call(
+            evm,
+            leg.pool,
+            calldata(
+                "exchange(int128,int128,uint256,uint256)",
+                &[
+                    Token::Int(i.into()),
+                    Token::Int(j.into()),
+                    Token::Uint(amount_in),
+                    Token::Uint(U256::zero()), // min_dy
+                ],
+            ),
+            value,
+            base_fee,
+        )?;
+        if coins[j] == ETH_COIN {
+            gas_used += self.call(
+                evm,
+                self.weth,
+                calldata("deposit()", &[]),
+                amount_out,
+                base_fee,
+            )?;
+        }
+        Ok(gas_used)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::ChainConfig;
+    use crate::interfaces::{Dex, TokenPair};
+    use crate::sim::core::{fork_evm, Braindance};
+    use crate::util::{get_all_trading_pools, get_block_info, test::get_test_client, ETH, WETH};
+    use ethers::providers::Middleware;
+
+    fn steth() -> Address {
+        "0xae7ab96520DE3A18E5e111B5EaAb095312D7fE84"
+            .parse()
+            .unwrap()
+    }
+
+    fn steth_pool() -> Address {
+        "0xDC24316b9AE028F1497c275EB9192a3Ea0f67022"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn it_trades_weth_as_eth() {
+        let token = Address::from_low_u64_be(1);
+        assert_eq!(coin_index(&[ETH_COIN, token], WETH, WETH), Some(0));
+        assert_eq!(coin_index(&[token, WETH], WETH, WETH), Some(1));
+        assert_eq!(coin_index(&[ETH_COIN, token], token, WETH), Some(1));
+        // only WETH trades as ETH
+        assert_eq!(coin_index(&[ETH_COIN, token], Address::zero(), WETH), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_finds_and_prices_curve_pools() -> Result<()> {
+        let client = get_test_client().await?;
+        let pools =
+            get_all_trading_pools(&client, &ChainConfig::default(), (WETH, steth())).await?;
+        let pool = pools
+            .iter()
+            .find(|pool| pool.variant == PoolVariant::Curve)
+            .expect("stETH/ETH pool should be found");
+        assert_eq!(pool.address, steth_pool());
+        assert_eq!(pool.dex, Some(Dex::Curve));
+
+        let block_num = client.get_block_number().await?.as_u64() - 1;
+        let block_info = get_block_info(&client, block_num).await?;
+        let mut evm = fork_evm(
+            &client,
+            &block_info,
+            &ChainConfig::default(),
+            &Braindance::default(),
+        )
+        .await?;
+        // stETH trades close to 1:1 w/ ETH
+        let price = sim_price_curve(&mut evm, steth_pool(), (WETH, steth()), WETH)?;
+        assert!(price > ETH * 9 / 10 && price < ETH * 11 / 10, "{}", price);
+        assert!(sim_curve_weth_depth(&mut evm, steth_pool(), WETH)? > ETH * 1000);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_swaps_on_curve_directly() -> Result<()> {
+        let client = get_test_client().await?;
+        let block_num = client.get_block_number().await?.as_u64() - 1;
+        let block_info = get_block_info(&client, block_num).await?;
+        let braindance = Braindance::default();
+        let mut evm = fork_evm(&client, &block_info, &ChainConfig::default(), &braindance).await?;
+        let swaps = DirectSwaps {
+            trader: curve_trader(),
+            weth: WETH,
+            v3_router: ChainConfig::default().uniswap_v3_router,
+            gas_limit: braindance.swap_gas_limit,
+        };
+        let tokens = TokenPair {
+            weth: WETH,
+            token: steth(),
+        };
+        // buy stETH w/ (unwrapped) WETH, then sell it back for ETH that's wrapped again
+        let legs = SwapLeg::round_trip(
+            &tokens,
+            (steth_pool(), PoolVariant::Curve),
+            (steth_pool(), PoolVariant::Curve),
+        );
+        let bought = swaps.swap(&mut evm, &legs[0], ETH, block_info.base_fee)?;
+        assert!(bought.amount_out > ETH * 9 / 10);
+        assert!(bought.gas_used > 0);
+        let sold = swaps.swap(&mut evm, &legs[1], bought.balance, block_info.base_fee)?;
+        // the round trip only loses the pool's fees
+        let start = braindance.starting_balance;
+        assert!(sold.balance < start && sold.balance > start - ETH / 100);
+        Ok(())
+    }
+}
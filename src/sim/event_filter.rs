@@ -0,0 +1,168 @@
+use crate::{debug, util::get_pair_tokens};
+use ethers::{providers::Middleware, types::Address};
+use mev_share_sse::EventHistory;
+use std::{collections::HashSet, sync::Arc};
+
+/// Decides which events are worth simulating, from the pools in their hinted logs.
+///
+/// A pool matches if it's in `pools` (unless that's empty), its pair includes one of `tokens`
+/// (unless that's empty), and its pair includes none of `deny_tokens`. An event is simulated
+/// if any of its hinted pools match. The default (empty) filter matches everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventFilter {
+    /// Pools to simulate. Empty means any pool.
+    pub pools: HashSet<Address>,
+    /// Tokens to simulate; a pool's pair must include one of them. Empty means any token.
+    pub tokens: HashSet<Address>,
+    /// Tokens to never simulate, e.g. known-toxic tokens.
+    pub deny_tokens: HashSet<Address>,
+}
+
+impl EventFilter {
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty() && self.tokens.is_empty() && self.deny_tokens.is_empty()
+    }
+
+    fn allows_pool_address(&self, pool: &Address) -> bool {
+        self.pools.is_empty() || self.pools.contains(pool)
+    }
+
+    fn allows_tokens(&self, tokens: (Address, Address)) -> bool {
+        let tokens = [tokens.0, tokens.1];
+        (self.tokens.is_empty() || tokens.iter().any(|token| self.tokens.contains(token)))
+            && !tokens.iter().any(|token| self.deny_tokens.contains(token))
+    }
+
+    /// Whether a trade on `pool`, whose pair is `tokens`, should be simulated.
+    pub fn allows_pool(&self, pool: Address, tokens: (Address, Address)) -> bool {
+        self.allows_pool_address(&pool) && self.allows_tokens(tokens)
+    }
+
+    /// Whether any of the pools in `event`'s hinted logs match the filter.
+    ///
+    /// Pool tokens are only fetched (and cached) if the filter lists tokens. Pools whose tokens
+    /// can't be fetched (e.g. addresses that aren't pools) don't match.
+    pub async fn allows_event<M: Middleware + 'static>(
+        &self,
+        client: &Arc<M>,
+        event: &EventHistory,
+    ) -> bool {
+        if self.is_empty() {
+            return true;
+        }
+        let needs_tokens = !self.tokens.is_empty() || !self.deny_tokens.is_empty();
+        let mut seen = HashSet::new();
+        for pool in event.hint.logs.iter().map(|log| log.address) {
+            if !seen.insert(pool) || !self.allows_pool_address(&pool) {
+                continue;
+            }
+            if !needs_tokens {
+                return true;
+            }
+            match get_pair_tokens(client, pool).await {
+                Ok(tokens) if self.allows_tokens(tokens) => return true,
+                Ok(_) => {}
+                Err(err) => debug!("failed to get tokens of pool {:?}: {}", pool, err),
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::metadata_cache;
+    use ethers::{
+        providers::{MockProvider, Provider},
+        types::H256,
+    };
+    use mev_share_sse::{EventTransactionLog, Hint};
+
+    fn address(n: u64) -> Address {
+        // high bits keep these clear of real addresses in the shared metadata cache
+        Address::from_low_u64_be(0xf11e_0000_0000 + n)
+    }
+
+    fn event(hash: u64, pools: &[Address]) -> EventHistory {
+        EventHistory {
+            block: 1,
+            timestamp: 1,
+            hint: Hint {
+                txs: vec![],
+                hash: H256::from_low_u64_be(hash),
+                logs: pools
+                    .iter()
+                    .map(|pool| EventTransactionLog {
+                        address: *pool,
+                        ..Default::default()
+                    })
+                    .collect(),
+                gas_used: None,
+                mev_gas_price: None,
+            },
+        }
+    }
+
+    /// Returns the hashes of the events in `events` that pass `filter`.
+    async fn filter_events(filter: &EventFilter, events: &[EventHistory]) -> Vec<u64> {
+        // every pool's tokens are cached, so the (empty) mock is never queried
+        let client = Arc::new(Provider::new(MockProvider::new()));
+        let mut passed = vec![];
+        for event in events {
+            if filter.allows_event(&client, event).await {
+                passed.push(event.hint.hash.to_low_u64_be());
+            }
+        }
+        passed
+    }
+
+    #[tokio::test]
+    async fn it_filters_events_by_pool_and_token() {
+        let (weth, good, other, toxic) = (address(1), address(2), address(3), address(4));
+        let (good_pool, other_pool, toxic_pool) = (address(11), address(12), address(13));
+        metadata_cache().insert_pair_tokens(good_pool, (good, weth));
+        metadata_cache().insert_pair_tokens(other_pool, (other, weth));
+        metadata_cache().insert_pair_tokens(toxic_pool, (good, toxic));
+        let events = vec![
+            event(1, &[good_pool]),
+            event(2, &[other_pool]),
+            event(3, &[toxic_pool]),
+            event(4, &[other_pool, good_pool]),
+            event(5, &[]),
+        ];
+
+        assert_eq!(
+            filter_events(&EventFilter::default(), &events).await,
+            vec![1, 2, 3, 4, 5]
+        );
+        let tokens = EventFilter {
+            tokens: HashSet::from([good]),
+            deny_tokens: HashSet::from([toxic]),
+            ..Default::default()
+        };
+        assert_eq!(filter_events(&tokens, &events).await, vec![1, 4]);
+        let pools = EventFilter {
+            pools: HashSet::from([other_pool]),
+            ..Default::default()
+        };
+        assert_eq!(filter_events(&pools, &events).await, vec![2, 4]);
+        let deny = EventFilter {
+            deny_tokens: HashSet::from([toxic, other]),
+            ..Default::default()
+        };
+        assert_eq!(filter_events(&deny, &events).await, vec![1, 4]);
+    }
+
+    #[test]
+    fn it_filters_trades_by_pool_and_token() {
+        let filter = EventFilter {
+            pools: HashSet::from([address(11)]),
+            tokens: HashSet::from([address(2)]),
+            deny_tokens: HashSet::new(),
+        };
+        assert!(filter.allows_pool(address(11), (address(2), address(1))));
+        assert!(!filter.allows_pool(address(12), (address(2), address(1))));
+        assert!(!filter.allows_pool(address(11), (address(3), address(1))));
+    }
+}
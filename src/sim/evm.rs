@@ -1,14 +1,24 @@
 use crate::{
-    debug, error::HindsightError, interfaces::PoolVariant, util::get_price_v3, Error, Result,
+    debug,
+    error::HindsightError,
+    interfaces::{PoolVariant, TokenPair},
+    sim::{curve::sim_curve_weth_depth, snapshot},
+    util::{get_price_v2, get_price_v3},
+    Error, Result,
 };
 use ethers::{
     abi::{self, ParamType},
     prelude::abigen,
     types::{Address, Bytes, Transaction, TransactionRequest, I256, U256, U64},
+    utils::{format_units, rlp},
 };
 use revm::{
-    primitives::{ExecutionResult, Output, ResultAndState, TransactTo, B160, U256 as rU256},
-    EVM,
+    db::{DatabaseCommit, DatabaseRef},
+    primitives::{
+        EVMError, EVMResult, ExecutionResult, Output, ResultAndState, TransactTo, B160,
+        U256 as rU256,
+    },
+    Database, EVM,
 };
 use rusty_sando::{
     prelude::fork_db::ForkDB,
@@ -16,78 +26,149 @@ use rusty_sando::{
     types::SimulationError,
     utils::{constants::get_eth_dev, tx_builder::braindance},
 };
-use std::{ops::Mul, str::FromStr};
+use std::str::FromStr;
+use uniswap_v3_math::{full_math::mul_div, sqrt_price_math::Q96};
 
-/// Execute a braindance swap on the forked EVM, commiting its state changes to the EVM's ForkDB.
-///
-/// Returns balance of token_out after tx is executed.
-pub fn commit_braindance_swap(
-    evm: &mut EVM<ForkDB>,
+/// Result of a braindance swap.
+#[derive(Clone, Copy, Debug)]
+pub struct SwapOutcome {
+    /// Amount of token_out that the pool sent.
+    pub amount_out: U256,
+    /// Braindance contract's balance of token_out after the swap.
+    pub balance: U256,
+    pub gas_used: u64,
+}
+
+/// Returns calldata for the braindance contract to swap `amount_in` of `token_in` for `token_out` on `target_pool`.
+/// Fails for Curve pools, which braindance can't swap on.
+pub fn braindance_swap_data(
     pool_variant: PoolVariant,
     amount_in: U256,
     target_pool: Address,
     token_in: Address,
     token_out: Address,
-    base_fee: U256,
-    _nonce: Option<u64>,
-) -> Result<U256> {
-    let swap_data = match pool_variant {
-        PoolVariant::UniswapV2 => {
-            braindance::build_swap_v2_data(amount_in, target_pool, token_in, token_out)
-        }
-        PoolVariant::UniswapV3 => braindance::build_swap_v3_data(
+) -> Result<Bytes> {
+    match pool_variant {
+        PoolVariant::UniswapV2 => Ok(braindance::build_swap_v2_data(
+            amount_in,
+            target_pool,
+            token_in,
+            token_out,
+        )),
+        PoolVariant::UniswapV3 => Ok(braindance::build_swap_v3_data(
             I256::from_raw(amount_in),
             target_pool,
             token_in,
             token_out,
-        ),
+        )),
+        PoolVariant::Curve => Err(anyhow::anyhow!(
+            "braindance can't swap on Curve pool {:?}",
+            target_pool
+        )),
+    }
+}
+
+/// Execute a braindance swap on the forked EVM, commiting its state changes to the EVM's ForkDB.
+/// The swap fails w/ `HindsightError::SwapHalted` if it runs out of `gas_limit`.
+pub fn commit_braindance_swap(
+    evm: &mut EVM<ForkDB>,
+    pool_variant: PoolVariant,
+    amount_in: U256,
+    target_pool: Address,
+    token_in: Address,
+    token_out: Address,
+    base_fee: U256,
+    nonce: Option<u64>,
+    gas_limit: u64,
+) -> Result<SwapOutcome> {
+    let swap_data =
+        braindance_swap_data(pool_variant, amount_in, target_pool, token_in, token_out)?;
+    let (output, gas_used) = commit_call(
+        evm,
+        braindance_controller_address().0.into(),
+        braindance_address(),
+        swap_data,
+        U256::zero(),
+        base_fee,
+        nonce,
+        gas_limit,
+    )?;
+    let (amount_out, balance) = match pool_variant {
+        PoolVariant::UniswapV2 => match braindance::decode_swap_v2_result(output) {
+            Ok(output) => output,
+            Err(e) => return Err(anyhow::anyhow!("failed to decode swap result: {:?}", e)),
+        },
+        PoolVariant::UniswapV3 => match braindance::decode_swap_v3_result(output) {
+            Ok(output) => output,
+            Err(e) => return Err(anyhow::anyhow!("failed to decode swap result: {:?}", e)),
+        },
+        PoolVariant::Curve => unreachable!("braindance swap data isn't built for Curve pools"),
     };
+    Ok(SwapOutcome {
+        amount_out,
+        balance,
+        gas_used,
+    })
+}
 
-    evm.env.tx.caller = braindance_controller_address();
-    evm.env.tx.transact_to = TransactTo::Call(braindance_address().0.into());
-    evm.env.tx.data = swap_data.0;
-    evm.env.tx.gas_limit = 700000;
+/// Calls `to` from `caller` (w/ `value` wei) on the forked EVM, commiting its state changes to
+/// the EVM's ForkDB. Returns the call's output & the gas it used.
+///
+/// Fails w/ `HindsightError::SwapReverted` if the call reverts, or `HindsightError::SwapHalted`
+/// if it runs out of `gas_limit`.
+pub fn commit_call(
+    evm: &mut EVM<ForkDB>,
+    caller: Address,
+    to: Address,
+    data: Bytes,
+    value: U256,
+    base_fee: U256,
+    nonce: Option<u64>,
+    gas_limit: u64,
+) -> Result<(Bytes, u64)> {
+    evm.env.tx.caller = caller.0.into();
+    evm.env.tx.transact_to = TransactTo::Call(to.0.into());
+    evm.env.tx.data = data.0;
+    evm.env.tx.gas_limit = gas_limit;
     evm.env.tx.gas_price = base_fee.into();
-    evm.env.tx.value = rU256::ZERO;
+    evm.env.tx.value = value.into();
+    evm.env.tx.nonce = nonce;
+    // clear fields left over from injected txs
+    evm.env.tx.gas_priority_fee = None;
+    evm.env.tx.access_list = vec![];
 
-    let res = match evm.transact_commit() {
+    let res = match transact_commit(evm) {
         Ok(res) => res,
         Err(e) => return Err(anyhow::anyhow!("failed to commit swap: {:?}", e)),
     };
-    let output = match res.to_owned() {
-        ExecutionResult::Success { output, .. } => match output {
-            Output::Call(o) => o,
-            Output::Create(o, _) => o,
+    match res {
+        ExecutionResult::Success {
+            output, gas_used, ..
+        } => match output {
+            Output::Call(o) => Ok((o.into(), gas_used)),
+            Output::Create(o, _) => Ok((o.into(), gas_used)),
         },
-        ExecutionResult::Revert { output, gas_used } => {
-            return Err(anyhow::anyhow!(
-                "swap reverted: {:?} (gas used: {:?})",
-                output,
-                gas_used
-            ))
+        ExecutionResult::Revert { output, gas_used } => Err(HindsightError::SwapReverted {
+            reason: decode_revert_reason(&output),
+            output: output.into(),
+            gas_used,
         }
-        ExecutionResult::Halt { reason, .. } => {
-            return Err(anyhow::anyhow!("swap halted: {:?}", reason))
-        }
-    };
-    let (_amount_out, balance) = match pool_variant {
-        PoolVariant::UniswapV2 => match braindance::decode_swap_v2_result(output.into()) {
-            Ok(output) => output,
-            Err(e) => return Err(anyhow::anyhow!("failed to decode swap result: {:?}", e)),
-        },
-        PoolVariant::UniswapV3 => match braindance::decode_swap_v3_result(output.into()) {
-            Ok(output) => output,
-            Err(e) => return Err(anyhow::anyhow!("failed to decode swap result: {:?}", e)),
-        },
-    };
-    Ok(balance)
+        .into()),
+        ExecutionResult::Halt { reason, .. } => Err(HindsightError::SwapHalted(reason).into()),
+    }
 }
 
-/// returns price of token1/token0 in forked EVM.
+/// Returns the price (TKN per WETH, 18 decimals) of a UniswapV3 pool in the forked EVM.
+/// One of the tokens must be `weth`.
+///
+/// The price is read from the pool's `slot0` & `liquidity` w/o committing anything, so probing
+/// doesn't move the fork's state, and it's converted w/ `get_price_v3` like the user's pool's
+/// price in `derive_trade_params`, so the two can be compared.
 pub async fn sim_price_v3(
     target_pool: Address,
     input_token: Address,
     output_token: Address,
+    weth: Address,
     evm: &mut EVM<ForkDB>,
 ) -> Result<U256> {
     abigen!(
@@ -110,31 +191,35 @@ pub async fn sim_price_v3(
         ],
         &output,
     )?;
-    let sqrt_price = slot0_tokens[0].clone().into_uint().expect("sqrt_price");
+    let sqrt_price = slot0_tokens[0].clone().into_uint().ok_or::<Error>(
+        HindsightError::MathError(format!(
+            "sqrt_price failed to cast token to uint (token={})",
+            slot0_tokens[0]
+        ))
+        .into(),
+    )?;
 
     let output = call_function(evm, "0x1a686502", target_pool)?; // liquidity()
     let liquidity_tokens = abi::decode(&vec![ParamType::Uint(128)], &output)?;
-    let liquidity = liquidity_tokens[0].clone().into_uint().expect("liquidity");
-
-    let token0 = match input_token < output_token {
-        true => input_token,
-        false => output_token,
-    };
-    let output = call_function(evm, "0x313ce567", token0)?; // decimals()
-    let token0_decimals_tokens = abi::decode(&vec![ParamType::Uint(8)], &output)?;
-    let token0_decimals = token0_decimals_tokens[0]
-        .clone()
-        .into_uint()
-        .expect("token0_decimals");
+    let liquidity = liquidity_tokens[0].clone().into_uint().ok_or::<Error>(
+        HindsightError::MathError(format!(
+            "liquidity failed to cast token to uint (token={})",
+            liquidity_tokens[0]
+        ))
+        .into(),
+    )?;
 
-    get_price_v3(liquidity, sqrt_price, token0_decimals)
+    let (token_decimals, token0_is_weth) = sim_pair_decimals(evm, input_token, output_token, weth)?;
+    get_price_v3(liquidity, sqrt_price, token_decimals, token0_is_weth)
 }
 
-/// returns price of token1/token0 in forked EVM.
+/// Returns the price (TKN per WETH, 18 decimals) of a UniswapV2 pair in the forked EVM.
+/// One of the tokens must be `weth`.
 pub async fn sim_price_v2(
     target_pool: Address,
     input_token: Address,
     output_token: Address,
+    weth: Address,
     evm: &mut EVM<ForkDB>,
 ) -> Result<U256> {
     // getReserves
@@ -145,7 +230,9 @@ pub async fn sim_price_v2(
     evm.env.tx.gas_price = rU256::from(100_000_000_000_i64);
     evm.env.tx.gas_limit = 900_000_u64;
     evm.env.tx.gas_priority_fee = Some(rU256::from(13_000_000_000_u64));
-    let result = match evm.transact_ref() {
+    evm.env.tx.nonce = None;
+    evm.env.tx.access_list = vec![];
+    let result = match transact_ref(evm) {
         Ok(result) => result.result,
         Err(e) => return Err(anyhow::format_err!(SimulationError::EvmError(e))),
     };
@@ -186,27 +273,120 @@ pub async fn sim_price_v2(
         .into(),
     )?;
 
-    let token0 = match input_token < output_token {
-        true => input_token,
-        false => output_token,
+    let (token_decimals, token0_is_weth) = sim_pair_decimals(evm, input_token, output_token, weth)?;
+    get_price_v2(reserves_0, reserves_1, token_decimals, token0_is_weth)
+}
+
+/// Returns how much WETH `pool` holds in the forked EVM. For V3 pools, that's the WETH in the
+/// virtual reserves of its in-range liquidity, which is what a swap can trade against; for Curve
+/// pools, it's their balance of ETH (or WETH).
+///
+/// `tokens` are the pool's WETH & other token.
+pub fn sim_weth_depth(
+    evm: &mut EVM<ForkDB>,
+    pool: Address,
+    pool_variant: PoolVariant,
+    tokens: &TokenPair,
+) -> Result<U256> {
+    let (reserves0, reserves1) = match pool_variant {
+        PoolVariant::UniswapV2 => {
+            let output = call_function(evm, "0x0902f1ac", pool)?; // getReserves()
+            let tokens = abi::decode(
+                &vec![
+                    ParamType::Uint(112), // reserve0
+                    ParamType::Uint(112), // reserve1
+                    ParamType::Uint(32),  // blockTimestampLast
+                ],
+                &output,
+            )?;
+            (
+                tokens[0].clone().into_uint().unwrap_or_default(),
+                tokens[1].clone().into_uint().unwrap_or_default(),
+            )
+        }
+        PoolVariant::UniswapV3 => {
+            let output = call_function(evm, "0x3850c7bd", pool)?; // slot0(), starting w/ sqrtPriceX96
+            let sqrt_price = abi::decode(&vec![ParamType::Uint(160)], &output)?[0]
+                .clone()
+                .into_uint()
+                .unwrap_or_default();
+            let output = call_function(evm, "0x1a686502", pool)?; // liquidity()
+            let liquidity = abi::decode(&vec![ParamType::Uint(128)], &output)?[0]
+                .clone()
+                .into_uint()
+                .unwrap_or_default();
+            if sqrt_price.is_zero() {
+                // uninitialized pool
+                return Ok(U256::zero());
+            }
+            (
+                mul_div(liquidity, Q96, sqrt_price)?,
+                mul_div(liquidity, sqrt_price, Q96)?,
+            )
+        }
+        PoolVariant::Curve => return sim_curve_weth_depth(evm, pool, tokens.weth),
+    };
+    Ok(if tokens.weth < tokens.token {
+        reserves0
+    } else {
+        reserves1
+    })
+}
+
+/// Returns the decimals of the pair's (token0, token1), and whether token0 is `weth`.
+fn sim_pair_decimals(
+    evm: &mut EVM<ForkDB>,
+    token_a: Address,
+    token_b: Address,
+    weth: Address,
+) -> Result<((U256, U256), bool)> {
+    let (token0, token1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
     };
-    let output = call_function(evm, "0x313ce567", token0)?; // decimals()
-    let token0_decimals_tokens = abi::decode(&vec![ParamType::Uint(8)], &output)?;
-    let token0_decimals = token0_decimals_tokens[0]
+    let mut decimals = vec![];
+    for token in [token0, token1] {
+        let output = call_function(evm, "0x313ce567", token)?; // decimals()
+        let decimals_tokens = abi::decode(&vec![ParamType::Uint(8)], &output)?;
+        decimals.push(decimals_tokens[0].clone().into_uint().ok_or::<Error>(
+            HindsightError::CallError("token decimals not found".to_owned()).into(),
+        )?);
+    }
+    Ok(((decimals[0], decimals[1]), token0 == weth))
+}
+
+/// Reads the ETH price (USD) from a Chainlink ETH/USD price feed as of the evm's block.
+pub fn sim_eth_usd_price(evm: &mut EVM<ForkDB>, feed: Address) -> Result<f64> {
+    let output = call_function(evm, "0x313ce567", feed)?; // decimals()
+    let decimals = abi::decode(&vec![ParamType::Uint(8)], &output)?[0]
         .clone()
         .into_uint()
-        .ok_or::<Error>(HindsightError::CallError("token decimals not found".to_owned()).into())?;
-
-    Ok(reserves_1
-        .mul(U256::from(10).pow(token0_decimals))
-        .checked_div(reserves_0)
         .ok_or::<Error>(
-            HindsightError::MathError(format!(
-                "failed to divide reserves (reserves_0, reserves_1)=({},{})",
-                reserves_0, reserves_1
-            ))
-            .into(),
-        )?)
+            HindsightError::CallError("price feed decimals not found".to_owned()).into(),
+        )?;
+    let output = call_function(evm, "0xfeaf968c", feed)?; // latestRoundData()
+    let round = abi::decode(
+        &vec![
+            ParamType::Uint(80),  // roundId
+            ParamType::Int(256),  // answer
+            ParamType::Uint(256), // startedAt
+            ParamType::Uint(256), // updatedAt
+            ParamType::Uint(80),  // answeredInRound
+        ],
+        &output,
+    )?;
+    let answer = round[1]
+        .clone()
+        .into_int()
+        .map(I256::from_raw)
+        .ok_or::<Error>(
+            HindsightError::CallError("price feed answer not found".to_owned()).into(),
+        )?;
+    if answer <= I256::zero() {
+        return Err(HindsightError::CallError(format!("price feed answered {}", answer)).into());
+    }
+    Ok(format_units(answer.into_raw(), decimals.as_u32())?.parse()?)
 }
 
 pub fn call_function(evm: &mut EVM<ForkDB>, method: &str, contract: Address) -> Result<Bytes> {
@@ -224,6 +404,31 @@ pub fn call_function(evm: &mut EVM<ForkDB>, method: &str, contract: Address) ->
     sim_tx_request(evm, tx)
 }
 
+/// Returns `owner`'s balance of the ERC20 `token` in the forked EVM.
+pub fn sim_balance_of(evm: &mut EVM<ForkDB>, token: Address, owner: Address) -> Result<U256> {
+    let mut data = ethers::utils::id("balanceOf(address)").to_vec();
+    data.extend(abi::encode(&[abi::Token::Address(owner)]));
+    let output = sim_tx_request(
+        evm,
+        TransactionRequest {
+            from: Some(get_eth_dev()),
+            to: Some(token.into()),
+            gas: Some(U256::from(900_000_u64)),
+            gas_price: Some(U256::from(1000_000_000_000_u64)),
+            data: Some(data.into()),
+            chain_id: Some(U64::from(1)),
+            ..Default::default()
+        },
+    )?;
+    let balance = abi::decode(&[ParamType::Uint(256)], &output)?[0]
+        .clone()
+        .into_uint()
+        .ok_or::<Error>(
+            HindsightError::CallError(format!("balanceOf({:?}) of {:?}", owner, token)).into(),
+        )?;
+    Ok(balance)
+}
+
 pub fn sim_tx_request(evm: &mut EVM<ForkDB>, tx: TransactionRequest) -> Result<Bytes> {
     evm.env.tx.caller = B160::from(tx.from.unwrap_or(get_eth_dev()));
     evm.env.tx.transact_to = TransactTo::Call(B160::from(
@@ -253,7 +458,10 @@ pub fn sim_tx_request(evm: &mut EVM<ForkDB>, tx: TransactionRequest) -> Result<B
     evm.env.tx.value = tx.value.unwrap_or_default().into();
     evm.env.tx.gas_price = tx.gas_price.unwrap_or_default().into();
     evm.env.tx.gas_limit = tx.gas.unwrap_or_default().as_u64();
-    let res = match evm.transact_ref() {
+    evm.env.tx.gas_priority_fee = None;
+    evm.env.tx.nonce = tx.nonce.map(|nonce| nonce.as_u64());
+    evm.env.tx.access_list = vec![];
+    let res = match transact_ref(evm) {
         Ok(res) => res.result,
         Err(err) => {
             return Err(anyhow::anyhow!("failed to simulate tx request: {:?}", err));
@@ -274,55 +482,253 @@ pub fn sim_tx_request(evm: &mut EVM<ForkDB>, tx: TransactionRequest) -> Result<B
     Ok(output)
 }
 
-fn inject_tx(evm: &mut EVM<ForkDB>, tx: &Transaction) -> Result<()> {
+/// Executes the tx in `evm`'s env without committing it, recording the state it touched if its
+/// block is being recorded (see `snapshot::Recording`).
+fn transact_ref(evm: &EVM<ForkDB>) -> EVMResult<<ForkDB as DatabaseRef>::Error> {
+    let res = evm.transact_ref()?;
+    snapshot::record_touched(evm.env.block.number, &res.state);
+    Ok(res)
+}
+
+/// Executes the tx in `evm`'s env & commits it, recording the state it touched if its block is
+/// being recorded (see `snapshot::Recording`).
+fn transact_commit(
+    evm: &mut EVM<ForkDB>,
+) -> Result<ExecutionResult, EVMError<<ForkDB as Database>::Error>> {
+    let ResultAndState { result, state } = evm.transact()?;
+    snapshot::record_touched(evm.env.block.number, &state);
+    if let Some(db) = evm.db.as_mut() {
+        db.commit(state);
+    }
+    Ok(result)
+}
+
+/// Loads `tx` into the EVM's tx env, so the next transact call executes it.
+///
+/// Legacy (type-0) & access-list (type-1) txs pay their `gas_price`; dynamic-fee (type-2) txs pay
+/// up to `max_fee_per_gas`, which revm lowers to `base fee + max_priority_fee_per_gas`. Txs that
+/// can't pay the forked block's base fee are rejected.
+///
+/// The tx's nonce & access list are loaded too, so it's checked & charged like it was onchain.
+fn inject_tx<DB>(evm: &mut EVM<DB>, tx: &Transaction) -> Result<()> {
     evm.env.tx.caller = B160::from(tx.from);
     evm.env.tx.transact_to = TransactTo::Call(B160::from(tx.to.unwrap_or_default().0));
     evm.env.tx.data = tx.input.to_owned().0;
     evm.env.tx.value = tx.value.into();
     evm.env.tx.chain_id = tx.chain_id.map(|id| id.as_u64());
     evm.env.tx.gas_limit = tx.gas.as_u64();
-    match tx.transaction_type {
-        Some(ethers::types::U64([0])) => {
-            evm.env.tx.gas_price = tx.gas_price.unwrap_or_default().into();
+    evm.env.tx.nonce = Some(tx.nonce.as_u64());
+    let (gas_price, access_list) = match tx.transaction_type.map(|t| t.as_u64()) {
+        // legacy tx
+        None | Some(0) => {
+            evm.env.tx.gas_priority_fee = None;
+            (tx.gas_price, None)
         }
-        Some(_) => {
-            // type-2 tx
-            evm.env.tx.gas_priority_fee = tx.max_priority_fee_per_gas.map(|fee| fee.into());
-            evm.env.tx.gas_price = tx.max_fee_per_gas.unwrap_or_default().into();
+        // access-list tx
+        Some(1) => {
+            evm.env.tx.gas_priority_fee = None;
+            (tx.gas_price, tx.access_list.as_ref())
         }
-        None => {
-            // legacy tx
-            evm.env.tx.gas_price = tx.gas_price.unwrap_or_default().into();
+        // dynamic-fee tx
+        Some(2) => {
+            evm.env.tx.gas_priority_fee = Some(
+                tx.max_priority_fee_per_gas
+                    .ok_or::<Error>(
+                        HindsightError::EvmParseError(format!(
+                            "type-2 tx missing max_priority_fee_per_gas (hash={:?})",
+                            tx.hash
+                        ))
+                        .into(),
+                    )?
+                    .into(),
+            );
+            (tx.max_fee_per_gas, tx.access_list.as_ref())
+        }
+        Some(tx_type) => {
+            return Err(HindsightError::EvmParseError(format!(
+                "unsupported tx type {} (hash={:?})",
+                tx_type, tx.hash
+            ))
+            .into())
         }
+    };
+    let gas_price: rU256 = gas_price
+        .ok_or::<Error>(
+            HindsightError::EvmParseError(format!("tx missing gas price (hash={:?})", tx.hash))
+                .into(),
+        )?
+        .into();
+    if gas_price < evm.env.block.basefee {
+        return Err(HindsightError::GasPriceBelowBaseFee {
+            tx_hash: tx.hash,
+            gas_price: gas_price.into(),
+            base_fee: evm.env.block.basefee.into(),
+        }
+        .into());
     }
+    evm.env.tx.gas_price = gas_price;
+    evm.env.tx.access_list = access_list
+        .map(|list| {
+            list.0
+                .iter()
+                .map(|item| {
+                    (
+                        B160::from(item.address),
+                        item.storage_keys
+                            .iter()
+                            .map(|key| rU256::from_be_bytes(key.0))
+                            .collect(),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
     Ok(())
 }
 
 /// Simulate a bundle of transactions, commiting each tx to the EVM's ForkDB.
 ///
-/// Returns array containing each tx's simulation result.
+/// Returns each tx's simulation result, in order. A tx that fails to execute doesn't stop the
+/// txs after it; callers decide which failures matter.
 pub async fn sim_bundle(
     evm: &mut EVM<ForkDB>,
     signed_txs: Vec<Transaction>,
-) -> Result<Vec<ExecutionResult>> {
+) -> Vec<Result<ExecutionResult>> {
     let mut results = vec![];
     for tx in signed_txs {
-        let res = commit_tx(evm, tx).await;
-        if let Ok(res) = res {
-            results.push(res.to_owned());
-        }
+        results.push(commit_tx(evm, tx).await);
     }
+    results
+}
 
-    Ok(results)
+/// Execute the user's tx like `commit_tx`, but fail with `HindsightError::UserTxFailed` unless
+/// it succeeds; backrunning a tx that didn't apply would arb pools it never moved.
+pub async fn commit_user_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ExecutionResult> {
+    let tx_hash = tx.hash;
+    let reason = match commit_tx(evm, tx).await {
+        Ok(res @ ExecutionResult::Success { .. }) => return Ok(res),
+        Ok(ExecutionResult::Revert { output, .. }) => {
+            format!("reverted: {}", decode_revert_reason(&output))
+        }
+        Ok(ExecutionResult::Halt { reason, .. }) => format!("halted: {:?}", reason),
+        Err(err) => err.to_string(),
+    };
+    Err(HindsightError::UserTxFailed { tx_hash, reason }.into())
 }
 
 /// Execute a transaction on the forked EVM, commiting its state changes to the EVM's ForkDB.
 pub async fn commit_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ExecutionResult> {
     inject_tx(evm, &tx)?;
-    let res = evm.transact_commit();
+    let res = transact_commit(evm);
     Ok(res.map_err(|err| anyhow::anyhow!("failed to simulate tx {:?}: {:?}", tx.hash, err))?)
 }
 
+/// Decodes RLP-encoded signed txs (legacy or typed, as sent in bundles) into the shape
+/// `inject_tx` reads, recovering their senders from their signatures.
+pub fn decode_signed_txs(raw_txs: &[Bytes]) -> Result<Vec<Transaction>> {
+    raw_txs
+        .iter()
+        .enumerate()
+        .map(|(i, raw)| {
+            let parse_err =
+                |err: String| HindsightError::EvmParseError(format!("bundle tx {}: {}", i, err));
+            if raw.is_empty() {
+                return Err(parse_err("empty tx".to_owned()).into());
+            }
+            let mut tx: Transaction =
+                rlp::decode(raw).map_err(|err| parse_err(format!("invalid RLP: {}", err)))?;
+            tx.recover_from_mut()
+                .map_err(|err| parse_err(format!("invalid signature: {}", err)))?;
+            Ok(tx)
+        })
+        .collect()
+}
+
+/// Commits the signed txs of a bundle (see `decode_signed_txs`) on `evm` in order, returning
+/// each one's result. Txs that revert don't stop the bundle, but invalid ones fail it (e.g. w/ a
+/// stale nonce, or a sender that can't pay for its gas; see `fork_evm_with_accounts`).
+pub async fn sim_signed_bundle(
+    evm: &mut EVM<ForkDB>,
+    raw_txs: &[Bytes],
+) -> Result<Vec<ExecutionResult>> {
+    let mut results = vec![];
+    for tx in decode_signed_txs(raw_txs)? {
+        results.push(commit_tx(evm, tx).await?);
+    }
+    Ok(results)
+}
+
+/// Returns a human-readable reason for a revert, decoded from the revert's output.
+///
+/// Handles `Error(string)` & `Panic(uint256)` payloads, and expands Uniswap's short revert strings.
+pub fn decode_revert_reason(output: &[u8]) -> String {
+    if output.is_empty() {
+        return "no revert data".to_owned();
+    }
+    if output.len() >= 4 {
+        let (selector, data) = output.split_at(4);
+        match selector {
+            // Error(string)
+            [0x08, 0xc3, 0x79, 0xa0] => {
+                if let Ok(tokens) = abi::decode(&[ParamType::String], data) {
+                    if let Some(reason) = tokens[0].clone().into_string() {
+                        return match uniswap_revert_description(&reason) {
+                            Some(description) => format!("{} ({})", reason, description),
+                            None => reason,
+                        };
+                    }
+                }
+            }
+            // Panic(uint256)
+            [0x4e, 0x48, 0x7b, 0x71] => {
+                if let Ok(tokens) = abi::decode(&[ParamType::Uint(256)], data) {
+                    if let Some(code) = tokens[0].clone().into_uint() {
+                        return format!(
+                            "panic 0x{:02x} ({})",
+                            code.low_u64(),
+                            panic_description(code)
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    format!("unknown revert data {}", Bytes::from(output.to_vec()))
+}
+
+/// Describes the short revert strings used by Uniswap V3 contracts.
+fn uniswap_revert_description(reason: &str) -> Option<&'static str> {
+    match reason {
+        "STF" => Some("safeTransferFrom failed"),
+        "TF" => Some("transfer failed"),
+        "ST" => Some("safeTransfer failed"),
+        "SA" => Some("safeApprove failed"),
+        "IIA" => Some("insufficient input amount"),
+        "SPL" => Some("sqrt price limit out of range"),
+        "AS" => Some("amount specified is zero"),
+        "LOK" => Some("pool locked"),
+        "L" => Some("no liquidity"),
+        _ => None,
+    }
+}
+
+/// Describes the compiler-inserted panic codes.
+fn panic_description(code: U256) -> &'static str {
+    match code.low_u64() {
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow/underflow",
+        0x12 => "division by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to uninitialized function",
+        _ => "unknown panic code",
+    }
+}
+
 pub async fn call_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ResultAndState> {
     inject_tx(evm, &tx)?;
     let res = evm.transact();
@@ -331,43 +737,585 @@ pub async fn call_tx(evm: &mut EVM<ForkDB>, tx: Transaction) -> Result<ResultAnd
 
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
-
-    use crate::{
-        sim::core::fork_evm,
-        util::{get_block_info, test::get_test_ws_client},
-        Result,
-    };
+    use super::{decode_revert_reason, decode_signed_txs, inject_tx};
+    use crate::{error::HindsightError, Result};
     use ethers::{
-        providers::Middleware,
-        types::{Address, U256},
+        abi::{self, Token},
+        signers::{LocalWallet, Signer},
+        types::{
+            transaction::{
+                eip2718::TypedTransaction,
+                eip2930::{AccessList, AccessListItem},
+            },
+            Address, Bytes, Eip1559TransactionRequest, Transaction, TransactionRequest, H256, U64,
+        },
     };
+    use revm::{
+        db::EmptyDB,
+        primitives::{B160, U256 as rU256},
+        EVM,
+    };
+
+    const GWEI: u64 = 1_000_000_000;
+
+    fn empty_evm(base_fee: u64) -> EVM<EmptyDB> {
+        let mut evm = EVM::new();
+        evm.env.block.basefee = rU256::from(base_fee);
+        evm
+    }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn it_gets_sim_price_v2() -> Result<()> {
-        let client = get_test_ws_client().await?;
-        let block_info = get_block_info(&client, client.get_block_number().await?.as_u64()).await?;
-        let mut evm = fork_evm(&client, &block_info).await?;
-        let target_pool = Address::from_str("0x811beEd0119b4AfCE20D2583EB608C6F7AF1954f")?; // UniV2 SHIB/WETH
-        let token_in = Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?; // WETH
-        let token_out = Address::from_str("0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE")?; // SHIB
-        let price = super::sim_price_v2(target_pool, token_in, token_out, &mut evm).await?;
-        println!("price: {}", price);
-        assert_ne!(price, U256::from(0));
+    fn access_list() -> AccessList {
+        AccessList(vec![AccessListItem {
+            address: Address::from_low_u64_be(0xbeef),
+            storage_keys: vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)],
+        }])
+    }
+
+    fn expected_access_list() -> Vec<(B160, Vec<rU256>)> {
+        vec![(
+            B160::from(Address::from_low_u64_be(0xbeef)),
+            vec![rU256::from(1), rU256::from(2)],
+        )]
+    }
+
+    #[test]
+    fn it_injects_legacy_txs() -> Result<()> {
+        let mut evm = empty_evm(20 * GWEI);
+        let tx = Transaction {
+            transaction_type: Some(U64::from(0)),
+            nonce: 7.into(),
+            gas: 21_000.into(),
+            gas_price: Some((30 * GWEI).into()),
+            ..Default::default()
+        };
+        inject_tx(&mut evm, &tx)?;
+        assert_eq!(evm.env.tx.gas_limit, 21_000);
+        assert_eq!(evm.env.tx.nonce, Some(7));
+        assert_eq!(evm.env.tx.gas_price, rU256::from(30 * GWEI));
+        assert_eq!(evm.env.tx.gas_priority_fee, None);
+        assert!(evm.env.tx.access_list.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_injects_access_list_txs() -> Result<()> {
+        let mut evm = empty_evm(20 * GWEI);
+        let tx = Transaction {
+            transaction_type: Some(U64::from(1)),
+            gas: 50_000.into(),
+            gas_price: Some((30 * GWEI).into()),
+            access_list: Some(access_list()),
+            ..Default::default()
+        };
+        inject_tx(&mut evm, &tx)?;
+        assert_eq!(evm.env.tx.gas_price, rU256::from(30 * GWEI));
+        assert_eq!(evm.env.tx.gas_priority_fee, None);
+        assert_eq!(evm.env.tx.access_list, expected_access_list());
         Ok(())
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn it_gets_sim_price_v3() -> Result<()> {
-        let client = get_test_ws_client().await?;
-        let block_info = get_block_info(&client, client.get_block_number().await?.as_u64()).await?;
-        let mut evm = fork_evm(&client, &block_info).await?;
-        let target_pool = Address::from_str("0x2F62f2B4c5fcd7570a709DeC05D68EA19c82A9ec")?; // UniV3 SHIB/WETH (fee=3000)
-        let token_in = Address::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?; // WETH
-        let token_out = Address::from_str("0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE")?; // SHIB
-        let price = super::sim_price_v3(target_pool, token_in, token_out, &mut evm).await?;
-        println!("price: {}", price);
-        assert_ne!(price, U256::from(0));
+    #[test]
+    fn it_injects_dynamic_fee_txs() -> Result<()> {
+        let mut evm = empty_evm(20 * GWEI);
+        let tx = Transaction {
+            transaction_type: Some(U64::from(2)),
+            gas: 50_000.into(),
+            max_fee_per_gas: Some((40 * GWEI).into()),
+            max_priority_fee_per_gas: Some((2 * GWEI).into()),
+            access_list: Some(access_list()),
+            ..Default::default()
+        };
+        inject_tx(&mut evm, &tx)?;
+        assert_eq!(evm.env.tx.gas_price, rU256::from(40 * GWEI));
+        assert_eq!(evm.env.tx.gas_priority_fee, Some(rU256::from(2 * GWEI)));
+        assert_eq!(evm.env.tx.access_list, expected_access_list());
+
+        // a max fee below the base fee can't land
+        let mut evm = empty_evm(50 * GWEI);
+        let err = inject_tx(&mut evm, &tx).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<HindsightError>(),
+            Some(HindsightError::GasPriceBelowBaseFee { .. })
+        ));
         Ok(())
     }
+
+    #[test]
+    fn it_decodes_signed_txs() -> Result<()> {
+        let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(1u64);
+        let to = Address::from_low_u64_be(0xbeef);
+        let legacy: TypedTransaction = TransactionRequest::new()
+            .to(to)
+            .value(1_000)
+            .nonce(7)
+            .gas(21_000)
+            .gas_price(30 * GWEI)
+            .chain_id(1)
+            .into();
+        let dynamic: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(to)
+            .data(vec![0x12, 0x34])
+            .nonce(8)
+            .gas(50_000)
+            .max_fee_per_gas(40 * GWEI)
+            .max_priority_fee_per_gas(2 * GWEI)
+            .chain_id(1)
+            .into();
+        let raw_txs = [&legacy, &dynamic]
+            .into_iter()
+            .map(|tx| Ok(tx.rlp_signed(&wallet.sign_transaction_sync(tx)?)))
+            .collect::<Result<Vec<Bytes>>>()?;
+        let txs = decode_signed_txs(&raw_txs)?;
+        assert_eq!(txs.len(), 2);
+        for tx in &txs {
+            assert_eq!(tx.from, wallet.address());
+            assert_eq!(tx.to, Some(to));
+        }
+        assert_eq!((txs[0].nonce, txs[0].value), (7.into(), 1_000.into()));
+        assert_eq!(txs[1].transaction_type, Some(U64::from(2)));
+        assert_eq!(txs[1].input, Bytes::from(vec![0x12, 0x34]));
+
+        // the txs inject like any other
+        let mut evm = empty_evm(20 * GWEI);
+        inject_tx(&mut evm, &txs[1])?;
+        assert_eq!(evm.env.tx.caller, B160::from(wallet.address()));
+        assert_eq!(evm.env.tx.nonce, Some(8));
+        assert_eq!(evm.env.tx.gas_priority_fee, Some(rU256::from(2 * GWEI)));
+
+        for raw in [Bytes::default(), Bytes::from(vec![0xde, 0xad])] {
+            let err = decode_signed_txs(&[raw]).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<HindsightError>(),
+                Some(HindsightError::EvmParseError(_))
+            ));
+        }
+        Ok(())
+    }
+
+    fn revert_payload(selector: [u8; 4], token: Token) -> Vec<u8> {
+        [selector.to_vec(), abi::encode(&[token])].concat()
+    }
+
+    #[test]
+    fn it_decodes_revert_reasons() {
+        let error_selector = [0x08, 0xc3, 0x79, 0xa0];
+        let output = revert_payload(
+            error_selector,
+            Token::String("UniswapV2: INSUFFICIENT_OUTPUT_AMOUNT".to_owned()),
+        );
+        assert_eq!(
+            decode_revert_reason(&output),
+            "UniswapV2: INSUFFICIENT_OUTPUT_AMOUNT"
+        );
+
+        let output = revert_payload(error_selector, Token::String("STF".to_owned()));
+        assert_eq!(
+            decode_revert_reason(&output),
+            "STF (safeTransferFrom failed)"
+        );
+
+        let output = revert_payload([0x4e, 0x48, 0x7b, 0x71], Token::Uint(0x11.into()));
+        assert_eq!(
+            decode_revert_reason(&output),
+            "panic 0x11 (arithmetic overflow/underflow)"
+        );
+
+        assert_eq!(decode_revert_reason(&[]), "no revert data");
+        assert_eq!(
+            decode_revert_reason(&[0xde, 0xad]),
+            "unknown revert data 0xdead"
+        );
+    }
+
+    /// Tests against a fork of mainnet at `FORK_BLOCK`, so they can assert exact amounts.
+    #[cfg(feature = "integration-tests")]
+    mod fork {
+        use super::GWEI;
+        use crate::{
+            chain::ChainConfig,
+            error::HindsightError,
+            interfaces::PoolVariant,
+            sim::{
+                core::{fork_evm, fork_evm_with_accounts, Braindance},
+                evm::{commit_braindance_swap, sim_signed_bundle},
+            },
+            test_chain::TestChain,
+            util::{get_price_v2, get_price_v3, RpcClient, ETH},
+            Result,
+        };
+        use ethers::signers::{LocalWallet, Signer};
+        use ethers::{
+            abi::{self, ParamType, Token},
+            providers::Middleware,
+            types::{
+                transaction::eip2718::TypedTransaction, Address, Bytes, TransactionRequest, U256,
+            },
+        };
+        use revm::{
+            db::DatabaseRef,
+            primitives::{ExecutionResult, B160},
+        };
+        use rusty_sando::{prelude::fork_db::ForkDB, simulate::braindance_starting_balance};
+        use std::str::FromStr;
+
+        /// ETH balance of `account` on `evm`'s fork.
+        fn sim_eth_balance(evm: &revm::EVM<ForkDB>, account: Address) -> Result<U256> {
+            let db = evm.db.as_ref().expect("evm has a db");
+            let info = DatabaseRef::basic(db, B160::from(account))
+                .map_err(|err| anyhow::anyhow!("failed to read balance: {:?}", err))?;
+            Ok(info
+                .map(|info| U256(info.balance.into_limbs()))
+                .unwrap_or_default())
+        }
+
+        const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+        const SHIB: &str = "0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE";
+        const SHIB_WETH_V2: &str = "0x811beEd0119b4AfCE20D2583EB608C6F7AF1954f"; // UniswapV2
+        const SHIB_WETH_V3: &str = "0x2F62f2B4c5fcd7570a709DeC05D68EA19c82A9ec"; // UniswapV3 (fee=3000)
+        /// UniswapV3's Quoter, which quotes a swap by simulating it on-chain.
+        const V3_QUOTER: &str = "0xb27308f9F90D607463bb33eA1BeBb41C27CE5AB6";
+
+        /// Calls `selector` on `contract` through anvil, rather than a fork of it.
+        async fn call_onchain(
+            client: &RpcClient,
+            contract: Address,
+            selector: &str,
+            output_types: &[ParamType],
+        ) -> Result<Vec<U256>> {
+            let tx: TypedTransaction = TransactionRequest::new()
+                .to(contract)
+                .data(Bytes::from_str(selector)?)
+                .into();
+            let output = client.call(&tx, None).await?;
+            Ok(abi::decode(output_types, &output)?
+                .into_iter()
+                .map(|token| token.into_uint().unwrap_or_default())
+                .collect())
+        }
+
+        /// Reserves of the UniswapV2 SHIB/WETH pair on anvil, as (SHIB, WETH).
+        async fn shib_weth_reserves(client: &RpcClient) -> Result<(U256, U256)> {
+            let reserves = call_onchain(
+                client,
+                SHIB_WETH_V2.parse()?,
+                "0x0902f1ac", // getReserves()
+                &[
+                    ParamType::Uint(112),
+                    ParamType::Uint(112),
+                    ParamType::Uint(32),
+                ],
+            )
+            .await?;
+            Ok((reserves[0], reserves[1]))
+        }
+
+        /// UniswapV2Library.getAmountOut
+        fn get_amount_out_v2(amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
+            let amount_in_with_fee = amount_in * 997;
+            amount_in_with_fee * reserve_out / (reserve_in * 1000 + amount_in_with_fee)
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_gets_sim_price_v2() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+            )
+            .await?;
+            let target_pool = Address::from_str(SHIB_WETH_V2)?;
+            let token_in = Address::from_str(WETH)?;
+            let token_out = Address::from_str(SHIB)?;
+            let price =
+                crate::sim::evm::sim_price_v2(target_pool, token_in, token_out, token_in, &mut evm)
+                    .await?;
+
+            // SHIB is token0; both have 18 decimals
+            let (shib, weth) = shib_weth_reserves(&chain.client).await?;
+            assert_eq!(
+                price,
+                get_price_v2(shib, weth, (18.into(), 18.into()), false)?
+            );
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_gets_sim_price_v3() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+            )
+            .await?;
+            let target_pool = Address::from_str(SHIB_WETH_V3)?;
+            let token_in = Address::from_str(WETH)?;
+            let token_out = Address::from_str(SHIB)?;
+            let price =
+                crate::sim::evm::sim_price_v3(target_pool, token_in, token_out, token_in, &mut evm)
+                    .await?;
+
+            let sqrt_price = call_onchain(
+                &chain.client,
+                target_pool,
+                "0x3850c7bd", // slot0(), starting w/ sqrtPriceX96
+                &[ParamType::Uint(160)],
+            )
+            .await?[0];
+            let liquidity = call_onchain(
+                &chain.client,
+                target_pool,
+                "0x1a686502", // liquidity()
+                &[ParamType::Uint(128)],
+            )
+            .await?[0];
+            assert_eq!(
+                price,
+                get_price_v3(liquidity, sqrt_price, (18.into(), 18.into()), false)?
+            );
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_matches_sim_price_v3_to_an_onchain_quote() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+            )
+            .await?;
+            let target_pool = Address::from_str(SHIB_WETH_V3)?;
+            let weth = Address::from_str(WETH)?;
+            let shib = Address::from_str(SHIB)?;
+            let price =
+                crate::sim::evm::sim_price_v3(target_pool, weth, shib, weth, &mut evm).await?;
+
+            // quote a swap small enough that its price impact is negligible next to the pool's fee
+            let amount_in = ETH / 100;
+            let mut data =
+                ethers::utils::id("quoteExactInputSingle(address,address,uint24,uint256,uint160)")
+                    .to_vec();
+            data.extend(abi::encode(&[
+                Token::Address(weth),
+                Token::Address(shib),
+                Token::Uint(3000.into()),
+                Token::Uint(amount_in),
+                Token::Uint(U256::zero()),
+            ]));
+            let tx: TypedTransaction = TransactionRequest::new()
+                .to(Address::from_str(V3_QUOTER)?)
+                .data(data)
+                .into();
+            let output = chain.client.call(&tx, None).await?;
+            let amount_out = U256::from_big_endian(&output[..32]);
+            let quoted_price = amount_out * ETH / amount_in;
+
+            // the quote pays the pool's 0.3% fee, which the sim (mid) price doesn't
+            assert!(
+                quoted_price < price,
+                "quoted {} should be below the sim price {}",
+                quoted_price,
+                price
+            );
+            let diff_bps = (price - quoted_price) * 10_000 / price;
+            assert!(
+                diff_bps <= 50.into(),
+                "sim price {} is {} bps from the quoted {}",
+                price,
+                diff_bps,
+                quoted_price
+            );
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_simulates_swaps() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+            )
+            .await?;
+            let pool = Address::from_str(SHIB_WETH_V2)?;
+            let (weth, shib) = (Address::from_str(WETH)?, Address::from_str(SHIB)?);
+            let (shib_reserve, weth_reserve) = shib_weth_reserves(&chain.client).await?;
+            let gas_price = U256::from(420 * GWEI);
+            let amount_in = ETH * 69;
+
+            // buy 69 ETH worth of SHIB
+            let bought = commit_braindance_swap(
+                &mut evm,
+                PoolVariant::UniswapV2,
+                amount_in,
+                pool,
+                weth,
+                shib,
+                gas_price,
+                None,
+                Braindance::default().swap_gas_limit,
+            )?;
+            let expected_shib = get_amount_out_v2(amount_in, weth_reserve, shib_reserve);
+            assert_eq!(bought.amount_out, expected_shib);
+            assert!(bought.gas_used > 0);
+
+            // sell all the SHIB back to the same pair, against the reserves the buy left it with
+            let sold = commit_braindance_swap(
+                &mut evm,
+                PoolVariant::UniswapV2,
+                bought.amount_out,
+                pool,
+                shib,
+                weth,
+                gas_price,
+                None,
+                Braindance::default().swap_gas_limit,
+            )?;
+            let expected_weth = get_amount_out_v2(
+                bought.amount_out,
+                shib_reserve - expected_shib,
+                weth_reserve + amount_in,
+            );
+            assert_eq!(sold.amount_out, expected_weth);
+            // fees are paid both ways
+            assert!(sold.amount_out < amount_in);
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_returns_swap_reverted_error() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+            )
+            .await?;
+
+            // try to swap more WETH than the braindance contract has
+            let res = commit_braindance_swap(
+                &mut evm,
+                PoolVariant::UniswapV2,
+                braindance_starting_balance() * 2,
+                Address::from_str(SHIB_WETH_V2)?,
+                Address::from_str(WETH)?,
+                Address::from_str(SHIB)?,
+                U256::from(420 * GWEI),
+                None,
+                Braindance::default().swap_gas_limit,
+            );
+            let err = res.expect_err("swap should revert");
+            assert!(matches!(
+                err.downcast_ref::<HindsightError>(),
+                Some(HindsightError::SwapReverted { .. })
+            ));
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_funds_braindance_with_configured_balance() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let braindance = Braindance {
+                starting_balance: braindance_starting_balance() * 3,
+                ..Default::default()
+            };
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &braindance,
+            )
+            .await?;
+
+            // more WETH than the default balance
+            let bought = commit_braindance_swap(
+                &mut evm,
+                PoolVariant::UniswapV2,
+                braindance_starting_balance() * 2,
+                Address::from_str(SHIB_WETH_V2)?,
+                Address::from_str(WETH)?,
+                Address::from_str(SHIB)?,
+                U256::from(420 * GWEI),
+                None,
+                braindance.swap_gas_limit,
+            )?;
+            assert!(bought.amount_out > U256::zero());
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_sims_signed_bundles_from_overridden_accounts() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(1u64);
+            let to = Address::from_low_u64_be(0xbeef);
+            let mut evm = fork_evm_with_accounts(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+                vec![(wallet.address(), ETH, 42)],
+            )
+            .await?;
+            let transfer = |nonce: u64| -> Result<Bytes> {
+                let tx: TypedTransaction = TransactionRequest::new()
+                    .to(to)
+                    .value(ETH / 2)
+                    .nonce(nonce)
+                    .gas(21_000)
+                    .gas_price(420 * GWEI)
+                    .chain_id(1)
+                    .into();
+                Ok(tx.rlp_signed(&wallet.sign_transaction_sync(&tx)?))
+            };
+
+            // a fresh EOA can only send these w/ its balance & nonce overridden
+            let results = sim_signed_bundle(&mut evm, &[transfer(42)?, transfer(43)?]).await?;
+            assert_eq!(results.len(), 2);
+            assert!(results
+                .iter()
+                .all(|res| matches!(res, ExecutionResult::Success { .. })));
+            assert_eq!(sim_eth_balance(&evm, to)?, ETH);
+
+            // a stale nonce fails the bundle
+            assert!(sim_signed_bundle(&mut evm, &[transfer(43)?]).await.is_err());
+            Ok(())
+        }
+
+        #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+        async fn it_halts_swaps_out_of_gas() -> Result<()> {
+            let chain = TestChain::new().await?;
+            let mut evm = fork_evm(
+                &chain.client,
+                &chain.block_info,
+                &ChainConfig::default(),
+                &Braindance::default(),
+            )
+            .await?;
+
+            let res = commit_braindance_swap(
+                &mut evm,
+                PoolVariant::UniswapV2,
+                ETH,
+                Address::from_str(SHIB_WETH_V2)?,
+                Address::from_str(WETH)?,
+                Address::from_str(SHIB)?,
+                U256::from(420 * GWEI),
+                None,
+                30_000,
+            );
+            let err = res.expect_err("swap should run out of gas");
+            assert!(matches!(
+                err.downcast_ref::<HindsightError>(),
+                Some(HindsightError::SwapHalted(_))
+            ));
+            Ok(())
+        }
+    }
 }
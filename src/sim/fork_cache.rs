@@ -0,0 +1,179 @@
+use crate::sim::core::{new_fork_factory, txs_state_db};
+use crate::util::{fork_provider, RpcClient};
+use crate::{debug, Result};
+use ethers::types::Transaction;
+use rusty_sando::{forked_db::fork_factory::ForkFactory, types::BlockInfo};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::OnceCell;
+
+/// Default number of blocks whose fork factories are kept.
+pub const DEFAULT_FORK_CACHE_BLOCKS: usize = 4;
+
+/// How much the fork factory of one block was shared.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ForkCacheStats {
+    /// Fork backends built for the block. Each one starts empty, so it fetches every account &
+    /// storage slot its sims read from the node.
+    pub backends_built: usize,
+    /// Events whose forks reused a backend that was already built, w/o fetching its state again.
+    pub reused: usize,
+}
+
+/// A block's fork factory, built by the first event on the block that needs it.
+struct CachedBlock {
+    number: u64,
+    fork_factory: Arc<OnceCell<ForkFactory>>,
+    stats: ForkCacheStats,
+}
+
+/// Fork factories of the latest blocks that were simulated, shared by the events that land in
+/// them. Many events land in the same block, and their sims read much of the same state (WETH,
+/// routers, popular pools), which a shared factory's backend only fetches once.
+///
+/// Each event gets its own copy of the block's factory, so the state of the txs it's placed
+/// after (and its braindance funding) is only layered on top of its own forks.
+/// Holds the factories of up to `capacity` blocks, evicting the least recently used.
+pub struct BlockForkCache {
+    capacity: usize,
+    /// Most recently used last.
+    blocks: Mutex<VecDeque<CachedBlock>>,
+}
+
+impl std::fmt::Debug for BlockForkCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlockForkCache")
+            .field("capacity", &self.capacity)
+            .field(
+                "blocks",
+                &self
+                    .blocks()
+                    .iter()
+                    .map(|block| block.number)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl BlockForkCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            blocks: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn blocks(&self) -> MutexGuard<VecDeque<CachedBlock>> {
+        self.blocks.lock().expect("fork cache poisoned")
+    }
+
+    /// Returns the (maybe not yet built) fork factory of block `number`, marking it as the most
+    /// recently used.
+    fn entry(&self, number: u64) -> Arc<OnceCell<ForkFactory>> {
+        let mut blocks = self.blocks();
+        if let Some(i) = blocks.iter().position(|block| block.number == number) {
+            let block = blocks.remove(i).expect("position is in bounds");
+            let fork_factory = block.fork_factory.clone();
+            blocks.push_back(block);
+            return fork_factory;
+        }
+        let fork_factory = Arc::new(OnceCell::new());
+        blocks.push_back(CachedBlock {
+            number,
+            fork_factory: fork_factory.clone(),
+            stats: ForkCacheStats::default(),
+        });
+        while blocks.len() > self.capacity {
+            if let Some(evicted) = blocks.pop_front() {
+                debug!("fork cache: block {} {:?}", evicted.number, evicted.stats);
+            }
+        }
+        fork_factory
+    }
+
+    fn count(&self, number: u64, built: bool) {
+        if let Some(block) = self
+            .blocks()
+            .iter_mut()
+            .find(|block| block.number == number)
+        {
+            if built {
+                block.stats.backends_built += 1;
+            } else {
+                block.stats.reused += 1;
+            }
+        }
+    }
+
+    /// How much block `number`'s fork factory has been shared, if it's cached.
+    pub fn stats(&self, number: u64) -> Option<ForkCacheStats> {
+        self.blocks()
+            .iter()
+            .find(|block| block.number == number)
+            .map(|block| block.stats)
+    }
+
+    /// Returns a fork factory for the state before `block_info`'s block, w/ `txs` applied on top,
+    /// like `new_fork_factory`, but sharing the block's backend w/ the other events on it.
+    pub async fn fork_factory(
+        &self,
+        client: &RpcClient,
+        block_info: &BlockInfo,
+        txs: &Vec<Transaction>,
+    ) -> Result<ForkFactory> {
+        let number = block_info.number.as_u64();
+        let built = AtomicBool::new(false);
+        let mut fork_factory = self
+            .entry(number)
+            .get_or_try_init(|| async {
+                built.store(true, Ordering::Relaxed);
+                new_fork_factory(client, block_info, &vec![]).await
+            })
+            .await?
+            .clone();
+        self.count(number, built.load(Ordering::Relaxed));
+        if txs.is_empty() {
+            return Ok(fork_factory);
+        }
+        let txs_db = txs_state_db(&fork_provider(client).await?, block_info, txs).await?;
+        for (address, account) in txs_db.accounts {
+            fork_factory.insert_account_info(address, account.info);
+            for (slot, value) in account.storage {
+                fork_factory
+                    .insert_account_storage(address, slot, value)
+                    .map_err(|err| {
+                        anyhow::anyhow!("failed to layer tx state on fork: {:?}", err)
+                    })?;
+            }
+        }
+        Ok(fork_factory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_evicts_least_recently_used_blocks() {
+        let cache = BlockForkCache::new(2);
+        cache.entry(1);
+        cache.entry(2);
+        cache.count(1, true);
+        // 1 is now the most recently used, so 2 is evicted for 3
+        cache.entry(1);
+        cache.entry(3);
+        cache.count(1, false);
+        assert_eq!(
+            cache.stats(1),
+            Some(ForkCacheStats {
+                backends_built: 1,
+                reused: 1
+            })
+        );
+        assert_eq!(cache.stats(2), None);
+        assert_eq!(cache.stats(3), Some(ForkCacheStats::default()));
+    }
+}
@@ -1,3 +1,8 @@
+pub mod bundle;
 pub mod core;
+pub mod curve;
+pub mod event_filter;
 pub mod evm;
+pub mod fork_cache;
 pub mod processor;
+pub mod snapshot;
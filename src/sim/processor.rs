@@ -1,25 +1,82 @@
+use crate::data::audit::AuditRecord;
 use crate::error::HindsightError;
-use crate::interfaces::SimArbResultBatch;
-use crate::{info, Error, Result};
-use crate::{sim::core::find_optimal_backrun_amount_in_out, util::WsClient};
+use crate::interfaces::{HintSummary, SimArbResultBatch};
+use crate::{info, warn, Error, Result};
+use crate::{
+    sim::core::{find_optimal_backrun_amount_in_out, non_hinted_decisions, SimOptions},
+    util::RpcClient,
+};
 use ethers::{
     providers::Middleware,
     types::{Transaction, H256, U256},
 };
 use mev_share_sse::EventHistory;
 use rusty_sando::types::BlockInfo;
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Instant};
 
 pub type H256Map<T> = HashMap<H256, T>;
 
+/// Simulates backrun-arbs for `tx`, whose event is looked up in `event_map`.
 pub async fn simulate_backrun_arbs(
-    client: &WsClient,
+    client: &RpcClient,
     tx: Transaction,
     event_map: &H256Map<EventHistory>,
+    sim_options: SimOptions,
+) -> Result<SimArbResultBatch> {
+    let event = match event_map.get(&tx.hash) {
+        Some(event) => event,
+        None => {
+            let res = Err(HindsightError::EventNotCached(tx.hash).into());
+            if let Some(log) = &sim_options.audit_log {
+                let mut audit = AuditRecord::new(tx.hash, tx.block_number.map(|b| b.as_u64()));
+                audit.finish(&res, Default::default());
+                if let Err(err) = log.write(&audit) {
+                    warn!("failed to write audit record for tx {:?}: {}", tx.hash, err);
+                }
+            }
+            return res;
+        }
+    };
+    simulate_event_backrun_arbs(client, tx, event, sim_options).await
+}
+
+/// Simulates backrun-arbs for `tx` (which must have landed), the tx of `event`, on the state
+/// before its block. Returns the results as a batch, ready to be saved.
+///
+/// Appends a record of the sims to the audit log (if there is one), whether they succeed or not.
+pub async fn simulate_event_backrun_arbs(
+    client: &RpcClient,
+    tx: Transaction,
+    event: &EventHistory,
+    sim_options: SimOptions,
 ) -> Result<SimArbResultBatch> {
-    let event = event_map
-        .get(&tx.hash)
-        .ok_or::<Error>(HindsightError::EventNotCached(tx.hash).into())?;
+    let started = Instant::now();
+    let audit_log = sim_options.audit_log.clone();
+    let mut audit = AuditRecord::new(event.hint.hash, Some(event.block));
+    let res = simulate_audited(client, tx, event, sim_options, &mut audit).await;
+    if let Some(log) = audit_log {
+        audit.finish(&res, started.elapsed());
+        if let Err(err) = log.write(&audit) {
+            warn!(
+                "failed to write audit record for tx {:?}: {}",
+                event.hint.hash, err
+            );
+        }
+    }
+    res
+}
+
+async fn simulate_audited(
+    client: &RpcClient,
+    tx: Transaction,
+    event: &EventHistory,
+    sim_options: SimOptions,
+    audit: &mut AuditRecord,
+) -> Result<SimArbResultBatch> {
+    // skip events we don't care about before doing anything expensive
+    if !sim_options.event_filter.allows_event(client, event).await {
+        return Err(HindsightError::EventFiltered(tx.hash).into());
+    }
     let sim_block_num = tx
         .block_number
         .ok_or::<Error>(HindsightError::TxNotLanded(tx.hash).into())?;
@@ -36,7 +93,34 @@ pub async fn simulate_backrun_arbs(
         base_fee: block.base_fee_per_gas.unwrap_or(1_000_000_000.into()),
     };
 
-    let res = find_optimal_backrun_amount_in_out(&client, tx, &event, &block_info).await?;
+    // cancels the event's searches once it's done (or timed out, or dropped), so none keep running
+    let cancel = sim_options.cancel.child_token();
+    let _cancel_guard = cancel.clone().drop_guard();
+    let timeout = sim_options.event_timeout;
+    let placement = sim_options.placement;
+    let hint_only = sim_options.hint_only;
+    let sim_options = SimOptions {
+        cancel,
+        ..sim_options
+    };
+    let sim = find_optimal_backrun_amount_in_out(&client, tx, &event, &block_info, sim_options);
+    let outcome = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, sim)
+            .await
+            .map_err::<Error, _>(|_| HindsightError::Timeout(event.hint.hash).into())??,
+        None => sim.await?,
+    };
+    audit.searched(&outcome);
+    if !outcome.failures.is_empty() {
+        warn!(
+            "sims for tx {:?}: {} succeeded, {}",
+            event.hint.hash,
+            outcome.results.len(),
+            outcome.failure_summary()
+        );
+    }
+    let non_hinted = non_hinted_decisions(&event.hint, &outcome.params);
+    let res = outcome.results;
     let mut max_profit = U256::from(0);
     /*
        Sum up the profit from each result. Generally there should only be one result, but if
@@ -51,9 +135,19 @@ pub async fn simulate_backrun_arbs(
             max_profit = res.backrun_trade.profit;
         }
     }
-    Ok(SimArbResultBatch {
+    let mut batch = SimArbResultBatch {
         event: event.to_owned(),
         max_profit,
         results: res,
-    })
+        failures: outcome.failures.len(),
+        live: false,
+        placement,
+        eth_usd_price: outcome.eth_usd_price,
+        profit_usd: None,
+        hint: Some(HintSummary::from(&event.hint)),
+        non_hinted,
+        hint_only,
+    };
+    batch.profit_usd = batch.to_usd(max_profit);
+    Ok(batch)
 }
@@ -0,0 +1,331 @@
+//! Snapshots of the fork state that sims touch, so they can be re-simulated without a node
+//! (e.g. by `simulate-tx --offline`, or `benches/arb_search.rs`).
+//!
+//! While a `Recording` is running, every tx simulated on a fork of its block adds the accounts &
+//! storage slots it touched to the recording. Finishing reads their values from a fresh fork (so
+//! they're the fork's state, not some sim's), which `StateSnapshot` can rebuild into a `ForkDB`
+//! that never goes to the node.
+
+use crate::{data::checkpoint::CACHE_DIR, interfaces::UserTradeParams, warn, Result};
+use ethers::{
+    types::{Address, Bytes, Transaction, H256, U256},
+    utils::keccak256,
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use mev_share_sse::EventHistory;
+use revm::{
+    db::{CacheDB, DatabaseRef, EmptyDB},
+    primitives::{AccountInfo, Bytecode, State, B160, B256, KECCAK_EMPTY, U256 as rU256},
+};
+use rusty_sando::{prelude::fork_db::ForkDB, types::BlockInfo};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Storage slots touched per account.
+type Touched = BTreeMap<B160, BTreeSet<rU256>>;
+
+/// State touched by the sims of a block while it's being recorded, and how many recordings of it
+/// are running.
+#[derive(Default)]
+struct BlockRecording {
+    recordings: usize,
+    touched: Touched,
+}
+
+/// Number of recordings running; checked before locking `RECORDINGS`, so sims don't contend for
+/// it when nothing's recording.
+static ACTIVE_RECORDINGS: AtomicUsize = AtomicUsize::new(0);
+/// Recordings by the block number of the EVMs they record. Events in the same block share one, so
+/// their snapshots may have some of each other's state, but never miss any of their own.
+static RECORDINGS: Mutex<BTreeMap<rU256, BlockRecording>> = Mutex::new(BTreeMap::new());
+
+/// Records the state touched by sims on forks of one block, until it's finished or dropped.
+pub struct Recording {
+    block: rU256,
+}
+
+impl Recording {
+    /// Starts recording the state touched by sims whose EVM env is at `block`.
+    pub fn start(block: rU256) -> Self {
+        let mut recordings = RECORDINGS.lock().expect("state recordings poisoned");
+        recordings.entry(block).or_default().recordings += 1;
+        ACTIVE_RECORDINGS.fetch_add(1, Ordering::SeqCst);
+        Self { block }
+    }
+
+    /// Stops recording, and snapshots the recorded accounts & slots as they are in `fork`.
+    pub fn finish<DB: DatabaseRef>(self, fork: &DB, block_info: &BlockInfo) -> Result<StateSnapshot>
+    where
+        DB::Error: std::fmt::Debug,
+    {
+        let touched = RECORDINGS
+            .lock()
+            .expect("state recordings poisoned")
+            .get(&self.block)
+            .map(|recording| recording.touched.to_owned())
+            .unwrap_or_default();
+        // stops recording before reading `fork`, which isn't a sim's state
+        drop(self);
+        StateSnapshot::capture(fork, &touched, block_info)
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        let mut recordings = RECORDINGS.lock().expect("state recordings poisoned");
+        if let Some(recording) = recordings.get_mut(&self.block) {
+            recording.recordings -= 1;
+            if recording.recordings == 0 {
+                recordings.remove(&self.block);
+            }
+        }
+        ACTIVE_RECORDINGS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Adds the accounts & slots in `state` (a tx's changes) to the recording of `block`, if there is one.
+pub(crate) fn record_touched(block: rU256, state: &State) {
+    if ACTIVE_RECORDINGS.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let mut recordings = RECORDINGS.lock().expect("state recordings poisoned");
+    if let Some(recording) = recordings.get_mut(&block) {
+        for (address, account) in state {
+            recording
+                .touched
+                .entry(*address)
+                .or_default()
+                .extend(account.storage.keys().copied());
+        }
+    }
+}
+
+/// Returns a fork of `db` with no node behind it; reading state that isn't in `db` fails instead
+/// of fetching it.
+pub fn offline_fork_db(db: CacheDB<EmptyDB>) -> ForkDB {
+    // the receiver's dropped, so fetches fail right away
+    let (backend, _) = futures::channel::mpsc::channel(1);
+    ForkDB::new(backend, db)
+}
+
+/// An account's state in a `StateSnapshot`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct AccountSnapshot {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Bytes,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+/// The state of a fork that some sims touched, at the block it forked.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct StateSnapshot {
+    pub block_number: u64,
+    pub timestamp: U256,
+    pub base_fee: U256,
+    pub accounts: BTreeMap<Address, AccountSnapshot>,
+}
+
+impl StateSnapshot {
+    /// An empty snapshot of the state at `block_info`'s block.
+    pub fn new(block_info: &BlockInfo) -> Self {
+        Self {
+            block_number: block_info.number.as_u64(),
+            timestamp: block_info.timestamp,
+            base_fee: block_info.base_fee,
+            accounts: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the `touched` accounts & slots from `fork`.
+    fn capture<DB: DatabaseRef>(
+        fork: &DB,
+        touched: &Touched,
+        block_info: &BlockInfo,
+    ) -> Result<Self>
+    where
+        DB::Error: std::fmt::Debug,
+    {
+        let db_err = |err: DB::Error| anyhow::anyhow!("failed to read fork state: {:?}", err);
+        let mut accounts = BTreeMap::new();
+        for (address, slots) in touched {
+            let info = fork.basic(*address).map_err(db_err)?.unwrap_or_default();
+            let code = match info.code {
+                Some(code) => code,
+                None if info.code_hash != KECCAK_EMPTY => {
+                    fork.code_by_hash(info.code_hash).map_err(db_err)?
+                }
+                None => Bytecode::new(),
+            };
+            let mut storage = BTreeMap::new();
+            for slot in slots {
+                let value = fork.storage(*address, *slot).map_err(db_err)?;
+                storage.insert(U256(slot.into_limbs()), U256(value.into_limbs()));
+            }
+            accounts.insert(
+                Address::from(address.0),
+                AccountSnapshot {
+                    balance: U256(info.balance.into_limbs()),
+                    nonce: info.nonce,
+                    code: code.original_bytes().into(),
+                    storage,
+                },
+            );
+        }
+        Ok(Self {
+            accounts,
+            ..Self::new(block_info)
+        })
+    }
+
+    pub fn block_info(&self) -> BlockInfo {
+        BlockInfo {
+            number: self.block_number.into(),
+            timestamp: self.timestamp,
+            base_fee: self.base_fee,
+        }
+    }
+
+    /// Returns an in-memory db holding the snapshot's state.
+    pub fn cache_db(&self) -> CacheDB<EmptyDB> {
+        let mut db = CacheDB::new(EmptyDB::default());
+        for (address, account) in &self.accounts {
+            let address = B160::from(address.0);
+            db.insert_account_info(
+                address,
+                AccountInfo {
+                    balance: rU256::from_limbs(account.balance.0),
+                    nonce: account.nonce,
+                    code_hash: B256::from(keccak256(&account.code)),
+                    code: Some(Bytecode::new_raw(account.code.0.clone())),
+                },
+            );
+            for (slot, value) in &account.storage {
+                // EmptyDB can't fail
+                let _ = db.insert_account_storage(
+                    address,
+                    rU256::from_limbs(slot.0),
+                    rU256::from_limbs(value.0),
+                );
+            }
+        }
+        db
+    }
+
+    /// Returns a fork of the snapshot's state; see `offline_fork_db`.
+    pub fn fork_db(&self) -> ForkDB {
+        offline_fork_db(self.cache_db())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+        if self.accounts.is_empty() {
+            warn!("saved an empty fork snapshot; were any sims recorded?");
+        }
+        Ok(())
+    }
+}
+
+/// Default directory of `EventSnapshot`s.
+pub fn default_dir() -> PathBuf {
+    Path::new(CACHE_DIR).join("state")
+}
+
+/// Everything needed to re-simulate an event's backruns without a node: the user's tx, its trade
+/// params, and the state its sims touched.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EventSnapshot {
+    pub event: EventHistory,
+    pub tx: Transaction,
+    pub params: Vec<UserTradeParams>,
+    /// Txs committed after the user's tx, before each backrun.
+    pub following_txs: Vec<Transaction>,
+    pub state: StateSnapshot,
+}
+
+impl EventSnapshot {
+    /// Path of the snapshot of the event for `tx_hash` in `dir`.
+    pub fn path(dir: impl AsRef<Path>, tx_hash: H256) -> PathBuf {
+        dir.as_ref().join(format!("{:?}.json.gz", tx_hash))
+    }
+
+    /// Saves the snapshot (gzipped) in `dir`, named by its tx hash. Returns the path it's saved to.
+    pub fn save(&self, dir: impl AsRef<Path>) -> Result<PathBuf> {
+        std::fs::create_dir_all(&dir)?;
+        let path = Self::path(dir, self.tx.hash);
+        let file = BufWriter::new(File::create(&path)?);
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        serde_json::to_writer(&mut encoder, self)?;
+        encoder.finish()?;
+        Ok(path)
+    }
+
+    /// Loads the snapshot of the event for `tx_hash` from `dir`.
+    pub fn load(dir: impl AsRef<Path>, tx_hash: H256) -> Result<Self> {
+        let path = Self::path(dir, tx_hash);
+        let file = File::open(&path)
+            .map_err(|err| anyhow::anyhow!("no state snapshot at {}: {}", path.display(), err))?;
+        Ok(serde_json::from_reader(GzDecoder::new(BufReader::new(
+            file,
+        )))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_round_trips_snapshots() -> Result<()> {
+        let address = B160::from_low_u64_be(0xbeef);
+        let code = Bytes::from(vec![0x60, 0x00, 0x54, 0x00]); // PUSH1 0 SLOAD STOP
+        let mut fork = CacheDB::new(EmptyDB::default());
+        fork.insert_account_info(
+            address,
+            AccountInfo {
+                balance: rU256::from(420),
+                nonce: 7,
+                code_hash: B256::from(keccak256(&code)),
+                code: Some(Bytecode::new_raw(code.0.clone())),
+            },
+        );
+        fork.insert_account_storage(address, rU256::from(1), rU256::from(69))?;
+        fork.insert_account_storage(address, rU256::from(2), rU256::from(70))?;
+        // only touched slots are snapshotted
+        let touched = Touched::from([(address, BTreeSet::from([rU256::from(1)]))]);
+        let block_info = BlockInfo {
+            number: 17_000_000.into(),
+            timestamp: 1_680_000_000.into(),
+            base_fee: 20_000_000_000u64.into(),
+        };
+
+        let snapshot = StateSnapshot::capture(&fork, &touched, &block_info)?;
+        let snapshot: StateSnapshot = serde_json::from_str(&serde_json::to_string(&snapshot)?)?;
+        assert_eq!(snapshot.block_info().number, block_info.number);
+        assert_eq!(snapshot.block_info().base_fee, block_info.base_fee);
+
+        let db = snapshot.cache_db();
+        let info = db.basic(address)?.expect("account was snapshotted");
+        assert_eq!(info.balance, rU256::from(420));
+        assert_eq!(info.nonce, 7);
+        assert_eq!(info.code_hash, B256::from(keccak256(&code)));
+        assert_eq!(db.storage(address, rU256::from(1))?, rU256::from(69));
+        assert_eq!(db.storage(address, rU256::from(2))?, rU256::ZERO);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! A local mainnet fork for integration tests, pinned to `FORK_BLOCK` so their results are
+//! reproducible.
+//!
+//! Needs `anvil` on the PATH and `FORK_RPC` set to the URL of an archive node to fork from.
+//! Tests using it only run with `cargo test --features integration-tests`.
+
+use crate::{
+    client::{FailoverPolicy, FailoverTransport, RetryPolicy, RetryTransport, RpcTransport},
+    util::{get_block_info, RpcClient},
+    Result,
+};
+use anyhow::Context;
+use ethers::{
+    providers::{Middleware, Provider},
+    utils::{Anvil, AnvilInstance},
+};
+use rusty_sando::types::BlockInfo;
+use std::{env, sync::Arc, time::Duration};
+
+/// Block that test chains fork from.
+pub const FORK_BLOCK: u64 = 17_600_000;
+/// Forking makes anvil fetch the block from `FORK_RPC` before it's ready, which can be slow.
+const ANVIL_STARTUP_TIMEOUT_MS: u64 = 60_000;
+const CONNECT_ATTEMPTS: u32 = 10;
+
+/// An anvil process forking mainnet at `FORK_BLOCK`, and a client connected to it over WebSocket.
+///
+/// Anvil is killed when the chain is dropped, which also happens when a test using it panics.
+pub struct TestChain {
+    pub client: RpcClient,
+    /// Block info of `FORK_BLOCK`.
+    pub block_info: BlockInfo,
+    // declared last so it's dropped after the client
+    _anvil: AnvilInstance,
+}
+
+impl TestChain {
+    /// Spawns anvil & waits until it serves `FORK_BLOCK`.
+    pub async fn new() -> Result<Self> {
+        let fork_rpc =
+            env::var("FORK_RPC").context("FORK_RPC must be set for integration tests")?;
+        // waits for anvil to start listening; panics (inside the task) if it doesn't
+        let anvil = tokio::task::spawn_blocking(move || {
+            Anvil::new()
+                .fork(fork_rpc)
+                .fork_block_number(FORK_BLOCK)
+                .timeout(ANVIL_STARTUP_TIMEOUT_MS)
+                .spawn()
+        })
+        .await
+        .context("failed to spawn anvil")?;
+
+        let client = connect(&anvil.ws_endpoint()).await?;
+        let block_num = client.get_block_number().await?.as_u64();
+        if block_num != FORK_BLOCK {
+            return Err(anyhow::anyhow!(
+                "anvil forked block {}, expected {}",
+                block_num,
+                FORK_BLOCK
+            ));
+        }
+        let block_info = get_block_info(&client, FORK_BLOCK).await?;
+        Ok(Self {
+            client,
+            block_info,
+            _anvil: anvil,
+        })
+    }
+}
+
+/// Connects to anvil at `ws_url`, retrying while its WebSocket server comes up.
+async fn connect(ws_url: &str) -> Result<RpcClient> {
+    let mut attempt = 0;
+    let transport = loop {
+        match RpcTransport::connect(ws_url, 0).await {
+            Ok(transport) => break transport,
+            Err(err) if attempt + 1 >= CONNECT_ATTEMPTS => {
+                return Err(err.context(format!("failed to connect to anvil at {}", ws_url)))
+            }
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+        }
+    };
+    Ok(Arc::new(Provider::new(RetryTransport::new(
+        FailoverTransport::new(
+            vec![(ws_url.to_owned(), transport)],
+            FailoverPolicy::default(),
+        ),
+        RetryPolicy::default(),
+    ))))
+}
@@ -1,34 +1,131 @@
 use crate::{
-    config::Config,
-    info,
-    interfaces::{PairPool, PoolVariant},
-    Result,
+    chain::ChainConfig,
+    client::{
+        redact_url, ws_bridge, FailoverPolicy, FailoverTransport, RateLimiter, RetryPolicy,
+        RetryTransport, RpcTransport,
+    },
+    config::{Config, Secret},
+    data::metadata::{MetadataCache, TokenMetadata},
+    debug, info,
+    interfaces::{Dex, IntermediatePools, PairPool, PoolVariant},
+    sim::curve::coin_index,
+    warn, Result,
 };
 use ethers::{
-    prelude::{abigen, H160},
+    abi::Token,
+    prelude::{abigen, Multicall, H160},
     providers::{Middleware, Provider, Ws},
-    types::{transaction::eip2718::TypedTransaction, Address, Transaction, H256, U256},
+    types::{
+        transaction::eip2718::TypedTransaction, Address, Transaction, TransactionReceipt, H256,
+        U256,
+    },
+    utils::format_ether,
 };
-use futures::future;
+use futures::{future, stream, StreamExt};
 use mev_share_sse::EventHistory;
 use rusty_sando::types::BlockInfo;
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
 use uniswap_v3_math::{full_math::mul_div, sqrt_price_math::Q96};
 
 pub use ethers::utils::WEI_IN_ETHER as ETH;
-pub type WsClient = Arc<Provider<Ws>>;
+pub type RpcClient = Arc<Provider<RetryTransport<FailoverTransport<RpcTransport>>>>;
 
-pub async fn get_ws_client(rpc_url: Option<String>) -> Result<WsClient> {
-    let rpc_url = if let Some(rpc_url) = rpc_url {
-        rpc_url
-    } else {
-        Config::default().rpc_url_ws
-    };
-    let provider = Provider::<Ws>::connect(rpc_url).await?;
-    Ok(Arc::new(provider))
+abigen!(
+    IPairTokens,
+    r#"[
+        function token0() external view returns (address)
+        function token1() external view returns (address)
+    ]"#
+);
+abigen!(
+    ITokenMetadata,
+    r#"[
+        function decimals() external view returns (uint256)
+        function symbol() external view returns (string)
+    ]"#
+);
+abigen!(
+    IUniswapV2Factory,
+    r#"[
+        function getPair(address tokenA, address tokenB) external view returns (address pair)
+    ]"#
+);
+abigen!(
+    ICurvePool,
+    r#"[
+        function coins(uint256 i) external view returns (address)
+    ]"#
+);
+abigen!(
+    IUniswapV3Factory,
+    r#"[
+        function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+    ]"#
+);
+
+/// WETH (mainnet). Sims use the configured chain's, `ChainConfig::weth`.
+pub const WETH: Address = H160([
+    0xc0, 0x2a, 0xaa, 0x39, 0xb2, 0x23, 0xfe, 0x8d, 0x0a, 0x0e, 0x5c, 0x4f, 0x27, 0xea, 0xd9, 0x08,
+    0x3c, 0x75, 0x6c, 0xc2,
+]);
+
+/// Connects to `rpc_urls` (default: `RPC_URL`) over WebSocket, HTTP, or IPC, depending on each URL.
+/// Requests are spread across the endpoints round-robin, failing over to the next one when an
+/// endpoint errors or times out. WebSocket connections are re-established up to `WS_RECONNECTS`
+/// times if they drop, and idempotent requests that fail on every endpoint are retried.
+/// Requests to endpoints with a rate limit (`RPC_RATE_LIMIT`) wait for their turn.
+///
+/// Endpoints that can't be connected to are skipped; fails if none can, or if the node isn't on
+/// the configured chain (`CHAIN`).
+pub async fn get_client(rpc_urls: Option<Vec<Secret<String>>>) -> Result<RpcClient> {
+    let config = Config::default();
+    let mut endpoints = vec![];
+    let mut connect_err = None;
+    let rpc_urls = rpc_urls.unwrap_or(config.rpc_urls.to_owned());
+    for (i, rpc_url) in rpc_urls.iter().enumerate() {
+        let rpc_url = rpc_url.expose();
+        let limiter = config
+            .rpc_rate_limit(i)
+            .map(|limit| Arc::new(RateLimiter::new(limit)));
+        match RpcTransport::connect(rpc_url, config.ws_reconnects).await {
+            Ok(transport) => endpoints.push((rpc_url.to_owned(), transport, limiter)),
+            Err(err) => {
+                warn!("skipping RPC endpoint {}: {}", redact_url(rpc_url), err);
+                connect_err = Some(err);
+            }
+        }
+    }
+    if endpoints.is_empty() {
+        return Err(connect_err
+            .unwrap_or(anyhow::anyhow!("no RPC_URL given"))
+            .context("failed to connect to any RPC endpoint"));
+    }
+    let client = Arc::new(Provider::new(RetryTransport::new(
+        FailoverTransport::rate_limited(endpoints, FailoverPolicy::default()),
+        RetryPolicy::default(),
+    )));
+    config.chain.verify(&client).await?;
+    Ok(client)
 }
 
-pub async fn fetch_txs(client: &WsClient, events: &Vec<EventHistory>) -> Result<Vec<Transaction>> {
+/// Returns a provider on the next live WebSocket endpoint of `client` (round-robin), without the
+/// retry or failover wrappers, for rusty-sando's fork backend, which needs a plain WebSocket provider.
+///
+/// If none of `client`'s endpoints are WebSocket, the provider is connected to a `ws_bridge` that
+/// forwards fork state requests to `client`, so forks can be made from HTTP or IPC endpoints too.
+pub async fn fork_provider(client: &RpcClient) -> Result<Arc<Provider<Ws>>> {
+    let endpoints = (**client).as_ref().inner();
+    match endpoints.next_endpoint(|transport| matches!(transport, RpcTransport::Ws(_))) {
+        Some(RpcTransport::Ws(ws)) => Ok(Arc::new(Provider::new(ws.clone()))),
+        _ => Ok(Arc::new(Provider::new(ws_bridge(client.clone()).await?))),
+    }
+}
+
+pub async fn fetch_txs(client: &RpcClient, events: &Vec<EventHistory>) -> Result<Vec<Transaction>> {
     let tx_hashes: Vec<H256> = events.iter().map(|e: &EventHistory| e.hint.hash).collect();
     let mut handles = vec![];
 
@@ -61,21 +158,63 @@ pub async fn fetch_txs(client: &WsClient, events: &Vec<EventHistory>) -> Result<
     Ok(results)
 }
 
-pub async fn get_pair_tokens(client: &WsClient, pair: Address) -> Result<(Address, Address)> {
-    abigen!(
-        IPairTokens,
-        r#"[
-            function token0() external view returns (address)
-            function token1() external view returns (address)
-        ]"#
-    );
+/// Fetches the receipts of `tx_hashes` concurrently, at most `concurrency` at a time.
+///
+/// Txs without a receipt (yet), or whose fetch failed, are left out; callers fetch those
+/// individually, so their errors surface there.
+pub async fn get_receipts<M: Middleware + 'static>(
+    client: &Arc<M>,
+    tx_hashes: &[H256],
+    concurrency: usize,
+) -> HashMap<H256, TransactionReceipt> {
+    stream::iter(tx_hashes.iter().copied())
+        .map(|tx_hash| async move { (tx_hash, client.get_transaction_receipt(tx_hash).await) })
+        .buffer_unordered(concurrency.max(1))
+        .filter_map(|(tx_hash, res)| async move {
+            match res {
+                Ok(receipt) => receipt.map(|receipt| (tx_hash, receipt)),
+                Err(err) => {
+                    debug!("failed to fetch receipt of tx {:?}: {}", tx_hash, err);
+                    None
+                }
+            }
+        })
+        .collect()
+        .await
+}
+
+static METADATA_CACHE: OnceLock<MetadataCache> = OnceLock::new();
+
+/// Returns the cache of pool tokens & token metadata shared by all sims.
+/// Only kept in memory unless `init_metadata_cache` was called first.
+pub fn metadata_cache() -> &'static MetadataCache {
+    METADATA_CACHE.get_or_init(MetadataCache::default)
+}
+
+/// Loads the metadata cache saved at `path`, which it will be saved to by `MetadataCache::save`.
+/// Must be called before the cache is first used.
+pub fn init_metadata_cache(path: PathBuf) -> Result<()> {
+    METADATA_CACHE
+        .set(MetadataCache::load(path)?)
+        .map_err(|_| anyhow::anyhow!("metadata cache already initialized"))
+}
+
+/// Returns the (token0, token1) of `pair`. Results are cached, since they never change.
+pub async fn get_pair_tokens<M: Middleware + 'static>(
+    client: &Arc<M>,
+    pair: Address,
+) -> Result<(Address, Address)> {
+    if let Some(tokens) = metadata_cache().pair_tokens(&pair) {
+        return Ok(tokens);
+    }
     let contract = IPairTokens::new(pair, client.clone());
     let token0 = contract.token_0().call().await?;
     let token1 = contract.token_1().call().await?;
+    metadata_cache().insert_pair_tokens(pair, (token0, token1));
     Ok((token0, token1))
 }
 
-pub async fn get_block_info(client: &WsClient, block_num: u64) -> Result<BlockInfo> {
+pub async fn get_block_info(client: &RpcClient, block_num: u64) -> Result<BlockInfo> {
     let block = client
         .get_block(block_num)
         .await?
@@ -87,111 +226,483 @@ pub async fn get_block_info(client: &WsClient, block_num: u64) -> Result<BlockIn
     })
 }
 
-async fn get_v2_pairs(client: &WsClient, pair_tokens: (Address, Address)) -> Result<Vec<Address>> {
-    abigen!(
-        IUniswapV2Factory,
-        r#"[
-            function getPair(address tokenA, address tokenB) external view returns (address pair)
-        ]"#
-    );
-    let uni_factory = IUniswapV2Factory::new(
-        "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f".parse::<H160>()?,
-        client.clone(),
-    );
-    let sushi_factory = IUniswapV2Factory::new(
-        "0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac".parse::<H160>()?,
-        client.clone(),
-    );
+/// Returns a Multicall3 instance at `chain`'s multicall address, or if it has none, selected by
+/// chain id. Returns None if it's not deployed (e.g. on custom devnets), in which case callers
+/// should fall back to individual calls.
+async fn get_multicall<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+) -> Option<Multicall<M>> {
+    match Multicall::new(client.clone(), chain.multicall).await {
+        Ok(multicall) => Some(multicall),
+        Err(err) => {
+            debug!(
+                "multicall unavailable, falling back to individual calls: {}",
+                err
+            );
+            None
+        }
+    }
+}
 
-    let uni_pair: Result<Address, _> = uni_factory
-        .get_pair(pair_tokens.0, pair_tokens.1)
-        .call()
-        .await;
-    let sushi_pair: Result<Address, _> = sushi_factory
-        .get_pair(pair_tokens.0, pair_tokens.1)
-        .call()
-        .await;
-    let mut pairs = vec![];
-    if let Ok(uni_pair) = uni_pair {
-        pairs.push(uni_pair);
+/// Fetches the tokens of each of `pairs` that isn't cached yet in a single multicall,
+/// so that subsequent `get_pair_tokens` calls hit the cache.
+///
+/// Does nothing if multicall is unavailable; `get_pair_tokens` will fetch them individually.
+pub async fn prefetch_pair_tokens<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    pairs: &[Address],
+) -> Result<()> {
+    let pairs = metadata_cache().missing_pair_tokens(pairs);
+    if pairs.is_empty() {
+        return Ok(());
     }
-    if let Ok(sushi_pair) = sushi_pair {
-        pairs.push(sushi_pair);
+    let mut multicall = match get_multicall(client, chain).await {
+        Some(multicall) => multicall,
+        None => return Ok(()),
+    };
+    for pair in &pairs {
+        let contract = IPairTokens::new(*pair, client.clone());
+        multicall.add_call(contract.token_0(), true);
+        multicall.add_call(contract.token_1(), true);
+    }
+    let results = multicall.call_raw().await?;
+    for (pair, tokens) in pairs.iter().zip(results.chunks(2)) {
+        // failed calls are left uncached, so that `get_pair_tokens` surfaces their errors
+        if let [Ok(Token::Address(token0)), Ok(Token::Address(token1))] = tokens {
+            metadata_cache().insert_pair_tokens(*pair, (*token0, *token1));
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the metadata of each of `tokens` that isn't cached yet in a single multicall,
+/// so that subsequent `get_token_metadata` calls hit the cache.
+///
+/// Does nothing if multicall is unavailable; `get_token_metadata` will fetch them individually.
+pub async fn prefetch_token_metadata<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    tokens: &[Address],
+) -> Result<()> {
+    let tokens = metadata_cache().missing_tokens(tokens);
+    if tokens.is_empty() {
+        return Ok(());
+    }
+    let mut multicall = match get_multicall(client, chain).await {
+        Some(multicall) => multicall,
+        None => return Ok(()),
+    };
+    for token in &tokens {
+        let contract = ITokenMetadata::new(*token, client.clone());
+        multicall.add_call(contract.decimals(), true);
+        multicall.add_call(contract.symbol(), true);
+    }
+    // a bytes32 symbol can't be decoded as a string, which fails the whole multicall
+    let results = match multicall.call_raw().await {
+        Ok(results) => results,
+        Err(err) => {
+            debug!("failed to prefetch token metadata: {}", err);
+            return Ok(());
+        }
+    };
+    for (token, metadata) in tokens.iter().zip(results.chunks(2)) {
+        // tokens without a string symbol are left uncached, so that `get_token_metadata` tries bytes32
+        if let [Ok(Token::Uint(decimals)), Ok(Token::String(symbol))] = metadata {
+            metadata_cache().insert_token(
+                *token,
+                TokenMetadata {
+                    symbol: Some(symbol.to_owned()),
+                    decimals: *decimals,
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns (pair address, DEX) for the pair trading `pair_tokens` on each of `chain`'s V2-style DEXes.
+async fn get_v2_pairs<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<(Address, Dex)>> {
+    let mut pairs = vec![];
+    for (factory, dex) in chain.v2_factories() {
+        let factory = IUniswapV2Factory::new(factory, client.clone());
+        let pair: Result<Address, _> = factory.get_pair(pair_tokens.0, pair_tokens.1).call().await;
+        if let Ok(pair) = pair {
+            pairs.push((pair, dex));
+        }
     }
 
     Ok(pairs)
 }
 
-async fn get_v3_pair(client: &WsClient, pair_tokens: (Address, Address)) -> Result<Address> {
+/// Returns the DEX of a V2-style `pair`, determined by the factory that deployed it.
+pub async fn get_v2_dex<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    pair: Address,
+) -> Result<Dex> {
     abigen!(
-        IUniswapV3Factory,
+        IUniswapV2Pair,
         r#"[
-            function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+            function factory() external view returns (address)
         ]"#
     );
-    let contract = IUniswapV3Factory::new(
-        "0x1F98431c8aD98523631AE4a59f267346ea31F984".parse::<H160>()?,
-        client.clone(),
-    );
-    Ok(contract
-        .get_pool(pair_tokens.0, pair_tokens.1, 3000)
+    let factory = IUniswapV2Pair::new(pair, client.clone())
+        .factory()
         .call()
-        .await?)
+        .await?;
+    Ok(if Some(factory) == chain.sushiswap_factory {
+        Dex::SushiSwap
+    } else {
+        Dex::UniswapV2
+    })
 }
 
-/// Get pair address from all supported factories, including the given pair.
+/// Fee tiers (in hundredths of a bip) enabled on the UniswapV3 factory.
+const V3_FEE_TIERS: [u32; 4] = [100, 500, 3000, 10000];
+
+/// Returns (pool address, fee tier) for every UniswapV3 pool trading `pair_tokens`.
+async fn get_v3_pools<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<(Address, u32)>> {
+    let contract = IUniswapV3Factory::new(chain.uniswap_v3_factory, client.clone());
+    let pools = future::join_all(V3_FEE_TIERS.iter().map(|fee| {
+        let contract = contract.clone();
+        async move {
+            contract
+                .get_pool(pair_tokens.0, pair_tokens.1, *fee)
+                .call()
+                .await
+                .map(|pool| (pool, *fee))
+        }
+    }))
+    .await;
+    let mut v3_pools = vec![];
+    for pool in pools {
+        let (address, fee) = pool?;
+        // factory returns the zero address if no pool exists for the fee tier
+        if !address.is_zero() {
+            v3_pools.push((address, fee));
+        }
+    }
+    Ok(v3_pools)
+}
+
+/// Get pair address from all of `chain`'s supported factories, including the given pair, and
+/// the Curve pools (of `chain.curve_pools`) that trade it.
 /// Filter what I return if you need to.
-pub async fn get_all_trading_pools(
-    client: &WsClient,
+///
+/// Queries all factories in a single multicall, if available.
+pub async fn get_all_trading_pools<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<PairPool>> {
+    let mut all_pairs = match get_multicall(client, chain).await {
+        Some(multicall) => {
+            get_all_trading_pools_multicall(client, chain, multicall, pair_tokens).await?
+        }
+        None => get_factory_pools(client, chain, pair_tokens).await?,
+    };
+    all_pairs.append(&mut get_curve_pools(client, chain, pair_tokens).await);
+    Ok(all_pairs)
+}
+
+/// `get_all_trading_pools`' factory pools, w/ each factory queried individually.
+async fn get_factory_pools<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
     pair_tokens: (Address, Address),
 ) -> Result<Vec<PairPool>> {
     let mut all_pairs = vec![];
-    // push v3 pair (there should only be one for a given fee, which we hard-code to 3000 in get_v3_pair)
-    all_pairs.push(PairPool {
-        address: get_v3_pair(client, pair_tokens).await?,
-        variant: PoolVariant::UniswapV3,
-    });
+    // push v3 pools (one for each fee tier that has been deployed)
+    all_pairs.append(
+        &mut get_v3_pools(client, chain, pair_tokens)
+            .await?
+            .into_iter()
+            .map(|(address, fee)| PairPool {
+                address,
+                variant: PoolVariant::UniswapV3,
+                fee: Some(fee),
+                dex: Some(Dex::UniswapV3),
+            })
+            .collect::<Vec<_>>(),
+    );
     // v2 pairs pull from multiple v2 clones
-    let v2_pairs = get_v2_pairs(client, pair_tokens).await?;
+    let v2_pairs = get_v2_pairs(client, chain, pair_tokens).await?;
     all_pairs.append(
         &mut v2_pairs
             .into_iter()
-            .map(|pair| PairPool {
+            .map(|(pair, dex)| PairPool {
                 address: pair,
                 variant: PoolVariant::UniswapV2,
+                fee: None,
+                dex: Some(dex),
             })
             .collect::<Vec<_>>(),
     );
     Ok(all_pairs)
 }
 
-/// Returns the price (token1 per token0).
-pub fn get_price_v2(reserves0: U256, reserves1: U256, token0_decimals: U256) -> Result<U256> {
-    Ok((reserves1 * U256::from(10).pow(token0_decimals)) / reserves0)
+/// Returns the (coin 0, coin 1) of Curve `pool`. Results are cached w/ pair tokens, since they
+/// never change.
+pub async fn get_curve_coins<M: Middleware + 'static>(
+    client: &Arc<M>,
+    pool: Address,
+) -> Result<(Address, Address)> {
+    if let Some(coins) = metadata_cache().pair_tokens(&pool) {
+        return Ok(coins);
+    }
+    let contract = ICurvePool::new(pool, client.clone());
+    let coin0 = contract.coins(0.into()).call().await?;
+    let coin1 = contract.coins(1.into()).call().await?;
+    metadata_cache().insert_pair_tokens(pool, (coin0, coin1));
+    Ok((coin0, coin1))
 }
 
-/// Returns the price (token1 per token0).
-pub fn get_price_v3(liquidity: U256, sqrt_price_x96: U256, token0_decimals: U256) -> Result<U256> {
+/// Returns the pools of `chain.curve_pools` that trade `pair_tokens`, w/ their ETH coin standing
+/// in for WETH. Pools whose coins can't be read are skipped.
+async fn get_curve_pools<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    pair_tokens: (Address, Address),
+) -> Vec<PairPool> {
+    let mut pools = vec![];
+    for &pool in &chain.curve_pools {
+        let coins = match get_curve_coins(client, pool).await {
+            Ok((coin0, coin1)) => [coin0, coin1],
+            Err(err) => {
+                debug!("skipping Curve pool {:?}: {}", pool, err);
+                continue;
+            }
+        };
+        let trades = |token| coin_index(&coins, token, chain.weth).is_some();
+        if trades(pair_tokens.0) && trades(pair_tokens.1) {
+            pools.push(PairPool {
+                address: pool,
+                variant: PoolVariant::Curve,
+                fee: None,
+                dex: Some(Dex::Curve),
+            });
+        }
+    }
+    pools
+}
+
+/// Returns the pools connecting WETH to `token` through each of `chain.intermediate_tokens`,
+/// skipping intermediates that either leg has no pool for.
+pub async fn get_intermediate_pools<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    token: Address,
+) -> Result<Vec<IntermediatePools>> {
+    let mut intermediate_pools = vec![];
+    for &intermediate in chain
+        .intermediate_tokens
+        .iter()
+        .filter(|&&intermediate| intermediate != token && intermediate != chain.weth)
+    {
+        let live_pools = |pools: Vec<PairPool>| {
+            pools
+                .into_iter()
+                .filter(|pool| !pool.address.is_zero())
+                .collect::<Vec<_>>()
+        };
+        let weth_pools =
+            live_pools(get_all_trading_pools(client, chain, (chain.weth, intermediate)).await?);
+        let token_pools =
+            live_pools(get_all_trading_pools(client, chain, (intermediate, token)).await?);
+        if weth_pools.is_empty() || token_pools.is_empty() {
+            continue;
+        }
+        intermediate_pools.push(IntermediatePools {
+            token: intermediate,
+            weth_pools,
+            token_pools,
+        });
+    }
+    Ok(intermediate_pools)
+}
+
+/// `get_all_trading_pools`, with every factory query batched into `multicall`.
+async fn get_all_trading_pools_multicall<M: Middleware + 'static>(
+    client: &Arc<M>,
+    chain: &ChainConfig,
+    mut multicall: Multicall<M>,
+    pair_tokens: (Address, Address),
+) -> Result<Vec<PairPool>> {
+    let v3_factory = IUniswapV3Factory::new(chain.uniswap_v3_factory, client.clone());
+    for fee in V3_FEE_TIERS {
+        multicall.add_call(
+            v3_factory.get_pool(pair_tokens.0, pair_tokens.1, fee),
+            false,
+        );
+    }
+    let v2_factories = chain.v2_factories();
+    for (factory, _) in v2_factories {
+        let factory = IUniswapV2Factory::new(factory, client.clone());
+        multicall.add_call(factory.get_pair(pair_tokens.0, pair_tokens.1), true);
+    }
+    let results = multicall.call_raw().await?;
+    let (v3_results, v2_results) = results.split_at(V3_FEE_TIERS.len());
+
+    let mut all_pairs = vec![];
+    for (fee, res) in V3_FEE_TIERS.iter().zip(v3_results) {
+        // factory returns the zero address if no pool exists for the fee tier
+        if let Ok(Token::Address(address)) = res {
+            if !address.is_zero() {
+                all_pairs.push(PairPool {
+                    address: *address,
+                    variant: PoolVariant::UniswapV3,
+                    fee: Some(*fee),
+                    dex: Some(Dex::UniswapV3),
+                });
+            }
+        }
+    }
+    for ((_, dex), res) in v2_factories.iter().zip(v2_results) {
+        if let Ok(Token::Address(address)) = res {
+            all_pairs.push(PairPool {
+                address: *address,
+                variant: PoolVariant::UniswapV2,
+                fee: None,
+                dex: Some(*dex),
+            });
+        }
+    }
+    Ok(all_pairs)
+}
+
+/// Returns the price of WETH in the pair's other token (TKN per WETH), as an 18-decimal
+/// fixed-point number.
+///
+/// `token_decimals` are the decimals of (token0, token1).
+fn normalize_price(
+    reserves0: U256,
+    reserves1: U256,
+    token_decimals: (U256, U256),
+    token0_is_weth: bool,
+) -> Result<U256> {
+    let ten = U256::from(10);
+    let (reserves_weth, reserves_token, weth_decimals, token_decimals) = if token0_is_weth {
+        (reserves0, reserves1, token_decimals.0, token_decimals.1)
+    } else {
+        (reserves1, reserves0, token_decimals.1, token_decimals.0)
+    };
+    Ok(mul_div(
+        reserves_token,
+        ten.pow(weth_decimals + 18),
+        reserves_weth * ten.pow(token_decimals),
+    )?)
+}
+
+/// Returns the price (TKN per WETH, 18 decimals) of a UniswapV2 pair from its reserves.
+pub fn get_price_v2(
+    reserves0: U256,
+    reserves1: U256,
+    token_decimals: (U256, U256),
+    token0_is_weth: bool,
+) -> Result<U256> {
+    normalize_price(reserves0, reserves1, token_decimals, token0_is_weth)
+}
+
+/// Returns the price (TKN per WETH, 18 decimals) of a UniswapV3 pool from its liquidity & sqrt price.
+pub fn get_price_v3(
+    liquidity: U256,
+    sqrt_price_x96: U256,
+    token_decimals: (U256, U256),
+    token0_is_weth: bool,
+) -> Result<U256> {
     let reserves0 = mul_div(liquidity, Q96, sqrt_price_x96)?;
     let reserves1 = mul_div(liquidity, sqrt_price_x96, Q96)?;
+    normalize_price(reserves0, reserves1, token_decimals, token0_is_weth)
+}
 
-    Ok((reserves1 * U256::from(10).pow(token0_decimals)) / reserves0)
+/// Returns how far (in basis points) a pool's price moved from `before` to `after`, in either
+/// direction. Both must be prices from `get_price_v2`/`get_price_v3`, so they're normalized to
+/// the same decimals. None if `before` is unknown (zero).
+pub fn price_impact_bps(before: U256, after: U256) -> Option<u64> {
+    if before.is_zero() {
+        return None;
+    }
+    let moved = if after > before {
+        after - before
+    } else {
+        before - after
+    };
+    let bps = mul_div(moved, 10_000.into(), before).ok()?;
+    Some(if bps > u64::MAX.into() {
+        u64::MAX
+    } else {
+        bps.as_u64()
+    })
 }
 
-pub async fn get_decimals(client: &WsClient, token: Address) -> Result<U256> {
+/// Converts wei to ETH. Lossy; only meant for display & quick filtering.
+pub fn wei_to_eth(wei: U256) -> f64 {
+    format_ether(wei).parse().unwrap_or(f64::MAX)
+}
+
+/// Returns the decimals of `token`. Results are cached, since they never change.
+pub async fn get_decimals<M: Middleware + 'static>(
+    client: &Arc<M>,
+    token: Address,
+) -> Result<U256> {
+    Ok(get_token_metadata(client, token).await?.decimals)
+}
+
+/// Returns the symbol of a token whose `symbol()` returns a null-padded bytes32 (e.g. MKR)
+/// rather than a string. None if that fails too.
+async fn get_bytes32_symbol<M: Middleware + 'static>(
+    client: &Arc<M>,
+    token: Address,
+) -> Option<String> {
     abigen!(
-        IERC20,
+        ITokenSymbolBytes32,
         r#"[
-            function decimals() external view returns (uint256)
+            function symbol() external view returns (bytes32)
         ]"#
     );
-    let contract = IERC20::new(token, client.clone());
+    let symbol = ITokenSymbolBytes32::new(token, client.clone())
+        .symbol()
+        .call()
+        .await
+        .ok()?;
+    let len = symbol.iter().position(|b| *b == 0).unwrap_or(symbol.len());
+    String::from_utf8(symbol[..len].to_vec())
+        .ok()
+        .filter(|symbol| !symbol.is_empty())
+}
+
+/// Returns the symbol & decimals of `token`. Results are cached, since they never change.
+pub async fn get_token_metadata<M: Middleware + 'static>(
+    client: &Arc<M>,
+    token: Address,
+) -> Result<TokenMetadata> {
+    if let Some(metadata) = metadata_cache().token(&token) {
+        return Ok(metadata);
+    }
+
+    let contract = ITokenMetadata::new(token, client.clone());
     let decimals = contract.decimals().call().await?;
-    Ok(decimals)
+    let symbol = match contract.symbol().call().await {
+        Ok(symbol) => Some(symbol),
+        Err(_) => get_bytes32_symbol(client, token).await,
+    };
+    let metadata = TokenMetadata { symbol, decimals };
+    metadata_cache().insert_token(token, metadata.to_owned());
+    Ok(metadata)
 }
 
 pub async fn get_balance_call(
-    client: &WsClient,
+    client: &RpcClient,
     token: Address,
     account: Address,
 ) -> Result<TypedTransaction> {
@@ -231,11 +742,161 @@ pub fn filter_events_by_topic(
 
 #[cfg(test)]
 pub mod test {
-    use crate::util::{get_ws_client, WsClient};
+    use super::*;
+    use crate::chain::Chain;
+    use crate::util::{get_client, RpcClient};
     use crate::Result;
+    use ethers::types::Bytes;
+
+    pub async fn get_test_client() -> Result<RpcClient> {
+        let rpc_client = get_client(None).await?;
+        Ok(rpc_client)
+    }
+
+    #[tokio::test]
+    async fn it_caches_pair_tokens() -> Result<()> {
+        let (client, mock) = Provider::mocked();
+        let client = Arc::new(client);
+        let pair = Address::random();
+        let (token0, token1) = (Address::random(), Address::random());
+        // responses are popped LIFO; token0() is called first
+        mock.push(Bytes::from(ethers::abi::encode(&[Token::Address(token1)])))?;
+        mock.push(Bytes::from(ethers::abi::encode(&[Token::Address(token0)])))?;
+
+        let (hits, misses) = metadata_cache().stats();
+        assert_eq!(get_pair_tokens(&client, pair).await?, (token0, token1));
+        // the mock has no responses left, so this fails if it makes any requests
+        assert_eq!(get_pair_tokens(&client, pair).await?, (token0, token1));
+        let (new_hits, new_misses) = metadata_cache().stats();
+        assert!(new_hits > hits && new_misses > misses);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_decodes_bytes32_symbols() -> Result<()> {
+        let (client, mock) = Provider::mocked();
+        let client = Arc::new(client);
+        let token = Address::random();
+        let mut mkr = [0u8; 32];
+        mkr[..3].copy_from_slice(b"MKR");
+        // responses are popped LIFO: decimals(), then symbol() as a string, then as bytes32
+        mock.push(Bytes::from(mkr.to_vec()))?;
+        mock.push(Bytes::from(mkr.to_vec()))?;
+        mock.push(Bytes::from(ethers::abi::encode(&[Token::Uint(18.into())])))?;
+
+        let metadata = get_token_metadata(&client, token).await?;
+        assert_eq!(metadata.symbol, Some("MKR".to_owned()));
+        assert_eq!(metadata.decimals, 18.into());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn it_falls_back_without_multicall() -> Result<()> {
+        let (client, mock) = Provider::mocked();
+        // a custom devnet
+        mock.push(U256::from(0xdeadbeef_u64))?;
+        let chain = ChainConfig::preset(Chain::Custom);
+        assert!(get_multicall(&Arc::new(client), &chain).await.is_none());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn multicall_finds_same_pools() -> Result<()> {
+        let client = get_test_client().await?;
+        let shib = "0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE".parse::<Address>()?;
+        let chain = ChainConfig::default();
+        let multicall = get_multicall(&client, &chain)
+            .await
+            .expect("mainnet has multicall");
+        let batched =
+            get_all_trading_pools_multicall(&client, &chain, multicall, (WETH, shib)).await?;
+
+        let mut individual = get_v3_pools(&client, &chain, (WETH, shib))
+            .await?
+            .into_iter()
+            .map(|(address, fee)| (address, Some(fee)))
+            .collect::<Vec<_>>();
+        individual.extend(
+            get_v2_pairs(&client, &chain, (WETH, shib))
+                .await?
+                .into_iter()
+                .map(|(address, _)| (address, None)),
+        );
+        assert_eq!(
+            batched
+                .iter()
+                .map(|pool| (pool.address, pool.fee))
+                .collect::<Vec<_>>(),
+            individual
+        );
+        Ok(())
+    }
+
+    /// Asserts that `price` is within 1% of `expected` (both 18-decimal fixed-point).
+    fn assert_price_near(price: U256, expected: U256) {
+        let diff = if price > expected {
+            price - expected
+        } else {
+            expected - price
+        };
+        assert!(
+            diff <= expected / 100,
+            "price {} not near {}",
+            price,
+            expected
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn it_finds_sushiswap_pools() -> Result<()> {
+        let client = get_test_client().await?;
+        let shib = "0x95aD61b0a150d79219dCF64E1E6Cc01f0B64C4cE".parse::<Address>()?;
+        let chain = ChainConfig::default();
+        let pools = get_all_trading_pools(&client, &chain, (WETH, shib)).await?;
+        let sushi_pool = pools
+            .iter()
+            .find(|pool| pool.dex == Some(Dex::SushiSwap))
+            .expect("SHIB/WETH pool should exist on SushiSwap");
+        assert_eq!(sushi_pool.variant, PoolVariant::UniswapV2);
+        assert_eq!(
+            get_v2_dex(&client, &chain, sushi_pool.address).await?,
+            Dex::SushiSwap
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_usdc_weth_price_v2() -> Result<()> {
+        // USDC (token0, 6 decimals) / WETH (token1, 18 decimals) reserves at ~1880 USDC/WETH
+        let reserves0 = U256::from(37_600_000) * U256::from(10).pow(6.into());
+        let reserves1 = U256::from(20_000) * ETH;
+        let price = get_price_v2(reserves0, reserves1, (6.into(), 18.into()), false)?;
+        assert_price_near(price, U256::from(1880) * ETH);
+
+        // same reserves w/ tokens flipped (WETH is token0)
+        let price = get_price_v2(reserves1, reserves0, (18.into(), 6.into()), true)?;
+        assert_price_near(price, U256::from(1880) * ETH);
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_usdc_weth_price_v3() -> Result<()> {
+        // representative sqrtPriceX96 & liquidity for the USDC/WETH 0.05% pool at ~1880 USDC/WETH
+        let sqrt_price_x96 = U256::from_dec_str("1827221134466418476167233329963045")?;
+        let liquidity = U256::from_dec_str("21244867223747713493")?;
+        let price = get_price_v3(liquidity, sqrt_price_x96, (6.into(), 18.into()), false)?;
+        assert_price_near(price, U256::from(1880) * ETH);
+        Ok(())
+    }
 
-    pub async fn get_test_ws_client() -> Result<WsClient> {
-        let ws_client = get_ws_client(None).await?;
-        Ok(ws_client)
+    #[test]
+    fn it_measures_price_impact_in_bps() {
+        let before = U256::from(1880) * ETH;
+        // 1880 -> 1786 USDC/WETH (a WETH sell) & 1880 -> 1974 USDC/WETH (a WETH buy) both move it 5%
+        assert_eq!(price_impact_bps(before, U256::from(1786) * ETH), Some(500));
+        assert_eq!(price_impact_bps(before, U256::from(1974) * ETH), Some(500));
+        assert_eq!(price_impact_bps(before, before), Some(0));
+        // no pre-trade price
+        assert_eq!(price_impact_bps(U256::zero(), before), None);
     }
 }